@@ -0,0 +1,387 @@
+//! Reading the model rewrites out of a shell script that switches providers
+//! with `sed` or `jq`, to turn them into provider files. The parsing is
+//! heuristic: literal `s/old/new/` substitutions and `.agents.<name>.model
+//! = "..."` assignments are understood, and each `case` branch or function
+//! becomes one provider. Lines that touch models in any other way are
+//! reported for the user to port by hand.
+
+use std::collections::BTreeMap;
+
+use crate::provider::{AgentModelConfig, ProviderConfig};
+
+/// One model change the script makes
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Rewrite {
+    /// `sed 's/from/to/'`: every model containing `from` gets it replaced
+    Substitute { from: String, to: String },
+    /// `jq '.agents.<agent>.model = "model"'`
+    Assign { agent: String, model: String },
+}
+
+/// What was read out of a script
+#[derive(Debug, Clone, Default)]
+pub struct Adoption {
+    /// Provider name to the rewrites that switch to it, in script order
+    pub providers: BTreeMap<String, Vec<Rewrite>>,
+    /// `sed` and `jq` lines that couldn't be understood, as (line number,
+    /// line)
+    pub skipped: Vec<(usize, String)>,
+}
+
+/// Read the model rewrites in `script`. Rewrites inside a `case` branch or
+/// a function belong to the provider it is named after (`use_openrouter()`
+/// becomes `openrouter`); others to the provider of the model they switch
+/// to.
+pub fn parse_script(script: &str) -> Adoption {
+    let mut adoption = Adoption::default();
+    let mut section: Option<String> = None;
+    for (index, raw) in script.lines().enumerate() {
+        let line = raw.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        if line == ";;" || line == "}" || line == "esac" {
+            section = None;
+            continue;
+        }
+        if let Some(name) = section_name(line) {
+            section = Some(name);
+        }
+
+        let rewrites = if contains_word(line, "sed") {
+            sed_rewrites(line)
+        } else if contains_word(line, "jq") {
+            jq_rewrites(line)
+        } else {
+            continue;
+        };
+        match rewrites {
+            Some(rewrites) if !rewrites.is_empty() => {
+                for rewrite in rewrites {
+                    let provider = section.clone().unwrap_or_else(|| target_provider(&rewrite));
+                    adoption
+                        .providers
+                        .entry(provider)
+                        .or_default()
+                        .push(rewrite);
+                }
+            }
+            _ => adoption.skipped.push((index + 1, line.to_string())),
+        }
+        if line.ends_with(";;") {
+            section = None;
+        }
+    }
+    adoption
+}
+
+impl Adoption {
+    /// The provider file each provider's rewrites amount to, applied to
+    /// `current` (the agent models in the config now). An agent is pinned
+    /// when a rewrite changes its model, or when its model already is what
+    /// a rewrite would give it.
+    pub fn provider_configs(&self, current: &ProviderConfig) -> BTreeMap<String, ProviderConfig> {
+        let mut configs = BTreeMap::new();
+        for (provider, rewrites) in &self.providers {
+            let mut config = ProviderConfig::default();
+            for (agent, model) in &current.agents {
+                let mut rewritten = model.model.clone();
+                let mut touched = false;
+                for rewrite in rewrites {
+                    match rewrite {
+                        Rewrite::Substitute { from, to } => {
+                            if rewritten.contains(from.as_str()) {
+                                rewritten = rewritten.replacen(from.as_str(), to, 1);
+                                touched = true;
+                            } else if rewritten.contains(to.as_str()) {
+                                touched = true;
+                            }
+                        }
+                        Rewrite::Assign {
+                            agent: target,
+                            model,
+                        } if target == agent => {
+                            rewritten = model.clone();
+                            touched = true;
+                        }
+                        Rewrite::Assign { .. } => {}
+                    }
+                }
+                if touched {
+                    config
+                        .agents
+                        .insert(agent.clone(), AgentModelConfig { model: rewritten });
+                }
+            }
+            // Assignments to agents the config doesn't have yet
+            for rewrite in rewrites {
+                if let Rewrite::Assign { agent, model } = rewrite {
+                    config
+                        .agents
+                        .entry(agent.clone())
+                        .or_insert_with(|| AgentModelConfig {
+                            model: model.clone(),
+                        });
+                }
+            }
+            configs.insert(provider.clone(), config);
+        }
+        configs
+    }
+}
+
+/// Provider named by a `case` label (`openrouter)`) or a function
+/// definition (`use_openrouter() {`, `function openrouter {`)
+fn section_name(line: &str) -> Option<String> {
+    let name = if let Some((label, _)) = line.split_once(')')
+        && !label.contains(['(', ' ', '$', '"', '\''])
+        && !label.is_empty()
+    {
+        // `copilot|gh)` names the provider after its first pattern
+        label.split('|').next().unwrap_or(label)
+    } else if let Some(rest) = line.strip_prefix("function ") {
+        rest.split(|c: char| c == '(' || c == '{' || c.is_whitespace())
+            .next()
+            .unwrap_or_default()
+    } else if let Some((name, rest)) = line.split_once("()")
+        && rest.trim_start().starts_with('{')
+    {
+        name.trim()
+    } else {
+        return None;
+    };
+    let mut name = name.trim_matches(['"', '\'']);
+    for prefix in ["switch_to_", "switch-to-", "use_", "use-", "to_", "to-"] {
+        name = name.strip_prefix(prefix).unwrap_or(name);
+    }
+    let name = name.replace('_', "-");
+    if name.is_empty()
+        || name == "*"
+        || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+    {
+        return None;
+    }
+    Some(name)
+}
+
+/// Provider a rewrite switches to, from the first segment of its model:
+/// `openrouter/...` is `openrouter`, `github-copilot/...` is `copilot`
+fn target_provider(rewrite: &Rewrite) -> String {
+    let model = match rewrite {
+        Rewrite::Substitute { to, .. } => to,
+        Rewrite::Assign { model, .. } => model,
+    };
+    match model.split('/').next().unwrap_or(model) {
+        "github-copilot" => "copilot".to_string(),
+        prefix => prefix.to_string(),
+    }
+}
+
+fn contains_word(line: &str, word: &str) -> bool {
+    shell_words(line).iter().any(|w| w == word)
+}
+
+/// `s/from/to/` expressions of a `sed` command. `None` if one of them uses
+/// a regular expression, a back-reference, or a shell variable, since its
+/// effect can't be worked out from the script alone.
+fn sed_rewrites(line: &str) -> Option<Vec<Rewrite>> {
+    let words = shell_words(line);
+    let start = words.iter().position(|w| w == "sed")? + 1;
+    let mut expressions = Vec::new();
+    let mut operands = Vec::new();
+    let mut words = words[start..].iter();
+    while let Some(word) = words.next() {
+        match word.as_str() {
+            "-e" | "--expression" => expressions.push(words.next()?),
+            "|" | "&&" | ";" | ">" => break,
+            w if w.starts_with('-') => {}
+            _ => operands.push(word),
+        }
+    }
+    // Without -e the first operand is the script and the rest are files
+    if expressions.is_empty() {
+        expressions.extend(operands.first());
+    }
+    let mut rewrites = Vec::new();
+    for expression in expressions {
+        for part in expression.split(';') {
+            rewrites.push(substitution(part.trim())?);
+        }
+    }
+    Some(rewrites)
+}
+
+/// Parse one `s<d>from<d>to<d>flags` expression
+fn substitution(expression: &str) -> Option<Rewrite> {
+    let mut chars = expression.chars();
+    if chars.next()? != 's' {
+        return None;
+    }
+    let delimiter = chars.next()?;
+    let mut fields = vec![String::new()];
+    let mut escaped = false;
+    for c in chars {
+        if escaped {
+            // `\.` and `\<delimiter>` stand for the character itself
+            if c != delimiter && c != '.' && c != '/' {
+                return None;
+            }
+            fields.last_mut()?.push(c);
+            escaped = false;
+        } else if c == '\\' {
+            escaped = true;
+        } else if c == delimiter {
+            fields.push(String::new());
+        } else {
+            fields.last_mut()?.push(c);
+        }
+    }
+    let [from, to, flags] = <[String; 3]>::try_from(fields).ok()?;
+    if !flags.chars().all(|c| c == 'g') {
+        return None;
+    }
+    let from = model_value(&from);
+    let to = model_value(&to);
+    let literal = |text: &str| !text.is_empty() && !text.contains(['*', '[', ']', '^', '$', '&']);
+    if !literal(from) || !literal(to) || from == to {
+        return None;
+    }
+    Some(Rewrite::Substitute {
+        from: from.to_string(),
+        to: to.to_string(),
+    })
+}
+
+/// The model inside a pattern matching a whole `"model": "..."` pair
+fn model_value(pattern: &str) -> &str {
+    let Some(rest) = pattern.strip_prefix("\"model\"") else {
+        return pattern;
+    };
+    let rest = rest
+        .trim_start()
+        .strip_prefix(':')
+        .unwrap_or(rest)
+        .trim_start();
+    rest.trim_matches('"')
+}
+
+/// `.agents.<agent>.model = "model"` assignments in a `jq` filter. The
+/// model may also be a `$name` given with `--arg name model`.
+fn jq_rewrites(line: &str) -> Option<Vec<Rewrite>> {
+    let words = shell_words(line);
+    let start = words.iter().position(|w| w == "jq")? + 1;
+    let mut args = BTreeMap::new();
+    let mut filter = None;
+    let mut words = words[start..].iter();
+    while let Some(word) = words.next() {
+        match word.as_str() {
+            "--arg" => {
+                let name = words.next()?;
+                args.insert(format!("${}", name), words.next()?.clone());
+            }
+            w if w.starts_with('-') => {}
+            _ => {
+                filter = Some(word);
+                break;
+            }
+        }
+    }
+    let filter = filter?;
+    // Pipes separate the assignments; `|=` is one of them
+    let mut parts = Vec::new();
+    let mut rest = filter.as_str();
+    while let Some(at) = rest
+        .match_indices('|')
+        .find(|(at, _)| !rest[at + 1..].starts_with('='))
+        .map(|(at, _)| at)
+    {
+        parts.push(&rest[..at]);
+        rest = &rest[at + 1..];
+    }
+    parts.push(rest);
+    parts
+        .into_iter()
+        .map(|part| assignment(part.trim(), &args))
+        .collect()
+}
+
+fn assignment(part: &str, args: &BTreeMap<String, String>) -> Option<Rewrite> {
+    let (path, value) = part.split_once('=')?;
+    let path = path.trim().trim_end_matches('|').trim();
+    let value = value.trim();
+    let model = if let Some(arg) = args.get(value) {
+        arg.as_str()
+    } else if value.len() >= 2 && value.starts_with('"') && value.ends_with('"') {
+        &value[1..value.len() - 1]
+    } else {
+        return None;
+    };
+    let agent = path.strip_prefix(".agents")?.strip_suffix(".model")?;
+    let agent = if let Some(quoted) = agent.strip_prefix("[\"") {
+        quoted.strip_suffix("\"]")?
+    } else {
+        agent.strip_prefix('.')?
+    };
+    let agent = agent.trim_matches('"');
+    if agent.is_empty() || model.is_empty() || model.contains(['\\', '$']) {
+        return None;
+    }
+    Some(Rewrite::Assign {
+        agent: agent.to_string(),
+        model: model.to_string(),
+    })
+}
+
+/// Split a line into words the way a shell would for simple cases: quotes
+/// group and are removed, `|`, `;` and `&&` stand alone. Variables are left
+/// as written, so rewrites that use them are recognisably not literal.
+fn shell_words(line: &str) -> Vec<String> {
+    let mut words = Vec::new();
+    let mut word = String::new();
+    let mut in_word = false;
+    let mut quote: Option<char> = None;
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        match (quote, c) {
+            (Some(q), c) if c == q => quote = None,
+            (Some('"'), '\\') if chars.peek().is_some_and(|n| matches!(n, '"' | '\\')) => {
+                word.extend(chars.next());
+            }
+            (Some(_), c) => word.push(c),
+            (None, '\'' | '"') => {
+                quote = Some(c);
+                in_word = true;
+            }
+            (None, '\\') => {
+                word.extend(chars.next());
+                in_word = true;
+            }
+            (None, '#') if !in_word => break,
+            (None, c) if c.is_whitespace() => {
+                if in_word {
+                    words.push(std::mem::take(&mut word));
+                    in_word = false;
+                }
+            }
+            (None, '|' | ';' | '&') => {
+                if in_word {
+                    words.push(std::mem::take(&mut word));
+                    in_word = false;
+                }
+                let mut operator = c.to_string();
+                if c == '&' && chars.peek() == Some(&'&') {
+                    operator.extend(chars.next());
+                }
+                words.push(operator);
+            }
+            (None, c) => {
+                word.push(c);
+                in_word = true;
+            }
+        }
+    }
+    if in_word {
+        words.push(word);
+    }
+    words
+}
@@ -0,0 +1,756 @@
+use anyhow::{Context, Result, anyhow};
+use chrono::{DateTime, Duration, NaiveDateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Mutex, OnceLock};
+
+use crate::crypto::{self, KEY_LEN, NONCE_LEN};
+use crate::error::PortalError;
+use crate::fsutil;
+use crate::jsonc;
+use crate::keychain;
+use crate::switch;
+use crate::zstd;
+
+/// Timestamp format embedded in backup filenames
+const TIMESTAMP_FORMAT: &str = "%Y-%m-%dT%H-%M-%S-%3fZ";
+
+/// Directory in portal's state directory holding every backup channel
+pub const STORE_DIR: &str = "backups";
+
+/// File in each channel directory naming the config it backs up
+const CHANNEL_FILE: &str = "channel.json";
+
+/// Start of the name a backup being migrated is written under until it is
+/// complete. Not a backup's prefix, so listings never see one.
+const MIGRATING_PREFIX: &str = ".migrating-";
+
+/// First bytes of an encrypted backup, ahead of the nonce and the sealed
+/// config; also authenticated along with it
+const ENCRYPTED_MAGIC: &[u8] = b"portal-encrypted-backup-v1\n";
+
+/// Ending of a compressed (and not encrypted) backup's filename
+const COMPRESSED_SUFFIX: &str = ".zst";
+
+/// Keychain secret holding the key encrypted backups are sealed with, as
+/// hex
+pub const KEY_SECRET: &str = "backup-key";
+
+/// How backups are written, under `"backup"` in settings.json
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BackupSettings {
+    /// Encrypt new backups with ChaCha20-Poly1305, under a key kept in the
+    /// keychain; backups already written stay as they are. Only backups:
+    /// the config itself, and portal's other copies of it, stay in clear,
+    /// though none of the copies hold secrets from the keychain.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub encrypt: bool,
+    /// Compress new backups with zstd, recording the config, provider, and
+    /// time alongside; needs `zstd` on the PATH
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub compress: bool,
+}
+
+impl BackupSettings {
+    pub fn is_default(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+/// One config's backup history: its own directory in the managed store,
+/// plus any older backups sitting next to the config itself. Operations on
+/// a channel never see another config's backups.
+#[derive(Debug, Clone)]
+pub struct Channel {
+    name: String,
+    config_path: PathBuf,
+    dir: PathBuf,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct ChannelMeta {
+    config: PathBuf,
+}
+
+/// What a compressed backup records about itself, ahead of the
+/// compressed config
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct BackupMeta {
+    config: PathBuf,
+    #[serde(default)]
+    provider: Option<String>,
+    created: DateTime<Utc>,
+}
+
+/// A channel found in the store, for listing
+#[derive(Debug, Clone, Serialize)]
+pub struct ChannelSummary {
+    pub name: String,
+    /// Config the channel backs up, if recorded
+    pub config: Option<PathBuf>,
+    pub backups: usize,
+}
+
+impl Channel {
+    /// The channel `name` in the store at `store_dir`
+    pub fn new(name: impl Into<String>, config_path: &Path, store_dir: &Path) -> Self {
+        let name = name.into();
+        Self {
+            dir: store_dir.join(&name),
+            name,
+            config_path: config_path.to_path_buf(),
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn config_path(&self) -> &Path {
+        &self.config_path
+    }
+
+    /// Where new backups are written
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+}
+
+/// Every channel in the store, by name
+pub fn list_channels(store_dir: &Path) -> Vec<ChannelSummary> {
+    let Ok(entries) = fs::read_dir(store_dir) else {
+        return Vec::new();
+    };
+    let mut channels: Vec<_> = entries
+        .flatten()
+        .filter(|e| e.path().is_dir())
+        .map(|e| {
+            let dir = e.path();
+            let config = fsutil::read_to_string(&dir.join(CHANNEL_FILE))
+                .ok()
+                .and_then(|c| serde_json::from_str::<ChannelMeta>(&c).ok())
+                .map(|meta| meta.config);
+            let backups = fs::read_dir(&dir)
+                .map(|entries| {
+                    entries
+                        .flatten()
+                        .filter(|e| e.file_name().to_string_lossy().contains(".bak."))
+                        .count()
+                })
+                .unwrap_or(0);
+            ChannelSummary {
+                name: e.file_name().to_string_lossy().to_string(),
+                config,
+                backups,
+            }
+        })
+        .collect();
+    channels.sort_by(|a, b| a.name.cmp(&b.name));
+    channels
+}
+
+/// What's known about one backup without restoring it
+#[derive(Debug, Clone, Serialize)]
+pub struct BackupInfo {
+    pub path: PathBuf,
+    /// From the filename, falling back to the file's modification time
+    pub created: Option<DateTime<Utc>>,
+    pub size: u64,
+    /// Provider the backed-up config was set to, if recognisable
+    pub provider: Option<String>,
+    /// Config the backup was taken of, for backups that record it
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub config: Option<PathBuf>,
+    pub encrypted: bool,
+    pub compressed: bool,
+}
+
+/// Filename prefix shared by every backup of `config_path`
+fn backup_prefix(config_path: &Path) -> String {
+    let name = config_path
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_else(|| "oh-my-opencode.json".to_string());
+    format!("{}.bak.", name)
+}
+
+/// Files in `dir` whose name starts with `prefix`
+fn backups_in(dir: &Path, prefix: &str) -> Vec<PathBuf> {
+    match fs::read_dir(dir) {
+        Ok(entries) => entries
+            .flatten()
+            .filter(|e| e.file_name().to_string_lossy().starts_with(prefix))
+            .map(|e| e.path())
+            .collect(),
+        Err(_) => Vec::new(),
+    }
+}
+
+/// All backups in `channel`, oldest first
+pub fn list_backups(channel: &Channel) -> Vec<PathBuf> {
+    let mut backups = backups_in(&channel.dir, &backup_prefix(&channel.config_path));
+    backups.extend(legacy_backups(channel));
+    backups.sort_by_key(|p| p.file_name().map(|n| n.to_os_string()));
+    backups
+}
+
+/// Backups made before the managed store, sitting next to the channel's
+/// config, oldest first
+pub fn legacy_backups(channel: &Channel) -> Vec<PathBuf> {
+    let legacy_dir = match channel.config_path.parent() {
+        Some(p) if !p.as_os_str().is_empty() => p,
+        _ => Path::new("."),
+    };
+    let mut backups = backups_in(legacy_dir, &backup_prefix(&channel.config_path));
+    backups.sort_by_key(|p| p.file_name().map(|n| n.to_os_string()));
+    backups
+}
+
+pub fn has_existing_backup(channel: &Channel) -> bool {
+    !list_backups(channel).is_empty()
+}
+
+pub fn find_latest_backup(channel: &Channel) -> Option<PathBuf> {
+    list_backups(channel).pop()
+}
+
+/// Save `contents`, standing in for the channel's config, to a timestamped
+/// backup in the channel, compressed and encrypted as `settings` ask
+pub fn create_backup(
+    channel: &Channel,
+    settings: &BackupSettings,
+    contents: &[u8],
+) -> Result<PathBuf> {
+    create_channel_dir(channel)?;
+    let now = Utc::now();
+    // An encrypted backup isn't a zstd file, whatever is inside it
+    let suffix = if settings.compress && !settings.encrypt {
+        COMPRESSED_SUFFIX
+    } else {
+        ""
+    };
+    let backup_path = channel.dir.join(format!(
+        "{}{}{}",
+        backup_prefix(&channel.config_path),
+        now.format(TIMESTAMP_FORMAT),
+        suffix
+    ));
+    if settings.compress || settings.encrypt {
+        let mut contents = contents.to_vec();
+        if settings.compress {
+            contents = compress(channel, &contents, now)?;
+        }
+        if settings.encrypt {
+            contents = encrypt(&contents)?;
+        }
+        write_private(&backup_path, &contents)?;
+    } else {
+        fs::write(&backup_path, contents).map_err(|source| PortalError::Backup {
+            path: backup_path.clone(),
+            source,
+        })?;
+        // Readable by whoever can read the config, as a copy would be
+        if let Ok(metadata) = fs::metadata(&channel.config_path) {
+            let _ = fs::set_permissions(&backup_path, metadata.permissions());
+        }
+    }
+    crate::portal_info!(
+        "Backed up {} to {}",
+        channel.config_path.display(),
+        backup_path.display()
+    );
+    Ok(backup_path)
+}
+
+/// Create the channel's directory in the store, recording the config it
+/// backs up
+fn create_channel_dir(channel: &Channel) -> Result<()> {
+    fs::create_dir_all(&channel.dir).with_context(|| {
+        format!(
+            "Failed to create backup directory: {}",
+            channel.dir.display()
+        )
+    })?;
+    let meta_path = channel.dir.join(CHANNEL_FILE);
+    if !meta_path.exists() {
+        let meta = ChannelMeta {
+            config: fs::canonicalize(&channel.config_path)
+                .unwrap_or_else(|_| channel.config_path.clone()),
+        };
+        fs::write(&meta_path, serde_json::to_string_pretty(&meta)?)
+            .with_context(|| format!("Failed to write {}", meta_path.display()))?;
+        crate::portal_debug!("Wrote {}", meta_path.display());
+    }
+    Ok(())
+}
+
+/// A backup moved into the managed store
+#[derive(Debug, Clone, Serialize)]
+pub struct Migrated {
+    pub from: PathBuf,
+    pub to: PathBuf,
+}
+
+/// Move the channel's [`legacy_backups`] into its directory in the store,
+/// compressed and encrypted as `settings` ask, `jobs` at a time.
+/// `progress` hears how many are done out of how many after each one.
+///
+/// Each backup is written in full under a temporary name and renamed into
+/// place before the original is deleted, so an interrupted migration
+/// loses nothing and the next one carries on with what is left. A failure
+/// stops the workers taking on more, and is returned once they are done.
+pub fn migrate_legacy(
+    channel: &Channel,
+    settings: &BackupSettings,
+    jobs: usize,
+    progress: impl Fn(usize, usize) + Sync,
+) -> Result<Vec<Migrated>> {
+    let legacy = legacy_backups(channel);
+    if legacy.is_empty() {
+        return Ok(Vec::new());
+    }
+    create_channel_dir(channel)?;
+    if settings.encrypt {
+        // Made here, so workers can't each make a key of their own
+        key(true)?;
+    }
+
+    let next = AtomicUsize::new(0);
+    let done = AtomicUsize::new(0);
+    let failed = AtomicBool::new(false);
+    let results = Mutex::new(Vec::new());
+    std::thread::scope(|scope| {
+        for _ in 0..jobs.clamp(1, legacy.len()) {
+            scope.spawn(|| {
+                while !failed.load(Ordering::Relaxed) {
+                    let Some(path) = legacy.get(next.fetch_add(1, Ordering::Relaxed)) else {
+                        break;
+                    };
+                    let result = migrate_one(channel, settings, path);
+                    if result.is_err() {
+                        failed.store(true, Ordering::Relaxed);
+                    }
+                    results
+                        .lock()
+                        .unwrap_or_else(|e| e.into_inner())
+                        .push(result);
+                    progress(done.fetch_add(1, Ordering::Relaxed) + 1, legacy.len());
+                }
+            });
+        }
+    });
+
+    let mut migrated = Vec::new();
+    let mut errors = Vec::new();
+    for result in results.into_inner().unwrap_or_else(|e| e.into_inner()) {
+        match result {
+            Ok(moved) => migrated.push(moved),
+            Err(e) => errors.push(e),
+        }
+    }
+    if let Some(error) = errors.into_iter().next() {
+        return Err(error.context(format!(
+            "Migrated {} of {} backup(s) before one failed; the next run carries on \
+             with the rest",
+            migrated.len(),
+            legacy.len()
+        )));
+    }
+    migrated.sort_by(|a, b| a.to.cmp(&b.to));
+    Ok(migrated)
+}
+
+/// Move one legacy backup into the channel's directory
+fn migrate_one(channel: &Channel, settings: &BackupSettings, path: &Path) -> Result<Migrated> {
+    let name = path
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .ok_or_else(|| anyhow!("Not a backup file: {}", path.display()))?;
+    let contents =
+        fsutil::read(path).with_context(|| format!("Failed to read backup: {}", path.display()))?;
+    // Already in the store's format, or written by hand: moved as it is
+    let plain = !is_encrypted(&contents) && !zstd::is_compressed(&contents);
+    let name = if plain && settings.compress && !settings.encrypt {
+        format!("{}{}", name, COMPRESSED_SUFFIX)
+    } else {
+        name
+    };
+    let target = channel.dir.join(&name);
+    let migrated = Migrated {
+        from: path.to_path_buf(),
+        to: target.clone(),
+    };
+
+    // An earlier run moved it in, but stopped before deleting the original
+    if target.exists() {
+        if read_backup(&target)? != decompress(path, decrypt(path, contents)?)? {
+            return Err(anyhow!(
+                "{} is already in the store as {}, with other contents; \
+                 keep one of the two and delete the other",
+                path.display(),
+                target.display()
+            ));
+        }
+        remove_migrated(path)?;
+        return Ok(migrated);
+    }
+
+    let created = filename_timestamp(&channel.config_path, path)
+        .or_else(|| {
+            fs::metadata(path)
+                .and_then(|m| m.modified())
+                .ok()
+                .map(DateTime::<Utc>::from)
+        })
+        .unwrap_or_else(Utc::now);
+    let partial = channel.dir.join(format!("{}{}", MIGRATING_PREFIX, name));
+    // Left over from a run that was interrupted while writing it
+    let _ = fs::remove_file(&partial);
+    if plain && (settings.compress || settings.encrypt) {
+        let mut contents = contents;
+        if settings.compress {
+            contents = compress(channel, &contents, created)?;
+        }
+        if settings.encrypt {
+            contents = encrypt(&contents)?;
+        }
+        write_private(&partial, &contents)?;
+    } else {
+        fs::copy(path, &partial).map_err(|source| PortalError::Backup {
+            path: partial.clone(),
+            source,
+        })?;
+    }
+    fs::rename(&partial, &target).map_err(|source| PortalError::Backup {
+        path: target.clone(),
+        source,
+    })?;
+    remove_migrated(path)?;
+    crate::portal_info!("Migrated {} to {}", path.display(), target.display());
+    Ok(migrated)
+}
+
+fn remove_migrated(path: &Path) -> Result<()> {
+    fs::remove_file(path).with_context(|| {
+        format!(
+            "Failed to delete {} after moving it into the store",
+            path.display()
+        )
+    })
+}
+
+/// When a backup was taken, according to its filename
+fn filename_timestamp(config_path: &Path, backup_path: &Path) -> Option<DateTime<Utc>> {
+    let name = backup_path.file_name()?.to_string_lossy().to_string();
+    let stamp = name.strip_prefix(&backup_prefix(config_path))?;
+    let stamp = stamp.strip_suffix(COMPRESSED_SUFFIX).unwrap_or(stamp);
+    NaiveDateTime::parse_from_str(stamp, TIMESTAMP_FORMAT)
+        .ok()
+        .map(|t| t.and_utc())
+}
+
+pub fn backup_info(channel: &Channel, backup_path: &Path) -> BackupInfo {
+    let metadata = fs::metadata(backup_path).ok();
+    let created = filename_timestamp(&channel.config_path, backup_path).or_else(|| {
+        metadata
+            .as_ref()
+            .and_then(|m| m.modified().ok())
+            .map(DateTime::<Utc>::from)
+    });
+    let contents = fsutil::read(backup_path).ok();
+    let encrypted = contents.as_deref().is_some_and(is_encrypted);
+    // An encrypted backup whose key is missing still lists, just without
+    // its provider
+    let contents = contents.and_then(|contents| decrypt(backup_path, contents).ok());
+    let compressed = contents.as_deref().is_some_and(zstd::is_compressed);
+    let meta = contents
+        .as_deref()
+        .and_then(zstd::leading_skippable_frame)
+        .and_then(|frame| serde_json::from_slice::<BackupMeta>(frame).ok());
+    let provider = match &meta {
+        Some(meta) => meta.provider.clone(),
+        None => contents
+            .and_then(|contents| decompress(backup_path, contents).ok())
+            .and_then(|contents| String::from_utf8(contents).ok())
+            .and_then(|c| jsonc::parse(&c).ok())
+            .and_then(|document| switch::detect_document_provider(&document)),
+    };
+
+    BackupInfo {
+        path: backup_path.to_path_buf(),
+        created,
+        size: metadata.map(|m| m.len()).unwrap_or(0),
+        provider,
+        config: meta.map(|meta| meta.config),
+        encrypted,
+        compressed,
+    }
+}
+
+/// A backup's contents, decrypted and decompressed as needed
+pub fn read_backup(path: &Path) -> Result<Vec<u8>> {
+    let contents =
+        fsutil::read(path).with_context(|| format!("Failed to read backup: {}", path.display()))?;
+    decompress(path, decrypt(path, contents)?)
+}
+
+/// [`read_backup`] as text
+pub fn read_backup_to_string(path: &Path) -> Result<String> {
+    String::from_utf8(read_backup(path)?)
+        .with_context(|| format!("Backup isn't valid UTF-8: {}", path.display()))
+}
+
+pub fn is_encrypted(contents: &[u8]) -> bool {
+    contents.starts_with(ENCRYPTED_MAGIC)
+}
+
+/// `contents` of the backup at `path`, decrypted if need be
+fn decrypt(path: &Path, contents: Vec<u8>) -> Result<Vec<u8>> {
+    let Some(rest) = contents.strip_prefix(ENCRYPTED_MAGIC) else {
+        return Ok(contents);
+    };
+    let key = key(false)?;
+    let (nonce, sealed) = rest
+        .split_at_checked(NONCE_LEN)
+        .ok_or_else(|| anyhow!("Encrypted backup is truncated: {}", path.display()))?;
+    let nonce: [u8; NONCE_LEN] = nonce.try_into().expect("split at the nonce length");
+    crypto::open(&key, &nonce, ENCRYPTED_MAGIC, sealed).ok_or_else(|| {
+        anyhow!(
+            "Failed to decrypt backup: {}\n\nIt was encrypted with another key than the \
+             keychain's '{}', or has been damaged.",
+            path.display(),
+            KEY_SECRET
+        )
+    })
+}
+
+/// `contents` of the backup at `path`, decompressed if need be
+fn decompress(path: &Path, contents: Vec<u8>) -> Result<Vec<u8>> {
+    if !zstd::is_compressed(&contents) {
+        return Ok(contents);
+    }
+    zstd::decompress(&contents)
+        .with_context(|| format!("Failed to decompress backup: {}", path.display()))
+}
+
+/// The config's `contents` compressed, after a frame recording where and
+/// when they came from
+fn compress(channel: &Channel, contents: &[u8], created: DateTime<Utc>) -> Result<Vec<u8>> {
+    let provider = std::str::from_utf8(contents)
+        .ok()
+        .and_then(|text| jsonc::parse(text).ok())
+        .and_then(|document| switch::detect_document_provider(&document));
+    let meta = BackupMeta {
+        config: fs::canonicalize(&channel.config_path)
+            .unwrap_or_else(|_| channel.config_path.clone()),
+        provider,
+        created,
+    };
+    let mut compressed = zstd::skippable_frame(&serde_json::to_vec(&meta)?);
+    compressed.extend(zstd::compress(contents).context("Failed to compress the backup")?);
+    Ok(compressed)
+}
+
+/// `plaintext` sealed under the backup key, behind the header and nonce
+fn encrypt(plaintext: &[u8]) -> Result<Vec<u8>> {
+    let key = key(true)?;
+    let nonce: [u8; NONCE_LEN] =
+        crypto::random_bytes().context("Failed to make a nonce for an encrypted backup")?;
+    let mut contents = ENCRYPTED_MAGIC.to_vec();
+    contents.extend_from_slice(&nonce);
+    contents.extend_from_slice(&crypto::seal(&key, &nonce, ENCRYPTED_MAGIC, plaintext));
+    Ok(contents)
+}
+
+/// Write a new backup readable only by its owner
+fn write_private(backup_path: &Path, contents: &[u8]) -> Result<()> {
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    let result = options.open(backup_path).and_then(|mut file| {
+        use std::io::Write;
+        file.write_all(contents)?;
+        file.sync_all()
+    });
+    result.map_err(|source| PortalError::Backup {
+        path: backup_path.to_path_buf(),
+        source,
+    })?;
+    Ok(())
+}
+
+/// The backup key from the keychain, made and stored there first if
+/// `create` is set and there is none yet. Looked up once per run.
+fn key(create: bool) -> Result<[u8; KEY_LEN]> {
+    static KEY: OnceLock<[u8; KEY_LEN]> = OnceLock::new();
+    if let Some(key) = KEY.get() {
+        return Ok(*key);
+    }
+    let key = match keychain::get(KEY_SECRET)? {
+        Some(hex) => parse_key(hex.trim()).ok_or_else(|| {
+            anyhow!(
+                "Keychain secret '{}' isn't a backup key (64 hex digits)",
+                KEY_SECRET
+            )
+        })?,
+        None if create => {
+            let key: [u8; KEY_LEN] =
+                crypto::random_bytes().context("Failed to make a backup key")?;
+            let hex: String = key.iter().map(|b| format!("{:02x}", b)).collect();
+            keychain::set(KEY_SECRET, &hex)?;
+            crate::portal_info!(
+                "Stored a new backup key in the keychain as '{}'",
+                KEY_SECRET
+            );
+            key
+        }
+        None => {
+            return Err(anyhow!(
+                "Backup is encrypted, but the keychain has no '{}' to decrypt it with. \
+                 Restore the key with `portal secret set {}`",
+                KEY_SECRET,
+                KEY_SECRET
+            ));
+        }
+    };
+    Ok(*KEY.get_or_init(|| key))
+}
+
+fn parse_key(hex: &str) -> Option<[u8; KEY_LEN]> {
+    if hex.len() != KEY_LEN * 2 || !hex.is_ascii() {
+        return None;
+    }
+    let mut key = [0u8; KEY_LEN];
+    for (byte, digits) in key.iter_mut().zip(hex.as_bytes().chunks_exact(2)) {
+        *byte = u8::from_str_radix(std::str::from_utf8(digits).ok()?, 16).ok()?;
+    }
+    Some(key)
+}
+
+/// Details for every backup in `channel`, oldest first
+pub fn list_backup_info(channel: &Channel) -> Vec<BackupInfo> {
+    list_backups(channel)
+        .iter()
+        .map(|path| backup_info(channel, path))
+        .collect()
+}
+
+/// Backups a prune would delete: everything but the newest `keep`, and of
+/// those only the ones older than `older_than` when that is given too
+pub fn prune_candidates(
+    channel: &Channel,
+    keep: Option<usize>,
+    older_than: Option<Duration>,
+) -> Vec<PathBuf> {
+    let mut backups = list_backup_info(channel);
+    if let Some(keep) = keep {
+        backups.truncate(backups.len().saturating_sub(keep));
+    }
+    if let Some(age) = older_than {
+        let cutoff = Utc::now() - age;
+        backups.retain(|b| b.created.is_some_and(|created| created < cutoff));
+    }
+    backups.into_iter().map(|b| b.path).collect()
+}
+
+/// Delete the backups a prune selects, returning what was removed
+pub fn prune_backups(
+    channel: &Channel,
+    keep: Option<usize>,
+    older_than: Option<Duration>,
+) -> Result<Vec<PathBuf>> {
+    let doomed = prune_candidates(channel, keep, older_than);
+    for path in &doomed {
+        fs::remove_file(path)
+            .with_context(|| format!("Failed to delete backup: {}", path.display()))?;
+    }
+    Ok(doomed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A fresh directory under the system's temporary one
+    fn scratch(name: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("portal-backup-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn migrates_legacy_backups_and_resumes() {
+        let dir = scratch("migrate");
+        let config = dir.join("oh-my-opencode.json");
+        fs::write(&config, "{}").unwrap();
+        let channel = Channel::new("global", &config, &dir.join(STORE_DIR));
+        let names: Vec<String> = (0..5)
+            .map(|i| format!("oh-my-opencode.json.bak.2026-01-01T00-00-0{}-000Z", i))
+            .collect();
+        for (i, name) in names.iter().enumerate() {
+            fs::write(dir.join(name), format!("{{\"n\": {}}}", i)).unwrap();
+        }
+        // As an interrupted run leaves things: one moved in but not yet
+        // deleted, and one half written
+        fs::create_dir_all(channel.dir()).unwrap();
+        fs::copy(dir.join(&names[0]), channel.dir().join(&names[0])).unwrap();
+        fs::write(
+            channel
+                .dir()
+                .join(format!("{}{}", MIGRATING_PREFIX, names[1])),
+            "{\"n\"",
+        )
+        .unwrap();
+
+        let calls = AtomicUsize::new(0);
+        let migrated = migrate_legacy(&channel, &BackupSettings::default(), 3, |done, total| {
+            calls.fetch_add(1, Ordering::Relaxed);
+            assert!(done <= total && total == 5);
+        })
+        .unwrap();
+        assert_eq!(migrated.len(), 5);
+        assert_eq!(calls.into_inner(), 5);
+        assert!(legacy_backups(&channel).is_empty());
+        let backups = list_backups(&channel);
+        assert_eq!(backups.len(), 5);
+        for (i, path) in backups.iter().enumerate() {
+            assert_eq!(path.parent(), Some(channel.dir()));
+            assert_eq!(
+                read_backup_to_string(path).unwrap(),
+                format!("{{\"n\": {}}}", i)
+            );
+        }
+        assert!(
+            !channel
+                .dir()
+                .join(format!("{}{}", MIGRATING_PREFIX, names[1]))
+                .exists()
+        );
+        assert!(
+            migrate_legacy(&channel, &BackupSettings::default(), 3, |_, _| {})
+                .unwrap()
+                .is_empty()
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn keeps_a_legacy_backup_that_conflicts() {
+        let dir = scratch("conflict");
+        let config = dir.join("oh-my-opencode.json");
+        let channel = Channel::new("global", &config, &dir.join(STORE_DIR));
+        let name = "oh-my-opencode.json.bak.2026-01-01T00-00-00-000Z";
+        fs::write(dir.join(name), "{\"a\": 1}").unwrap();
+        fs::create_dir_all(channel.dir()).unwrap();
+        fs::write(channel.dir().join(name), "{\"a\": 2}").unwrap();
+
+        assert!(migrate_legacy(&channel, &BackupSettings::default(), 1, |_, _| {}).is_err());
+        assert!(dir.join(name).exists());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,209 @@
+//! `portal batch`: a script of switches and single-model edits applied to
+//! the config one after the other in memory, then written once, under one
+//! lock and with at most one backup. One step per line, in the same words
+//! as the commands they stand for:
+//!
+//! ```text
+//! # comments and blank lines are ignored
+//! switch openrouter
+//! switch work --agent oracle --agent explore
+//! set librarian google/gemini-3-flash-preview
+//! set subagents.reviewer my-gateway/new-model --force
+//! ```
+
+use anyhow::{Result, anyhow};
+use std::path::PathBuf;
+
+use crate::compat::Compatibility;
+use crate::diff::ModelChange;
+use crate::switch::SwitchReport;
+
+/// One thing a batch does to the config
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Step {
+    /// `switch <provider> [--agent NAME]... [--no-global-models]`
+    Switch {
+        provider: String,
+        /// Only switch these agents; `None` switches all of them
+        agents: Option<Vec<String>>,
+        /// Leave top-level model fields alone
+        skip_top_level: bool,
+    },
+    /// `set <agent> <model> [--force]`
+    Set {
+        agent: String,
+        model: String,
+        /// Accept a model portal doesn't know from its mappings
+        force: bool,
+    },
+}
+
+/// A step with where it came from, for error messages
+#[derive(Debug, Clone)]
+pub struct Line {
+    /// 1-based line number in the script
+    pub number: usize,
+    /// The line as written, without its comment
+    pub text: String,
+    pub step: Step,
+}
+
+/// What running a batch did, or would do
+#[derive(Debug, Clone)]
+pub struct BatchOutcome {
+    /// Report of each switch step, with the provider it switched to
+    pub switches: Vec<(String, SwitchReport)>,
+    pub compatibility: Compatibility,
+    /// Per-agent models before and after the whole batch
+    pub changes: Vec<ModelChange>,
+    /// Backup created before writing, if any
+    pub backup: Option<PathBuf>,
+    /// Problems after the write, such as a failing post-switch hook
+    pub warnings: Vec<String>,
+}
+
+impl BatchOutcome {
+    /// Provider of the last switch step, which the config ends up on
+    pub fn provider(&self) -> Option<&str> {
+        self.switches.last().map(|(provider, _)| provider.as_str())
+    }
+}
+
+/// Parse a batch script. Fails on the first line that isn't a step, naming
+/// it, so nothing runs from a script with a typo in it.
+pub fn parse(script: &str) -> Result<Vec<Line>> {
+    let mut lines = Vec::new();
+    for (index, raw) in script.lines().enumerate() {
+        let text = raw.split('#').next().unwrap_or_default().trim();
+        if text.is_empty() {
+            continue;
+        }
+        let step = parse_step(text).map_err(|e| anyhow!("Line {}: {}: {}", index + 1, text, e))?;
+        lines.push(Line {
+            number: index + 1,
+            text: text.to_string(),
+            step,
+        });
+    }
+    Ok(lines)
+}
+
+fn parse_step(text: &str) -> Result<Step> {
+    let mut words = text.split_whitespace();
+    let command = words.next().unwrap_or_default();
+    let mut positional = Vec::new();
+    let mut agents = Vec::new();
+    let (mut skip_top_level, mut force) = (false, false);
+    while let Some(word) = words.next() {
+        match (command, word) {
+            ("switch", "--agent") => agents.push(
+                words
+                    .next()
+                    .ok_or_else(|| anyhow!("--agent needs an agent name"))?
+                    .to_string(),
+            ),
+            ("switch", "--no-global-models") => skip_top_level = true,
+            ("set", "--force") => force = true,
+            (_, option) if option.starts_with('-') => {
+                return Err(anyhow!("unknown option {} for {}", option, command));
+            }
+            _ => positional.push(word.to_string()),
+        }
+    }
+    match (command, positional.as_slice()) {
+        ("switch", [provider]) => Ok(Step::Switch {
+            provider: provider.clone(),
+            agents: (!agents.is_empty()).then_some(agents),
+            skip_top_level,
+        }),
+        ("switch", _) => Err(anyhow!("expected `switch <provider>`")),
+        ("set", [agent, model]) => Ok(Step::Set {
+            agent: agent.clone(),
+            model: model.clone(),
+            force,
+        }),
+        ("set", _) => Err(anyhow!("expected `set <agent> <model>`")),
+        (command, _) => Err(anyhow!(
+            "'{}' can't be batched; only switch and set can",
+            command
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn error(script: &str) -> String {
+        parse(script).unwrap_err().to_string()
+    }
+
+    #[test]
+    fn parses_steps() {
+        let lines = parse(
+            "# comments and blank lines are ignored\n\n\
+             switch openrouter\n\
+             switch work --agent oracle --agent explore --no-global-models\n\
+             set subagents.reviewer my-gateway/new-model --force # why\n",
+        )
+        .unwrap();
+        let steps: Vec<_> = lines.iter().map(|line| line.step.clone()).collect();
+        assert_eq!(
+            steps,
+            [
+                Step::Switch {
+                    provider: "openrouter".to_string(),
+                    agents: None,
+                    skip_top_level: false,
+                },
+                Step::Switch {
+                    provider: "work".to_string(),
+                    agents: Some(vec!["oracle".to_string(), "explore".to_string()]),
+                    skip_top_level: true,
+                },
+                Step::Set {
+                    agent: "subagents.reviewer".to_string(),
+                    model: "my-gateway/new-model".to_string(),
+                    force: true,
+                },
+            ]
+        );
+        assert_eq!(lines[2].number, 5);
+        assert_eq!(
+            lines[2].text,
+            "set subagents.reviewer my-gateway/new-model --force"
+        );
+    }
+
+    #[test]
+    fn names_the_line_that_fails() {
+        assert_eq!(
+            error("switch openrouter\nrevert\n"),
+            "Line 2: revert: 'revert' can't be batched; only switch and set can"
+        );
+    }
+
+    #[test]
+    fn rejects_malformed_steps() {
+        assert_eq!(
+            error("switch"),
+            "Line 1: switch: expected `switch <provider>`"
+        );
+        assert_eq!(
+            error("switch a b"),
+            "Line 1: switch a b: expected `switch <provider>`"
+        );
+        assert_eq!(
+            error("set oracle"),
+            "Line 1: set oracle: expected `set <agent> <model>`"
+        );
+        assert_eq!(
+            error("switch work --agent"),
+            "Line 1: switch work --agent: --agent needs an agent name"
+        );
+        assert_eq!(
+            error("set oracle a/b --agent x"),
+            "Line 1: set oracle a/b --agent x: unknown option --agent for set"
+        );
+    }
+}
@@ -0,0 +1,263 @@
+use anyhow::{Context, Result, anyhow};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::backup;
+use crate::changes::{self, ConfigChange};
+use crate::config;
+use crate::error::PortalError;
+use crate::fsutil;
+use crate::hash;
+use crate::jsonc;
+use crate::keychain;
+use crate::portal::Portal;
+
+/// An in-progress search through the backups of one config for the first
+/// one with a problem. Candidates are the backups oldest first, followed by
+/// the config as it was when the bisect started.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Bisect {
+    pub backups: Vec<PathBuf>,
+    /// Config text when the bisect started, restored when it ends
+    pub original: String,
+    /// Newest candidate known to be good
+    pub good: Option<usize>,
+    /// Oldest candidate known to be bad
+    pub bad: usize,
+    /// Candidate currently restored for testing
+    pub testing: Option<usize>,
+    /// SHA-256 of what bisect last wrote, to notice edits in between
+    pub written: Option<String>,
+}
+
+/// What to do next
+#[derive(Debug, Clone)]
+pub enum Step {
+    /// This candidate is now the config; test it and mark it
+    Test {
+        candidate: String,
+        /// Candidates still in the running, including this one
+        remaining: usize,
+    },
+    /// The search is over and the original config is back
+    Found(Finding),
+}
+
+/// The first bad candidate and what changed from the last good one
+#[derive(Debug, Clone)]
+pub struct Finding {
+    pub first_bad: String,
+    /// `None` when even the oldest backup is bad
+    pub last_good: Option<String>,
+    pub changes: Vec<ConfigChange>,
+}
+
+pub fn load(portal: &Portal) -> Result<Option<Bisect>> {
+    let path = portal.bisect_path();
+    if !path.exists() {
+        return Ok(None);
+    }
+    let content = fsutil::read_to_string(&path)
+        .with_context(|| format!("Failed to read bisect state: {}", path.display()))?;
+    let state =
+        serde_json::from_str(&content).map_err(PortalError::parse("bisect state", &path))?;
+    Ok(Some(state))
+}
+
+fn save(portal: &Portal, state: &Bisect) -> Result<()> {
+    let path = portal.bisect_path();
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)
+            .with_context(|| format!("Failed to create state directory: {}", parent.display()))?;
+    }
+    fsutil::write_atomic(&path, serde_json::to_string(state)?.as_bytes())
+        .with_context(|| format!("Failed to write bisect state: {}", path.display()))
+}
+
+fn in_progress(portal: &Portal) -> Result<Bisect> {
+    load(portal)?.ok_or_else(|| anyhow!("No bisect in progress; run `portal bisect start`"))
+}
+
+impl Bisect {
+    fn current(&self) -> usize {
+        self.backups.len()
+    }
+
+    /// Backup path, or "current config" for the last candidate
+    pub fn label(&self, index: usize) -> String {
+        match self.backups.get(index) {
+            Some(path) => path.display().to_string(),
+            None => "current config".to_string(),
+        }
+    }
+
+    fn content(&self, index: usize) -> Result<String> {
+        match self.backups.get(index) {
+            // Backups keep placeholders for the secrets portal filled in
+            Some(path) => Ok(keychain::resolve_config(&backup::read_backup_to_string(path)?)?.0),
+            None => Ok(self.original.clone()),
+        }
+    }
+
+    /// Next candidate to test, or `None` once the first bad one is known
+    fn next(&self) -> Option<usize> {
+        match self.good {
+            // Until something is known to be good, try the oldest backup
+            None if self.bad > 0 => Some(0),
+            None => None,
+            Some(good) if self.bad - good > 1 => Some(good + (self.bad - good) / 2),
+            Some(_) => None,
+        }
+    }
+
+    fn index_of(&self, path: &Path) -> Result<usize> {
+        let canonical = fs::canonicalize(path).ok();
+        self.backups
+            .iter()
+            .position(|b| fs::canonicalize(b).ok() == canonical)
+            .ok_or_else(|| anyhow!("Not a backup of this config: {}", path.display()))
+    }
+}
+
+/// Write candidate `index` over the config. With `check`, refuse if the
+/// config was edited since bisect last wrote it.
+fn restore(portal: &Portal, state: &mut Bisect, index: usize, check: bool) -> Result<()> {
+    let current = config::fingerprint(portal.config_path())?;
+    let expected = state
+        .written
+        .clone()
+        .unwrap_or_else(|| hash::sha256_hex(state.original.as_bytes()));
+    if check && current.as_deref() != Some(expected.as_str()) {
+        return Err(anyhow!(
+            "Config file was modified during the bisect: {}\n\n\
+             Nothing was written. Undo the edit, or run `portal bisect reset` to put \
+             the original config back.",
+            portal.config_path().display()
+        ));
+    }
+    let content = state.content(index)?;
+    fsutil::write_atomic(portal.config_path(), content.as_bytes())
+        .with_context(|| format!("Failed to write config: {}", portal.config_path().display()))?;
+    state.written = Some(hash::sha256_hex(content.as_bytes()));
+    state.testing = Some(index);
+    Ok(())
+}
+
+/// Restore the next candidate, or finish and put the original back
+fn advance(portal: &Portal, mut state: Bisect) -> Result<Step> {
+    match state.next() {
+        Some(index) => {
+            restore(portal, &mut state, index, true)?;
+            let remaining = match state.good {
+                Some(good) => state.bad - good - 1,
+                None => state.bad,
+            };
+            save(portal, &state)?;
+            Ok(Step::Test {
+                candidate: state.label(index),
+                remaining,
+            })
+        }
+        None => {
+            let parse = |index: usize| -> Result<serde_json::Value> {
+                jsonc::parse(&state.content(index)?)
+                    .with_context(|| format!("Failed to parse {}", state.label(index)))
+            };
+            let changes = match state.good {
+                Some(good) => changes::config_changes(&parse(good)?, &parse(state.bad)?),
+                None => Vec::new(),
+            };
+            let finding = Finding {
+                first_bad: state.label(state.bad),
+                last_good: state.good.map(|good| state.label(good)),
+                changes,
+            };
+            finish(portal, &mut state, true)?;
+            Ok(Step::Found(finding))
+        }
+    }
+}
+
+fn finish(portal: &Portal, state: &mut Bisect, check: bool) -> Result<()> {
+    let current = state.current();
+    if state.testing.is_some_and(|index| index != current) {
+        restore(portal, state, current, check)?;
+    }
+    let path = portal.bisect_path();
+    match fs::remove_file(&path) {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+            Err(e).with_context(|| format!("Failed to remove bisect state: {}", path.display()))
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Begin a bisect. `bad` defaults to the current config; without `good`,
+/// the oldest backup is tested first.
+pub fn start(portal: &Portal, good: Option<&Path>, bad: Option<&Path>) -> Result<Step> {
+    if load(portal)?.is_some() {
+        return Err(anyhow!(
+            "A bisect is already in progress; finish it or run `portal bisect reset`"
+        ));
+    }
+    let backups = backup::list_backups(&portal.backups());
+    if backups.is_empty() {
+        return Err(anyhow!(
+            "No backups of {} to bisect",
+            portal.config_path().display()
+        ));
+    }
+    let original = fsutil::read_to_string(portal.config_path()).with_context(|| {
+        format!(
+            "Failed to read config file: {}",
+            portal.config_path().display()
+        )
+    })?;
+
+    let mut state = Bisect {
+        bad: backups.len(),
+        backups,
+        original,
+        good: None,
+        testing: None,
+        written: None,
+    };
+    if let Some(bad) = bad {
+        state.bad = state.index_of(bad)?;
+    }
+    if let Some(good) = good {
+        let good = state.index_of(good)?;
+        if good >= state.bad {
+            return Err(anyhow!("The good backup must be older than the bad one"));
+        }
+        state.good = Some(good);
+    }
+    advance(portal, state)
+}
+
+/// Mark the candidate under test as good or bad and move on
+pub fn mark(portal: &Portal, good: bool) -> Result<Step> {
+    let mut state = in_progress(portal)?;
+    let index = state
+        .testing
+        .ok_or_else(|| anyhow!("Nothing is being tested right now"))?;
+    if good {
+        state.good = Some(index);
+    } else {
+        state.bad = index;
+    }
+    advance(portal, state)
+}
+
+/// Abandon the bisect and put the original config back, even over edits
+/// made in the meantime. `false` if none was in progress.
+pub fn reset(portal: &Portal) -> Result<bool> {
+    match load(portal)? {
+        Some(mut state) => {
+            finish(portal, &mut state, false)?;
+            Ok(true)
+        }
+        None => Ok(false),
+    }
+}
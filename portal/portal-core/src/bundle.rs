@@ -0,0 +1,334 @@
+//! Bundles: a setup packed into one `.tar.gz` to carry to another machine
+//! or hand to a team. `portal export` packs the provider files,
+//! `mappings.json`, and `settings.json`, with a manifest listing them;
+//! `portal import` checks every file parses before writing any of them
+//! into the portal directory. Archives are made and read by `tar`.
+
+use anyhow::{Context, Result, anyhow};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::error::PortalError;
+use crate::mapping::{self, MAPPINGS_FILE};
+use crate::settings::{self, SETTINGS_FILE};
+use crate::{fsutil, provider, secrets};
+
+/// File in every bundle describing it
+pub const MANIFEST_FILE: &str = "portal-bundle.json";
+
+/// Bundle layout this version writes, and the newest it reads
+const FORMAT: u32 = 1;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Manifest {
+    pub format: u32,
+    pub created: DateTime<Utc>,
+    /// Version of portal that made the bundle
+    pub portal: String,
+    /// Files in the bundle besides the manifest
+    pub files: Vec<String>,
+}
+
+/// What an import does with one bundled file
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ImportState {
+    Added,
+    Replaced,
+    /// Already there, with the same contents
+    Unchanged,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ImportedFile {
+    pub name: String,
+    pub path: PathBuf,
+    pub state: ImportState,
+}
+
+/// Files in `portal_dir` a bundle carries: settings, mappings, then each
+/// provider file
+pub fn bundled_files(portal_dir: &Path) -> Result<Vec<String>> {
+    let mut files: Vec<String> = [SETTINGS_FILE, MAPPINGS_FILE]
+        .into_iter()
+        .filter(|name| portal_dir.join(name).is_file())
+        .map(str::to_string)
+        .collect();
+    files.extend(
+        provider::list_custom_providers(portal_dir)?
+            .into_iter()
+            .map(|name| format!("{}.json", name))
+            // Would clash with the manifest
+            .filter(|name| name != MANIFEST_FILE),
+    );
+    Ok(files)
+}
+
+/// Pack the setup in `portal_dir` into `bundle`. Provider files holding
+/// what looks like a raw API key are refused unless
+/// `allow_inline_secrets`, since a bundle is made to be passed around.
+pub fn export(
+    portal_dir: &Path,
+    bundle: &Path,
+    force: bool,
+    allow_inline_secrets: bool,
+    dry_run: bool,
+) -> Result<Manifest> {
+    let files = bundled_files(portal_dir)?;
+    if files.is_empty() {
+        return Err(anyhow!(
+            "Nothing to export: no provider files, {}, or {} in {}",
+            MAPPINGS_FILE,
+            SETTINGS_FILE,
+            portal_dir.display()
+        ));
+    }
+    if bundle.exists() && !force {
+        return Err(anyhow!(
+            "{} already exists\n\nUse --force to overwrite it.",
+            bundle.display()
+        ));
+    }
+    for name in &files {
+        let Some(stem) = provider_name(name) else {
+            continue;
+        };
+        let config = provider::read_provider_config(portal_dir, stem)?
+            .ok_or_else(|| anyhow!("Provider file disappeared: {}", name))?;
+        let found = secrets::inline_secrets(&config.options);
+        if !found.is_empty() && !allow_inline_secrets {
+            return Err(anyhow!(
+                "Provider '{}' has what looks like a raw secret in {}, which the bundle \
+                 would hand to everyone it is given to. Reference an environment variable \
+                 (\"{{env:NAME}}\") or a keychain secret (\"{{secret:NAME}}\") instead, \
+                 or pass --allow-inline-secrets",
+                stem,
+                found.join(", ")
+            ));
+        }
+    }
+    let manifest = Manifest {
+        format: FORMAT,
+        created: Utc::now(),
+        portal: env!("CARGO_PKG_VERSION").to_string(),
+        files,
+    };
+    if dry_run {
+        return Ok(manifest);
+    }
+
+    let staging = Staging::new("export")?;
+    for name in &manifest.files {
+        fs::copy(portal_dir.join(name), staging.0.join(name))
+            .with_context(|| format!("Failed to copy {} into the bundle", name))?;
+    }
+    fs::write(
+        staging.0.join(MANIFEST_FILE),
+        serde_json::to_string_pretty(&manifest)?,
+    )
+    .context("Failed to write the bundle manifest")?;
+
+    let bundle_arg = std::path::absolute(bundle).unwrap_or_else(|_| bundle.to_path_buf());
+    let mut args = vec![
+        "-czf".into(),
+        bundle_arg.into_os_string(),
+        "-C".into(),
+        staging.0.clone().into_os_string(),
+        MANIFEST_FILE.into(),
+    ];
+    args.extend(manifest.files.iter().map(Into::into));
+    tar(&args).with_context(|| format!("Failed to write bundle: {}", bundle.display()))?;
+    crate::portal_info!(
+        "Exported {} file(s) to {}",
+        manifest.files.len(),
+        bundle.display()
+    );
+    Ok(manifest)
+}
+
+/// Unpack `bundle` into `portal_dir`. Nothing is written unless every file
+/// in it is valid, and files that differ from the bundle's are only
+/// replaced with `force`.
+pub fn import(
+    portal_dir: &Path,
+    bundle: &Path,
+    force: bool,
+    dry_run: bool,
+) -> Result<(Manifest, Vec<ImportedFile>)> {
+    if !bundle.is_file() {
+        return Err(anyhow!("Bundle not found: {}", bundle.display()));
+    }
+    // Only plain names, so nothing unpacks outside the staging directory
+    let listing = tar(&["-tzf".into(), bundle.as_os_str().to_os_string()])
+        .with_context(|| format!("Failed to read bundle: {}", bundle.display()))?;
+    for entry in listing.lines() {
+        let name = entry.strip_prefix("./").unwrap_or(entry);
+        if !name.is_empty() && name != "." && !is_bundle_name(name) {
+            return Err(anyhow!(
+                "Not importing {}: it holds '{}', and bundles only hold .json files at \
+                 the top level",
+                bundle.display(),
+                entry
+            ));
+        }
+    }
+
+    let staging = Staging::new("import")?;
+    tar(&[
+        "-xzf".into(),
+        bundle.as_os_str().to_os_string(),
+        "-C".into(),
+        staging.0.clone().into_os_string(),
+    ])
+    .with_context(|| format!("Failed to unpack bundle: {}", bundle.display()))?;
+
+    let manifest_path = staging.0.join(MANIFEST_FILE);
+    let text = fsutil::read_to_string(&manifest_path).map_err(|_| {
+        anyhow!(
+            "{} isn't a portal bundle: it has no {}",
+            bundle.display(),
+            MANIFEST_FILE
+        )
+    })?;
+    let manifest: Manifest =
+        serde_json::from_str(&text).map_err(PortalError::parse("bundle manifest", bundle))?;
+    if manifest.format > FORMAT {
+        return Err(anyhow!(
+            "{} was made by a newer portal ({}); update portal to import it",
+            bundle.display(),
+            manifest.portal
+        ));
+    }
+
+    let mut imported = Vec::new();
+    let mut conflicts = Vec::new();
+    for name in &manifest.files {
+        let staged = staging.0.join(name);
+        let is_file = is_bundle_name(name)
+            && fs::symlink_metadata(&staged).is_ok_and(|metadata| metadata.is_file());
+        if !is_file {
+            return Err(anyhow!("Bundle lists {} but doesn't hold it", name));
+        }
+        check(&staging.0, name)?;
+
+        let path = portal_dir.join(name);
+        let contents = fs::read(&staged)?;
+        let state = match fs::read(&path) {
+            Ok(existing) if existing == contents => ImportState::Unchanged,
+            Ok(_) => {
+                conflicts.push(name.clone());
+                ImportState::Replaced
+            }
+            Err(_) => ImportState::Added,
+        };
+        imported.push(ImportedFile {
+            name: name.clone(),
+            path,
+            state,
+        });
+    }
+    if !conflicts.is_empty() && !force {
+        return Err(anyhow!(
+            "Importing would replace files that differ from the bundle's: {}\n\n\
+             Use --force to replace them.",
+            conflicts.join(", ")
+        ));
+    }
+    if dry_run {
+        return Ok((manifest, imported));
+    }
+
+    fs::create_dir_all(portal_dir).with_context(|| {
+        format!(
+            "Failed to create portal directory: {}",
+            portal_dir.display()
+        )
+    })?;
+    for file in &imported {
+        if file.state == ImportState::Unchanged {
+            continue;
+        }
+        let contents = fs::read(staging.0.join(&file.name))?;
+        fsutil::write_atomic(&file.path, &contents)
+            .with_context(|| format!("Failed to write {}", file.path.display()))?;
+    }
+    crate::portal_info!(
+        "Imported {} from {}",
+        portal_dir.display(),
+        bundle.display()
+    );
+    Ok((manifest, imported))
+}
+
+/// Fail unless the bundled file `name` in `dir` is valid for what it is
+fn check(dir: &Path, name: &str) -> Result<()> {
+    match name {
+        SETTINGS_FILE => settings::read_settings(dir).map(drop),
+        MAPPINGS_FILE => mapping::read_mappings_file(dir).map(drop),
+        _ => {
+            let stem = provider_name(name)
+                .ok_or_else(|| anyhow!("Bundle holds {}, which isn't a provider file", name))?;
+            provider::validate_name(stem)?;
+            provider::read_provider_config(dir, stem).map(drop)
+        }
+    }
+    .with_context(|| format!("Bundle has an invalid {}", name))
+}
+
+/// The provider a bundled file name is for, if it is a provider file
+fn provider_name(name: &str) -> Option<&str> {
+    let stem = name.strip_suffix(".json")?;
+    (name != SETTINGS_FILE && name != MAPPINGS_FILE && name != MANIFEST_FILE).then_some(stem)
+}
+
+/// A name a bundle can hold: a `.json` file at the top of it
+fn is_bundle_name(name: &str) -> bool {
+    name.ends_with(".json")
+        && !name.starts_with('.')
+        && !name.contains(['/', '\\'])
+        && !name.contains("..")
+}
+
+fn tar(args: &[std::ffi::OsString]) -> Result<String> {
+    let output = Command::new("tar")
+        .args(args)
+        .output()
+        .context("Failed to run tar, which bundles need")?;
+    if !output.status.success() {
+        return Err(anyhow!(
+            "tar failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// A scratch directory, removed when dropped
+struct Staging(PathBuf);
+
+impl Staging {
+    fn new(purpose: &str) -> Result<Self> {
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.subsec_nanos())
+            .unwrap_or_default();
+        let dir = std::env::temp_dir().join(format!(
+            "portal-{}-{}-{}",
+            purpose,
+            std::process::id(),
+            nanos
+        ));
+        fs::create_dir_all(&dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+        Ok(Self(dir))
+    }
+}
+
+impl Drop for Staging {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
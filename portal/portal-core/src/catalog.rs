@@ -0,0 +1,173 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::http;
+use crate::mapping::{MappingOverride, MappingTable};
+
+/// OpenRouter's public model listing
+pub const OPENROUTER_MODELS_URL: &str = "https://openrouter.ai/api/v1/models";
+
+/// opencode provider prefix of OpenRouter model strings
+pub const OPENROUTER_PREFIX: &str = "openrouter/";
+
+/// How many close matches to suggest for an unknown slug
+const MAX_SUGGESTIONS: usize = 3;
+
+/// Model vendors `portal mappings sync` takes rows from unless told otherwise
+pub const DEFAULT_SYNC_VENDORS: &[&str] = &["anthropic", "google", "openai"];
+
+/// Model slugs a provider actually serves, e.g. `anthropic/claude-opus-4.5`
+#[derive(Debug, Clone, Default)]
+pub struct Catalog {
+    slugs: BTreeSet<String>,
+}
+
+#[derive(Deserialize)]
+struct ModelsResponse {
+    data: Vec<ModelEntry>,
+}
+
+#[derive(Deserialize)]
+struct ModelEntry {
+    id: String,
+}
+
+/// A model the catalog doesn't list
+#[derive(Debug, Clone, Serialize)]
+pub struct UnknownModel {
+    pub agent: String,
+    pub model: String,
+    /// Closest listed models, as full opencode model strings
+    pub suggestions: Vec<String>,
+}
+
+impl Catalog {
+    /// Fetch the current OpenRouter catalog
+    pub fn fetch_openrouter() -> Result<Self> {
+        Self::from_openrouter_json(&http::get(OPENROUTER_MODELS_URL)?)
+    }
+
+    /// Parse a response of OpenRouter's `/models` endpoint
+    pub fn from_openrouter_json(text: &str) -> Result<Self> {
+        let response: ModelsResponse =
+            serde_json::from_str(text).context("Failed to parse OpenRouter model list")?;
+        Ok(Self {
+            slugs: response.data.into_iter().map(|m| m.id).collect(),
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.slugs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.slugs.is_empty()
+    }
+
+    pub fn contains(&self, slug: &str) -> bool {
+        self.slugs.contains(slug)
+    }
+
+    /// Listed slugs closest to `slug` by edit distance, best first
+    pub fn suggest(&self, slug: &str) -> Vec<String> {
+        let limit = (slug.len() / 3).max(3);
+        let mut scored: Vec<(usize, &String)> = self
+            .slugs
+            .iter()
+            .map(|candidate| (edit_distance(slug, candidate), candidate))
+            .filter(|(distance, _)| *distance <= limit)
+            .collect();
+        scored.sort();
+        scored
+            .into_iter()
+            .take(MAX_SUGGESTIONS)
+            .map(|(_, candidate)| candidate.clone())
+            .collect()
+    }
+
+    /// The `openrouter/...` models among `models` (agent, model) that the
+    /// catalog doesn't list. Other providers' models are skipped.
+    pub fn unknown_models<'a>(
+        &self,
+        models: impl IntoIterator<Item = (&'a str, &'a str)>,
+    ) -> Vec<UnknownModel> {
+        models
+            .into_iter()
+            .filter_map(|(agent, model)| {
+                let slug = model.strip_prefix(OPENROUTER_PREFIX)?;
+                if self.contains(slug) {
+                    return None;
+                }
+                Some(UnknownModel {
+                    agent: agent.to_string(),
+                    model: model.to_string(),
+                    suggestions: self
+                        .suggest(slug)
+                        .into_iter()
+                        .map(|s| format!("{}{}", OPENROUTER_PREFIX, s))
+                        .collect(),
+                })
+            })
+            .collect()
+    }
+
+    /// Mapping rows for listed models of `vendors` that `table` has no row
+    /// for, keyed by base model. The base model is the slug's model part
+    /// without a `-preview` suffix, and Copilot is assumed to use the same
+    /// name. Variants such as `:free` are left out.
+    pub fn mapping_candidates(
+        &self,
+        table: &MappingTable,
+        vendors: &[String],
+    ) -> BTreeMap<String, MappingOverride> {
+        let mut candidates = BTreeMap::new();
+        for slug in &self.slugs {
+            let Some((vendor, model)) = slug.split_once('/') else {
+                continue;
+            };
+            if !vendors.iter().any(|v| v == vendor) || model.contains(':') {
+                continue;
+            }
+            let base = model.strip_suffix("-preview").unwrap_or(model);
+            if table.find(base).is_some() || table.find(model).is_some() {
+                continue;
+            }
+            // A model listed both with and without -preview maps to the
+            // released one
+            match candidates.get(base) {
+                Some(MappingOverride {
+                    openrouter: Some(existing),
+                    ..
+                }) if !existing.ends_with("-preview") => continue,
+                _ => {}
+            }
+            candidates.insert(
+                base.to_string(),
+                MappingOverride {
+                    copilot: Some(base.to_string()),
+                    openrouter: Some(slug.clone()),
+                    tier: None,
+                    providers: BTreeMap::new(),
+                },
+            );
+        }
+        candidates
+    }
+}
+
+/// Levenshtein distance between two strings, by character
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut previous = row[0];
+        row[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let substitute = previous + usize::from(ca != *cb);
+            previous = row[j + 1];
+            row[j + 1] = substitute.min(row[j] + 1).min(previous + 1);
+        }
+    }
+    row[b.len()]
+}
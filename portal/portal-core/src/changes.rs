@@ -0,0 +1,98 @@
+use serde::Serialize;
+use serde_json::Value;
+
+/// What kind of edit a [`ConfigChange`] is, for grouping in reports
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ChangeKind {
+    Model,
+    AgentAdded,
+    AgentRemoved,
+    Mcp,
+    Permission,
+    Other,
+}
+
+impl ChangeKind {
+    pub fn label(self) -> &'static str {
+        match self {
+            ChangeKind::Model => "Model edits",
+            ChangeKind::AgentAdded => "New agents",
+            ChangeKind::AgentRemoved => "Removed agents",
+            ChangeKind::Mcp => "MCP servers",
+            ChangeKind::Permission => "Permissions",
+            ChangeKind::Other => "Other changes",
+        }
+    }
+}
+
+/// One difference between two config documents
+#[derive(Debug, Clone, Serialize)]
+pub struct ConfigChange {
+    pub kind: ChangeKind,
+    /// Dotted path to the changed value, e.g. `agents.oracle.model`
+    pub path: String,
+    pub before: Option<Value>,
+    pub after: Option<Value>,
+}
+
+fn classify(path: &[String], before: &Option<Value>, after: &Option<Value>) -> ChangeKind {
+    let in_agents = matches!(
+        path.first().map(String::as_str),
+        Some("agents" | "subagents")
+    );
+    if in_agents && path.len() == 2 {
+        match (before, after) {
+            (None, Some(_)) => return ChangeKind::AgentAdded,
+            (Some(_), None) => return ChangeKind::AgentRemoved,
+            _ => {}
+        }
+    }
+    if in_agents && path.len() == 3 && path[2] == "model" {
+        return ChangeKind::Model;
+    }
+    if path.iter().any(|p| p.to_lowercase().contains("permission")) {
+        return ChangeKind::Permission;
+    }
+    if path.iter().any(|p| p.to_lowercase().contains("mcp")) {
+        return ChangeKind::Mcp;
+    }
+    ChangeKind::Other
+}
+
+fn walk(
+    path: &mut Vec<String>,
+    before: Option<&Value>,
+    after: Option<&Value>,
+    out: &mut Vec<ConfigChange>,
+) {
+    if let (Some(Value::Object(b)), Some(Value::Object(a))) = (before, after) {
+        let mut keys: Vec<&String> = b.keys().chain(a.keys()).collect();
+        keys.sort();
+        keys.dedup();
+        for key in keys {
+            path.push(key.clone());
+            walk(path, b.get(key), a.get(key), out);
+            path.pop();
+        }
+        return;
+    }
+    if before != after {
+        let before = before.cloned();
+        let after = after.cloned();
+        out.push(ConfigChange {
+            kind: classify(path, &before, &after),
+            path: path.join("."),
+            before,
+            after,
+        });
+    }
+}
+
+/// Every difference from `before` to `after`, sorted by kind then path
+pub fn config_changes(before: &Value, after: &Value) -> Vec<ConfigChange> {
+    let mut changes = Vec::new();
+    walk(&mut Vec::new(), Some(before), Some(after), &mut changes);
+    changes.sort_by(|a, b| (a.kind, &a.path).cmp(&(b.kind, &b.path)));
+    changes
+}
@@ -0,0 +1,90 @@
+//! Time that the wall clock changing can't upset. Suspend and resume,
+//! NTP corrections, and hand-set clocks all move the wall clock; the time
+//! since boot (counting suspend) only moves forward, so portal measures
+//! from that where it can and uses wall time across reboots.
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::time::Duration;
+
+/// How far the wall clock may drift from the time since boot before it
+/// counts as changed
+pub const TOLERANCE: Duration = Duration::from_secs(120);
+
+/// Identifies the current boot: the kernel's boot ID on Linux, the boot
+/// time elsewhere. `None` if neither can be read.
+pub fn current_boot_id() -> Option<String> {
+    if let Ok(id) = fs::read_to_string("/proc/sys/kernel/random/boot_id") {
+        return Some(id.trim().to_string());
+    }
+    let output = std::process::Command::new("sysctl")
+        .args(["-n", "kern.boottime"])
+        .output()
+        .ok()?;
+    let text = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    (output.status.success() && !text.is_empty()).then_some(text)
+}
+
+/// Time since boot, including time spent suspended. `None` where the
+/// system doesn't say (only Linux does, through `/proc/uptime`).
+pub fn uptime() -> Option<Duration> {
+    let text = fs::read_to_string("/proc/uptime").ok()?;
+    let seconds: f64 = text.split_whitespace().next()?.parse().ok()?;
+    (seconds.is_finite() && seconds >= 0.0).then(|| Duration::from_secs_f64(seconds))
+}
+
+/// A point in time by both clocks, so time since then can be measured
+/// without trusting the wall clock
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ClockStamp {
+    pub boot_id: String,
+    /// Seconds since boot
+    pub uptime: f64,
+}
+
+impl ClockStamp {
+    /// Now, or `None` if this system can't say how long it has been up
+    pub fn now() -> Option<Self> {
+        Some(Self {
+            boot_id: current_boot_id()?,
+            uptime: uptime()?.as_secs_f64(),
+        })
+    }
+
+    /// Time since the stamp by the time since boot; `None` after a reboot,
+    /// when the two can't be compared
+    pub fn elapsed(&self) -> Option<Duration> {
+        let now = Self::now()?;
+        if now.boot_id != self.boot_id {
+            return None;
+        }
+        Some(Duration::from_secs_f64((now.uptime - self.uptime).max(0.0)))
+    }
+}
+
+/// The current time for something that happened at `then` (by the wall
+/// clock) and `stamp`: `now`, unless the wall clock has moved by more than
+/// [`TOLERANCE`] away from the time since boot since then, in which case
+/// it was changed and `then` plus the time since boot is used instead
+pub fn trusted_now(
+    then: DateTime<Utc>,
+    stamp: Option<&ClockStamp>,
+    now: DateTime<Utc>,
+) -> DateTime<Utc> {
+    let Some(elapsed) = stamp
+        .and_then(ClockStamp::elapsed)
+        .and_then(|elapsed| chrono::Duration::from_std(elapsed).ok())
+    else {
+        return now;
+    };
+    let measured = then + elapsed;
+    let skew = (now - measured).abs().to_std().unwrap_or_default();
+    if skew > TOLERANCE { measured } else { now }
+}
+
+/// How long ago `then` was, or `None` if it is in the future: the clock
+/// was set back since, so the age can't be known
+pub fn age(then: DateTime<Utc>, now: DateTime<Utc>) -> Option<Duration> {
+    (now - then).to_std().ok()
+}
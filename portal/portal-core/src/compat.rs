@@ -0,0 +1,89 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// Schema reference oh-my-opencode writes into new configs
+pub const DEFAULT_SCHEMA_URL: &str = "https://raw.githubusercontent.com/code-yeongyu/oh-my-opencode/master/assets/oh-my-opencode.schema.json";
+
+/// Newest oh-my-opencode schema major version portal understands
+pub const SUPPORTED_SCHEMA_VERSION: u64 = 3;
+
+/// Whether portal fully understands a config document. Any reasons listed
+/// mean switching should only touch known model fields.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Compatibility {
+    pub reasons: Vec<String>,
+}
+
+impl Compatibility {
+    pub fn is_conservative(&self) -> bool {
+        !self.reasons.is_empty()
+    }
+}
+
+/// Check a raw config document against the structure portal knows
+pub fn assess(document: &Value) -> Compatibility {
+    let mut reasons = Vec::new();
+
+    match document.get("$schema") {
+        Some(Value::String(url)) => {
+            if let Some(version) = schema_version(url)
+                && version > SUPPORTED_SCHEMA_VERSION
+            {
+                reasons.push(format!(
+                    "$schema declares version {} (portal understands up to {})",
+                    version, SUPPORTED_SCHEMA_VERSION
+                ));
+            }
+        }
+        Some(_) => reasons.push("$schema is not a string".to_string()),
+        None => {}
+    }
+
+    if let Some(value) = document.get("google_auth")
+        && !value.is_boolean()
+    {
+        reasons.push("google_auth is not a boolean".to_string());
+    }
+    if let Some(value) = document.get("subagents")
+        && !value.is_object()
+    {
+        reasons.push("subagents is not an object".to_string());
+    }
+
+    match document.get("agents").and_then(Value::as_object) {
+        Some(agents) => {
+            for (name, agent) in agents {
+                if !agent.get("model").is_some_and(Value::is_string) {
+                    reasons.push(format!("agent '{}' has no string model field", name));
+                }
+            }
+        }
+        None => reasons.push("agents section is missing or not an object".to_string()),
+    }
+
+    Compatibility { reasons }
+}
+
+/// Major version embedded in a schema URL, e.g. `.../v4/schema.json`,
+/// `oh-my-opencode@4.1.0/...` or `schema.v4.json`
+fn schema_version(url: &str) -> Option<u64> {
+    for segment in url.split('/') {
+        if let Some((_, version)) = segment.split_once('@') {
+            return leading_number(version);
+        }
+        for part in segment.split(['.', '-', '_']) {
+            if let Some(rest) = part.strip_prefix('v')
+                && !rest.is_empty()
+                && rest.chars().all(|c| c.is_ascii_digit())
+            {
+                return rest.parse().ok();
+            }
+        }
+    }
+    None
+}
+
+fn leading_number(s: &str) -> Option<u64> {
+    let digits: String = s.chars().take_while(|c| c.is_ascii_digit()).collect();
+    digits.parse().ok()
+}
@@ -0,0 +1,207 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::Path;
+
+use crate::error::PortalError;
+use crate::{fsutil, hash, jsonc};
+
+/// Main oh-my-opencode configuration file structure
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OhMyOpenCodeConfig {
+    #[serde(rename = "$schema", skip_serializing_if = "Option::is_none")]
+    pub schema: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub google_auth: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub subagents: Option<HashMap<String, serde_json::Value>>,
+    pub agents: HashMap<String, AgentConfig>,
+    #[serde(flatten)]
+    pub extra: HashMap<String, serde_json::Value>,
+}
+
+/// Per-agent configuration
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AgentConfig {
+    pub model: String,
+    #[serde(flatten)]
+    pub extra: HashMap<String, serde_json::Value>,
+}
+
+impl OhMyOpenCodeConfig {
+    /// Parse a config from its JSON text (comments and trailing commas allowed)
+    pub fn from_json(content: &str) -> Result<Self> {
+        Ok(serde_json::from_str(&jsonc::strip(content))?)
+    }
+
+    /// Render the config as pretty-printed JSON with a trailing newline
+    pub fn to_json(&self) -> Result<String> {
+        Ok(format!("{}\n", serde_json::to_string_pretty(self)?))
+    }
+}
+
+/// A config file as read from disk
+#[derive(Debug, Clone)]
+pub struct Source {
+    pub text: String,
+    pub document: serde_json::Value,
+    /// SHA-256 of `text`
+    pub hash: String,
+}
+
+pub fn read_config(path: &Path) -> Result<OhMyOpenCodeConfig> {
+    let content = fsutil::read_to_string(path)
+        .with_context(|| format!("Failed to read config file: {}", path.display()))?;
+    Ok(serde_json::from_str(&jsonc::strip(&content))
+        .map_err(PortalError::parse("config file", path))?)
+}
+
+pub fn write_config(path: &Path, config: &OhMyOpenCodeConfig) -> Result<()> {
+    write_document(path, &serde_json::to_value(config)?)
+}
+
+/// Read the config as an untyped JSON document
+pub fn read_document(path: &Path) -> Result<serde_json::Value> {
+    Ok(read_source(path)?.document)
+}
+
+/// Read the config's text, parsed document, and content hash in one go
+pub fn read_source(path: &Path) -> Result<Source> {
+    let text = fsutil::read_to_string(path)
+        .with_context(|| format!("Failed to read config file: {}", path.display()))?;
+    let document = jsonc::parse(&text).map_err(PortalError::parse("config file", path))?;
+    let hash = hash::sha256_hex(text.as_bytes());
+    Ok(Source {
+        text,
+        document,
+        hash,
+    })
+}
+
+/// SHA-256 of a file's contents, or `None` if it doesn't exist
+pub fn fingerprint(path: &Path) -> Result<Option<String>> {
+    match fsutil::read(path) {
+        Ok(bytes) => Ok(Some(hash::sha256_hex(&bytes))),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e).with_context(|| format!("Failed to read file: {}", path.display())),
+    }
+}
+
+/// Text to write for `document`: `original` edited in place, keeping its
+/// comments and layout, or the document pretty-printed from scratch when
+/// there's no original or it doesn't parse.
+pub fn render_document(original: Option<&str>, document: &serde_json::Value) -> Result<String> {
+    if let Some(text) = original.and_then(|o| jsonc::update(o, document)) {
+        return Ok(text);
+    }
+    if original.is_some() {
+        crate::portal_debug!("Couldn't edit in place, so the whole file is written afresh");
+    }
+    Ok(format!("{}\n", serde_json::to_string_pretty(document)?))
+}
+
+/// Write `document` over `path`, preserving the existing file's comments
+/// and layout where possible
+pub fn write_document(path: &Path, document: &serde_json::Value) -> Result<()> {
+    let original = fsutil::read_to_string(path).ok();
+    write_rendered(path, original.as_deref(), document)
+}
+
+/// Write `document` to `path`, rendered as an edit of `original`
+pub fn write_rendered(
+    path: &Path,
+    original: Option<&str>,
+    document: &serde_json::Value,
+) -> Result<()> {
+    let content = render_document(original, document)?;
+    fsutil::write_atomic(path, content.as_bytes())
+        .with_context(|| format!("Failed to write config file: {}", path.display()))
+}
+
+/// Top-level fields holding a default model outside `agents`
+pub const TOP_LEVEL_MODEL_FIELDS: &[&str] = &["model", "small_model"];
+
+/// `(field, model)` pairs for the top-level model fields present as strings
+pub fn top_level_models(document: &serde_json::Value) -> Vec<(String, String)> {
+    TOP_LEVEL_MODEL_FIELDS
+        .iter()
+        .filter_map(|field| {
+            let model = document.get(*field)?.as_str()?;
+            Some((field.to_string(), model.to_string()))
+        })
+        .collect()
+}
+
+/// `(agent, model)` pairs from a config document, sorted by agent name
+pub fn agent_models(document: &serde_json::Value) -> Vec<(String, String)> {
+    let mut models: Vec<_> = document
+        .get("agents")
+        .and_then(serde_json::Value::as_object)
+        .map(|agents| {
+            agents
+                .iter()
+                .filter_map(|(name, agent)| {
+                    let model = agent.get("model")?.as_str()?;
+                    Some((name.clone(), model.to_string()))
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+    models.sort();
+    models
+}
+
+/// `(subagent, model)` pairs for `subagents` entries with a string model,
+/// sorted by name
+pub fn subagent_models(document: &serde_json::Value) -> Vec<(String, String)> {
+    let mut models: Vec<_> = document
+        .get("subagents")
+        .and_then(serde_json::Value::as_object)
+        .map(|subagents| {
+            subagents
+                .iter()
+                .filter_map(|(name, subagent)| {
+                    let model = subagent.get("model")?.as_str()?;
+                    Some((name.clone(), model.to_string()))
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+    models.sort();
+    models
+}
+
+/// The model of an agent, of a subagent named as `subagents.<name>`, or a
+/// top-level model field not shadowed by an agent of the same name, if it
+/// exists and is a string
+pub fn model_slot<'a>(document: &'a serde_json::Value, agent: &str) -> Option<&'a str> {
+    let (section, name) = match agent.strip_prefix("subagents.") {
+        Some(name) => ("subagents", name),
+        None => ("agents", agent),
+    };
+    let is_agent = document.get(section).and_then(|s| s.get(name)).is_some();
+    let model = if !is_agent && TOP_LEVEL_MODEL_FIELDS.contains(&agent) {
+        document.get(agent)?
+    } else {
+        document.get(section)?.get(name)?.get("model")?
+    };
+    model.as_str()
+}
+
+/// [`model_slot`] as the JSON value, to replace
+pub fn model_slot_mut<'a>(
+    document: &'a mut serde_json::Value,
+    agent: &str,
+) -> Option<&'a mut serde_json::Value> {
+    let (section, name) = match agent.strip_prefix("subagents.") {
+        Some(name) => ("subagents", name),
+        None => ("agents", agent),
+    };
+    let is_agent = document.get(section).and_then(|s| s.get(name)).is_some();
+    let model = if !is_agent && TOP_LEVEL_MODEL_FIELDS.contains(&agent) {
+        document.get_mut(agent)?
+    } else {
+        document.get_mut(section)?.get_mut(name)?.get_mut("model")?
+    };
+    model.is_string().then_some(model)
+}
@@ -0,0 +1,351 @@
+//! ChaCha20-Poly1305 (RFC 8439), used to encrypt backups.
+
+use std::io::{self, Read};
+
+const CONSTANTS: [u32; 4] = [0x61707865, 0x3320646e, 0x79622d32, 0x6b206574];
+
+/// Bytes in a key
+pub const KEY_LEN: usize = 32;
+
+/// Bytes in a nonce
+pub const NONCE_LEN: usize = 12;
+
+/// Bytes in the tag that ends every sealed message
+pub const TAG_LEN: usize = 16;
+
+fn le32(bytes: &[u8]) -> u32 {
+    u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
+}
+
+fn quarter_round(state: &mut [u32; 16], a: usize, b: usize, c: usize, d: usize) {
+    state[a] = state[a].wrapping_add(state[b]);
+    state[d] = (state[d] ^ state[a]).rotate_left(16);
+    state[c] = state[c].wrapping_add(state[d]);
+    state[b] = (state[b] ^ state[c]).rotate_left(12);
+    state[a] = state[a].wrapping_add(state[b]);
+    state[d] = (state[d] ^ state[a]).rotate_left(8);
+    state[c] = state[c].wrapping_add(state[d]);
+    state[b] = (state[b] ^ state[c]).rotate_left(7);
+}
+
+/// One 64-byte block of keystream
+fn chacha20_block(key: &[u8; KEY_LEN], counter: u32, nonce: &[u8; NONCE_LEN]) -> [u8; 64] {
+    let mut initial = [0u32; 16];
+    initial[..4].copy_from_slice(&CONSTANTS);
+    for (word, bytes) in initial[4..12].iter_mut().zip(key.chunks_exact(4)) {
+        *word = le32(bytes);
+    }
+    initial[12] = counter;
+    for (word, bytes) in initial[13..].iter_mut().zip(nonce.chunks_exact(4)) {
+        *word = le32(bytes);
+    }
+
+    let mut state = initial;
+    for _ in 0..10 {
+        quarter_round(&mut state, 0, 4, 8, 12);
+        quarter_round(&mut state, 1, 5, 9, 13);
+        quarter_round(&mut state, 2, 6, 10, 14);
+        quarter_round(&mut state, 3, 7, 11, 15);
+        quarter_round(&mut state, 0, 5, 10, 15);
+        quarter_round(&mut state, 1, 6, 11, 12);
+        quarter_round(&mut state, 2, 7, 8, 13);
+        quarter_round(&mut state, 3, 4, 9, 14);
+    }
+
+    let mut block = [0u8; 64];
+    for ((chunk, word), start) in block.chunks_exact_mut(4).zip(state).zip(initial) {
+        chunk.copy_from_slice(&word.wrapping_add(start).to_le_bytes());
+    }
+    block
+}
+
+/// XOR `data` with the keystream, starting at block `counter`
+fn chacha20_xor(key: &[u8; KEY_LEN], counter: u32, nonce: &[u8; NONCE_LEN], data: &mut [u8]) {
+    for (i, chunk) in data.chunks_mut(64).enumerate() {
+        let block = chacha20_block(key, counter.wrapping_add(i as u32), nonce);
+        for (byte, key_byte) in chunk.iter_mut().zip(block) {
+            *byte ^= key_byte;
+        }
+    }
+}
+
+/// Poly1305 tag of `message` under the one-time `key`, with 26-bit limbs
+fn poly1305(key: &[u8; 32], message: &[u8]) -> [u8; TAG_LEN] {
+    const MASK: u32 = 0x3ffffff;
+    let r0 = le32(&key[0..]) & 0x3ffffff;
+    let r1 = (le32(&key[3..]) >> 2) & 0x3ffff03;
+    let r2 = (le32(&key[6..]) >> 4) & 0x3ffc0ff;
+    let r3 = (le32(&key[9..]) >> 6) & 0x3f03fff;
+    let r4 = (le32(&key[12..]) >> 8) & 0x00fffff;
+    let (s1, s2, s3, s4) = (r1 * 5, r2 * 5, r3 * 5, r4 * 5);
+    let mut h = [0u32; 5];
+
+    for chunk in message.chunks(16) {
+        let mut block = [0u8; 17];
+        block[..chunk.len()].copy_from_slice(chunk);
+        // The 2^128 bit, or for a short last block the byte after it
+        block[chunk.len()] = 1;
+        let high = (block[16] as u32) << 24;
+
+        h[0] += le32(&block[0..]) & MASK;
+        h[1] += (le32(&block[3..]) >> 2) & MASK;
+        h[2] += (le32(&block[6..]) >> 4) & MASK;
+        h[3] += (le32(&block[9..]) >> 6) & MASK;
+        h[4] += (le32(&block[12..]) >> 8) | high;
+
+        let [h0, h1, h2, h3, h4] = h.map(u64::from);
+        let (r0, r1, r2, r3, r4) = (r0 as u64, r1 as u64, r2 as u64, r3 as u64, r4 as u64);
+        let (s1, s2, s3, s4) = (s1 as u64, s2 as u64, s3 as u64, s4 as u64);
+        let d = [
+            h0 * r0 + h1 * s4 + h2 * s3 + h3 * s2 + h4 * s1,
+            h0 * r1 + h1 * r0 + h2 * s4 + h3 * s3 + h4 * s2,
+            h0 * r2 + h1 * r1 + h2 * r0 + h3 * s4 + h4 * s3,
+            h0 * r3 + h1 * r2 + h2 * r1 + h3 * r0 + h4 * s4,
+            h0 * r4 + h1 * r3 + h2 * r2 + h3 * r1 + h4 * r0,
+        ];
+
+        let mut carry = 0u64;
+        for (limb, d) in h.iter_mut().zip(d) {
+            let d = d + carry;
+            *limb = d as u32 & MASK;
+            carry = d >> 26;
+        }
+        h[0] += carry as u32 * 5;
+        h[1] += h[0] >> 26;
+        h[0] &= MASK;
+    }
+
+    // Carry all the way through, then reduce mod 2^130 - 5
+    let mut carry = 0;
+    for limb in h[1..].iter_mut() {
+        *limb += carry;
+        carry = *limb >> 26;
+        *limb &= MASK;
+    }
+    h[0] += carry * 5;
+    h[1] += h[0] >> 26;
+    h[0] &= MASK;
+
+    let mut g = [0u32; 5];
+    let mut carry = 5;
+    for (g, h) in g[..4].iter_mut().zip(h) {
+        *g = h + carry;
+        carry = *g >> 26;
+        *g &= MASK;
+    }
+    // Not masked: the carry out of the top limb is what says h + 5 >= 2^130
+    g[4] = (h[4] + carry).wrapping_sub(1 << 26);
+    // All ones when h >= 2^130 - 5, so g is the reduced value
+    let use_g = (g[4] >> 31).wrapping_sub(1);
+    for (h, g) in h.iter_mut().zip(g) {
+        *h = (*h & !use_g) | (g & use_g);
+    }
+
+    let words = [
+        h[0] | (h[1] << 26),
+        (h[1] >> 6) | (h[2] << 20),
+        (h[2] >> 12) | (h[3] << 14),
+        (h[3] >> 18) | (h[4] << 8),
+    ];
+    let mut tag = [0u8; TAG_LEN];
+    let mut carry = 0u64;
+    for ((chunk, word), pad) in tag
+        .chunks_exact_mut(4)
+        .zip(words)
+        .zip(key[16..].chunks_exact(4))
+    {
+        let sum = word as u64 + le32(pad) as u64 + carry;
+        chunk.copy_from_slice(&(sum as u32).to_le_bytes());
+        carry = sum >> 32;
+    }
+    tag
+}
+
+fn tag(
+    key: &[u8; KEY_LEN],
+    nonce: &[u8; NONCE_LEN],
+    aad: &[u8],
+    ciphertext: &[u8],
+) -> [u8; TAG_LEN] {
+    let block = chacha20_block(key, 0, nonce);
+    let mut one_time = [0u8; 32];
+    one_time.copy_from_slice(&block[..32]);
+
+    let padded = |len: usize| len.div_ceil(16) * 16;
+    let mut mac = Vec::with_capacity(padded(aad.len()) + padded(ciphertext.len()) + 16);
+    mac.extend_from_slice(aad);
+    mac.resize(padded(aad.len()), 0);
+    mac.extend_from_slice(ciphertext);
+    mac.resize(padded(aad.len()) + padded(ciphertext.len()), 0);
+    mac.extend_from_slice(&(aad.len() as u64).to_le_bytes());
+    mac.extend_from_slice(&(ciphertext.len() as u64).to_le_bytes());
+    poly1305(&one_time, &mac)
+}
+
+/// `plaintext` encrypted, followed by a tag covering it and `aad`
+pub fn seal(key: &[u8; KEY_LEN], nonce: &[u8; NONCE_LEN], aad: &[u8], plaintext: &[u8]) -> Vec<u8> {
+    let mut sealed = plaintext.to_vec();
+    chacha20_xor(key, 1, nonce, &mut sealed);
+    let tag = tag(key, nonce, aad, &sealed);
+    sealed.extend_from_slice(&tag);
+    sealed
+}
+
+/// The plaintext of what [`seal`] produced, or `None` if it was sealed
+/// with another key, nonce, or `aad`, or has been tampered with
+pub fn open(
+    key: &[u8; KEY_LEN],
+    nonce: &[u8; NONCE_LEN],
+    aad: &[u8],
+    sealed: &[u8],
+) -> Option<Vec<u8>> {
+    let split = sealed.len().checked_sub(TAG_LEN)?;
+    let (ciphertext, given) = sealed.split_at(split);
+    let expected = tag(key, nonce, aad, ciphertext);
+    // Look at every byte, so timing doesn't tell how much of a forgery matched
+    let diff = given
+        .iter()
+        .zip(expected)
+        .fold(0, |diff, (a, b)| diff | (a ^ b));
+    if diff != 0 {
+        return None;
+    }
+    let mut plaintext = ciphertext.to_vec();
+    chacha20_xor(key, 1, nonce, &mut plaintext);
+    Some(plaintext)
+}
+
+/// `N` bytes from the operating system's random number generator
+pub fn random_bytes<const N: usize>() -> io::Result<[u8; N]> {
+    let mut bytes = [0u8; N];
+    if cfg!(unix) {
+        std::fs::File::open("/dev/urandom")?.read_exact(&mut bytes)?;
+        Ok(bytes)
+    } else {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "random keys and nonces can only be made on Unix",
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SUNSCREEN: &[u8] = b"Ladies and Gentlemen of the class of '99: If I could offer you \
+        only one tip for the future, sunscreen would be it.";
+
+    fn hex(text: &str) -> Vec<u8> {
+        let digits: Vec<u8> = text
+            .bytes()
+            .filter(u8::is_ascii_hexdigit)
+            .map(|c| (c as char).to_digit(16).unwrap() as u8)
+            .collect();
+        digits
+            .chunks(2)
+            .map(|pair| pair[0] << 4 | pair[1])
+            .collect()
+    }
+
+    fn array<const N: usize>(bytes: &[u8]) -> [u8; N] {
+        bytes.try_into().unwrap()
+    }
+
+    // RFC 8439, section 2.4.2
+    #[test]
+    fn chacha20_encrypts_the_rfc_example() {
+        let key: [u8; KEY_LEN] = array(&(0..32).collect::<Vec<u8>>());
+        let nonce = array(&hex("000000000000004a00000000"));
+        let mut data = SUNSCREEN.to_vec();
+        chacha20_xor(&key, 1, &nonce, &mut data);
+        assert_eq!(
+            data,
+            hex(
+                "6e2e359a2568f98041ba0728dd0d6981e97e7aec1d4360c20a27afccfd9fae0b
+                 f91b65c5524733ab8f593dabcd62b3571639d624e65152ab8f530c359f0861d8
+                 07ca0dbf500d6a6156a38e088a22b65e52bc514d16ccf806818ce91ab7793736
+                 5af90bbf74a35be6b40b8eedf2785e42874d"
+            )
+        );
+    }
+
+    // RFC 8439, section 2.5.2
+    #[test]
+    fn poly1305_tags_the_rfc_example() {
+        let key = array(&hex(
+            "85d6be7857556d337f4452fe42d506a80103808afb0db2fd4abff6af4149f51b",
+        ));
+        assert_eq!(
+            poly1305(&key, b"Cryptographic Forum Research Group").to_vec(),
+            hex("a8061dc1305136c6c22b8baf0c0127a9")
+        );
+    }
+
+    // RFC 8439, appendix A.3, vectors 5, 6, and 8: h ends up in [p, 2^130)
+    #[test]
+    fn poly1305_fully_reduces() {
+        let mut three = [0u8; TAG_LEN];
+        three[0] = 3;
+
+        let mut r2 = [0u8; 32];
+        r2[0] = 2;
+        assert_eq!(poly1305(&r2, &[0xff; 16]), three);
+
+        let mut key = r2;
+        key[16..].fill(0xff);
+        let mut message = [0u8; 16];
+        message[0] = 2;
+        assert_eq!(poly1305(&key, &message), three);
+
+        let mut r1 = [0u8; 32];
+        r1[0] = 1;
+        let mut message = vec![0xff; 16];
+        message.push(0xfb);
+        message.extend([0xfe; 15]);
+        message.extend([0x01; 16]);
+        assert_eq!(poly1305(&r1, &message), [0; TAG_LEN]);
+    }
+
+    // RFC 8439, section 2.8.2
+    #[test]
+    fn seals_the_rfc_example() {
+        let key: [u8; KEY_LEN] = array(&(0x80..0xa0).collect::<Vec<u8>>());
+        let nonce = array(&hex("070000004041424344454647"));
+        let aad = hex("50515253c0c1c2c3c4c5c6c7");
+        let sealed = seal(&key, &nonce, &aad, SUNSCREEN);
+        let mut expected = hex(
+            "d31a8d34648e60db7b86afbc53ef7ec2a4aded51296e08fea9e2b5a736ee62d6
+             3dbea45e8ca9671282fafb69da92728b1a71de0a9e060b2905d6a5b67ecd3b36
+             92ddbd7f2d778b8c9803aee328091b58fab324e4fad675945585808b4831d7bc
+             3ff4def08e4b7a9de576d26586cec64b6116",
+        );
+        expected.extend(hex("1ae10b594f09e26a7e902ecbd0600691"));
+        assert_eq!(sealed, expected);
+        assert_eq!(
+            open(&key, &nonce, &aad, &sealed).as_deref(),
+            Some(SUNSCREEN)
+        );
+    }
+
+    #[test]
+    fn open_rejects_tampering() {
+        let key = [7u8; KEY_LEN];
+        let nonce = [9u8; NONCE_LEN];
+        let sealed = seal(&key, &nonce, b"header", b"the backup");
+
+        for i in 0..sealed.len() {
+            let mut tampered = sealed.clone();
+            tampered[i] ^= 0x01;
+            assert_eq!(open(&key, &nonce, b"header", &tampered), None, "byte {}", i);
+        }
+        assert_eq!(open(&key, &nonce, b"other", &sealed), None);
+        assert_eq!(open(&[8u8; KEY_LEN], &nonce, b"header", &sealed), None);
+        assert_eq!(open(&key, &[0u8; NONCE_LEN], b"header", &sealed), None);
+        assert_eq!(open(&key, &nonce, b"header", &sealed[..TAG_LEN - 1]), None);
+        assert_eq!(
+            open(&key, &nonce, b"header", &sealed).as_deref(),
+            Some(&b"the backup"[..])
+        );
+    }
+}
@@ -0,0 +1,201 @@
+use anyhow::{Context, Result, anyhow};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::error::PortalError;
+use crate::fsutil;
+use crate::jsonc;
+use crate::paths;
+use crate::portal::{Portal, SwitchOptions};
+use crate::quota::QuotaAccount;
+use crate::ratelimit::{Fallback, FallbackTarget};
+
+/// File in the portal directory describing what the daemon keeps applied
+pub const DAEMON_FILE: &str = "daemon.json";
+
+/// File in the state directory holding the running daemon's process ID
+pub const PID_FILE: &str = "daemon.pid";
+
+/// Default time to let a burst of writes settle, in milliseconds
+pub const DEFAULT_DEBOUNCE_MS: u64 = 500;
+
+/// What `portal daemon` watches
+///
+/// ```json
+/// {
+///   "debounce_ms": 500,
+///   "watch": [
+///     { "provider": "openrouter", "except": ["explore"] },
+///     { "config": "~/work/.opencode/oh-my-opencode.json", "provider": "work" }
+///   ],
+///   "quotas": [
+///     { "name": "personal", "provider": "openrouter", "key_env": "OPENROUTER_API_KEY" }
+///   ],
+///   "fallback": { "from": "copilot", "to": "openrouter", "cooldown_secs": 1800 }
+/// }
+/// ```
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct DaemonConfig {
+    #[serde(default)]
+    pub debounce_ms: Option<u64>,
+    #[serde(default)]
+    pub watch: Vec<WatchEntry>,
+    /// Accounts whose quota to poll and cache
+    #[serde(default)]
+    pub quotas: Vec<QuotaAccount>,
+    /// Provider to switch to when opencode's logs show rate limiting
+    #[serde(default)]
+    pub fallback: Option<Fallback>,
+}
+
+/// One config to keep switched to a provider
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct WatchEntry {
+    /// Config to watch; the default (or `--config`) config when absent
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub config: Option<PathBuf>,
+    /// Provider to keep applied; the one last switched to when absent
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub provider: Option<String>,
+    /// Only re-apply these agents
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub agents: Vec<String>,
+    /// Agents to leave alone
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub except: Vec<String>,
+}
+
+/// A watch entry resolved against the filesystem and checked
+#[derive(Debug, Clone)]
+pub struct Target {
+    pub portal: Portal,
+    pub provider: String,
+    pub options: SwitchOptions,
+    pub except: Vec<String>,
+}
+
+impl DaemonConfig {
+    pub fn debounce_ms(&self) -> u64 {
+        self.debounce_ms.unwrap_or(DEFAULT_DEBOUNCE_MS)
+    }
+
+    /// Fail if a quota account is unusable
+    pub fn check(&self) -> Result<()> {
+        let mut names = BTreeSet::new();
+        for account in &self.quotas {
+            account.check()?;
+            if !names.insert(&account.name) {
+                return Err(anyhow!("quota account '{}' is listed twice", account.name));
+            }
+        }
+        Ok(())
+    }
+
+    /// Resolve every watch entry against `portal`, failing on the first one
+    /// that names a config, provider, or policy that doesn't hold up
+    pub fn targets(&self, portal: &Portal) -> Result<Vec<Target>> {
+        self.watch
+            .iter()
+            .enumerate()
+            .map(|(i, entry)| {
+                entry
+                    .resolve(portal)
+                    .with_context(|| format!("watch entry {}", i + 1))
+            })
+            .collect()
+    }
+
+    /// The fallback resolved against `portal`, if there is one
+    pub fn fallback(&self, portal: &Portal) -> Result<Option<FallbackTarget>> {
+        self.fallback
+            .as_ref()
+            .map(|fallback| fallback.resolve(portal).context("fallback"))
+            .transpose()
+    }
+}
+
+impl WatchEntry {
+    fn resolve(&self, portal: &Portal) -> Result<Target> {
+        let portal = match &self.config {
+            Some(path) => portal.for_config(paths::expand_home(path)),
+            None => portal.clone(),
+        };
+        let provider = match &self.provider {
+            Some(provider) => portal.resolve_provider(provider)?,
+            None => portal.last_provider()?.ok_or_else(|| {
+                anyhow!(
+                    "Portal hasn't switched {} yet; set \"provider\"",
+                    portal.config_path().display()
+                )
+            })?,
+        };
+        // Load everything a re-apply will need now, so mistakes surface at
+        // (re)load time rather than on the next external edit
+        portal.verify_provider(&provider)?;
+        portal.provider_config(&provider)?;
+        portal.mappings()?;
+        Ok(Target {
+            provider,
+            options: SwitchOptions {
+                agents: (!self.agents.is_empty()).then(|| self.agents.clone()),
+                ..Default::default()
+            },
+            except: self.except.clone(),
+            portal,
+        })
+    }
+}
+
+pub fn daemon_file_path(portal_dir: &Path) -> PathBuf {
+    portal_dir.join(DAEMON_FILE)
+}
+
+pub fn read_daemon_config(portal_dir: &Path) -> Result<Option<DaemonConfig>> {
+    let path = daemon_file_path(portal_dir);
+    if !path.exists() {
+        return Ok(None);
+    }
+    let content = fsutil::read_to_string(&path)
+        .with_context(|| format!("Failed to read daemon file: {}", path.display()))?;
+    let config = serde_json::from_str(&jsonc::strip(&content))
+        .map_err(PortalError::parse("daemon file", &path))?;
+    Ok(Some(config))
+}
+
+pub fn pid_file_path(state_dir: &Path) -> PathBuf {
+    state_dir.join(PID_FILE)
+}
+
+/// Process ID recorded by a running daemon, if any
+pub fn read_pid(state_dir: &Path) -> Result<Option<u32>> {
+    let path = pid_file_path(state_dir);
+    match fsutil::read_to_string(&path) {
+        Ok(content) => content
+            .trim()
+            .parse()
+            .map(Some)
+            .with_context(|| format!("Invalid pid file: {}", path.display())),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e).with_context(|| format!("Failed to read pid file: {}", path.display())),
+    }
+}
+
+pub fn write_pid(state_dir: &Path, pid: u32) -> Result<()> {
+    fs::create_dir_all(state_dir)
+        .with_context(|| format!("Failed to create state directory: {}", state_dir.display()))?;
+    let path = pid_file_path(state_dir);
+    fsutil::write_atomic(&path, format!("{}\n", pid).as_bytes())
+        .with_context(|| format!("Failed to write pid file: {}", path.display()))
+}
+
+pub fn remove_pid(state_dir: &Path) -> Result<()> {
+    let path = pid_file_path(state_dir);
+    match fs::remove_file(&path) {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+            Err(e).with_context(|| format!("Failed to remove pid file: {}", path.display()))
+        }
+        _ => Ok(()),
+    }
+}
@@ -0,0 +1,73 @@
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::config::{TOP_LEVEL_MODEL_FIELDS, agent_models, subagent_models, top_level_models};
+
+/// One agent's (or `subagents.<name>` entry's) model before and after an
+/// operation
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ModelChange {
+    pub agent: String,
+    /// `None` if the agent doesn't exist before
+    pub before: Option<String>,
+    /// `None` if the agent doesn't exist after
+    pub after: Option<String>,
+}
+
+impl ModelChange {
+    pub fn is_changed(&self) -> bool {
+        self.before != self.after
+    }
+
+    /// Dotted path to the model field in the config, as
+    /// [`crate::changes::ConfigChange::path`] has it
+    pub fn path(&self) -> String {
+        if self.agent.starts_with("subagents.") {
+            format!("{}.model", self.agent)
+        } else if TOP_LEVEL_MODEL_FIELDS.contains(&self.agent.as_str()) {
+            self.agent.clone()
+        } else {
+            format!("agents.{}.model", self.agent)
+        }
+    }
+}
+
+/// All models in a document keyed the way [`ModelChange::agent`] names them
+fn all_models(document: &Value) -> Vec<(String, String)> {
+    let mut models = top_level_models(document);
+    models.extend(agent_models(document));
+    models.extend(
+        subagent_models(document)
+            .into_iter()
+            .map(|(name, model)| (format!("subagents.{}", name), model)),
+    );
+    models
+}
+
+/// Per-agent model comparison of two config documents, sorted by agent.
+/// Top-level model fields appear under their field name, subagents as
+/// `subagents.<name>`.
+pub fn agent_changes(before: &Value, after: &Value) -> Vec<ModelChange> {
+    let before = all_models(before);
+    let after = all_models(after);
+
+    let mut agents: Vec<&String> = before.iter().chain(after.iter()).map(|(a, _)| a).collect();
+    agents.sort();
+    agents.dedup();
+
+    let lookup = |models: &[(String, String)], agent: &str| {
+        models
+            .iter()
+            .find(|(name, _)| name == agent)
+            .map(|(_, model)| model.clone())
+    };
+
+    agents
+        .into_iter()
+        .map(|agent| ModelChange {
+            agent: agent.clone(),
+            before: lookup(&before, agent),
+            after: lookup(&after, agent),
+        })
+        .collect()
+}
@@ -0,0 +1,90 @@
+use anyhow::{Context, Result, anyhow};
+use std::collections::BTreeMap;
+use std::process::Command;
+
+use crate::config::OhMyOpenCodeConfig;
+use crate::mapping::MappingTable;
+use crate::provider::{AgentModelConfig, BUILTIN_PROVIDERS, ProviderConfig};
+
+/// opencode provider ids that portal already handles as built-ins
+const BUILTIN_PROVIDER_IDS: &[&str] = &["github-copilot", "openrouter"];
+
+/// Run `opencode models` and return its raw `provider/model` listing
+pub fn opencode_models() -> Result<String> {
+    let output = Command::new("opencode")
+        .arg("models")
+        .output()
+        .context("Failed to run `opencode models` (is opencode installed and on PATH?)")?;
+    if !output.status.success() {
+        return Err(anyhow!(
+            "`opencode models` failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// Group a `provider/model` per-line listing by provider
+pub fn parse_model_list(listing: &str) -> BTreeMap<String, Vec<String>> {
+    let mut providers: BTreeMap<String, Vec<String>> = BTreeMap::new();
+    for line in listing.lines().map(str::trim) {
+        if let Some((provider, model)) = line.split_once('/')
+            && !provider.is_empty()
+            && !model.is_empty()
+        {
+            providers
+                .entry(provider.to_string())
+                .or_default()
+                .push(model.to_string());
+        }
+    }
+    providers
+}
+
+pub fn is_builtin_provider(provider: &str) -> bool {
+    BUILTIN_PROVIDER_IDS.contains(&provider)
+        || BUILTIN_PROVIDERS.iter().any(|(n, _)| *n == provider)
+}
+
+/// Lowercase and treat `.` and `-` as equivalent so `claude-opus-4.5`
+/// matches `claude-opus-4-5`
+fn normalize(model: &str) -> String {
+    model.to_lowercase().replace('.', "-")
+}
+
+/// Build a candidate provider file by matching each agent's current base
+/// model (or any of its mapped names) against the provider's model list
+pub fn candidate_provider(
+    config: &OhMyOpenCodeConfig,
+    mappings: &MappingTable,
+    provider: &str,
+    models: &[String],
+) -> ProviderConfig {
+    let mut candidate = ProviderConfig::default();
+
+    for (agent_name, agent) in &config.agents {
+        let base = mappings.base_model(&agent.model);
+        let mut names = vec![normalize(&base)];
+        if let Some(mapping) = mappings.find(&base) {
+            names.push(normalize(&mapping.base));
+            names.push(normalize(&mapping.copilot));
+            names.push(normalize(&mapping.openrouter_model));
+        }
+
+        let found = models.iter().find(|m| {
+            let candidate = normalize(m);
+            let tail = normalize(&mappings.base_model(&format!("{}/{}", provider, m)));
+            names.iter().any(|n| *n == candidate || *n == tail)
+        });
+        if let Some(model) = found {
+            candidate.agents.insert(
+                agent_name.clone(),
+                AgentModelConfig {
+                    model: format!("{}/{}", provider, model),
+                },
+            );
+        }
+    }
+
+    candidate
+}
@@ -0,0 +1,282 @@
+use anyhow::{Context, Result};
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::PathBuf;
+
+use crate::backup;
+use crate::compat::{self, DEFAULT_SCHEMA_URL};
+use crate::config;
+use crate::fsutil;
+use crate::jsonc;
+use crate::mapping;
+use crate::policy;
+use crate::portal::Portal;
+use crate::provider;
+use crate::secrets;
+use crate::settings;
+use crate::storage::Storage;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Warning,
+    Error,
+}
+
+/// Something doctor found, with an automated fix when one is safe
+#[derive(Debug, Clone)]
+pub struct Problem {
+    pub severity: Severity,
+    pub message: String,
+    pub fix: Option<Fix>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Fix {
+    /// Create a missing directory
+    CreateDir(PathBuf),
+    /// Point the config's `$schema` at the oh-my-opencode schema
+    SetSchema,
+    /// Delete a backup that can't be restored
+    RemoveBackup(PathBuf),
+    /// Add a starter row for a base model to mappings.json
+    AddMappingStub(String),
+}
+
+impl Fix {
+    pub fn describe(&self) -> String {
+        match self {
+            Fix::CreateDir(path) => format!("create directory {}", path.display()),
+            Fix::SetSchema => format!("set $schema to {}", DEFAULT_SCHEMA_URL),
+            Fix::RemoveBackup(path) => format!("delete {}", path.display()),
+            Fix::AddMappingStub(base) => {
+                let stub = mapping::mapping_stub(base);
+                format!(
+                    "add '{}' to {} (copilot: {}, openrouter: {})",
+                    base,
+                    mapping::MAPPINGS_FILE,
+                    stub.copilot.unwrap_or_default(),
+                    stub.openrouter.unwrap_or_default()
+                )
+            }
+        }
+    }
+
+    pub fn apply(&self, portal: &Portal) -> Result<()> {
+        match self {
+            Fix::CreateDir(path) => fs::create_dir_all(path)
+                .with_context(|| format!("Failed to create directory: {}", path.display())),
+            Fix::SetSchema => {
+                let mut document = config::read_document(portal.config_path())?;
+                if let Some(object) = document.as_object_mut() {
+                    object.insert(
+                        "$schema".to_string(),
+                        Value::String(DEFAULT_SCHEMA_URL.to_string()),
+                    );
+                }
+                portal.write_document(&document, false)?;
+                Ok(())
+            }
+            Fix::RemoveBackup(path) => fs::remove_file(path)
+                .with_context(|| format!("Failed to delete backup: {}", path.display())),
+            Fix::AddMappingStub(base) => {
+                let mut file =
+                    mapping::read_mappings_file(portal.portal_dir())?.unwrap_or_default();
+                file.mappings
+                    .entry(base.clone())
+                    .or_insert_with(|| mapping::mapping_stub(base));
+                mapping::write_mappings_file(portal.portal_dir(), &file)?;
+                Ok(())
+            }
+        }
+    }
+}
+
+fn problem(severity: Severity, message: String, fix: Option<Fix>) -> Problem {
+    Problem {
+        severity,
+        message,
+        fix,
+    }
+}
+
+/// Check the config, portal directory, provider files, and backups
+pub fn diagnose(portal: &Portal) -> Vec<Problem> {
+    let mut problems = Vec::new();
+
+    if !portal.portal_dir().exists() {
+        problems.push(problem(
+            Severity::Warning,
+            format!(
+                "Portal directory missing: {}",
+                portal.portal_dir().display()
+            ),
+            Some(Fix::CreateDir(portal.portal_dir().to_path_buf())),
+        ));
+    }
+
+    for name in provider::list_custom_providers(portal.portal_dir()).unwrap_or_default() {
+        match portal.provider_config(&name) {
+            Ok(Some(config)) => {
+                let found = secrets::inline_secrets(&config.options);
+                if !found.is_empty() {
+                    problems.push(problem(
+                        Severity::Warning,
+                        format!(
+                            "Provider '{}' has raw secrets in {}; switches to it are refused",
+                            name,
+                            found.join(", ")
+                        ),
+                        None,
+                    ));
+                }
+            }
+            Ok(None) => {}
+            Err(e) => problems.push(problem(
+                Severity::Error,
+                format!("Provider '{}' is invalid: {:#}", name, e),
+                None,
+            )),
+        }
+    }
+    match portal.aliases() {
+        Ok(aliases) => {
+            let custom = provider::list_custom_providers(portal.portal_dir()).unwrap_or_default();
+            for (alias, target) in &aliases {
+                if provider::is_builtin_name(alias) || custom.contains(alias) {
+                    problems.push(problem(
+                        Severity::Warning,
+                        format!("Alias '{}' hides the provider of the same name", alias),
+                        None,
+                    ));
+                }
+                if aliases.contains_key(target) {
+                    problems.push(problem(
+                        Severity::Warning,
+                        format!(
+                            "Alias '{}' points at another alias, '{}'; aliases don't chain",
+                            alias, target
+                        ),
+                        None,
+                    ));
+                }
+            }
+        }
+        Err(e) => problems.push(problem(Severity::Error, format!("{:#}", e), None)),
+    }
+    if settings::read_settings(portal.portal_dir())
+        .ok()
+        .flatten()
+        .is_some_and(|settings| settings.storage == Storage::Sqlite)
+        && std::process::Command::new("sqlite3")
+            .arg("-version")
+            .output()
+            .is_err()
+    {
+        problems.push(problem(
+            Severity::Error,
+            "settings.json keeps history in SQLite, but sqlite3 isn't installed; \
+             events aren't being recorded"
+                .to_string(),
+            None,
+        ));
+    }
+    match policy::read_policy(portal.portal_dir()) {
+        Ok(Some(policy)) => {
+            let pinned = policy.providers.keys().cloned();
+            let custom = provider::list_custom_providers(portal.portal_dir()).unwrap_or_default();
+            let names: BTreeSet<String> = pinned.chain(custom).collect();
+            for name in names {
+                if let Err(e) = policy.verify(portal.portal_dir(), &name) {
+                    let message = format!("{:#}", e);
+                    let summary = message.lines().next().unwrap_or_default().to_string();
+                    problems.push(problem(Severity::Error, summary, None));
+                }
+            }
+        }
+        Ok(None) => {}
+        Err(e) => problems.push(problem(Severity::Error, format!("{:#}", e), None)),
+    }
+    let mappings = match portal.mappings() {
+        Ok(mappings) => Some(mappings),
+        Err(e) => {
+            problems.push(problem(Severity::Error, format!("{:#}", e), None));
+            None
+        }
+    };
+
+    for path in backup::list_backups(&portal.backups()) {
+        let valid = fsutil::read_to_string(&path)
+            .ok()
+            .and_then(|c| jsonc::parse(&c).ok())
+            .is_some_and(|v| v.is_object());
+        if !valid {
+            problems.push(problem(
+                Severity::Warning,
+                format!("Backup is not valid JSON: {}", path.display()),
+                Some(Fix::RemoveBackup(path)),
+            ));
+        }
+    }
+
+    let config_path = portal.config_path();
+    if !config_path.exists() {
+        problems.push(problem(
+            Severity::Error,
+            format!(
+                "Config file not found: {} (is oh-my-opencode configured?)",
+                config_path.display()
+            ),
+            None,
+        ));
+        return problems;
+    }
+    let document = match config::read_document(config_path) {
+        Ok(document) => document,
+        Err(e) => {
+            problems.push(problem(
+                Severity::Error,
+                format!("{:#} (try `portal revert`)", e),
+                None,
+            ));
+            return problems;
+        }
+    };
+
+    if document.get("$schema").is_none() {
+        problems.push(problem(
+            Severity::Warning,
+            "Config has no $schema reference".to_string(),
+            Some(Fix::SetSchema),
+        ));
+    }
+    for reason in compat::assess(&document).reasons {
+        problems.push(problem(
+            Severity::Warning,
+            format!("Config not fully understood: {}", reason),
+            None,
+        ));
+    }
+
+    if let Some(mappings) = mappings {
+        let unmapped: BTreeSet<String> = config::agent_models(&document)
+            .iter()
+            .map(|(_, model)| mappings.base_model(model))
+            .filter(|base| mappings.find(base).is_none())
+            .collect();
+        for base in unmapped {
+            problems.push(problem(
+                Severity::Warning,
+                format!(
+                    "Model '{}' has no mapping; switches will guess its name",
+                    base
+                ),
+                Some(Fix::AddMappingStub(base)),
+            ));
+        }
+    }
+
+    problems
+}
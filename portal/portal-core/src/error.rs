@@ -0,0 +1,112 @@
+//! Failures callers can tell apart. Functions keep returning
+//! `anyhow::Result`; where one of these is the cause, it can be matched
+//! with `error.downcast_ref::<PortalError>()`, whatever context was added
+//! on the way up, e.g. to offer `portal init` on
+//! [`PortalError::ConfigNotFound`] but report anything else as it is.
+//!
+//! The messages are the ones portal has always printed, and stay that way.
+
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum PortalError {
+    /// The config file doesn't exist
+    ConfigNotFound(PathBuf),
+    /// A file isn't valid JSON (or JSONC), or doesn't have the shape its
+    /// kind of file needs; `what` names the kind, e.g. "provider config"
+    Parse {
+        what: &'static str,
+        path: PathBuf,
+        source: serde_json::Error,
+    },
+    /// A provider that needs a provider file has none
+    UnknownProvider {
+        name: String,
+        portal_dir: PathBuf,
+    },
+    /// The mapping table has no model for `model` on `provider`
+    MappingMissing {
+        model: String,
+        provider: String,
+    },
+    /// Copying the config to a backup failed
+    Backup {
+        path: PathBuf,
+        source: io::Error,
+    },
+    /// The config changed on disk while portal was working on it, so
+    /// nothing was written
+    ConfigChanged(PathBuf),
+    Io(io::Error),
+    Json(serde_json::Error),
+}
+
+impl PortalError {
+    /// For `map_err`: a [`PortalError::Parse`] of `path`
+    pub fn parse(what: &'static str, path: &Path) -> impl FnOnce(serde_json::Error) -> Self {
+        let path = path.to_path_buf();
+        move |source| Self::Parse { what, path, source }
+    }
+}
+
+impl fmt::Display for PortalError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::ConfigNotFound(path) => write!(
+                f,
+                "Config file not found: {}\n\nMake sure oh-my-opencode is configured.",
+                path.display()
+            ),
+            Self::Parse { what, path, .. } => {
+                write!(f, "Failed to parse {}: {}", what, path.display())
+            }
+            Self::UnknownProvider { name, portal_dir } => write!(
+                f,
+                "Provider '{}' has no file in {}",
+                name,
+                portal_dir.display()
+            ),
+            Self::MappingMissing { model, provider } => {
+                write!(f, "No mapping for '{}' on provider '{}'", model, provider)
+            }
+            Self::Backup { path, .. } => {
+                write!(f, "Failed to create backup at: {}", path.display())
+            }
+            Self::ConfigChanged(path) => write!(
+                f,
+                "Config file changed while portal was working: {}\n\n\
+                 Nothing was written. Check what modified it, then run the command again.",
+                path.display()
+            ),
+            Self::Io(e) => e.fmt(f),
+            Self::Json(e) => e.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for PortalError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Parse { source, .. } => Some(source),
+            Self::Backup { source, .. } => Some(source),
+            Self::Io(e) => e.source(),
+            Self::Json(e) => e.source(),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for PortalError {
+    fn from(error: io::Error) -> Self {
+        Self::Io(error)
+    }
+}
+
+impl From<serde_json::Error> for PortalError {
+    fn from(error: serde_json::Error) -> Self {
+        Self::Json(error)
+    }
+}
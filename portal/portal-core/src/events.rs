@@ -0,0 +1,252 @@
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufRead, BufReader, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::Duration;
+
+use crate::fsutil;
+
+/// Event log file name inside the state directory
+pub const EVENTS_FILE: &str = "events.jsonl";
+
+/// How often `follow` checks the log for new lines
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// One line of the event log
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Event {
+    pub timestamp: DateTime<Utc>,
+    #[serde(flatten)]
+    pub kind: EventKind,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum EventKind {
+    Switch {
+        config: PathBuf,
+        provider: String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        previous_provider: Option<String>,
+        /// Agents whose model changed
+        agents: Vec<String>,
+    },
+    Revert {
+        config: PathBuf,
+        backup: PathBuf,
+    },
+    /// Models set directly rather than by switching provider
+    Edit {
+        config: PathBuf,
+        /// Agents whose model changed
+        agents: Vec<String>,
+    },
+    /// A journaled write was stepped back or reapplied
+    Undo {
+        config: PathBuf,
+        action: String,
+    },
+    Redo {
+        config: PathBuf,
+        action: String,
+    },
+    /// A temporary switch ran out and the config was put back
+    Expire {
+        config: PathBuf,
+        provider: String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        restored_provider: Option<String>,
+    },
+}
+
+impl EventKind {
+    /// Config the event happened to
+    pub fn config(&self) -> &Path {
+        match self {
+            EventKind::Switch { config, .. }
+            | EventKind::Revert { config, .. }
+            | EventKind::Edit { config, .. }
+            | EventKind::Undo { config, .. }
+            | EventKind::Redo { config, .. }
+            | EventKind::Expire { config, .. } => config,
+        }
+    }
+
+    /// What happened in a few words, e.g. `switch to openrouter`
+    pub fn summary(&self) -> String {
+        match self {
+            EventKind::Switch { provider, .. } => format!("switch to {}", provider),
+            EventKind::Revert { backup, .. } => format!(
+                "revert to {}",
+                backup.file_name().unwrap_or_default().to_string_lossy()
+            ),
+            EventKind::Edit { agents, .. } => format!("set {}", agents.join(", ")),
+            EventKind::Undo { action, .. } => format!("undo {}", action),
+            EventKind::Redo { action, .. } => format!("redo {}", action),
+            EventKind::Expire { provider, .. } => {
+                format!("end temporary switch to {}", provider)
+            }
+        }
+    }
+}
+
+impl EventKind {
+    /// Call provider `from` `to` wherever the event names it; whether it did
+    pub fn rename_provider(&mut self, from: &str, to: &str) -> bool {
+        let names: Vec<&mut String> = match self {
+            EventKind::Switch {
+                provider,
+                previous_provider,
+                ..
+            } => std::iter::once(provider)
+                .chain(previous_provider.as_mut())
+                .collect(),
+            EventKind::Expire {
+                provider,
+                restored_provider,
+                ..
+            } => std::iter::once(provider)
+                .chain(restored_provider.as_mut())
+                .collect(),
+            _ => Vec::new(),
+        };
+        let mut renamed = false;
+        for name in names {
+            if name == from {
+                *name = to.to_string();
+                renamed = true;
+            }
+        }
+        renamed
+    }
+}
+
+impl Event {
+    pub fn now(kind: EventKind) -> Self {
+        Self {
+            timestamp: Utc::now(),
+            kind,
+        }
+    }
+}
+
+/// Append-only, line-delimited JSON log of portal's actions
+#[derive(Debug, Clone)]
+pub struct EventLog {
+    path: PathBuf,
+}
+
+impl EventLog {
+    pub fn new(state_dir: &Path) -> Self {
+        Self {
+            path: state_dir.join(EVENTS_FILE),
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn append(&self, event: &Event) -> Result<()> {
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent).with_context(|| {
+                format!("Failed to create state directory: {}", parent.display())
+            })?;
+        }
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .with_context(|| format!("Failed to open event log: {}", self.path.display()))?;
+        writeln!(file, "{}", serde_json::to_string(event)?)
+            .with_context(|| format!("Failed to write event log: {}", self.path.display()))?;
+        crate::portal_debug!("Appended an event to {}", self.path.display());
+        Ok(())
+    }
+
+    /// All recorded events, skipping lines that don't parse
+    pub fn read_all(&self) -> Result<Vec<Event>> {
+        if !self.path.exists() {
+            return Ok(Vec::new());
+        }
+        let file = File::open(&self.path)
+            .with_context(|| format!("Failed to open event log: {}", self.path.display()))?;
+        Ok(BufReader::new(file)
+            .lines()
+            .map_while(|line| line.ok())
+            .filter_map(|line| serde_json::from_str(&line).ok())
+            .collect())
+    }
+
+    /// Rewrite the events naming provider `from` to name `to` instead,
+    /// returning how many there were. Lines that don't parse are kept as
+    /// they are.
+    pub fn rename_provider(&self, from: &str, to: &str) -> Result<usize> {
+        let text = match fsutil::read_to_string(&self.path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(0),
+            Err(e) => {
+                return Err(e)
+                    .with_context(|| format!("Failed to read event log: {}", self.path.display()));
+            }
+        };
+        let mut renamed = 0;
+        let mut content = String::with_capacity(text.len());
+        for line in text.lines() {
+            let event = serde_json::from_str::<Event>(line)
+                .ok()
+                .and_then(|mut event| event.kind.rename_provider(from, to).then_some(event));
+            match event {
+                Some(event) => {
+                    content.push_str(&serde_json::to_string(&event)?);
+                    renamed += 1;
+                }
+                None => content.push_str(line),
+            }
+            content.push('\n');
+        }
+        if renamed > 0 {
+            fsutil::write_atomic(&self.path, content.as_bytes())
+                .with_context(|| format!("Failed to write event log: {}", self.path.display()))?;
+        }
+        Ok(renamed)
+    }
+
+    /// Call `on_line` with every raw log line, then keep waiting for new ones.
+    /// Starts over if the log is truncated or replaced. Only returns on error.
+    pub fn follow(&self, mut on_line: impl FnMut(&str) -> Result<()>) -> Result<()> {
+        let mut offset = 0u64;
+        let mut pending = String::new();
+
+        loop {
+            let len = fs::metadata(&self.path).map(|m| m.len()).unwrap_or(0);
+            if len < offset {
+                offset = 0;
+                pending.clear();
+            }
+            if len > offset {
+                let mut file = File::open(&self.path).with_context(|| {
+                    format!("Failed to open event log: {}", self.path.display())
+                })?;
+                file.seek(SeekFrom::Start(offset))?;
+                let mut reader = BufReader::new(file);
+                loop {
+                    let read = reader.read_line(&mut pending)?;
+                    if read == 0 {
+                        break;
+                    }
+                    offset += read as u64;
+                    // Hold back a partially written last line until it's complete
+                    if pending.ends_with('\n') {
+                        on_line(pending.trim_end())?;
+                        pending.clear();
+                    }
+                }
+            }
+            thread::sleep(POLL_INTERVAL);
+        }
+    }
+}
@@ -0,0 +1,137 @@
+//! Switching several configs as one, e.g. the same setup on machines
+//! mounted over SSHFS or under other user accounts: either every config
+//! ends up on the provider or none does. Each config is previewed before
+//! anything is written, so most problems stop the switch before it
+//! starts; if a write fails anyway, the configs already switched are put
+//! back as they were.
+
+use anyhow::Result;
+use std::path::PathBuf;
+
+use crate::portal::{Portal, Snapshot, SwitchOptions, SwitchOutcome};
+use crate::temporary::{self, Expiry};
+
+/// What became of one config in a fleet switch
+#[derive(Debug)]
+pub enum FleetState {
+    /// Switched, and it stays switched
+    Switched(SwitchOutcome),
+    /// Switched, then put back because another config failed
+    RolledBack(SwitchOutcome),
+    /// Switched, but putting it back failed as well, so it is still
+    /// switched
+    RollbackFailed(SwitchOutcome, anyhow::Error),
+    /// The config that failed
+    Failed(anyhow::Error),
+    /// Not tried, because another config failed first
+    Skipped,
+}
+
+/// One config of a fleet switch and what became of it
+#[derive(Debug)]
+pub struct FleetResult {
+    pub config: PathBuf,
+    pub state: FleetState,
+}
+
+/// Every config of a fleet switch, in the order given
+#[derive(Debug)]
+pub struct FleetOutcome {
+    pub results: Vec<FleetResult>,
+}
+
+impl FleetOutcome {
+    /// Whether every config was switched and kept
+    pub fn succeeded(&self) -> bool {
+        self.results
+            .iter()
+            .all(|result| matches!(result.state, FleetState::Switched(_)))
+    }
+
+    /// The config that failed, and why
+    pub fn failure(&self) -> Option<(&PathBuf, &anyhow::Error)> {
+        self.results.iter().find_map(|result| match &result.state {
+            FleetState::Failed(error) => Some((&result.config, error)),
+            _ => None,
+        })
+    }
+}
+
+/// Switch every one of `portals` to `provider`, all or nothing; until
+/// `expiry` if given
+pub fn switch_all(
+    portals: &[Portal],
+    provider: &str,
+    options: &SwitchOptions,
+    expiry: Option<&Expiry>,
+) -> FleetOutcome {
+    let mut results: Vec<FleetResult> = portals
+        .iter()
+        .map(|portal| FleetResult {
+            config: portal.config_path().to_path_buf(),
+            state: FleetState::Skipped,
+        })
+        .collect();
+
+    // A config that can't be switched stops them all before any is written
+    for (portal, result) in portals.iter().zip(&mut results) {
+        if let Err(error) = portal.preview_switch(provider, options) {
+            result.state = FleetState::Failed(error);
+            return FleetOutcome { results };
+        }
+    }
+
+    let action = format!("switch to {}", provider);
+    // Each config switched so far, by index
+    let mut written: Vec<(usize, Snapshot, bool, SwitchOutcome)> = Vec::new();
+    for (index, portal) in portals.iter().enumerate() {
+        match switch_one(portal, provider, options, expiry) {
+            Ok((before, was_temporary, outcome)) => {
+                written.push((index, before, was_temporary, outcome));
+            }
+            Err(error) => {
+                results[index].state = FleetState::Failed(error);
+                break;
+            }
+        }
+    }
+    if written.len() == portals.len() {
+        for (index, _, _, outcome) in written {
+            results[index].state = FleetState::Switched(outcome);
+        }
+        return FleetOutcome { results };
+    }
+
+    for (index, before, was_temporary, outcome) in written.into_iter().rev() {
+        let portal = &portals[index];
+        let rolled_back = portal.roll_back(&before, &action).and_then(|()| {
+            // A temporary switch this one started goes with it
+            if expiry.is_some() && !was_temporary {
+                temporary::remove_record(&portal.temporary_path())?;
+            }
+            Ok(())
+        });
+        results[index].state = match rolled_back {
+            Ok(()) => FleetState::RolledBack(outcome),
+            Err(error) => FleetState::RollbackFailed(outcome, error),
+        };
+    }
+    FleetOutcome { results }
+}
+
+/// Switch one config, along with what it held before and whether a
+/// temporary switch was already running on it
+fn switch_one(
+    portal: &Portal,
+    provider: &str,
+    options: &SwitchOptions,
+    expiry: Option<&Expiry>,
+) -> Result<(Snapshot, bool, SwitchOutcome)> {
+    let before = portal.snapshot()?;
+    let was_temporary = portal.temporary_switch()?.is_some();
+    let outcome = match expiry {
+        Some(expiry) => portal.switch_temporarily(provider, options, expiry),
+        None => portal.switch(provider, options),
+    }?;
+    Ok((before, was_temporary, outcome))
+}
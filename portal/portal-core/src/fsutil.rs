@@ -0,0 +1,112 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+/// Temporary sibling of `path` that a write is staged in
+fn temp_path(path: &Path) -> PathBuf {
+    let name = path
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_default();
+    path.with_file_name(format!(".{}.tmp.{}", name, std::process::id()))
+}
+
+/// Replace `path` with `contents` so readers see either the old file or the
+/// new one, never a partial write: stage in a temp file in the same
+/// directory, fsync it, then rename it into place.
+pub fn write_atomic(path: &Path, contents: &[u8]) -> io::Result<()> {
+    let temp = temp_path(path);
+    let result = (|| {
+        let mut file = OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(&temp)?;
+        // Keep the original's permissions (the config may hold API keys)
+        if let Ok(metadata) = fs::metadata(path) {
+            file.set_permissions(metadata.permissions())?;
+        }
+        file.write_all(contents)?;
+        file.sync_all()?;
+        fs::rename(&temp, path)
+    })();
+    if result.is_err() {
+        let _ = fs::remove_file(&temp);
+    }
+    if let Err(e) = &result {
+        crate::portal_debug!("Couldn't write {}: {}", path.display(), e);
+    }
+    result?;
+    sync_parent(path);
+    crate::portal_debug!("Wrote {} ({} bytes)", path.display(), contents.len());
+    Ok(())
+}
+
+/// Make `link` a symlink to `target`, replacing whatever was at `link` in
+/// one step: readers see the old file or the new link, never neither
+pub fn symlink_atomic(link: &Path, target: &Path) -> io::Result<()> {
+    #[cfg(unix)]
+    {
+        let temp = temp_path(link);
+        let _ = fs::remove_file(&temp);
+        let result =
+            std::os::unix::fs::symlink(target, &temp).and_then(|()| fs::rename(&temp, link));
+        if result.is_err() {
+            let _ = fs::remove_file(&temp);
+        }
+        result?;
+        sync_parent(link);
+        crate::portal_debug!("Linked {} to {}", link.display(), target.display());
+        Ok(())
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = (link, target);
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "symlinks can only be switched on Unix",
+        ))
+    }
+}
+
+/// [`fs::read_to_string`], logged
+pub fn read_to_string(path: &Path) -> io::Result<String> {
+    let result = fs::read_to_string(path);
+    log_read(path, result.as_ref().map(String::len));
+    result
+}
+
+/// [`fs::read`], logged
+pub fn read(path: &Path) -> io::Result<Vec<u8>> {
+    let result = fs::read(path);
+    log_read(path, result.as_ref().map(Vec::len));
+    result
+}
+
+fn log_read(path: &Path, result: Result<usize, &io::Error>) {
+    match result {
+        Ok(len) => crate::portal_debug!("Read {} ({} bytes)", path.display(), len),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            crate::portal_debug!("Read {}: not found", path.display())
+        }
+        Err(e) => crate::portal_debug!("Couldn't read {}: {}", path.display(), e),
+    }
+}
+
+/// Flush the directory entry for a rename. Best-effort: not every platform
+/// or filesystem lets a directory be opened and synced.
+fn sync_parent(path: &Path) {
+    #[cfg(unix)]
+    if let Some(parent) = path.parent() {
+        let parent = if parent.as_os_str().is_empty() {
+            Path::new(".")
+        } else {
+            parent
+        };
+        if let Ok(dir) = File::open(parent) {
+            let _ = dir.sync_all();
+        }
+    }
+    #[cfg(not(unix))]
+    let _ = path;
+}
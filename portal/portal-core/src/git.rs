@@ -0,0 +1,57 @@
+//! Committing config writes to the git repository the config lives in,
+//! for configs kept in a dotfiles repo
+
+use anyhow::{Context, Result, anyhow};
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+
+pub(crate) fn git(dir: &Path, args: &[&str]) -> Result<std::process::Output> {
+    Command::new("git")
+        .arg("-C")
+        .arg(dir)
+        .args(args)
+        .output()
+        .context("Failed to run git")
+}
+
+/// Stage `path` and commit it alone with `message`, leaving anything else
+/// already staged out of the commit. A symlinked config is committed where
+/// it really lives. Returns `false` if there was nothing to commit.
+pub fn commit_file(path: &Path, message: &str) -> Result<bool> {
+    let path =
+        fs::canonicalize(path).with_context(|| format!("Failed to resolve {}", path.display()))?;
+    let dir = path.parent().unwrap_or(Path::new("."));
+    let file = path.to_string_lossy();
+
+    if !git(dir, &["rev-parse", "--is-inside-work-tree"])?
+        .status
+        .success()
+    {
+        return Err(anyhow!("{} is not in a git repository", path.display()));
+    }
+    let added = git(dir, &["add", "--", &file])?;
+    if !added.status.success() {
+        return Err(anyhow!(
+            "git add failed: {}",
+            String::from_utf8_lossy(&added.stderr).trim()
+        ));
+    }
+    if git(dir, &["diff", "--cached", "--quiet", "--", &file])?
+        .status
+        .success()
+    {
+        return Ok(false);
+    }
+    let committed = git(
+        dir,
+        &["commit", "--quiet", "-m", message, "--only", "--", &file],
+    )?;
+    if !committed.status.success() {
+        return Err(anyhow!(
+            "git commit failed: {}",
+            String::from_utf8_lossy(&committed.stderr).trim()
+        ));
+    }
+    Ok(true)
+}
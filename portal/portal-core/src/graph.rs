@@ -0,0 +1,383 @@
+//! How each agent's model is routed to each provider: agent, then base
+//! model, then the model a switch would write, as data or as a Graphviz or
+//! Mermaid diagram.
+
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::config;
+use crate::mapping::MappingTable;
+use crate::provider::ProviderConfig;
+use crate::switch::{self, Resolution, SwitchContext};
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Graph {
+    pub agents: Vec<AgentNode>,
+    pub routes: Vec<Route>,
+}
+
+/// An agent (or `subagents.<name>`) and the model it has now
+#[derive(Debug, Clone, Serialize)]
+pub struct AgentNode {
+    pub name: String,
+    pub model: String,
+    /// Base model the mapping table sees in `model`
+    pub base: String,
+}
+
+/// Where one agent goes on one provider
+#[derive(Debug, Clone, Serialize)]
+pub struct Route {
+    pub agent: String,
+    pub provider: String,
+    /// `None` if nothing maps the agent, so a switch leaves it alone
+    pub model: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub resolution: Option<Resolution>,
+}
+
+/// Route every agent in `document` through each of `providers`, with its
+/// provider file if it has one
+pub fn build(
+    document: &Value,
+    providers: &[(String, Option<ProviderConfig>)],
+    mappings: &MappingTable,
+) -> Graph {
+    let agents: Vec<(String, String, String)> = config::agent_models(document)
+        .into_iter()
+        .map(|(name, model)| (name.clone(), name, model))
+        .chain(
+            config::subagent_models(document)
+                .into_iter()
+                .map(|(name, model)| (format!("subagents.{}", name), name, model)),
+        )
+        .collect();
+
+    let mut graph = Graph {
+        agents: Vec::new(),
+        routes: Vec::new(),
+    };
+    for (label, name, model) in &agents {
+        graph.agents.push(AgentNode {
+            name: label.clone(),
+            model: model.clone(),
+            base: switch::canonical_base(model, mappings),
+        });
+        for (provider, provider_config) in providers {
+            let ctx = SwitchContext {
+                provider_config: provider_config.as_ref(),
+                ..SwitchContext::new(provider, mappings)
+            };
+            let resolved = switch::explain_model(name, model, &ctx);
+            graph.routes.push(Route {
+                agent: label.clone(),
+                provider: provider.clone(),
+                model: resolved.as_ref().map(|(model, _)| model.clone()),
+                resolution: resolved.map(|(_, resolution)| resolution),
+            });
+        }
+    }
+    graph
+}
+
+/// Graph nodes named `a<n>` (agents), `b<n>` (base models), `m<n>`
+/// (target models), and `unmapped`, with edges deduplicated
+struct Layout {
+    agents: Vec<String>,
+    bases: Vec<String>,
+    models: Vec<String>,
+    unmapped: bool,
+    edges: BTreeSet<Edge>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+struct Edge {
+    from: String,
+    to: String,
+    label: Option<String>,
+    style: EdgeStyle,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum EdgeStyle {
+    /// Agent to base model, and mapped routes
+    Solid,
+    /// Routes the provider file pins, straight from the agent
+    Bold,
+    Guessed,
+    Unmapped,
+}
+
+fn index_of(names: &mut Vec<String>, name: &str) -> usize {
+    match names.iter().position(|n| n == name) {
+        Some(i) => i,
+        None => {
+            names.push(name.to_string());
+            names.len() - 1
+        }
+    }
+}
+
+impl Layout {
+    fn new(graph: &Graph) -> Self {
+        let mut layout = Self {
+            agents: Vec::new(),
+            bases: Vec::new(),
+            models: Vec::new(),
+            unmapped: false,
+            edges: BTreeSet::new(),
+        };
+        let mut agent_ids = BTreeMap::new();
+        let mut base_ids = BTreeMap::new();
+        for agent in &graph.agents {
+            let a = format!("a{}", index_of(&mut layout.agents, &agent.name));
+            let b = format!("b{}", index_of(&mut layout.bases, &agent.base));
+            layout.edges.insert(Edge {
+                from: a.clone(),
+                to: b.clone(),
+                label: None,
+                style: EdgeStyle::Solid,
+            });
+            agent_ids.insert(agent.name.as_str(), a);
+            base_ids.insert(agent.name.as_str(), b);
+        }
+        for route in &graph.routes {
+            let (Some(a), Some(b)) = (
+                agent_ids.get(route.agent.as_str()),
+                base_ids.get(route.agent.as_str()),
+            ) else {
+                continue;
+            };
+            let edge = match (&route.model, route.resolution) {
+                (Some(model), Some(resolution)) => {
+                    let to = format!("m{}", index_of(&mut layout.models, model));
+                    match resolution {
+                        Resolution::ProviderFile => Edge {
+                            from: a.clone(),
+                            to,
+                            label: Some(format!("{} (provider file)", route.provider)),
+                            style: EdgeStyle::Bold,
+                        },
+                        Resolution::Rule => Edge {
+                            from: b.clone(),
+                            to,
+                            label: Some(format!("{} (rule)", route.provider)),
+                            style: EdgeStyle::Solid,
+                        },
+                        Resolution::Mapped => Edge {
+                            from: b.clone(),
+                            to,
+                            label: Some(route.provider.clone()),
+                            style: EdgeStyle::Solid,
+                        },
+                        Resolution::Guessed => Edge {
+                            from: b.clone(),
+                            to,
+                            label: Some(format!("{} (guessed)", route.provider)),
+                            style: EdgeStyle::Guessed,
+                        },
+                    }
+                }
+                _ => {
+                    layout.unmapped = true;
+                    Edge {
+                        from: b.clone(),
+                        to: "unmapped".to_string(),
+                        label: Some(route.provider.clone()),
+                        style: EdgeStyle::Unmapped,
+                    }
+                }
+            };
+            layout.edges.insert(edge);
+        }
+        layout
+    }
+}
+
+fn dot_quote(text: &str) -> String {
+    format!("\"{}\"", text.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+/// Graphviz source: `portal graph --format dot | dot -Tsvg > routing.svg`
+pub fn render_dot(graph: &Graph) -> String {
+    let layout = Layout::new(graph);
+    let mut out = String::from("digraph portal {\n  rankdir=LR;\n  node [shape=box];\n\n");
+    let column = |out: &mut String, prefix: &str, names: &[String], shape: &str| {
+        if names.is_empty() {
+            return;
+        }
+        for (i, name) in names.iter().enumerate() {
+            out.push_str(&format!(
+                "  {}{} [label={}, shape={}];\n",
+                prefix,
+                i,
+                dot_quote(name),
+                shape
+            ));
+        }
+        let ids: Vec<String> = (0..names.len())
+            .map(|i| format!("{}{}", prefix, i))
+            .collect();
+        out.push_str(&format!("  {{ rank=same; {}; }}\n\n", ids.join("; ")));
+    };
+    column(&mut out, "a", &layout.agents, "box");
+    column(&mut out, "b", &layout.bases, "ellipse");
+    column(&mut out, "m", &layout.models, "box");
+    if layout.unmapped {
+        out.push_str("  unmapped [label=\"no mapping (left as is)\", shape=note];\n\n");
+    }
+    for edge in &layout.edges {
+        let mut attrs = Vec::new();
+        if let Some(label) = &edge.label {
+            attrs.push(format!("label={}", dot_quote(label)));
+        }
+        match edge.style {
+            EdgeStyle::Solid => {}
+            EdgeStyle::Bold => attrs.push("style=bold".to_string()),
+            EdgeStyle::Guessed => attrs.push("style=dashed".to_string()),
+            EdgeStyle::Unmapped => attrs.push("style=dotted".to_string()),
+        }
+        out.push_str(&format!("  {} -> {}", edge.from, edge.to));
+        if !attrs.is_empty() {
+            out.push_str(&format!(" [{}]", attrs.join(", ")));
+        }
+        out.push_str(";\n");
+    }
+    out.push_str("}\n");
+    out
+}
+
+/// Mermaid's entity for `"`, the one character its quoted labels can't hold
+fn mermaid_quote(text: &str) -> String {
+    format!("\"{}\"", text.replace('"', "#quot;"))
+}
+
+/// Mermaid edge labels can't contain `|`
+fn mermaid_label(text: &str) -> String {
+    text.replace('|', "/")
+}
+
+/// Mermaid flowchart, for Markdown files and wikis that render it
+pub fn render_mermaid(graph: &Graph) -> String {
+    let layout = Layout::new(graph);
+    let mut out = String::from("flowchart LR\n");
+    for (i, name) in layout.agents.iter().enumerate() {
+        out.push_str(&format!("  a{}[{}]\n", i, mermaid_quote(name)));
+    }
+    for (i, name) in layout.bases.iter().enumerate() {
+        out.push_str(&format!("  b{}([{}])\n", i, mermaid_quote(name)));
+    }
+    for (i, name) in layout.models.iter().enumerate() {
+        out.push_str(&format!("  m{}[{}]\n", i, mermaid_quote(name)));
+    }
+    if layout.unmapped {
+        out.push_str("  unmapped>\"no mapping (left as is)\"]\n");
+    }
+    for edge in &layout.edges {
+        let arrow = match edge.style {
+            EdgeStyle::Solid => "-->",
+            EdgeStyle::Bold => "==>",
+            EdgeStyle::Guessed | EdgeStyle::Unmapped => "-.->",
+        };
+        match &edge.label {
+            Some(label) => out.push_str(&format!(
+                "  {} {}|{}| {}\n",
+                edge.from,
+                arrow,
+                mermaid_label(label),
+                edge.to
+            )),
+            None => out.push_str(&format!("  {} {} {}\n", edge.from, arrow, edge.to)),
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::provider::AgentModelConfig;
+    use serde_json::json;
+
+    fn sample() -> Graph {
+        let document = json!({
+            "agents": {
+                "oracle": { "model": "github-copilot/claude-opus-4.5" },
+                "explore": { "model": "github-copilot/mystery-1" },
+            },
+        });
+        let gateway = ProviderConfig {
+            agents: BTreeMap::from([(
+                "oracle".to_string(),
+                AgentModelConfig {
+                    model: "gw/opus".to_string(),
+                },
+            )]),
+            ..ProviderConfig::default()
+        };
+        let providers = [
+            ("copilot".to_string(), None),
+            ("gateway".to_string(), Some(gateway)),
+        ];
+        build(&document, &providers, &MappingTable::builtin())
+    }
+
+    fn route<'a>(graph: &'a Graph, agent: &str, provider: &str) -> &'a Route {
+        graph
+            .routes
+            .iter()
+            .find(|route| route.agent == agent && route.provider == provider)
+            .unwrap()
+    }
+
+    #[test]
+    fn routes_every_agent_through_every_provider() {
+        let graph = sample();
+        assert_eq!(graph.agents.len(), 2);
+        assert_eq!(graph.routes.len(), 4);
+
+        let oracle = route(&graph, "oracle", "copilot");
+        assert_eq!(
+            oracle.model.as_deref(),
+            Some("github-copilot/claude-opus-4.5")
+        );
+        assert_eq!(oracle.resolution, Some(Resolution::Mapped));
+        let pinned = route(&graph, "oracle", "gateway");
+        assert_eq!(pinned.model.as_deref(), Some("gw/opus"));
+        assert_eq!(pinned.resolution, Some(Resolution::ProviderFile));
+        assert_eq!(
+            route(&graph, "explore", "copilot").resolution,
+            Some(Resolution::Guessed)
+        );
+        let unmapped = route(&graph, "explore", "gateway");
+        assert_eq!(unmapped.model, None);
+        assert_eq!(unmapped.resolution, None);
+    }
+
+    #[test]
+    fn dot_styles_each_resolution() {
+        let dot = render_dot(&sample());
+        assert!(dot.starts_with("digraph portal {\n"));
+        assert!(dot.contains("[label=\"gateway (provider file)\", style=bold];"));
+        assert!(dot.contains("[label=\"copilot (guessed)\", style=dashed];"));
+        assert!(dot.contains(" -> unmapped [label=\"gateway\", style=dotted];"));
+        assert!(dot.contains("unmapped [label=\"no mapping (left as is)\", shape=note];"));
+    }
+
+    #[test]
+    fn mermaid_arrows_follow_the_resolution() {
+        let mermaid = render_mermaid(&sample());
+        assert!(mermaid.starts_with("flowchart LR\n"));
+        assert!(mermaid.contains(" ==>|gateway (provider file)| m"));
+        assert!(mermaid.contains(" -.->|copilot (guessed)| m"));
+        assert!(mermaid.contains(" -.->|gateway| unmapped\n"));
+    }
+
+    #[test]
+    fn labels_are_quoted_for_each_format() {
+        assert_eq!(dot_quote(r#"a "b" \c"#), r#""a \"b\" \\c""#);
+        assert_eq!(mermaid_quote(r#"a "b""#), "\"a #quot;b#quot;\"");
+        assert_eq!(mermaid_label("a|b"), "a/b");
+    }
+}
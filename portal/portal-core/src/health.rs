@@ -0,0 +1,199 @@
+//! Checking that a provider's endpoint answers before switching to it:
+//! OpenRouter's key endpoint, GitHub's Copilot token endpoint, or the
+//! `baseURL` in a custom provider's options, probed with whatever key
+//! portal can find for it.
+
+use anyhow::{Result, anyhow};
+use serde::Serialize;
+use serde_json::Value;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use crate::fsutil;
+use crate::http;
+use crate::keychain;
+use crate::provider::ProviderOptions;
+use crate::quota::OPENROUTER_KEY_URL;
+
+/// Endpoint that hands out Copilot API tokens for a GitHub OAuth token
+pub const COPILOT_TOKEN_URL: &str = "https://api.github.com/copilot_internal/v2/token";
+
+const OPENROUTER_KEY_ENV: &str = "OPENROUTER_API_KEY";
+const GITHUB_TOKEN_ENVS: &[&str] = &["GITHUB_TOKEN", "GH_TOKEN"];
+
+/// Whether the endpoint took the key
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Auth {
+    /// The key was accepted
+    Ok,
+    /// The endpoint refused the key (401 or 403)
+    Rejected,
+    /// No key was found to send
+    Missing,
+    /// The response doesn't say either way
+    Unknown,
+}
+
+impl Auth {
+    pub fn describe(self) -> &'static str {
+        match self {
+            Auth::Ok => "ok",
+            Auth::Rejected => "rejected",
+            Auth::Missing => "no key found",
+            Auth::Unknown => "not confirmed",
+        }
+    }
+}
+
+/// What probing a provider's endpoint found
+#[derive(Debug, Clone, Serialize)]
+pub struct Health {
+    pub provider: String,
+    pub url: String,
+    /// Where the key came from, e.g. `OPENROUTER_API_KEY`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub key_source: Option<String>,
+    pub reachable: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub status: Option<u16>,
+    #[serde(
+        rename = "latency_ms",
+        serialize_with = "serialize_millis",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub latency: Option<Duration>,
+    pub auth: Auth,
+    /// Why the endpoint couldn't be reached
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+fn serialize_millis<S: serde::Serializer>(
+    latency: &Option<Duration>,
+    serializer: S,
+) -> std::result::Result<S::Ok, S::Error> {
+    match latency {
+        Some(latency) => serializer.serialize_u64(latency.as_millis() as u64),
+        None => serializer.serialize_none(),
+    }
+}
+
+impl Health {
+    /// Whether a switch to the provider looks like it will work
+    pub fn is_healthy(&self) -> bool {
+        self.reachable && !matches!(self.auth, Auth::Rejected)
+    }
+}
+
+/// Probe `provider`'s endpoint. `options` are its client options, if it
+/// has a provider file; a `baseURL` there takes precedence over the
+/// built-in endpoints.
+pub fn check(provider: &str, options: Option<&ProviderOptions>) -> Result<Health> {
+    let option = |name: &str| {
+        options
+            .and_then(|options| options.extra.get(name))
+            .and_then(Value::as_str)
+            .map(str::to_string)
+    };
+    let option_key = match option("apiKey") {
+        Some(key) => Some((resolve(&key)?, "options.apiKey".to_string())),
+        None => None,
+    };
+
+    let (url, key) = match (option("baseURL"), provider) {
+        (Some(base), _) => {
+            let base = resolve(&base)?;
+            (format!("{}/models", base.trim_end_matches('/')), option_key)
+        }
+        (None, "openrouter") => (
+            OPENROUTER_KEY_URL.to_string(),
+            option_key.or_else(|| env_key(&[OPENROUTER_KEY_ENV])),
+        ),
+        (None, "copilot" | "github-copilot") => (
+            COPILOT_TOKEN_URL.to_string(),
+            env_key(GITHUB_TOKEN_ENVS).or_else(opencode_copilot_token),
+        ),
+        (None, _) => {
+            return Err(anyhow!(
+                "No endpoint known for provider '{}'. Set options.baseURL in its provider file.",
+                provider
+            ));
+        }
+    };
+
+    let (key, key_source) = match key {
+        Some((key, source)) => (Some(key), Some(source)),
+        None => (None, None),
+    };
+    let mut health = Health {
+        provider: provider.to_string(),
+        url: url.clone(),
+        key_source,
+        reachable: false,
+        status: None,
+        latency: None,
+        auth: if key.is_some() {
+            Auth::Unknown
+        } else {
+            Auth::Missing
+        },
+        error: None,
+    };
+    match http::probe(&url, key.as_deref()) {
+        Ok((status, latency)) => {
+            health.reachable = status != 0;
+            health.status = Some(status);
+            health.latency = Some(latency);
+            if key.is_some() {
+                health.auth = match status {
+                    200..=299 => Auth::Ok,
+                    401 | 403 => Auth::Rejected,
+                    _ => Auth::Unknown,
+                };
+            }
+        }
+        Err(e) => health.error = Some(format!("{:#}", e)),
+    }
+    Ok(health)
+}
+
+/// Fill in `{env:NAME}` and `{secret:NAME}` references the way a switch
+/// (and opencode) would
+fn resolve(value: &str) -> Result<String> {
+    let mut result = String::new();
+    let mut rest = value;
+    while let Some(start) = rest.find("{env:") {
+        let after = &rest[start + "{env:".len()..];
+        let Some(end) = after.find('}') else {
+            break;
+        };
+        let name = &after[..end];
+        let value =
+            std::env::var(name).map_err(|_| anyhow!("Environment variable {} isn't set", name))?;
+        result.push_str(&rest[..start]);
+        result.push_str(&value);
+        rest = &after[end + 1..];
+    }
+    result.push_str(rest);
+    keychain::resolve_text(&result)
+}
+
+fn env_key(names: &[&str]) -> Option<(String, String)> {
+    names.iter().find_map(|name| {
+        let value = std::env::var(name).ok().filter(|v| !v.is_empty())?;
+        Some((value, name.to_string()))
+    })
+}
+
+/// GitHub OAuth token opencode saved when logging in to Copilot, in
+/// `$XDG_DATA_HOME/opencode/auth.json` (`~/.local/share` on every platform)
+fn opencode_copilot_token() -> Option<(String, String)> {
+    let data_home = std::env::var_os("XDG_DATA_HOME")
+        .map(PathBuf::from)
+        .or_else(|| Some(dirs::home_dir()?.join(".local").join("share")))?;
+    let path = data_home.join("opencode").join("auth.json");
+    let auth: Value = serde_json::from_str(&fsutil::read_to_string(&path).ok()?).ok()?;
+    let token = auth.get("github-copilot")?.get("refresh")?.as_str()?;
+    Some((token.to_string(), path.display().to_string()))
+}
@@ -0,0 +1,102 @@
+//! Commands run around a switch: an executable named after the hook in the
+//! `hooks` directory of the portal directory, then the command line under
+//! `hooks` in settings.json. They learn about the switch from `PORTAL_*`
+//! environment variables.
+
+use anyhow::{Context, Result, anyhow};
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+use crate::settings;
+
+/// Directory in the portal directory holding hook executables
+pub const HOOKS_DIR: &str = "hooks";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Hook {
+    /// Before a switch is written; failing stops the switch
+    PreSwitch,
+    /// After a switch is written, or a temporary one ends
+    PostSwitch,
+}
+
+impl Hook {
+    pub fn name(self) -> &'static str {
+        match self {
+            Hook::PreSwitch => "pre-switch",
+            Hook::PostSwitch => "post-switch",
+        }
+    }
+}
+
+/// What a hook is told about the switch
+#[derive(Debug, Clone, Copy)]
+pub struct HookContext<'a> {
+    /// `switch`, or `expire` when a temporary switch ends
+    pub action: &'a str,
+    /// Provider the config is switching to, if known
+    pub provider: Option<&'a str>,
+    /// Provider the config is switching from, if known
+    pub previous_provider: Option<&'a str>,
+    pub config: &'a Path,
+}
+
+/// Run `hook`'s commands in turn, stopping at the first that fails. Hook
+/// output goes to stderr so it can't mix with portal's own, e.g. JSON.
+pub fn run(portal_dir: &Path, hook: Hook, ctx: &HookContext) -> Result<()> {
+    let mut commands: Vec<(String, Command)> = Vec::new();
+    let file = portal_dir.join(HOOKS_DIR).join(hook.name());
+    if file.is_file() {
+        commands.push((file.display().to_string(), Command::new(&file)));
+    }
+    if let Some(line) = settings::read_settings(portal_dir)?
+        .and_then(|settings| settings.hooks.get(hook.name()).cloned())
+    {
+        commands.push((format!("'{}'", line), shell(&line)));
+    }
+
+    for (label, mut command) in commands {
+        crate::portal_info!("Running {} hook {}", hook.name(), label);
+        command
+            .env("PORTAL_HOOK", hook.name())
+            .env("PORTAL_ACTION", ctx.action)
+            .env("PORTAL_CONFIG_PATH", ctx.config)
+            .stdin(Stdio::null())
+            .stdout(std::io::stderr());
+        for (name, value) in [
+            ("PORTAL_PROVIDER", ctx.provider),
+            ("PORTAL_PREVIOUS_PROVIDER", ctx.previous_provider),
+        ] {
+            match value {
+                Some(value) => command.env(name, value),
+                None => command.env_remove(name),
+            };
+        }
+        let status = command
+            .status()
+            .with_context(|| format!("Failed to run {} hook {}", hook.name(), label))?;
+        if !status.success() {
+            return Err(anyhow!(
+                "{} hook {} failed ({})",
+                hook.name(),
+                label,
+                status
+            ));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(unix)]
+fn shell(line: &str) -> Command {
+    let mut command = Command::new("sh");
+    command.arg("-c").arg(line);
+    command
+}
+
+#[cfg(not(unix))]
+fn shell(line: &str) -> Command {
+    let mut command = Command::new("cmd");
+    command.arg("/C").arg(line);
+    command
+}
@@ -0,0 +1,91 @@
+//! Minimal HTTP GET by shelling out to `curl`, which is on every machine
+//! portal targets and saves pulling in a TLS stack.
+
+use anyhow::{Context, Result, anyhow};
+use std::io::Write;
+use std::process::{Command, Output, Stdio};
+use std::time::Duration;
+
+/// How long to wait for a response
+const TIMEOUT_SECS: &str = "10";
+
+/// Body of a successful GET of `url`
+pub fn get(url: &str) -> Result<String> {
+    let output = Command::new("curl")
+        .args(["-fsSL", "--max-time", TIMEOUT_SECS, url])
+        .output()
+        .with_context(|| format!("Failed to run curl to fetch {}", url))?;
+    body(url, output)
+}
+
+/// Body of a successful GET of `url` sent with `Authorization: Bearer
+/// <token>`. The header goes to curl on stdin so the token never shows up
+/// in the process list.
+pub fn get_with_bearer(url: &str, token: &str) -> Result<String> {
+    let mut child = Command::new("curl")
+        .args(["-fsSL", "--max-time", TIMEOUT_SECS, "-H", "@-", url])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .with_context(|| format!("Failed to run curl to fetch {}", url))?;
+    if let Some(mut stdin) = child.stdin.take() {
+        writeln!(stdin, "Authorization: Bearer {}", token)
+            .with_context(|| format!("Failed to pass headers to curl for {}", url))?;
+    }
+    let output = child
+        .wait_with_output()
+        .with_context(|| format!("Failed to run curl to fetch {}", url))?;
+    body(url, output)
+}
+
+/// HTTP status and total time of a GET of `url`, whatever the status, with
+/// `Authorization: Bearer <token>` if given (passed on stdin, as in
+/// [`get_with_bearer`]). Fails only when no response came back.
+pub fn probe(url: &str, token: Option<&str>) -> Result<(u16, Duration)> {
+    let mut child = Command::new("curl")
+        .args(["-sS", "-o", "/dev/null", "-w", "%{http_code} %{time_total}"])
+        .args(["--max-time", TIMEOUT_SECS, "-H", "@-", url])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .with_context(|| format!("Failed to run curl to reach {}", url))?;
+    if let Some(mut stdin) = child.stdin.take()
+        && let Some(token) = token
+    {
+        writeln!(stdin, "Authorization: Bearer {}", token)
+            .with_context(|| format!("Failed to pass headers to curl for {}", url))?;
+    }
+    let output = child
+        .wait_with_output()
+        .with_context(|| format!("Failed to run curl to reach {}", url))?;
+    if !output.status.success() {
+        return Err(anyhow!(
+            "Failed to reach {}: {}",
+            url,
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    let written = String::from_utf8_lossy(&output.stdout);
+    let (code, time) = written
+        .trim()
+        .split_once(' ')
+        .ok_or_else(|| anyhow!("Unexpected output from curl for {}: {}", url, written))?;
+    let code = code
+        .parse()
+        .with_context(|| format!("Unexpected status from curl for {}: {}", url, code))?;
+    let secs: f64 = time.parse().unwrap_or(0.0);
+    Ok((code, Duration::from_secs_f64(secs.max(0.0))))
+}
+
+fn body(url: &str, output: Output) -> Result<String> {
+    if !output.status.success() {
+        return Err(anyhow!(
+            "Failed to fetch {}: {}",
+            url,
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    String::from_utf8(output.stdout).with_context(|| format!("Response from {} is not UTF-8", url))
+}
@@ -0,0 +1,53 @@
+//! Example provider files written by `portal init`: for each built-in
+//! provider, the models a switch would give each agent, written out so
+//! they are easy to edit.
+
+use anyhow::Result;
+
+use crate::mapping::MappingTable;
+use crate::provider::{AgentModelConfig, ProviderConfig};
+use crate::switch::{self, Resolution, SwitchContext};
+
+/// Agents and models to base the examples on when there is no config to
+/// read them from
+const FALLBACK_AGENTS: &[(&str, &str)] = &[
+    ("sisyphus", "claude-opus-4.5"),
+    ("oracle", "gpt-5.2"),
+    ("librarian", "claude-sonnet-4.5"),
+];
+
+/// Give each of `agents` (name, current model) the model the mapping
+/// table gives it on `provider`. Agents the table doesn't know are left
+/// out rather than guessed.
+pub fn example_provider(
+    agents: &[(String, String)],
+    provider: &str,
+    mappings: &MappingTable,
+) -> ProviderConfig {
+    let fallback: Vec<(String, String)> = FALLBACK_AGENTS
+        .iter()
+        .map(|(agent, model)| (agent.to_string(), model.to_string()))
+        .collect();
+    let agents = if agents.is_empty() { &fallback } else { agents };
+
+    let ctx = SwitchContext::new(provider, mappings);
+    let mut example = ProviderConfig::default();
+    for (agent, model) in agents {
+        if let Some((model, Resolution::Mapped)) = switch::explain_model(agent, model, &ctx) {
+            example
+                .agents
+                .insert(agent.clone(), AgentModelConfig { model });
+        }
+    }
+    example
+}
+
+/// The example as provider file content, with a comment saying what it is
+pub fn render_example(provider: &str, example: &ProviderConfig) -> Result<String> {
+    Ok(format!(
+        "// Example '{provider}' provider written by `portal init`. Each agent listed\n\
+         // here gets exactly this model on `portal switch {provider}`; agents left out\n\
+         // follow the mapping table. Edit or remove entries as you like.\n{}\n",
+        serde_json::to_string_pretty(example)?
+    ))
+}
@@ -0,0 +1,211 @@
+//! Queries to the running daemon over a unix socket in the state
+//! directory. The daemon keeps each config it is asked about parsed in
+//! memory and parses it again only when the file changes, so a `portal
+//! status` run from a prompt doesn't parse the config every time. One
+//! JSON request per connection, answered with one JSON line:
+//!
+//! ```json
+//! { "query": "status", "config": "/home/me/.config/opencode/oh-my-opencode.json" }
+//! ```
+//!
+//! Clients fall back to doing the work themselves whenever no daemon
+//! answers, so a stale socket or a daemon that is busy costs at most
+//! [`TIMEOUT`].
+
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use crate::portal::Status;
+
+/// Socket in the state directory the daemon answers queries on
+pub const SOCKET_FILE: &str = "daemon.sock";
+
+/// Longest a client waits for the daemon before doing the work itself
+pub const TIMEOUT: Duration = Duration::from_millis(500);
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "query", rename_all = "snake_case")]
+pub enum Request {
+    /// The status of a config, as [`crate::Portal::status`] returns it
+    Status { config: PathBuf },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum Response {
+    Status(Box<Status>),
+    Error(String),
+}
+
+pub fn socket_path(state_dir: &Path) -> PathBuf {
+    state_dir.join(SOCKET_FILE)
+}
+
+/// The status of `config` from the running daemon; `None` when no daemon
+/// answers, or it couldn't work it out, and the caller has to
+pub fn query_status(state_dir: &Path, config: &Path) -> Option<Status> {
+    let absolute = std::path::absolute(config).unwrap_or_else(|_| config.to_path_buf());
+    let request = Request::Status { config: absolute };
+    match imp::query(&socket_path(state_dir), &request)? {
+        Response::Status(mut status) => {
+            crate::portal_debug!("Status from the daemon");
+            // As it was given, like a status worked out here
+            status.config_path = config.to_path_buf();
+            Some(*status)
+        }
+        Response::Error(e) => {
+            crate::portal_debug!("The daemon couldn't answer: {}", e);
+            None
+        }
+    }
+}
+
+#[cfg(unix)]
+mod imp {
+    use super::*;
+    use anyhow::{Context, Result};
+    use std::collections::HashMap;
+    use std::fs;
+    use std::io::{BufRead, BufReader, Write};
+    use std::os::unix::fs::{MetadataExt, PermissionsExt};
+    use std::os::unix::net::{UnixListener, UnixStream};
+
+    use crate::portal::Portal;
+
+    pub(super) fn query(socket: &Path, request: &Request) -> Option<Response> {
+        let mut stream = UnixStream::connect(socket).ok()?;
+        stream.set_read_timeout(Some(TIMEOUT)).ok()?;
+        stream.set_write_timeout(Some(TIMEOUT)).ok()?;
+        let mut line = serde_json::to_string(request).ok()?;
+        line.push('\n');
+        stream.write_all(line.as_bytes()).ok()?;
+        let mut answer = String::new();
+        BufReader::new(stream).read_line(&mut answer).ok()?;
+        serde_json::from_str(&answer).ok()
+    }
+
+    /// What a config's status was worked out from: when any of it changes,
+    /// the file was written
+    #[derive(PartialEq, Eq)]
+    struct Stamp {
+        inode: u64,
+        len: u64,
+        modified: (i64, i64),
+    }
+
+    impl Stamp {
+        fn of(path: &Path) -> Option<Self> {
+            let metadata = fs::metadata(path).ok()?;
+            Some(Self {
+                inode: metadata.ino(),
+                len: metadata.len(),
+                modified: (metadata.mtime(), metadata.mtime_nsec()),
+            })
+        }
+    }
+
+    /// Answers queries on the socket from a thread of its own until
+    /// dropped, when the socket is removed
+    pub struct Server {
+        path: PathBuf,
+    }
+
+    impl Server {
+        /// Listen on the socket in `portal`'s state directory. Only call
+        /// this once no other daemon is running: a socket left behind is
+        /// replaced.
+        pub fn start(portal: &Portal) -> Result<Self> {
+            let path = socket_path(portal.state_dir());
+            let _ = fs::remove_file(&path);
+            let listener = UnixListener::bind(&path)
+                .with_context(|| format!("Failed to listen on {}", path.display()))?;
+            fs::set_permissions(&path, fs::Permissions::from_mode(0o600))
+                .with_context(|| format!("Failed to restrict {}", path.display()))?;
+            let portal = portal.clone();
+            std::thread::spawn(move || {
+                let mut cache = HashMap::new();
+                for stream in listener.incoming().flatten() {
+                    if let Err(e) = answer(&portal, &mut cache, stream) {
+                        crate::portal_debug!("Dropped a query: {:#}", e);
+                    }
+                }
+            });
+            Ok(Self { path })
+        }
+    }
+
+    impl Drop for Server {
+        fn drop(&mut self) {
+            let _ = fs::remove_file(&self.path);
+        }
+    }
+
+    fn answer(
+        portal: &Portal,
+        cache: &mut HashMap<PathBuf, (Stamp, Status)>,
+        stream: UnixStream,
+    ) -> Result<()> {
+        stream.set_read_timeout(Some(TIMEOUT))?;
+        stream.set_write_timeout(Some(TIMEOUT))?;
+        let mut line = String::new();
+        BufReader::new(&stream).read_line(&mut line)?;
+        let response = match serde_json::from_str(&line) {
+            Ok(Request::Status { config }) => match status(portal, cache, config) {
+                Ok(status) => Response::Status(Box::new(status)),
+                Err(e) => Response::Error(format!("{:#}", e)),
+            },
+            Err(e) => Response::Error(format!("Unknown query: {}", e)),
+        };
+        let mut answer = serde_json::to_string(&response)?;
+        answer.push('\n');
+        (&stream).write_all(answer.as_bytes())?;
+        Ok(())
+    }
+
+    fn status(
+        portal: &Portal,
+        cache: &mut HashMap<PathBuf, (Stamp, Status)>,
+        config: PathBuf,
+    ) -> Result<Status> {
+        let stamp = Stamp::of(&config);
+        if let (Some(stamp), Some((cached, status))) = (&stamp, cache.get(&config))
+            && stamp == cached
+        {
+            return Ok(status.clone());
+        }
+        let status = portal.for_config(&config).status()?;
+        match stamp {
+            Some(stamp) => {
+                cache.insert(config, (stamp, status.clone()));
+            }
+            None => {
+                cache.remove(&config);
+            }
+        }
+        Ok(status)
+    }
+}
+
+#[cfg(not(unix))]
+mod imp {
+    use super::*;
+    use anyhow::Result;
+
+    use crate::portal::Portal;
+
+    pub(super) fn query(_socket: &Path, _request: &Request) -> Option<Response> {
+        None
+    }
+
+    /// Queries need a unix socket, so elsewhere there is nothing to serve
+    pub struct Server;
+
+    impl Server {
+        pub fn start(_portal: &Portal) -> Result<Self> {
+            Ok(Self)
+        }
+    }
+}
+
+pub use imp::Server;
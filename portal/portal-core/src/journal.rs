@@ -0,0 +1,77 @@
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+
+use crate::error::PortalError;
+use crate::fsutil;
+
+/// How many writes the journal remembers per config
+pub const JOURNAL_LIMIT: usize = 50;
+
+/// One config write, with the full text before and after it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JournalEntry {
+    pub timestamp: DateTime<Utc>,
+    /// Short description, e.g. "switch to openrouter"
+    pub action: String,
+    pub before: String,
+    pub after: String,
+}
+
+/// Undo/redo history of portal's writes to one config. Entries before
+/// `position` can be undone; entries from `position` on can be redone.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Journal {
+    pub entries: Vec<JournalEntry>,
+    pub position: usize,
+}
+
+impl Journal {
+    /// Load the journal at `path`; a missing file is an empty journal
+    pub fn load(path: &Path) -> Result<Self> {
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let content = fsutil::read_to_string(path)
+            .with_context(|| format!("Failed to read journal: {}", path.display()))?;
+        let mut journal: Self =
+            serde_json::from_str(&content).map_err(PortalError::parse("journal", path))?;
+        journal.position = journal.position.min(journal.entries.len());
+        Ok(journal)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).with_context(|| {
+                format!("Failed to create journal directory: {}", parent.display())
+            })?;
+        }
+        let content = serde_json::to_string(self)?;
+        fsutil::write_atomic(path, content.as_bytes())
+            .with_context(|| format!("Failed to write journal: {}", path.display()))
+    }
+
+    /// Record a new write. Anything that could have been redone is dropped,
+    /// and the oldest entries go once the journal is over its limit.
+    pub fn push(&mut self, entry: JournalEntry) {
+        self.entries.truncate(self.position);
+        self.entries.push(entry);
+        if self.entries.len() > JOURNAL_LIMIT {
+            let excess = self.entries.len() - JOURNAL_LIMIT;
+            self.entries.drain(..excess);
+        }
+        self.position = self.entries.len();
+    }
+
+    /// The write `undo` would reverse
+    pub fn undo_entry(&self) -> Option<&JournalEntry> {
+        self.position.checked_sub(1).map(|i| &self.entries[i])
+    }
+
+    /// The write `redo` would reapply
+    pub fn redo_entry(&self) -> Option<&JournalEntry> {
+        self.entries.get(self.position)
+    }
+}
@@ -0,0 +1,546 @@
+//! Lenient JSON: `//` and `/* */` comments plus trailing commas (JSONC, the
+//! subset of JSON5 people put in hand-edited configs), with in-place edits
+//! that keep comments and layout intact.
+
+use anyhow::{Result, anyhow};
+use serde_json::Value;
+use std::ops::Range;
+
+/// Blank out comments and trailing commas with spaces so the result is
+/// strict JSON with every remaining byte at its original offset
+pub fn strip(text: &str) -> String {
+    let mut bytes = text.as_bytes().to_vec();
+    let mut i = 0;
+    // Offset of the last comma seen outside a string, cleared by any
+    // significant character after it
+    let mut pending_comma = None;
+
+    while i < bytes.len() {
+        match bytes[i] {
+            b'"' => {
+                pending_comma = None;
+                i += 1;
+                while i < bytes.len() && bytes[i] != b'"' {
+                    if bytes[i] == b'\\' {
+                        i += 1;
+                    }
+                    i += 1;
+                }
+                i += 1;
+            }
+            b'/' if bytes.get(i + 1) == Some(&b'/') => {
+                while i < bytes.len() && bytes[i] != b'\n' {
+                    bytes[i] = b' ';
+                    i += 1;
+                }
+            }
+            b'/' if bytes.get(i + 1) == Some(&b'*') => {
+                let end = find(&bytes, i + 2, b"*/").map_or(bytes.len(), |e| e + 2);
+                for b in &mut bytes[i..end] {
+                    if *b != b'\n' {
+                        *b = b' ';
+                    }
+                }
+                i = end;
+            }
+            b',' => {
+                pending_comma = Some(i);
+                i += 1;
+            }
+            b'}' | b']' => {
+                if let Some(comma) = pending_comma.take() {
+                    bytes[comma] = b' ';
+                }
+                i += 1;
+            }
+            b if b.is_ascii_whitespace() => i += 1,
+            _ => {
+                pending_comma = None;
+                i += 1;
+            }
+        }
+    }
+
+    // Only ASCII bytes outside strings were replaced, so this stays UTF-8
+    String::from_utf8(bytes).expect("stripping preserves UTF-8")
+}
+
+fn find(haystack: &[u8], from: usize, needle: &[u8]) -> Option<usize> {
+    haystack
+        .get(from..)?
+        .windows(needle.len())
+        .position(|w| w == needle)
+        .map(|p| p + from)
+}
+
+/// Parse JSON that may contain comments and trailing commas
+pub fn parse(text: &str) -> serde_json::Result<Value> {
+    serde_json::from_str(&strip(text))
+}
+
+/// A parsed value that remembers where it sits in the source text
+enum Node {
+    Object(Vec<Member>, Range<usize>),
+    Array(Vec<Node>, Range<usize>),
+    Scalar(Value, Range<usize>),
+}
+
+struct Member {
+    key: String,
+    /// Offset of the key's opening quote
+    key_start: usize,
+    value: Node,
+}
+
+impl Node {
+    fn span(&self) -> &Range<usize> {
+        match self {
+            Node::Object(_, span) | Node::Array(_, span) | Node::Scalar(_, span) => span,
+        }
+    }
+}
+
+struct Scanner<'a> {
+    text: &'a str,
+    pos: usize,
+}
+
+impl Scanner<'_> {
+    fn skip_whitespace(&mut self) {
+        let rest = &self.text[self.pos..];
+        self.pos += rest.len() - rest.trim_start().len();
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.text.as_bytes().get(self.pos).copied()
+    }
+
+    fn expect(&mut self, byte: u8) -> Result<()> {
+        self.skip_whitespace();
+        if self.peek() != Some(byte) {
+            return Err(anyhow!("expected '{}' at byte {}", byte as char, self.pos));
+        }
+        self.pos += 1;
+        Ok(())
+    }
+
+    fn value(&mut self) -> Result<Node> {
+        self.skip_whitespace();
+        let start = self.pos;
+        match self.peek() {
+            Some(b'{') => {
+                self.pos += 1;
+                let mut members = Vec::new();
+                self.skip_whitespace();
+                if self.peek() == Some(b'}') {
+                    self.pos += 1;
+                    return Ok(Node::Object(members, start..self.pos));
+                }
+                loop {
+                    self.skip_whitespace();
+                    let key_start = self.pos;
+                    let key = match self.value()? {
+                        Node::Scalar(Value::String(key), _) => key,
+                        _ => return Err(anyhow!("expected a key at byte {}", self.pos)),
+                    };
+                    self.expect(b':')?;
+                    members.push(Member {
+                        key,
+                        key_start,
+                        value: self.value()?,
+                    });
+                    self.skip_whitespace();
+                    match self.peek() {
+                        Some(b',') => self.pos += 1,
+                        Some(b'}') => {
+                            self.pos += 1;
+                            return Ok(Node::Object(members, start..self.pos));
+                        }
+                        _ => return Err(anyhow!("expected ',' or '}}' at byte {}", self.pos)),
+                    }
+                }
+            }
+            Some(b'[') => {
+                self.pos += 1;
+                let mut items = Vec::new();
+                self.skip_whitespace();
+                if self.peek() == Some(b']') {
+                    self.pos += 1;
+                    return Ok(Node::Array(items, start..self.pos));
+                }
+                loop {
+                    items.push(self.value()?);
+                    self.skip_whitespace();
+                    match self.peek() {
+                        Some(b',') => self.pos += 1,
+                        Some(b']') => {
+                            self.pos += 1;
+                            return Ok(Node::Array(items, start..self.pos));
+                        }
+                        _ => return Err(anyhow!("expected ',' or ']' at byte {}", self.pos)),
+                    }
+                }
+            }
+            Some(b'"') => {
+                let bytes = self.text.as_bytes();
+                self.pos += 1;
+                while self.pos < bytes.len() && bytes[self.pos] != b'"' {
+                    if bytes[self.pos] == b'\\' {
+                        self.pos += 1;
+                    }
+                    self.pos += 1;
+                }
+                self.pos += 1;
+                self.scalar(start)
+            }
+            Some(_) => {
+                let rest = &self.text[start..];
+                let len = rest
+                    .find(|c: char| !(c.is_ascii_alphanumeric() || "+-.".contains(c)))
+                    .unwrap_or(rest.len());
+                self.pos += len;
+                self.scalar(start)
+            }
+            None => Err(anyhow!("unexpected end of input")),
+        }
+    }
+
+    fn scalar(&self, start: usize) -> Result<Node> {
+        let span = start..self.pos.min(self.text.len());
+        let value = serde_json::from_str(&self.text[span.clone()])?;
+        Ok(Node::Scalar(value, span))
+    }
+}
+
+/// Text replacements, each a byte range of the original and what replaces it
+type Edits = Vec<(Range<usize>, String)>;
+
+/// The original text alongside its stripped copy, whose structure is what
+/// gets scanned; the two share every offset
+struct Source<'a> {
+    text: &'a str,
+    stripped: &'a str,
+}
+
+impl Source<'_> {
+    fn line_start(&self, pos: usize) -> usize {
+        self.text[..pos].rfind('\n').map_or(0, |i| i + 1)
+    }
+
+    /// End of the line holding `pos`, before its newline
+    fn line_end(&self, pos: usize) -> usize {
+        self.text[pos..]
+            .find('\n')
+            .map_or(self.text.len(), |i| pos + i)
+    }
+
+    /// Whitespace before `pos` on its line, if nothing else comes first
+    fn indent_at(&self, pos: usize) -> Option<&str> {
+        let before = &self.text[self.line_start(pos)..pos];
+        before.trim().is_empty().then_some(before)
+    }
+
+    /// Leading whitespace of the line holding `pos`
+    fn line_indent(&self, pos: usize) -> &str {
+        let line = &self.text[self.line_start(pos)..];
+        &line[..line.len() - line.trim_start_matches([' ', '\t']).len()]
+    }
+
+    /// Offset of the comma right after `pos`, skipping whitespace (and
+    /// comments, which are blank in the stripped copy)
+    fn comma_after(&self, pos: usize) -> Option<usize> {
+        let rest = &self.stripped[pos..];
+        let skipped = rest.len() - rest.trim_start().len();
+        rest[skipped..].starts_with(',').then_some(pos + skipped)
+    }
+
+    /// A trailing comma after `pos` that stripping blanked out
+    fn trailing_comma_after(&self, pos: usize) -> Option<usize> {
+        let rest = &self.text[pos..self.line_end(pos)];
+        let skipped = rest.len() - rest.trim_start().len();
+        rest[skipped..].starts_with(',').then_some(pos + skipped)
+    }
+
+    /// Whether only comments and whitespace follow `pos` on its line
+    fn rest_of_line_blank(&self, pos: usize) -> bool {
+        self.stripped[pos..self.line_end(pos)].trim().is_empty()
+    }
+}
+
+/// `value` pretty-printed for a spot whose line is indented by `indent`
+fn render(value: &Value, indent: &str) -> String {
+    serde_json::to_string_pretty(value)
+        .unwrap_or_default()
+        .replace('\n', &format!("\n{}", indent))
+}
+
+fn render_key(key: &str) -> String {
+    serde_json::to_string(key).unwrap_or_default()
+}
+
+/// Collect the text replacements turning `node` into `target`. Scalars are
+/// swapped, keys are added after an object's last kept member or removed
+/// along with their comma, and anything else (a different type, an array
+/// of another length) is printed afresh in its place.
+fn collect_edits(source: &Source, node: &Node, target: &Value, edits: &mut Edits) {
+    match (node, target) {
+        (Node::Object(members, _), Value::Object(wanted))
+            if members.iter().any(|m| wanted.contains_key(&m.key)) =>
+        {
+            let mut last_kept = None;
+            for (i, member) in members.iter().enumerate() {
+                // Of a key written twice, parsers keep the last; earlier
+                // copies stay exactly as they are
+                let shadowed = members[i + 1..].iter().any(|m| m.key == member.key);
+                match wanted.get(&member.key) {
+                    Some(_) if shadowed => last_kept = Some(i),
+                    Some(value) => {
+                        collect_edits(source, &member.value, value, edits);
+                        last_kept = Some(i);
+                    }
+                    None => edits.push(removal(source, member)),
+                }
+            }
+            let last = &members[last_kept.expect("some member is kept")];
+            let added: Vec<(&String, &Value)> = wanted
+                .iter()
+                .filter(|(key, _)| !members.iter().any(|m| &m.key == *key))
+                .collect();
+            let value_end = last.value.span().end;
+            let comma = source.comma_after(value_end);
+            if added.is_empty() {
+                // Members after the last kept one are gone, so is its comma
+                if let Some(comma) = comma
+                    && last_kept != Some(members.len() - 1)
+                {
+                    edits.push((comma..comma + 1, String::new()));
+                }
+                return;
+            }
+            edits.extend(addition(source, last, comma, &added));
+        }
+        (Node::Array(items, _), Value::Array(wanted)) if items.len() == wanted.len() => {
+            for (item, value) in items.iter().zip(wanted) {
+                collect_edits(source, item, value, edits);
+            }
+        }
+        (Node::Scalar(current, span), value) if !value.is_object() && !value.is_array() => {
+            if current != value {
+                edits.push((span.clone(), value.to_string()));
+            }
+        }
+        (node, value) => {
+            let span = node.span().clone();
+            // Keep to one line when the line carries on past the value
+            let after = source
+                .comma_after(span.end)
+                .map_or(span.end, |comma| comma + 1);
+            let text = if source.rest_of_line_blank(after) {
+                render(value, source.line_indent(span.start))
+            } else {
+                serde_json::to_string(value).unwrap_or_default()
+            };
+            edits.push((span, text));
+        }
+    }
+}
+
+/// The edit taking `member` out of its object, with its comma, and its
+/// whole line when it has one to itself
+fn removal(source: &Source, member: &Member) -> (Range<usize>, String) {
+    let value_end = member.value.span().end;
+    let mut end = source
+        .comma_after(value_end)
+        .or_else(|| source.trailing_comma_after(value_end))
+        .map_or(value_end, |comma| comma + 1);
+    let blank = |c: u8| c == b' ' || c == b'\t';
+    let bytes = source.text.as_bytes();
+    let start = match source.indent_at(member.key_start) {
+        Some(_) if source.rest_of_line_blank(end) => {
+            end = (source.line_end(end) + 1).min(source.text.len());
+            source.line_start(member.key_start)
+        }
+        // Inline, the space after the comma goes too, or the space before
+        // the last member
+        _ if end > value_end => {
+            while end < bytes.len() && blank(bytes[end]) {
+                end += 1;
+            }
+            member.key_start
+        }
+        _ => {
+            let mut start = member.key_start;
+            while start > 0 && blank(bytes[start - 1]) {
+                start -= 1;
+            }
+            start
+        }
+    };
+    (start..end, String::new())
+}
+
+/// The edits adding `added` after `last`, the object's last kept member,
+/// one per line in multi-line objects and inline otherwise
+fn addition(
+    source: &Source,
+    last: &Member,
+    comma: Option<usize>,
+    added: &[(&String, &Value)],
+) -> Edits {
+    let value_end = last.value.span().end;
+    // A JSONC trailing comma: new members get one too
+    let trailing = comma
+        .is_none()
+        .then(|| source.trailing_comma_after(value_end))
+        .flatten();
+    let anchor = comma.or(trailing).map_or(value_end, |comma| comma + 1);
+    let needs_comma = comma.is_none() && trailing.is_none();
+    let trailing = trailing.is_some();
+    let mut edits = Vec::new();
+
+    match source.indent_at(last.key_start) {
+        Some(indent) if source.rest_of_line_blank(anchor) => {
+            let at = source.line_end(anchor);
+            let mut text = String::new();
+            if needs_comma && at != value_end {
+                edits.push((value_end..value_end, ",".to_string()));
+            } else if needs_comma {
+                text.push(',');
+            }
+            let lines: Vec<String> = added
+                .iter()
+                .map(|(key, value)| {
+                    format!("\n{}{}: {}", indent, render_key(key), render(value, indent))
+                })
+                .collect();
+            text.push_str(&lines.join(","));
+            if trailing {
+                text.push(',');
+            }
+            edits.push((at..at, text));
+        }
+        _ => {
+            let members: Vec<String> = added
+                .iter()
+                .map(|(key, value)| {
+                    format!(
+                        " {}: {}",
+                        render_key(key),
+                        serde_json::to_string(value).unwrap_or_default()
+                    )
+                })
+                .collect();
+            let mut text = if needs_comma {
+                ",".to_string()
+            } else {
+                String::new()
+            };
+            text.push_str(&members.join(","));
+            if trailing {
+                text.push(',');
+            }
+            edits.push((anchor..anchor, text));
+        }
+    }
+    edits
+}
+
+/// Rewrite `text` so it parses to `target`, leaving comments, key order,
+/// whitespace, and the spelling of values (`1.50`, `"\u00e9"`) alone
+/// wherever `target` agrees with it. New keys go at the end of their
+/// object. `None` if `text` doesn't parse.
+pub fn update(text: &str, target: &Value) -> Option<String> {
+    let stripped = strip(text);
+    let mut scanner = Scanner {
+        text: &stripped,
+        pos: 0,
+    };
+    let root = scanner.value().ok()?;
+    let source = Source {
+        text,
+        stripped: &stripped,
+    };
+
+    let mut edits = Vec::new();
+    collect_edits(&source, &root, target, &mut edits);
+    edits.sort_by_key(|(span, _)| std::cmp::Reverse((span.start, span.end)));
+
+    let mut out = text.to_string();
+    for (span, replacement) in edits {
+        out.replace_range(span, &replacement);
+    }
+    // Anything the edits got wrong falls back to a fresh rendering
+    (parse(&out).ok().as_ref() == Some(target)).then_some(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    const CONFIG: &str = r#"{
+  // Picked by hand
+  "agents": {
+    "oracle": { "model": "openrouter/openai/gpt-5.2" }, /* for now */
+    "explore": { "model": "github-copilot/gpt-5-mini", },
+  },
+  "url": "https://example.com/a//b",
+  "price": 1.50,
+}
+"#;
+
+    #[test]
+    fn strips_comments_and_trailing_commas_in_place() {
+        let stripped = strip(CONFIG);
+        assert_eq!(stripped.len(), CONFIG.len());
+        assert!(!stripped.contains("Picked") && !stripped.contains("for now"));
+        // Slashes inside strings aren't comments
+        assert!(stripped.contains("https://example.com/a//b"));
+        let offset = CONFIG.find("\"url\"").unwrap();
+        assert_eq!(&stripped[offset..offset + 5], "\"url\"");
+    }
+
+    #[test]
+    fn parses_jsonc() {
+        let document = parse(CONFIG).unwrap();
+        assert_eq!(
+            document["agents"]["oracle"]["model"],
+            "openrouter/openai/gpt-5.2"
+        );
+        assert_eq!(document["url"], "https://example.com/a//b");
+        assert!(parse("{ \"a\": 1 // unterminated }").is_err());
+    }
+
+    #[test]
+    fn updates_keeping_comments_and_layout() {
+        let mut target = parse(CONFIG).unwrap();
+        target["agents"]["oracle"]["model"] = json!("anthropic/claude-opus-4.5");
+        let updated = update(CONFIG, &target).unwrap();
+        assert_eq!(parse(&updated).unwrap(), target);
+        assert_eq!(
+            updated,
+            CONFIG.replace("openrouter/openai/gpt-5.2", "anthropic/claude-opus-4.5")
+        );
+    }
+
+    #[test]
+    fn round_trips_unchanged_text() {
+        let document = parse(CONFIG).unwrap();
+        assert_eq!(update(CONFIG, &document).unwrap(), CONFIG);
+    }
+
+    #[test]
+    fn adds_and_removes_keys() {
+        let mut target = parse(CONFIG).unwrap();
+        target["agents"]["librarian"] = json!({ "model": "google/gemini-3-flash-preview" });
+        target.as_object_mut().unwrap().remove("price");
+        let updated = update(CONFIG, &target).unwrap();
+        assert_eq!(parse(&updated).unwrap(), target);
+        assert!(updated.contains("// Picked by hand") && updated.contains("/* for now */"));
+    }
+
+    #[test]
+    fn refuses_text_that_does_not_parse() {
+        assert_eq!(update("{ \"a\": ", &json!({ "a": 1 })), None);
+    }
+}
@@ -0,0 +1,441 @@
+//! Secrets kept in the operating system's keychain: the macOS Keychain,
+//! the Secret Service on Linux (through `secret-tool`), or the Windows
+//! credential vault. Provider options name them as `{secret:NAME}`, and
+//! the placeholder is filled in only when portal writes the config, so
+//! the provider files hold no keys, and neither do the journal and backups
+//! portal keeps of the config.
+
+use anyhow::{Context, Result, anyhow};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::io::Write;
+use std::process::{Command, Output, Stdio};
+
+use crate::{config, jsonc};
+
+/// Service the secrets are filed under
+pub const SERVICE: &str = "portal";
+
+const PLACEHOLDER: &str = "{secret:";
+
+/// Exit status the macOS `security` tool (and the Windows script below)
+/// uses for an item that isn't there
+const NOT_FOUND: i32 = 44;
+
+const WINDOWS_VAULT: &str = "[void][Windows.Security.Credentials.PasswordVault,\
+Windows.Security.Credentials,ContentType=WindowsRuntime]; \
+$vault = New-Object Windows.Security.Credentials.PasswordVault; ";
+
+/// Where this platform keeps secrets
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Backend {
+    /// macOS Keychain, through `security`
+    Keychain,
+    /// Windows credential vault, through PowerShell
+    CredentialVault,
+    /// Secret Service (GNOME Keyring, KWallet), through `secret-tool`
+    SecretService,
+}
+
+fn backend() -> Backend {
+    if cfg!(target_os = "macos") {
+        Backend::Keychain
+    } else if cfg!(windows) {
+        Backend::CredentialVault
+    } else {
+        Backend::SecretService
+    }
+}
+
+/// Fail unless `name` can be used for a secret: letters, digits, `_`, `-`
+/// and `.`
+pub fn validate_name(name: &str) -> Result<()> {
+    let valid = !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '.'));
+    if valid {
+        Ok(())
+    } else {
+        Err(anyhow!(
+            "Invalid secret name: '{}' (use letters, digits, '_', '-' and '.')",
+            name
+        ))
+    }
+}
+
+/// Store `value` as `name`, replacing any earlier value
+pub fn set(name: &str, value: &str) -> Result<()> {
+    validate_name(name)?;
+    let output = match backend() {
+        // security only takes the password as an argument, so the command
+        // goes to its interactive mode on stdin, out of the process list
+        Backend::Keychain => run(
+            Command::new("security").arg("-i"),
+            Some(&keychain_command(name, value)?),
+        )?,
+        Backend::CredentialVault => powershell(
+            name,
+            "$vault.Add((New-Object Windows.Security.Credentials.PasswordCredential(\
+             'portal', $env:PORTAL_SECRET_NAME, [Console]::In.ReadToEnd())))",
+            Some(value),
+        )?,
+        Backend::SecretService => run(
+            Command::new("secret-tool")
+                .arg("store")
+                .arg(format!("--label={}: {}", SERVICE, name))
+                .args(["service", SERVICE, "account", name]),
+            Some(value),
+        )?,
+    };
+    // security -i carries on past a command that fails, so check that
+    // this one stored the value
+    if !output.status.success()
+        || (backend() == Backend::Keychain && get(name)?.as_deref() != Some(value))
+    {
+        return Err(failure(name, "store", &output));
+    }
+    Ok(())
+}
+
+/// The `security -i` line storing `value` as `name`, with the value in
+/// double quotes
+fn keychain_command(name: &str, value: &str) -> Result<String> {
+    if value.contains(['\n', '\r']) {
+        return Err(anyhow!(
+            "Secret '{}' can't hold a line break in the macOS Keychain",
+            name
+        ));
+    }
+    let value = value.replace('\\', "\\\\").replace('"', "\\\"");
+    Ok(format!(
+        "add-generic-password -U -s {} -a {} -w \"{}\"\n",
+        SERVICE, name, value
+    ))
+}
+
+/// The value stored as `name`, if there is one
+pub fn get(name: &str) -> Result<Option<String>> {
+    validate_name(name)?;
+    let output = match backend() {
+        Backend::Keychain => run(
+            Command::new("security").args([
+                "find-generic-password",
+                "-s",
+                SERVICE,
+                "-a",
+                name,
+                "-w",
+            ]),
+            None,
+        )?,
+        Backend::CredentialVault => powershell(
+            name,
+            "try { $item = $vault.Retrieve('portal', $env:PORTAL_SECRET_NAME) } catch { exit 44 }; \
+             $item.RetrievePassword(); [Console]::Out.Write($item.Password)",
+            None,
+        )?,
+        Backend::SecretService => run(
+            Command::new("secret-tool").args(["lookup", "service", SERVICE, "account", name]),
+            None,
+        )?,
+    };
+    if missing(&output) {
+        return Ok(None);
+    }
+    if !output.status.success() {
+        return Err(failure(name, "read", &output));
+    }
+    let value = String::from_utf8(output.stdout)
+        .with_context(|| format!("Secret '{}' isn't valid UTF-8", name))?;
+    // security ends the password with a newline
+    let value = match backend() {
+        Backend::Keychain => value.strip_suffix('\n').unwrap_or(&value).to_string(),
+        _ => value,
+    };
+    Ok(Some(value))
+}
+
+/// Remove `name`; `false` if it wasn't there
+pub fn delete(name: &str) -> Result<bool> {
+    validate_name(name)?;
+    if backend() == Backend::SecretService {
+        // secret-tool clear succeeds whether or not anything matched
+        let existed = get(name)?.is_some();
+        let output = run(
+            Command::new("secret-tool").args(["clear", "service", SERVICE, "account", name]),
+            None,
+        )?;
+        if !output.status.success() {
+            return Err(failure(name, "delete", &output));
+        }
+        return Ok(existed);
+    }
+    let output = if backend() == Backend::Keychain {
+        run(
+            Command::new("security").args(["delete-generic-password", "-s", SERVICE, "-a", name]),
+            None,
+        )?
+    } else {
+        powershell(
+            name,
+            "try { $vault.Remove($vault.Retrieve('portal', $env:PORTAL_SECRET_NAME)) } \
+             catch { exit 44 }",
+            None,
+        )?
+    };
+    if missing(&output) {
+        return Ok(false);
+    }
+    if !output.status.success() {
+        return Err(failure(name, "delete", &output));
+    }
+    Ok(true)
+}
+
+/// Where a written config holds secrets: the JSON pointer of each string
+/// filled in from a `{secret:NAME}` placeholder, with the placeholder it
+/// was filled in from. It holds no values, so it can be kept in state.
+pub type SecretPaths = BTreeMap<String, String>;
+
+/// Fill in every `{secret:NAME}` in `document`'s strings, returning where
+/// they were. A document without placeholders doesn't touch the keychain,
+/// and a secret named twice is only looked up once.
+pub fn resolve_document(document: &mut Value) -> Result<SecretPaths> {
+    let mut paths = SecretPaths::new();
+    collect_placeholders(document, String::new(), &mut paths);
+    let mut found = BTreeMap::new();
+    for (pointer, text) in &paths {
+        let value = fill(text, &mut found)?;
+        if let Some(slot) = document.pointer_mut(pointer) {
+            *slot = Value::String(value);
+        }
+    }
+    Ok(paths)
+}
+
+/// Config `text` with its placeholders filled in, edited in place to keep
+/// its comments, and where they were. Text that doesn't parse is left as
+/// it is.
+pub fn resolve_config(text: &str) -> Result<(String, SecretPaths)> {
+    if !text.contains(PLACEHOLDER) {
+        return Ok((text.to_string(), SecretPaths::new()));
+    }
+    let Ok(mut document) = jsonc::parse(text) else {
+        return Ok((text.to_string(), SecretPaths::new()));
+    };
+    let paths = resolve_document(&mut document)?;
+    if paths.is_empty() {
+        return Ok((text.to_string(), paths));
+    }
+    Ok((config::render_document(Some(text), &document)?, paths))
+}
+
+/// Put the placeholders back at `paths` in `document`, over the values
+/// filled in for them. Returns whether anything changed.
+pub fn redact(document: &mut Value, paths: &SecretPaths) -> bool {
+    let mut changed = false;
+    for (pointer, text) in paths {
+        if let Some(slot @ Value::String(_)) = document.pointer_mut(pointer)
+            && slot.as_str() != Some(text.as_str())
+        {
+            *slot = Value::String(text.clone());
+            changed = true;
+        }
+    }
+    changed
+}
+
+/// Config `text` with the placeholders put back at `paths`, edited in
+/// place. Text that doesn't parse is left as it is.
+pub fn redact_config(text: &str, paths: &SecretPaths) -> String {
+    if paths.is_empty() {
+        return text.to_string();
+    }
+    let Ok(mut document) = jsonc::parse(text) else {
+        return text.to_string();
+    };
+    if !redact(&mut document, paths) {
+        return text.to_string();
+    }
+    config::render_document(Some(text), &document).unwrap_or_else(|_| text.to_string())
+}
+
+fn collect_placeholders(value: &Value, pointer: String, paths: &mut SecretPaths) {
+    match value {
+        Value::String(text) if text.contains(PLACEHOLDER) => {
+            paths.insert(pointer, text.clone());
+        }
+        Value::Object(map) => {
+            for (key, value) in map {
+                let key = key.replace('~', "~0").replace('/', "~1");
+                collect_placeholders(value, format!("{}/{}", pointer, key), paths);
+            }
+        }
+        Value::Array(items) => {
+            for (index, value) in items.iter().enumerate() {
+                collect_placeholders(value, format!("{}/{}", pointer, index), paths);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// `text` with every `{secret:NAME}` replaced by the secret's value
+pub fn resolve_text(text: &str) -> Result<String> {
+    fill(text, &mut BTreeMap::new())
+}
+
+/// [`resolve_text`], looking up only the secrets `found` doesn't have yet
+fn fill(text: &str, found: &mut BTreeMap<String, String>) -> Result<String> {
+    let mut result = String::new();
+    let mut rest = text;
+    while let Some(start) = rest.find(PLACEHOLDER) {
+        let after = &rest[start + PLACEHOLDER.len()..];
+        let Some(end) = after.find('}') else {
+            break;
+        };
+        let name = &after[..end];
+        if !found.contains_key(name) {
+            let secret = get(name)?.ok_or_else(|| {
+                anyhow!(
+                    "Secret '{}' isn't in the keychain. Store it with `portal secret set {}`",
+                    name,
+                    name
+                )
+            })?;
+            found.insert(name.to_string(), secret);
+        }
+        result.push_str(&rest[..start]);
+        result.push_str(&found[name]);
+        rest = &after[end + 1..];
+    }
+    result.push_str(rest);
+    Ok(result)
+}
+
+fn run(command: &mut Command, input: Option<&str>) -> Result<Output> {
+    let program = command.get_program().to_string_lossy().into_owned();
+    command
+        .stdin(if input.is_some() {
+            Stdio::piped()
+        } else {
+            Stdio::null()
+        })
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped());
+    let mut child = command
+        .spawn()
+        .with_context(|| format!("Failed to run {}, which the keychain needs", program))?;
+    if let Some(input) = input {
+        child
+            .stdin
+            .take()
+            .expect("stdin is piped")
+            .write_all(input.as_bytes())
+            .with_context(|| format!("Failed to send the secret to {}", program))?;
+    }
+    child
+        .wait_with_output()
+        .with_context(|| format!("Failed to run {}", program))
+}
+
+/// Run a PowerShell script against the credential vault, with the secret's
+/// name in `PORTAL_SECRET_NAME` so it needs no quoting
+fn powershell(name: &str, script: &str, input: Option<&str>) -> Result<Output> {
+    run(
+        Command::new("powershell")
+            .args(["-NoProfile", "-NonInteractive", "-Command"])
+            .arg(format!("{}{}", WINDOWS_VAULT, script))
+            .env("PORTAL_SECRET_NAME", name),
+        input,
+    )
+}
+
+fn missing(output: &Output) -> bool {
+    match backend() {
+        Backend::Keychain | Backend::CredentialVault => output.status.code() == Some(NOT_FOUND),
+        // secret-tool lookup fails quietly when nothing matches
+        Backend::SecretService => {
+            !output.status.success() && output.stdout.is_empty() && output.stderr.is_empty()
+        }
+    }
+}
+
+fn failure(name: &str, action: &str, output: &Output) -> anyhow::Error {
+    anyhow!(
+        "Failed to {} secret '{}' in the keychain: {}",
+        action,
+        name,
+        String::from_utf8_lossy(&output.stderr).trim()
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn quotes_the_value_for_security() {
+        assert_eq!(
+            keychain_command("work", r#"a "b" \c"#).unwrap(),
+            "add-generic-password -U -s portal -a work -w \"a \\\"b\\\" \\\\c\"\n"
+        );
+        assert!(keychain_command("work", "a\nb").is_err());
+    }
+
+    #[test]
+    fn finds_placeholders_by_pointer() {
+        let document = json!({
+            "provider": { "a/b": { "options": { "apiKey": "{secret:work}" } } },
+            "headers": ["plain", "Bearer {secret:token}"],
+        });
+        let mut paths = SecretPaths::new();
+        collect_placeholders(&document, String::new(), &mut paths);
+        assert_eq!(
+            paths,
+            SecretPaths::from([
+                (
+                    "/headers/1".to_string(),
+                    "Bearer {secret:token}".to_string()
+                ),
+                (
+                    "/provider/a~1b/options/apiKey".to_string(),
+                    "{secret:work}".to_string()
+                ),
+            ])
+        );
+        for pointer in paths.keys() {
+            assert!(document.pointer(pointer).is_some());
+        }
+    }
+
+    #[test]
+    fn redacts_in_place() {
+        let text = "{\n  // keys\n  \"options\": { \"apiKey\": \"s3cr3t\", \"timeout\": 5 }\n}\n";
+        let paths =
+            SecretPaths::from([("/options/apiKey".to_string(), "{secret:work}".to_string())]);
+        let redacted = redact_config(text, &paths);
+        assert!(redacted.contains("// keys"));
+        assert!(redacted.contains("\"{secret:work}\""));
+        assert!(!redacted.contains("s3cr3t"));
+        assert_eq!(redact_config(&redacted, &paths), redacted);
+    }
+
+    #[test]
+    fn leaves_what_it_cannot_redact() {
+        let paths = SecretPaths::from([("/apiKey".to_string(), "{secret:work}".to_string())]);
+        assert_eq!(redact_config("{ not json", &paths), "{ not json");
+        // Something other than a string was written there since
+        assert_eq!(redact_config("{\"apiKey\": 5}", &paths), "{\"apiKey\": 5}");
+    }
+
+    #[test]
+    fn resolves_nothing_without_placeholders() {
+        let text = "{ \"apiKey\": \"{env:KEY}\" }";
+        let (resolved, paths) = resolve_config(text).unwrap();
+        assert_eq!(resolved, text);
+        assert!(paths.is_empty());
+    }
+}
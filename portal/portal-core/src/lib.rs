@@ -0,0 +1,70 @@
+//! Core logic behind the `portal` CLI: oh-my-opencode config parsing,
+//! model mappings, and provider switch/revert operations.
+
+pub mod adopt;
+pub mod backup;
+pub mod batch;
+pub mod bisect;
+pub mod bundle;
+pub mod catalog;
+pub mod changes;
+pub mod clock;
+pub mod compat;
+pub mod config;
+pub mod crypto;
+pub mod daemon;
+pub mod diff;
+pub mod discover;
+pub mod doctor;
+pub mod error;
+pub mod events;
+pub mod fleet;
+pub mod fsutil;
+pub mod git;
+pub mod graph;
+pub mod hash;
+pub mod health;
+pub mod hooks;
+pub mod http;
+pub mod init;
+pub mod ipc;
+pub mod journal;
+pub mod jsonc;
+pub mod keychain;
+pub mod lock;
+pub mod log;
+pub mod manage;
+pub mod mapping;
+pub mod matrix;
+pub mod notify;
+pub mod paths;
+pub mod policy;
+pub mod portal;
+pub mod pricing;
+pub mod project;
+pub mod provider;
+pub mod quota;
+pub mod ratelimit;
+pub mod rendered;
+pub mod report;
+pub mod schema;
+pub mod secrets;
+pub mod selftest;
+pub mod service;
+pub mod settings;
+pub mod sources;
+pub mod storage;
+pub mod suggest;
+pub mod switch;
+pub mod sync;
+pub mod temporary;
+pub mod throttle;
+pub mod validate;
+pub mod zstd;
+
+pub use config::{AgentConfig, OhMyOpenCodeConfig};
+pub use error::PortalError;
+pub use mapping::MappingTable;
+pub use portal::{Portal, Status, SwitchOptions, SwitchOutcome, SwitchPreview};
+pub use provider::{AgentModelConfig, ProviderConfig};
+pub use switch::{SwitchContext, SwitchReport};
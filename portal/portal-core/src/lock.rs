@@ -0,0 +1,79 @@
+//! Advisory locks that keep portal processes from writing the same config
+//! at once. The lock lives in a file of its own, since configs are
+//! replaced by rename and a lock on the old file would guard nothing; the
+//! operating system releases it when its holder exits, however that
+//! happens.
+
+use anyhow::{Context, Result, anyhow};
+use std::fs::{self, File, OpenOptions, TryLockError};
+use std::io::{Read, Seek, Write};
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+/// How long to wait for another process to finish writing
+pub const LOCK_TIMEOUT: Duration = Duration::from_secs(10);
+
+const RETRY_INTERVAL: Duration = Duration::from_millis(50);
+
+/// A held lock, released when dropped
+#[derive(Debug)]
+pub struct FileLock {
+    _file: File,
+}
+
+/// Lock `path` exclusively, waiting up to `timeout` for whoever holds it.
+/// `what` names the file being protected, for the error. On filesystems
+/// without locking, carries on without.
+pub fn acquire(path: &Path, what: &Path, timeout: Duration) -> Result<FileLock> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)
+            .with_context(|| format!("Failed to create directory: {}", parent.display()))?;
+    }
+    let mut file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(path)
+        .with_context(|| format!("Failed to open lock file: {}", path.display()))?;
+    let started = Instant::now();
+    loop {
+        match file.try_lock() {
+            Ok(()) => break,
+            Err(TryLockError::WouldBlock) if started.elapsed() < timeout => {
+                std::thread::sleep(RETRY_INTERVAL);
+            }
+            Err(TryLockError::WouldBlock) => {
+                let holder = holder(&mut file)
+                    .map(|pid| format!(" (pid {})", pid))
+                    .unwrap_or_default();
+                return Err(anyhow!(
+                    "Another portal process{} is writing {}\n\n\
+                     Gave up after waiting {}s. Nothing was written; run the command \
+                     again once it has finished.",
+                    holder,
+                    what.display(),
+                    timeout.as_secs()
+                ));
+            }
+            Err(TryLockError::Error(e)) if e.kind() == std::io::ErrorKind::Unsupported => {
+                return Ok(FileLock { _file: file });
+            }
+            Err(TryLockError::Error(e)) => {
+                return Err(e).with_context(|| format!("Failed to lock: {}", path.display()));
+            }
+        }
+    }
+    // Say who holds it, for anyone left waiting
+    let _ = file.set_len(0);
+    let _ = file.rewind();
+    let _ = write!(file, "{}", std::process::id());
+    Ok(FileLock { _file: file })
+}
+
+fn holder(file: &mut File) -> Option<u32> {
+    let mut text = String::new();
+    file.rewind().ok()?;
+    file.read_to_string(&mut text).ok()?;
+    text.trim().parse().ok()
+}
@@ -0,0 +1,89 @@
+//! Diagnostics from the library. Nothing is written anywhere until the
+//! program using it sets a [`Sink`] with [`set_sink`]; the `portal` CLI's
+//! writes warnings to stderr, and more with `-v`.
+//!
+//! Log through the [`portal_warn!`](crate::portal_warn),
+//! [`portal_info!`](crate::portal_info), and
+//! [`portal_debug!`](crate::portal_debug) macros, which skip formatting the
+//! message when the sink doesn't want its level.
+
+use std::sync::OnceLock;
+
+/// How much a log line matters, least verbose first
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Level {
+    /// Something the user should know about, such as a skipped agent
+    Warn,
+    /// A step portal takes, such as a backup or a config write
+    Info,
+    /// A detail for debugging, such as how each agent's model was picked
+    Debug,
+}
+
+impl Level {
+    pub fn name(self) -> &'static str {
+        match self {
+            Level::Warn => "warn",
+            Level::Info => "info",
+            Level::Debug => "debug",
+        }
+    }
+}
+
+/// Where log lines go
+pub trait Sink: Send + Sync {
+    /// Whether lines at `level` are wanted; others aren't even formatted
+    fn enabled(&self, level: Level) -> bool;
+
+    /// Write one line, whatever its level; `target` is the module it comes
+    /// from
+    fn record(&self, level: Level, target: &str, message: &str);
+}
+
+static SINK: OnceLock<Box<dyn Sink>> = OnceLock::new();
+
+/// Send log lines to `sink` for the rest of the run. Only the first sink
+/// set is used: `false` if there was one already.
+pub fn set_sink(sink: impl Sink + 'static) -> bool {
+    SINK.set(Box::new(sink)).is_ok()
+}
+
+/// Whether lines at `level` go anywhere
+pub fn enabled(level: Level) -> bool {
+    SINK.get().is_some_and(|sink| sink.enabled(level))
+}
+
+/// Hand one line to the sink, if there is one, whatever the level
+pub fn record(level: Level, target: &str, message: &str) {
+    if let Some(sink) = SINK.get() {
+        sink.record(level, target, message);
+    }
+}
+
+#[doc(hidden)]
+#[macro_export]
+macro_rules! portal_log_at {
+    ($level:expr, $($arg:tt)+) => {
+        if $crate::log::enabled($level) {
+            $crate::log::record($level, module_path!(), &format!($($arg)+));
+        }
+    };
+}
+
+/// Log a warning
+#[macro_export]
+macro_rules! portal_warn {
+    ($($arg:tt)+) => { $crate::portal_log_at!($crate::log::Level::Warn, $($arg)+) };
+}
+
+/// Log a step portal takes
+#[macro_export]
+macro_rules! portal_info {
+    ($($arg:tt)+) => { $crate::portal_log_at!($crate::log::Level::Info, $($arg)+) };
+}
+
+/// Log a detail for debugging
+#[macro_export]
+macro_rules! portal_debug {
+    ($($arg:tt)+) => { $crate::portal_log_at!($crate::log::Level::Debug, $($arg)+) };
+}
@@ -0,0 +1,642 @@
+//! Adding, removing, renaming, and copying provider files, together with
+//! what refers to a provider by name elsewhere: aliases, command aliases
+//! and environments in `settings.json`, custom columns in `mappings.json`,
+//! `daemon.json` watch entries, `policy.json` pins, pending temporary
+//! switches, and the event history.
+
+use anyhow::{Context, Result, anyhow};
+use serde::Serialize;
+use serde_json::Value;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::error::PortalError;
+use crate::portal::Portal;
+use crate::provider::{self, AgentModelConfig, ProviderConfig};
+use crate::{config, daemon, fsutil, init, jsonc, mapping, policy, settings, temporary};
+
+/// One reference to a provider that was (or, on a dry run, would be)
+/// changed, or that is left pointing at a removed provider
+#[derive(Debug, Clone, Serialize)]
+pub struct Reference {
+    pub path: PathBuf,
+    /// What in the file refers to the provider, e.g. `alias 'or'`
+    pub detail: String,
+}
+
+/// What a rename, copy, or removal touched
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct Changes {
+    /// The provider file written or removed
+    pub file: PathBuf,
+    pub updated: Vec<Reference>,
+    /// References a removal leaves dangling, to fix by hand
+    pub remaining: Vec<Reference>,
+}
+
+/// Write a new provider file `name` naming `agents`, or the current agent
+/// models when there are none
+pub fn add(
+    portal: &Portal,
+    name: &str,
+    agents: &[(String, String)],
+    options: provider::ProviderOptions,
+    force: bool,
+    dry_run: bool,
+) -> Result<(PathBuf, ProviderConfig)> {
+    provider::validate_name(name)?;
+    let path = provider::provider_config_path(portal.portal_dir(), name);
+    if path.exists() && !force {
+        return Err(anyhow!(
+            "Provider '{}' already exists: {}\n\nUse --force to overwrite it.",
+            name,
+            path.display()
+        ));
+    }
+    let mut config = if agents.is_empty() {
+        portal.capture()?
+    } else {
+        ProviderConfig::default()
+    };
+    for (agent, model) in agents {
+        config.agents.insert(
+            agent.clone(),
+            AgentModelConfig {
+                model: model.clone(),
+            },
+        );
+    }
+    config.options = options;
+    if !dry_run {
+        provider::write_provider_config(portal.portal_dir(), name, &config)?;
+    }
+    Ok((path, config))
+}
+
+/// Rename provider `from` to `to`, following every reference to it
+pub fn rename(portal: &Portal, from: &str, to: &str, dry_run: bool) -> Result<Changes> {
+    if provider::is_builtin_name(from) {
+        return Err(anyhow!(
+            "'{}' is a built-in provider and can't be renamed; copy it instead",
+            from
+        ));
+    }
+    provider::validate_name(to)?;
+    let source = existing_file(portal, from)?;
+    let target = provider::provider_config_path(portal.portal_dir(), to);
+    if target.exists() {
+        return Err(anyhow!(
+            "Provider '{}' already exists: {}",
+            to,
+            target.display()
+        ));
+    }
+
+    let mut changes = Changes {
+        file: target.clone(),
+        ..Changes::default()
+    };
+    let dir = portal.portal_dir();
+    edit_document(
+        &settings::settings_file_path(dir),
+        dry_run,
+        &mut changes.updated,
+        |document| rename_in_settings(document, from, to),
+    )?;
+    edit_document(
+        &mapping::mappings_file_path(dir),
+        dry_run,
+        &mut changes.updated,
+        |document| rename_columns(document.get_mut("mappings"), from, to, ""),
+    )?;
+    edit_document(
+        &daemon::daemon_file_path(dir),
+        dry_run,
+        &mut changes.updated,
+        |document| rename_in_daemon(document, from, to),
+    )?;
+    edit_document(
+        &policy::policy_file_path(dir),
+        dry_run,
+        &mut changes.updated,
+        |document| rename_in_policy(document, from, to),
+    )?;
+    for child in provider::list_custom_providers(dir)? {
+        if child == from {
+            continue;
+        }
+        edit_document(
+            &provider::provider_config_path(dir, &child),
+            dry_run,
+            &mut changes.updated,
+            |document| {
+                let Some(extends) = document.get_mut("extends") else {
+                    return Vec::new();
+                };
+                if extends.as_str() != Some(from) {
+                    return Vec::new();
+                }
+                *extends = Value::String(to.to_string());
+                vec!["extends".to_string()]
+            },
+        )?;
+    }
+
+    for path in temporary::record_paths(portal.state_dir()) {
+        let Some(mut record) = temporary::read_record(&path)? else {
+            continue;
+        };
+        let mut renamed = false;
+        for name in std::iter::once(&mut record.provider).chain(record.previous_provider.as_mut()) {
+            if name == from {
+                *name = to.to_string();
+                renamed = true;
+            }
+        }
+        if renamed {
+            if !dry_run {
+                temporary::write_record(&path, &record)?;
+            }
+            changes.updated.push(Reference {
+                detail: format!("temporary switch of {}", record.config.display()),
+                path,
+            });
+        }
+    }
+
+    let events = portal.events();
+    let count = if dry_run {
+        events
+            .read_all()?
+            .into_iter()
+            .filter(|event| event.kind.clone().rename_provider(from, to))
+            .count()
+    } else {
+        events.rename_provider(from, to)?
+    };
+    if count > 0 {
+        changes.updated.push(Reference {
+            path: events.path().to_path_buf(),
+            detail: format!("{} event(s)", count),
+        });
+    }
+
+    if !dry_run {
+        fs::rename(&source, &target).with_context(|| {
+            format!(
+                "Failed to rename {} to {}",
+                source.display(),
+                target.display()
+            )
+        })?;
+    }
+    Ok(changes)
+}
+
+/// Copy provider `from` to a new provider `to`. A built-in provider is
+/// copied as the models it gives the current agents, with any models in
+/// its file on top. Custom columns in `mappings.json` are copied too, so the
+/// copy switches the same way.
+pub fn copy(portal: &Portal, from: &str, to: &str, dry_run: bool) -> Result<Changes> {
+    provider::validate_name(to)?;
+    let target = provider::provider_config_path(portal.portal_dir(), to);
+    if target.exists() {
+        return Err(anyhow!(
+            "Provider '{}' already exists: {}",
+            to,
+            target.display()
+        ));
+    }
+    let mut changes = Changes {
+        file: target.clone(),
+        ..Changes::default()
+    };
+
+    if provider::is_builtin_name(from) {
+        let agents: Vec<(String, String)> = if portal.config_path().exists() {
+            portal
+                .capture()?
+                .agents
+                .into_iter()
+                .map(|(agent, model)| (agent, model.model))
+                .collect()
+        } else {
+            Vec::new()
+        };
+        let mut config = init::example_provider(&agents, from, &portal.mappings()?);
+        if let Some(file) = portal.provider_config(from)? {
+            config.agents.extend(file.agents);
+            config.options = file.options;
+        }
+        if !dry_run {
+            provider::write_provider_config(portal.portal_dir(), to, &config)?;
+        }
+        return Ok(changes);
+    }
+
+    let source = existing_file(portal, from)?;
+    edit_document(
+        &mapping::mappings_file_path(portal.portal_dir()),
+        dry_run,
+        &mut changes.updated,
+        |document| copy_columns(document.get_mut("mappings"), from, to),
+    )?;
+    if !dry_run {
+        let content = fsutil::read_to_string(&source)
+            .with_context(|| format!("Failed to read provider config: {}", source.display()))?;
+        fsutil::write_atomic(&target, content.as_bytes())
+            .with_context(|| format!("Failed to write provider config: {}", target.display()))?;
+    }
+    Ok(changes)
+}
+
+/// Remove provider file `name`, along with the aliases pointing at it and
+/// its `policy.json` pin. Environments, watch entries, and mapping columns
+/// that name it are reported rather than changed. Fails while another
+/// provider file extends it, unless it is built in and so outlives its file.
+pub fn remove(portal: &Portal, name: &str, dry_run: bool) -> Result<Changes> {
+    let source = existing_file(portal, name)?;
+    let dir = portal.portal_dir();
+    let children = extended_by(dir, name)?;
+    if !children.is_empty() && !provider::is_builtin_name(name) {
+        return Err(anyhow!(
+            "Provider '{}' is extended by {}\n\nChange their `extends` first.",
+            name,
+            children.join(", ")
+        ));
+    }
+    let mut changes = Changes {
+        file: source.clone(),
+        ..Changes::default()
+    };
+
+    edit_document(
+        &settings::settings_file_path(dir),
+        dry_run,
+        &mut changes.updated,
+        |document| {
+            let Some(aliases) = document.get_mut("aliases").and_then(Value::as_object_mut) else {
+                return Vec::new();
+            };
+            let removed: Vec<String> = aliases
+                .iter()
+                .filter(|(_, target)| target.as_str() == Some(name))
+                .map(|(alias, _)| alias.clone())
+                .collect();
+            for alias in &removed {
+                aliases.remove(alias);
+            }
+            removed
+                .iter()
+                .map(|alias| format!("removed alias '{}'", alias))
+                .collect()
+        },
+    )?;
+    edit_document(
+        &policy::policy_file_path(dir),
+        dry_run,
+        &mut changes.updated,
+        |document| {
+            let mut details = Vec::new();
+            if let Some(pins) = document.get_mut("providers").and_then(Value::as_object_mut)
+                && pins.remove(name).is_some()
+            {
+                details.push("removed approval pin".to_string());
+            }
+            if let Some(kiosk) = kiosk_providers(document) {
+                let before = kiosk.len();
+                kiosk.retain(|provider| provider.as_str() != Some(name));
+                if kiosk.len() != before {
+                    details.push("removed from kiosk providers".to_string());
+                }
+            }
+            details
+        },
+    )?;
+
+    // Only reported: what to use in their place is the user's call
+    find_references(
+        &settings::settings_file_path(dir),
+        &mut changes.remaining,
+        |document| {
+            if let Some(settings) = document.as_object_mut() {
+                settings.remove("aliases");
+            }
+            rename_in_settings(document, name, "")
+        },
+    )?;
+    find_references(
+        &daemon::daemon_file_path(dir),
+        &mut changes.remaining,
+        |document| rename_in_daemon(document, name, ""),
+    )?;
+    find_references(
+        &mapping::mappings_file_path(dir),
+        &mut changes.remaining,
+        |document| rename_columns(document.get_mut("mappings"), name, "", ""),
+    )?;
+
+    if !dry_run {
+        fs::remove_file(&source)
+            .with_context(|| format!("Failed to remove {}", source.display()))?;
+    }
+    Ok(changes)
+}
+
+fn existing_file(portal: &Portal, name: &str) -> Result<PathBuf> {
+    let path = provider::provider_config_path(portal.portal_dir(), name);
+    if provider::is_reserved_name(name) {
+        return Err(anyhow!("'{}' is a reserved name, not a provider", name));
+    }
+    if !path.exists() {
+        return Err(PortalError::UnknownProvider {
+            name: name.to_string(),
+            portal_dir: portal.portal_dir().to_path_buf(),
+        }
+        .into());
+    }
+    Ok(path)
+}
+
+/// The provider files whose `extends` names `name`
+fn extended_by(portal_dir: &Path, name: &str) -> Result<Vec<String>> {
+    let mut children = Vec::new();
+    for child in provider::list_custom_providers(portal_dir)? {
+        let path = provider::provider_config_path(portal_dir, &child);
+        if let Some((_, document)) = read_document(&path)?
+            && document.get("extends").and_then(Value::as_str) == Some(name)
+        {
+            children.push(child);
+        }
+    }
+    Ok(children)
+}
+
+fn read_document(path: &Path) -> Result<Option<(String, Value)>> {
+    let text = match fsutil::read_to_string(path) {
+        Ok(text) => text,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e).with_context(|| format!("Failed to read {}", path.display())),
+    };
+    let document =
+        jsonc::parse(&text).with_context(|| format!("Failed to parse {}", path.display()))?;
+    Ok(Some((text, document)))
+}
+
+/// Apply `edit` to the JSON file at `path`, if there is one, and write it
+/// back unless on a dry run. `edit` returns what it changed.
+fn edit_document(
+    path: &Path,
+    dry_run: bool,
+    updated: &mut Vec<Reference>,
+    edit: impl FnOnce(&mut Value) -> Vec<String>,
+) -> Result<()> {
+    let Some((original, mut document)) = read_document(path)? else {
+        return Ok(());
+    };
+    let details = edit(&mut document);
+    if details.is_empty() {
+        return Ok(());
+    }
+    if !dry_run {
+        let content = config::render_document(Some(&original), &document)?;
+        fsutil::write_atomic(path, content.as_bytes())
+            .with_context(|| format!("Failed to write {}", path.display()))?;
+    }
+    updated.extend(details.into_iter().map(|detail| Reference {
+        path: path.to_path_buf(),
+        detail,
+    }));
+    Ok(())
+}
+
+/// The references `find` turns up in the JSON file at `path`, by renaming
+/// them in a copy that is thrown away
+fn find_references(
+    path: &Path,
+    found: &mut Vec<Reference>,
+    find: impl FnOnce(&mut Value) -> Vec<String>,
+) -> Result<()> {
+    if let Some((_, mut document)) = read_document(path)? {
+        found.extend(find(&mut document).into_iter().map(|detail| Reference {
+            path: path.to_path_buf(),
+            detail,
+        }));
+    }
+    Ok(())
+}
+
+fn rename_in_settings(document: &mut Value, from: &str, to: &str) -> Vec<String> {
+    let mut details = Vec::new();
+    if let Some(aliases) = document.get_mut("aliases").and_then(Value::as_object_mut) {
+        for (alias, target) in aliases.iter_mut() {
+            if target.as_str() == Some(from) {
+                *target = Value::String(to.to_string());
+                details.push(format!("alias '{}'", alias));
+            }
+        }
+    }
+    if let Some(commands) = document.get_mut("commands").and_then(Value::as_object_mut) {
+        for (name, line) in commands.iter_mut() {
+            let Some(text) = line.as_str() else {
+                continue;
+            };
+            if !text.split_whitespace().any(|word| word == from) {
+                continue;
+            }
+            let text: Vec<&str> = text
+                .split(' ')
+                .map(|word| if word == from { to } else { word })
+                .collect();
+            *line = Value::String(text.join(" "));
+            details.push(format!("command alias '{}'", name));
+        }
+    }
+    if let Some(environments) = document
+        .get_mut("environments")
+        .and_then(Value::as_object_mut)
+    {
+        for (name, environment) in environments.iter_mut() {
+            if let Some(provider) = environment.get_mut("provider")
+                && provider.as_str() == Some(from)
+            {
+                *provider = Value::String(to.to_string());
+                details.push(format!("environment '{}'", name));
+            }
+            details.extend(rename_columns(
+                environment.get_mut("mappings"),
+                from,
+                to,
+                &format!("environment '{}' ", name),
+            ));
+        }
+    }
+    details
+}
+
+fn rename_in_daemon(document: &mut Value, from: &str, to: &str) -> Vec<String> {
+    let mut details = Vec::new();
+    if let Some(entries) = document.get_mut("watch").and_then(Value::as_array_mut) {
+        for (i, entry) in entries.iter_mut().enumerate() {
+            if let Some(provider) = entry.get_mut("provider")
+                && provider.as_str() == Some(from)
+            {
+                *provider = Value::String(to.to_string());
+                details.push(format!("watch entry {}", i + 1));
+            }
+        }
+    }
+    if let Some(fallback) = document.get_mut("fallback") {
+        for key in ["from", "to"] {
+            if let Some(provider) = fallback.get_mut(key)
+                && provider.as_str() == Some(from)
+            {
+                *provider = Value::String(to.to_string());
+                details.push(format!("fallback {}", key));
+            }
+        }
+    }
+    details
+}
+
+fn rename_in_policy(document: &mut Value, from: &str, to: &str) -> Vec<String> {
+    let mut details = Vec::new();
+    // The file's contents don't change, so the pin still holds
+    if let Some(pins) = document.get_mut("providers").and_then(Value::as_object_mut)
+        && let Some(pin) = pins.remove(from)
+    {
+        pins.insert(to.to_string(), pin);
+        details.push("approval pin".to_string());
+    }
+    if let Some(kiosk) = kiosk_providers(document) {
+        for provider in kiosk.iter_mut() {
+            if provider.as_str() == Some(from) {
+                *provider = Value::String(to.to_string());
+                details.push("kiosk providers".to_string());
+            }
+        }
+    }
+    details
+}
+
+fn kiosk_providers(document: &mut Value) -> Option<&mut Vec<Value>> {
+    document
+        .get_mut("kiosk")?
+        .get_mut("providers")?
+        .as_array_mut()
+}
+
+/// Rename the `from` column of each row in a `mappings` object
+fn rename_columns(mappings: Option<&mut Value>, from: &str, to: &str, owner: &str) -> Vec<String> {
+    let Some(rows) = mappings.and_then(Value::as_object_mut) else {
+        return Vec::new();
+    };
+    let mut details = Vec::new();
+    for (base, row) in rows.iter_mut() {
+        if let Some(row) = row.as_object_mut()
+            && let Some(column) = row.remove(from)
+        {
+            row.insert(to.to_string(), column);
+            details.push(format!("{}mapping column for '{}'", owner, base));
+        }
+    }
+    details
+}
+
+/// Add a `to` column with the value of the `from` column to each row in a
+/// `mappings` object that has one
+fn copy_columns(mappings: Option<&mut Value>, from: &str, to: &str) -> Vec<String> {
+    let Some(rows) = mappings.and_then(Value::as_object_mut) else {
+        return Vec::new();
+    };
+    let mut details = Vec::new();
+    for (base, row) in rows.iter_mut() {
+        if let Some(row) = row.as_object_mut()
+            && let Some(column) = row.get(from).cloned()
+            && !row.contains_key(to)
+        {
+            row.insert(to.to_string(), column);
+            details.push(format!("mapping column for '{}'", base));
+        }
+    }
+    details
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A portal in a fresh directory under the system's temporary one, with
+    /// a provider file for each `(name, content)`
+    fn scratch(name: &str, files: &[(&str, &str)]) -> Portal {
+        let dir =
+            std::env::temp_dir().join(format!("portal-manage-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("portal")).unwrap();
+        for (provider, content) in files {
+            fs::write(
+                dir.join("portal").join(format!("{}.json", provider)),
+                content,
+            )
+            .unwrap();
+        }
+        Portal::new(dir.join("oh-my-opencode.json"), dir.join("portal"))
+            .with_state_dir(dir.join("state"))
+    }
+
+    fn read(portal: &Portal, provider: &str) -> Value {
+        let path = provider::provider_config_path(portal.portal_dir(), provider);
+        jsonc::parse(&fs::read_to_string(path).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn rename_follows_extends() {
+        let portal = scratch(
+            "rename",
+            &[
+                ("base", r#"{"agents": {}}"#),
+                (
+                    "child",
+                    "{\n  // Kept\n  \"extends\": \"base\",\n  \"agents\": {}\n}\n",
+                ),
+                ("other", r#"{"extends": "openrouter", "agents": {}}"#),
+            ],
+        );
+
+        let changes = rename(&portal, "base", "work", true).unwrap();
+        assert_eq!(changes.updated.len(), 1);
+        assert_eq!(read(&portal, "child")["extends"], "base");
+
+        let changes = rename(&portal, "base", "work", false).unwrap();
+        assert_eq!(changes.updated.len(), 1);
+        assert_eq!(changes.updated[0].detail, "extends");
+        assert!(!provider::provider_config_path(portal.portal_dir(), "base").exists());
+        assert_eq!(read(&portal, "child")["extends"], "work");
+        assert_eq!(read(&portal, "other")["extends"], "openrouter");
+        let child = provider::provider_config_path(portal.portal_dir(), "child");
+        assert!(fs::read_to_string(child).unwrap().contains("// Kept"));
+    }
+
+    #[test]
+    fn remove_refuses_while_extended() {
+        let portal = scratch(
+            "remove",
+            &[
+                ("base", r#"{"agents": {}}"#),
+                ("child", r#"{"extends": "base", "agents": {}}"#),
+                ("openrouter", r#"{"agents": {}}"#),
+                ("routed", r#"{"extends": "openrouter", "agents": {}}"#),
+            ],
+        );
+
+        let error = remove(&portal, "base", false).unwrap_err().to_string();
+        assert!(error.contains("extended by child"), "{}", error);
+        assert!(provider::provider_config_path(portal.portal_dir(), "base").exists());
+
+        // The built-in provider is still there once its file is gone
+        remove(&portal, "openrouter", false).unwrap();
+        remove(&portal, "child", false).unwrap();
+        remove(&portal, "base", false).unwrap();
+        assert!(!provider::provider_config_path(portal.portal_dir(), "base").exists());
+    }
+}
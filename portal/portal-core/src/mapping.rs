@@ -0,0 +1,540 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::error::PortalError;
+use crate::{config, fsutil, jsonc};
+
+/// File in the portal directory holding user mapping overrides
+pub const MAPPINGS_FILE: &str = "mappings.json";
+
+// ============================================================================
+// Built-in Table
+// ============================================================================
+
+struct BuiltinMapping {
+    base: &'static str,
+    copilot: &'static str,
+    openrouter_provider: &'static str,
+    openrouter_model: &'static str,
+    tier: Tier,
+}
+
+const MODEL_MAPPINGS: &[BuiltinMapping] = &[
+    BuiltinMapping {
+        base: "claude-opus-4.5",
+        copilot: "claude-opus-4.5",
+        openrouter_provider: "anthropic",
+        openrouter_model: "claude-opus-4.5",
+        tier: Tier::Max,
+    },
+    BuiltinMapping {
+        base: "claude-sonnet-4.5",
+        copilot: "claude-sonnet-4.5",
+        openrouter_provider: "anthropic",
+        openrouter_model: "claude-sonnet-4.5",
+        tier: Tier::Balanced,
+    },
+    BuiltinMapping {
+        base: "claude-sonnet-4",
+        copilot: "claude-sonnet-4",
+        openrouter_provider: "anthropic",
+        openrouter_model: "claude-sonnet-4",
+        tier: Tier::Balanced,
+    },
+    BuiltinMapping {
+        base: "gpt-5.2",
+        copilot: "gpt-5.2",
+        openrouter_provider: "openai",
+        openrouter_model: "gpt-5.2",
+        tier: Tier::Max,
+    },
+    BuiltinMapping {
+        base: "gpt-4.1",
+        copilot: "gpt-4.1",
+        openrouter_provider: "openai",
+        openrouter_model: "gpt-4.1",
+        tier: Tier::Balanced,
+    },
+    BuiltinMapping {
+        base: "o3",
+        copilot: "o3",
+        openrouter_provider: "openai",
+        openrouter_model: "o3",
+        tier: Tier::Max,
+    },
+    BuiltinMapping {
+        base: "o4-mini",
+        copilot: "o4-mini",
+        openrouter_provider: "openai",
+        openrouter_model: "o4-mini",
+        tier: Tier::Fast,
+    },
+    BuiltinMapping {
+        base: "gemini-3-flash",
+        copilot: "gemini-3-flash",
+        openrouter_provider: "google",
+        openrouter_model: "gemini-3-flash-preview",
+        tier: Tier::Fast,
+    },
+    BuiltinMapping {
+        base: "gemini-3-pro",
+        copilot: "gemini-3-pro",
+        openrouter_provider: "google",
+        openrouter_model: "gemini-3-pro-preview",
+        tier: Tier::Max,
+    },
+];
+
+// ============================================================================
+// Tiers
+// ============================================================================
+
+/// How capable a model is, and roughly what it costs: the smaller, quicker
+/// models are `fast`, the flagships `max`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Tier {
+    Fast,
+    Balanced,
+    Max,
+}
+
+impl Tier {
+    pub const ALL: [Tier; 3] = [Tier::Fast, Tier::Balanced, Tier::Max];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Tier::Fast => "fast",
+            Tier::Balanced => "balanced",
+            Tier::Max => "max",
+        }
+    }
+
+    /// The tier a base model's name suggests, for rows that don't give one:
+    /// `opus`, `-pro`, and `-max` models are `max`, `sonnet` ones
+    /// `balanced`, and `haiku`, `flash`, `mini`, `nano`, and `lite` ones
+    /// `fast`
+    pub fn guess(base_model: &str) -> Option<Tier> {
+        let name = base_model.to_lowercase();
+        let has_part = |part: &str| name.split(['-', '.', '_', ':']).any(|p| p == part);
+        if ["haiku", "flash", "mini", "nano", "lite"]
+            .iter()
+            .any(|part| has_part(part))
+        {
+            Some(Tier::Fast)
+        } else if has_part("opus") || has_part("pro") || has_part("max") {
+            Some(Tier::Max)
+        } else if has_part("sonnet") {
+            Some(Tier::Balanced)
+        } else {
+            None
+        }
+    }
+}
+
+impl fmt::Display for Tier {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+// ============================================================================
+// Mapping Table
+// ============================================================================
+
+/// One row of the model table: a base model and its per-provider names
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ModelMapping {
+    pub base: String,
+    pub copilot: String,
+    pub openrouter_provider: String,
+    pub openrouter_model: String,
+    /// Extra provider columns: provider name -> full model string
+    pub providers: BTreeMap<String, String>,
+    pub tier: Option<Tier>,
+}
+
+impl ModelMapping {
+    fn matches(&self, base_model: &str, table: &MappingTable) -> bool {
+        self.base == base_model
+            || self.copilot == base_model
+            || self.openrouter_model == base_model
+            || self
+                .providers
+                .values()
+                .any(|m| m == base_model || table.base_model(m) == base_model)
+    }
+}
+
+/// User overrides as stored in `mappings.json`
+///
+/// ```json
+/// {
+///   "mappings": {
+///     "claude-opus-4.6": {
+///       "copilot": "claude-opus-4.6",
+///       "openrouter": "anthropic/claude-opus-4.6",
+///       "bedrock": "bedrock/anthropic.claude-opus-4-6-v1:0",
+///       "tier": "max"
+///     }
+///   },
+///   "canonicalize": {
+///     "work-gateway": { "segments": 3, "variant_separator": ":", "ignore_case": true }
+///   }
+/// }
+/// ```
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct MappingsFile {
+    #[serde(default)]
+    pub mappings: BTreeMap<String, MappingOverride>,
+    /// How to find the base model in model strings, by provider prefix
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub canonicalize: BTreeMap<String, CanonicalRule>,
+}
+
+/// How to find the base model in model strings under one provider prefix,
+/// for gateways whose slugs don't follow the usual layouts. Without a rule,
+/// up to three leading segments are dropped, keeping at least one:
+/// `openrouter/anthropic/claude-opus-4.5` is `claude-opus-4.5`.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CanonicalRule {
+    /// Path segments before the model name, the provider ID included;
+    /// `3` makes `gw/fast/anthropic/claude-opus-4.5` `claude-opus-4.5`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub segments: Option<usize>,
+    /// Where a variant starts in the model name (`:` in `model:thinking`,
+    /// `@` in `model@2025-01-01`); it is dropped from the last one on
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub variant_separator: Option<String>,
+    /// Compare model names case-insensitively, by lowercasing them
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub ignore_case: bool,
+}
+
+impl CanonicalRule {
+    /// The base model in `model` under this rule
+    pub fn apply(&self, model: &str) -> String {
+        let mut base = match self.segments {
+            Some(segments) => {
+                let parts: Vec<&str> = model.split('/').collect();
+                // Slugs shorter than the rule expects keep their last segment
+                let skip = segments.min(parts.len().saturating_sub(1));
+                parts[skip..].join("/")
+            }
+            None => extract_base_model(model),
+        };
+        if let Some(separator) = self.variant_separator.as_deref().filter(|s| !s.is_empty())
+            && let Some(at) = base.rfind(separator)
+            && at > 0
+        {
+            base.truncate(at);
+        }
+        if self.ignore_case {
+            base = base.to_lowercase();
+        }
+        base
+    }
+}
+
+/// Columns to set for one base model; unset columns keep their current value
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct MappingOverride {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub copilot: Option<String>,
+    /// OpenRouter slug as `provider/model`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub openrouter: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tier: Option<Tier>,
+    /// Custom provider columns, written to the config as-is
+    #[serde(flatten)]
+    pub providers: BTreeMap<String, String>,
+}
+
+/// Built-in mappings merged with any user overrides
+#[derive(Debug, Clone)]
+pub struct MappingTable {
+    rows: Vec<ModelMapping>,
+    /// Canonicalization rules by provider prefix
+    rules: BTreeMap<String, CanonicalRule>,
+}
+
+impl Default for MappingTable {
+    fn default() -> Self {
+        Self::builtin()
+    }
+}
+
+impl MappingTable {
+    pub fn builtin() -> Self {
+        let rows = MODEL_MAPPINGS
+            .iter()
+            .map(|m| ModelMapping {
+                base: m.base.to_string(),
+                copilot: m.copilot.to_string(),
+                openrouter_provider: m.openrouter_provider.to_string(),
+                openrouter_model: m.openrouter_model.to_string(),
+                providers: BTreeMap::new(),
+                tier: Some(m.tier),
+            })
+            .collect();
+        Self {
+            rows,
+            rules: BTreeMap::new(),
+        }
+    }
+
+    /// Built-in table merged with `mappings.json` from `portal_dir`, if present
+    pub fn load(portal_dir: &Path) -> Result<Self> {
+        let mut table = Self::builtin();
+        if let Some(file) = read_mappings_file(portal_dir)? {
+            table.merge(file);
+        }
+        Ok(table)
+    }
+
+    /// Apply user overrides: existing rows are updated column by column,
+    /// unknown base models are appended, with the tier their name suggests
+    /// unless one is given
+    pub fn merge(&mut self, file: MappingsFile) {
+        self.rules.extend(file.canonicalize);
+        for (base, over) in file.mappings {
+            let idx = match self.rows.iter().position(|m| m.base == base) {
+                Some(idx) => idx,
+                None => {
+                    let (openrouter_provider, openrouter_model) =
+                        split_openrouter_slug(&infer_openrouter_slug(&base));
+                    self.rows.push(ModelMapping {
+                        copilot: base.clone(),
+                        openrouter_provider,
+                        openrouter_model,
+                        tier: Tier::guess(&base),
+                        base: base.clone(),
+                        providers: BTreeMap::new(),
+                    });
+                    self.rows.len() - 1
+                }
+            };
+
+            let row = &mut self.rows[idx];
+            if let Some(copilot) = over.copilot {
+                row.copilot = copilot;
+            }
+            if let Some(slug) = over.openrouter {
+                (row.openrouter_provider, row.openrouter_model) = split_openrouter_slug(&slug);
+            }
+            if let Some(tier) = over.tier {
+                row.tier = Some(tier);
+            }
+            row.providers.extend(over.providers);
+        }
+    }
+
+    pub fn rows(&self) -> &[ModelMapping] {
+        &self.rows
+    }
+
+    pub fn find(&self, base_model: &str) -> Option<&ModelMapping> {
+        self.rows.iter().find(|m| m.matches(base_model, self))
+    }
+
+    /// The base model in a full model string, by the rule for its provider
+    /// prefix (the longest one that matches) or the default layouts
+    pub fn base_model(&self, model: &str) -> String {
+        let rule = self
+            .rules
+            .iter()
+            .filter(|(prefix, _)| {
+                model
+                    .strip_prefix(prefix.as_str())
+                    .is_some_and(|rest| rest.starts_with('/'))
+            })
+            .max_by_key(|(prefix, _)| prefix.len())
+            .map(|(_, rule)| rule);
+        match rule {
+            Some(rule) => rule.apply(model),
+            None => extract_base_model(model),
+        }
+    }
+
+    /// The tier of the model in a full model string, if its row has one
+    pub fn tier(&self, model: &str) -> Option<Tier> {
+        self.find(&self.base_model(model))?.tier
+    }
+
+    /// Model string for `base_model` on `provider`, if the table has one
+    pub fn transform(&self, base_model: &str, provider: &str) -> Option<String> {
+        let mapping = self.find(base_model)?;
+
+        if let Some(model) = mapping.providers.get(provider) {
+            return Some(model.clone());
+        }
+        match provider {
+            "copilot" | "github-copilot" => Some(format!("github-copilot/{}", mapping.copilot)),
+            "openrouter" => Some(format!(
+                "openrouter/{}/{}",
+                mapping.openrouter_provider, mapping.openrouter_model
+            )),
+            _ => None,
+        }
+    }
+}
+
+pub fn mappings_file_path(portal_dir: &Path) -> PathBuf {
+    portal_dir.join(MAPPINGS_FILE)
+}
+
+pub fn read_mappings_file(portal_dir: &Path) -> Result<Option<MappingsFile>> {
+    let path = mappings_file_path(portal_dir);
+    if !path.exists() {
+        return Ok(None);
+    }
+    let content = fsutil::read_to_string(&path)
+        .with_context(|| format!("Failed to read mappings file: {}", path.display()))?;
+    let file = serde_json::from_str(&jsonc::strip(&content))
+        .map_err(PortalError::parse("mappings file", &path))?;
+    Ok(Some(file))
+}
+
+/// Write `mappings.json`, keeping the comments and layout of the file it
+/// replaces where the contents agree
+pub fn write_mappings_file(portal_dir: &Path, file: &MappingsFile) -> Result<PathBuf> {
+    fs::create_dir_all(portal_dir).with_context(|| {
+        format!(
+            "Failed to create portal directory: {}",
+            portal_dir.display()
+        )
+    })?;
+    let path = mappings_file_path(portal_dir);
+    let original = fsutil::read_to_string(&path).ok();
+    let content = config::render_document(original.as_deref(), &serde_json::to_value(file)?)?;
+    fsutil::write_atomic(&path, content.as_bytes())
+        .with_context(|| format!("Failed to write mappings file: {}", path.display()))?;
+    Ok(path)
+}
+
+/// Starter override for a base model missing from the table, using the
+/// same defaults [`MappingTable::merge`] would apply
+pub fn mapping_stub(base_model: &str) -> MappingOverride {
+    MappingOverride {
+        copilot: Some(base_model.to_string()),
+        openrouter: Some(infer_openrouter_slug(base_model)),
+        tier: Tier::guess(base_model),
+        providers: BTreeMap::new(),
+    }
+}
+
+fn split_openrouter_slug(slug: &str) -> (String, String) {
+    match slug.split_once('/') {
+        Some((provider, model)) => (provider.to_string(), model.to_string()),
+        None => ("unknown".to_string(), slug.to_string()),
+    }
+}
+
+// ============================================================================
+// Model Name Helpers
+// ============================================================================
+
+/// The base model in a full model string by the default layouts, with no
+/// canonicalization rules; see [`MappingTable::base_model`]
+pub fn extract_base_model(model: &str) -> String {
+    let parts: Vec<&str> = model.split('/').collect();
+    match parts.as_slice() {
+        [_, model] => model.to_string(),
+        [_, _, model] => model.to_string(),
+        [_, _, _, rest @ ..] => rest.join("/"),
+        _ => model.to_string(),
+    }
+}
+
+/// Best-guess OpenRouter `provider/model` slug from the model family
+fn infer_openrouter_slug(base_model: &str) -> String {
+    let provider = if base_model.starts_with("claude") {
+        "anthropic"
+    } else if base_model.starts_with("gpt")
+        || base_model.starts_with("o1")
+        || base_model.starts_with("o3")
+        || base_model.starts_with("o4")
+    {
+        "openai"
+    } else if base_model.starts_with("gemini") {
+        "google"
+    } else {
+        "unknown"
+    };
+    format!("{}/{}", provider, base_model)
+}
+
+pub fn infer_openrouter_model(base_model: &str) -> String {
+    format!("openrouter/{}", infer_openrouter_slug(base_model))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn table(mappings: serde_json::Value) -> MappingTable {
+        let mut table = MappingTable::builtin();
+        table.merge(serde_json::from_value(mappings).unwrap());
+        table
+    }
+
+    #[test]
+    fn overrides_replace_only_the_columns_they_set() {
+        let table = table(serde_json::json!({
+            "mappings": {
+                "claude-opus-4.5": { "copilot": "claude-opus-4.5-preview", "gateway": "gw/opus" },
+            },
+        }));
+        assert_eq!(
+            table.transform("claude-opus-4.5", "copilot").as_deref(),
+            Some("github-copilot/claude-opus-4.5-preview")
+        );
+        assert_eq!(
+            table.transform("claude-opus-4.5", "openrouter").as_deref(),
+            Some("openrouter/anthropic/claude-opus-4.5")
+        );
+        assert_eq!(
+            table.transform("claude-opus-4.5", "gateway").as_deref(),
+            Some("gw/opus")
+        );
+        assert_eq!(table.transform("claude-sonnet-4.5", "gateway"), None);
+    }
+
+    #[test]
+    fn unknown_base_models_are_appended_with_a_guessed_tier() {
+        let table = table(serde_json::json!({
+            "mappings": { "claude-haiku-9": {} },
+        }));
+        let row = table.find("claude-haiku-9").unwrap();
+        assert_eq!(row.copilot, "claude-haiku-9");
+        assert_eq!(row.tier, Tier::guess("claude-haiku-9"));
+    }
+
+    #[test]
+    fn longest_canonical_prefix_wins() {
+        let table = table(serde_json::json!({
+            "canonicalize": {
+                "gw": { "segments": 1 },
+                "gw/fast": { "segments": 3, "variant_separator": ":" },
+            },
+        }));
+        assert_eq!(
+            table.base_model("gw/fast/anthropic/claude-opus-4.5:thinking"),
+            "claude-opus-4.5"
+        );
+        assert_eq!(
+            table.base_model("gw/anthropic/claude-opus-4.5"),
+            "anthropic/claude-opus-4.5"
+        );
+        // `gw` is a prefix of `gwx` but not a path segment of it
+        assert_eq!(
+            table.base_model("gwx/anthropic/claude-opus-4.5"),
+            "claude-opus-4.5"
+        );
+    }
+}
@@ -0,0 +1,127 @@
+use anyhow::{Context, Result, anyhow};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use crate::error::PortalError;
+use crate::fsutil;
+use crate::jsonc;
+use crate::mapping::MappingTable;
+use crate::provider::{self, AgentModelConfig, ProviderConfig};
+
+/// Compact definition of a family of provider files: every combination of
+/// provider, tier, and (optionally) account becomes one file
+///
+/// ```json
+/// {
+///   "name": "{account}-{provider}-{tier}",
+///   "providers": ["copilot", "openrouter"],
+///   "tiers": {
+///     "max":  { "sisyphus": "claude-opus-4.5",   "oracle": "gpt-5.2" },
+///     "fast": { "sisyphus": "claude-sonnet-4.5", "oracle": "gpt-5.2" }
+///   },
+///   "accounts": {
+///     "personal": {},
+///     "work": { "prefixes": { "openrouter": "openrouter-work" } }
+///   }
+/// }
+/// ```
+#[derive(Debug, Clone, Deserialize)]
+pub struct Matrix {
+    /// Provider file name template using `{provider}`, `{tier}`, `{account}`
+    #[serde(default)]
+    pub name: Option<String>,
+    /// Provider columns to translate each tier's base models with
+    pub providers: Vec<String>,
+    /// Tier name to `agent -> model`. Models are base names translated
+    /// through the mapping table, or full `provider/model` strings used
+    /// as-is.
+    pub tiers: BTreeMap<String, BTreeMap<String, String>>,
+    #[serde(default)]
+    pub accounts: BTreeMap<String, Account>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct Account {
+    /// Provider column to the opencode provider ID this account uses in
+    /// place of the default one (the first segment of the model string)
+    #[serde(default)]
+    pub prefixes: BTreeMap<String, String>,
+}
+
+pub fn read_matrix(path: &Path) -> Result<Matrix> {
+    let content = fsutil::read_to_string(path)
+        .with_context(|| format!("Failed to read matrix file: {}", path.display()))?;
+    Ok(serde_json::from_str(&jsonc::strip(&content))
+        .map_err(PortalError::parse("matrix file", path))?)
+}
+
+impl Matrix {
+    fn template(&self) -> &str {
+        match &self.name {
+            Some(name) => name,
+            None if self.accounts.is_empty() => "{provider}-{tier}",
+            None => "{account}-{provider}-{tier}",
+        }
+    }
+
+    /// Every provider file the matrix describes, keyed by file name
+    pub fn expand(&self, mappings: &MappingTable) -> Result<BTreeMap<String, ProviderConfig>> {
+        // No accounts means a single, unnamed one
+        let accounts: Vec<(&str, Account)> = if self.accounts.is_empty() {
+            vec![("", Account::default())]
+        } else {
+            self.accounts
+                .iter()
+                .map(|(name, account)| (name.as_str(), account.clone()))
+                .collect()
+        };
+
+        let mut files = BTreeMap::new();
+        for (account_name, account) in &accounts {
+            for provider in &self.providers {
+                for (tier, agents) in &self.tiers {
+                    let name = self
+                        .template()
+                        .replace("{account}", account_name)
+                        .replace("{provider}", provider)
+                        .replace("{tier}", tier);
+                    provider::validate_name(&name)?;
+
+                    let mut config = ProviderConfig::default();
+                    for (agent, model) in agents {
+                        let mut model = if model.contains('/') {
+                            model.clone()
+                        } else {
+                            mappings
+                                .transform(model, provider)
+                                .ok_or_else(|| PortalError::MappingMissing {
+                                    model: model.clone(),
+                                    provider: provider.clone(),
+                                })
+                                .with_context(|| format!("Tier '{}', agent '{}'", tier, agent))?
+                        };
+                        if let Some(prefix) = account.prefixes.get(provider)
+                            && let Some((_, rest)) = model.split_once('/')
+                        {
+                            model = format!("{}/{}", prefix, rest);
+                        }
+                        config
+                            .agents
+                            .insert(agent.clone(), AgentModelConfig { model });
+                    }
+
+                    if files.insert(name.clone(), config).is_some() {
+                        return Err(anyhow!(
+                            "Matrix name template '{}' produces '{}' more than once; \
+                             include every dimension that has several values",
+                            self.template(),
+                            name
+                        ));
+                    }
+                }
+            }
+        }
+        Ok(files)
+    }
+}
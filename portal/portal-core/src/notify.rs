@@ -0,0 +1,53 @@
+//! Desktop notifications, for switches portal makes on its own (watch
+//! mode, the daemon, rate-limit fallback, temporary switches ending) with
+//! `"notify": true` in settings.json. Sent with `osascript` on macOS and
+//! `notify-send` elsewhere.
+
+use anyhow::{Context, Result, anyhow};
+use std::process::Command;
+
+/// Title of every notification
+const TITLE: &str = "portal";
+
+/// Show `message` as a desktop notification
+pub fn send(message: &str) -> Result<()> {
+    let output = if cfg!(target_os = "macos") {
+        let script = format!(
+            "display notification {} with title {}",
+            applescript_string(message),
+            applescript_string(TITLE)
+        );
+        Command::new("osascript")
+            .args(["-e", &script])
+            .output()
+            .context("Failed to run osascript to show a notification")?
+    } else {
+        Command::new("notify-send")
+            .args(["--app-name", TITLE, TITLE, message])
+            .output()
+            .context("Failed to run notify-send to show a notification")?
+    };
+    if !output.status.success() {
+        return Err(anyhow!(
+            "Failed to show a notification: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    Ok(())
+}
+
+/// The message for a switch of `config`, from `previous` (if known) to
+/// `provider`, saying why
+pub fn switch_message(config: &str, previous: Option<&str>, provider: &str, why: &str) -> String {
+    match previous {
+        Some(previous) if previous != provider => format!(
+            "Switched {} from '{}' to '{}': {}",
+            config, previous, provider, why
+        ),
+        _ => format!("Switched {} to '{}': {}", config, provider, why),
+    }
+}
+
+fn applescript_string(text: &str) -> String {
+    format!("\"{}\"", text.replace('\\', "\\\\").replace('"', "\\\""))
+}
@@ -0,0 +1,276 @@
+use std::path::{Path, PathBuf};
+
+/// What portal's directories depend on: the platform and the environment
+/// variables that move them. [`Env::current`] reads the real ones; tests
+/// and embedders can fill one in by hand.
+#[derive(Debug, Clone, Default)]
+pub struct Env {
+    pub windows: bool,
+    pub home: Option<PathBuf>,
+    pub xdg_config_home: Option<PathBuf>,
+    pub xdg_data_home: Option<PathBuf>,
+    pub xdg_state_home: Option<PathBuf>,
+    /// `%APPDATA%`, only consulted on Windows
+    pub appdata: Option<PathBuf>,
+    /// `%LOCALAPPDATA%`, only consulted on Windows
+    pub local_appdata: Option<PathBuf>,
+}
+
+impl Env {
+    /// This process's platform and environment
+    pub fn current() -> Self {
+        let var = |name: &str| std::env::var_os(name).map(PathBuf::from);
+        Self {
+            windows: cfg!(windows),
+            home: dirs::home_dir(),
+            xdg_config_home: var("XDG_CONFIG_HOME"),
+            xdg_data_home: var("XDG_DATA_HOME"),
+            xdg_state_home: var("XDG_STATE_HOME"),
+            appdata: dirs::config_dir(),
+            local_appdata: dirs::data_local_dir(),
+        }
+    }
+
+    fn home_dir(&self) -> PathBuf {
+        self.home
+            .clone()
+            .expect("Could not determine home directory")
+    }
+
+    /// Where opencode looks for its config: `$XDG_CONFIG_HOME`, falling
+    /// back to `~/.config` on every platform, Windows included
+    fn xdg_config_home(&self) -> PathBuf {
+        self.xdg_config_home
+            .clone()
+            .unwrap_or_else(|| self.home_dir().join(".config"))
+    }
+
+    /// Base config directory: `$XDG_CONFIG_HOME`, falling back to
+    /// `~/.config`, or to `%APPDATA%` on Windows
+    pub fn config_home(&self) -> PathBuf {
+        if self.windows
+            && self.xdg_config_home.is_none()
+            && let Some(appdata) = &self.appdata
+        {
+            return appdata.clone();
+        }
+        self.xdg_config_home()
+    }
+
+    /// Directory holding portal's own files (custom providers, settings).
+    /// On Windows a `~/.config/portal` from before portal used `%APPDATA%`
+    /// is kept as long as there is nothing in the new place.
+    pub fn portal_dir(&self) -> PathBuf {
+        let dir = self.config_home().join("portal");
+        let legacy = self.xdg_config_home().join("portal");
+        if self.windows && !dir.exists() && legacy.exists() {
+            legacy
+        } else {
+            dir
+        }
+    }
+
+    /// Directory holding opencode's config files. opencode uses `~/.config`
+    /// on Windows too; `%APPDATA%\opencode` is only used when it alone
+    /// exists.
+    fn opencode_dir(&self) -> PathBuf {
+        let dir = self.xdg_config_home().join("opencode");
+        if self.windows
+            && !dir.exists()
+            && let Some(appdata) = self.appdata.as_ref().map(|d| d.join("opencode"))
+            && appdata.exists()
+        {
+            return appdata;
+        }
+        dir
+    }
+
+    /// Directory opencode keeps its data and logs in:
+    /// `$XDG_DATA_HOME/opencode`, falling back to `~/.local/share/opencode`
+    /// on every platform
+    pub fn opencode_data_dir(&self) -> PathBuf {
+        self.xdg_data_home
+            .clone()
+            .unwrap_or_else(|| self.home_dir().join(".local").join("share"))
+            .join("opencode")
+    }
+
+    /// Default location of oh-my-opencode.json, or oh-my-opencode.jsonc
+    /// when only that exists
+    pub fn default_config_path(&self) -> PathBuf {
+        let dir = self.opencode_dir();
+        let json = dir.join("oh-my-opencode.json");
+        let jsonc = dir.join("oh-my-opencode.jsonc");
+        if !json.exists() && jsonc.exists() {
+            jsonc
+        } else {
+            json
+        }
+    }
+
+    /// Base state directory: `$XDG_STATE_HOME`, falling back to
+    /// `~/.local/state`, or to `%LOCALAPPDATA%` on Windows
+    pub fn state_home(&self) -> PathBuf {
+        if let Some(dir) = &self.xdg_state_home {
+            return dir.clone();
+        }
+        if self.windows
+            && let Some(local) = &self.local_appdata
+        {
+            return local.clone();
+        }
+        self.home_dir().join(".local").join("state")
+    }
+
+    /// Directory holding portal's runtime state (event log, journals)
+    pub fn state_dir(&self) -> PathBuf {
+        self.state_home().join("portal")
+    }
+}
+
+/// [`Env::config_home`] for this process
+pub fn config_home() -> PathBuf {
+    Env::current().config_home()
+}
+
+/// [`Env::portal_dir`] for this process
+pub fn portal_dir() -> PathBuf {
+    Env::current().portal_dir()
+}
+
+/// [`Env::opencode_data_dir`] for this process
+pub fn opencode_data_dir() -> PathBuf {
+    Env::current().opencode_data_dir()
+}
+
+/// [`Env::default_config_path`] for this process
+pub fn default_config_path() -> PathBuf {
+    Env::current().default_config_path()
+}
+
+/// [`Env::state_home`] for this process
+pub fn state_home() -> PathBuf {
+    Env::current().state_home()
+}
+
+/// [`Env::state_dir`] for this process
+pub fn state_dir() -> PathBuf {
+    Env::current().state_dir()
+}
+
+/// Replace a leading `~/` (or `~\` on Windows) with the home directory
+pub fn expand_home(path: &Path) -> PathBuf {
+    match (path.strip_prefix("~"), dirs::home_dir()) {
+        (Ok(rest), Some(home)) => home.join(rest),
+        _ => path.to_path_buf(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    /// A platform whose home is somewhere that doesn't exist, so no
+    /// fallback is taken because of files left on the machine running
+    /// the tests
+    fn env(windows: bool) -> Env {
+        Env {
+            windows,
+            home: Some(PathBuf::from("/nonexistent/home")),
+            ..Env::default()
+        }
+    }
+
+    #[test]
+    fn uses_xdg_directories_when_set() {
+        for windows in [false, true] {
+            let env = Env {
+                xdg_config_home: Some(PathBuf::from("/xdg/config")),
+                xdg_data_home: Some(PathBuf::from("/xdg/data")),
+                xdg_state_home: Some(PathBuf::from("/xdg/state")),
+                appdata: Some(PathBuf::from("/appdata")),
+                local_appdata: Some(PathBuf::from("/localappdata")),
+                ..env(windows)
+            };
+            assert_eq!(env.portal_dir(), Path::new("/xdg/config/portal"));
+            assert_eq!(
+                env.default_config_path(),
+                Path::new("/xdg/config/opencode/oh-my-opencode.json")
+            );
+            assert_eq!(env.opencode_data_dir(), Path::new("/xdg/data/opencode"));
+            assert_eq!(env.state_dir(), Path::new("/xdg/state/portal"));
+        }
+    }
+
+    #[test]
+    fn falls_back_to_the_home_directory() {
+        let env = env(false);
+        assert_eq!(env.config_home(), Path::new("/nonexistent/home/.config"));
+        assert_eq!(
+            env.portal_dir(),
+            Path::new("/nonexistent/home/.config/portal")
+        );
+        assert_eq!(
+            env.default_config_path(),
+            Path::new("/nonexistent/home/.config/opencode/oh-my-opencode.json")
+        );
+        assert_eq!(
+            env.opencode_data_dir(),
+            Path::new("/nonexistent/home/.local/share/opencode")
+        );
+        assert_eq!(
+            env.state_dir(),
+            Path::new("/nonexistent/home/.local/state/portal")
+        );
+    }
+
+    #[test]
+    fn uses_appdata_on_windows() {
+        let env = Env {
+            appdata: Some(PathBuf::from("/appdata")),
+            local_appdata: Some(PathBuf::from("/localappdata")),
+            ..env(true)
+        };
+        assert_eq!(env.portal_dir(), Path::new("/appdata/portal"));
+        assert_eq!(env.state_dir(), Path::new("/localappdata/portal"));
+        // opencode itself keeps to ~/.config on Windows too
+        assert_eq!(
+            env.default_config_path(),
+            Path::new("/nonexistent/home/.config/opencode/oh-my-opencode.json")
+        );
+        // Off Windows, APPDATA means nothing
+        let linux = Env {
+            windows: false,
+            ..env
+        };
+        assert_eq!(
+            linux.portal_dir(),
+            Path::new("/nonexistent/home/.config/portal")
+        );
+    }
+
+    #[test]
+    fn keeps_whichever_windows_directory_exists() {
+        let root = std::env::temp_dir().join(format!("portal-paths-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        let home = root.join("home");
+        let appdata = root.join("appdata");
+        fs::create_dir_all(home.join(".config/portal")).unwrap();
+        fs::create_dir_all(appdata.join("opencode")).unwrap();
+        let env = Env {
+            windows: true,
+            home: Some(home.clone()),
+            appdata: Some(appdata.clone()),
+            ..Env::default()
+        };
+        // ~/.config/portal from before %APPDATA% was used
+        assert_eq!(env.portal_dir(), home.join(".config/portal"));
+        // %APPDATA%\opencode when it alone exists
+        assert_eq!(
+            env.default_config_path(),
+            appdata.join("opencode/oh-my-opencode.json")
+        );
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
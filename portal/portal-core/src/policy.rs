@@ -0,0 +1,278 @@
+use anyhow::{Context, Result, anyhow};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::error::PortalError;
+use crate::provider;
+use crate::{config, fsutil, hash, jsonc};
+
+/// File in the portal directory holding the provider approval policy
+pub const POLICY_FILE: &str = "policy.json";
+
+/// Which provider definitions may be switched to
+///
+/// ```json
+/// {
+///   "require_pinned": true,
+///   "providers": { "work-gateway": { "sha256": "3f2a...e9" } },
+///   "kiosk": { "providers": ["copilot", "work-gateway"] }
+/// }
+/// ```
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Policy {
+    /// Refuse custom providers that have no pin at all
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub require_pinned: bool,
+    #[serde(default)]
+    pub providers: BTreeMap<String, ProviderPin>,
+    /// Restrict portal to switching among a few providers
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub kiosk: Option<Kiosk>,
+}
+
+/// Kiosk mode, for shared machines: users may switch among `providers` but
+/// nothing else portal can change is open to them
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Kiosk {
+    /// Providers that may be switched to; when absent, the ones pinned in
+    /// `providers`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub providers: Option<Vec<String>>,
+}
+
+/// Approved contents of one provider file
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ProviderPin {
+    pub sha256: String,
+}
+
+pub fn policy_file_path(portal_dir: &Path) -> PathBuf {
+    portal_dir.join(POLICY_FILE)
+}
+
+pub fn read_policy(portal_dir: &Path) -> Result<Option<Policy>> {
+    let path = policy_file_path(portal_dir);
+    if !path.exists() {
+        return Ok(None);
+    }
+    let content = fsutil::read_to_string(&path)
+        .with_context(|| format!("Failed to read policy file: {}", path.display()))?;
+    let policy = serde_json::from_str(&jsonc::strip(&content))
+        .map_err(PortalError::parse("policy file", &path))?;
+    Ok(Some(policy))
+}
+
+/// Write `policy.json`, keeping the comments and layout of the file it
+/// replaces where the contents agree
+pub fn write_policy(portal_dir: &Path, policy: &Policy) -> Result<PathBuf> {
+    fs::create_dir_all(portal_dir).with_context(|| {
+        format!(
+            "Failed to create portal directory: {}",
+            portal_dir.display()
+        )
+    })?;
+    let path = policy_file_path(portal_dir);
+    let original = fsutil::read_to_string(&path).ok();
+    let content = config::render_document(original.as_deref(), &serde_json::to_value(policy)?)?;
+    fsutil::write_atomic(&path, content.as_bytes())
+        .with_context(|| format!("Failed to write policy file: {}", path.display()))?;
+    Ok(path)
+}
+
+/// SHA-256 of a provider file as it is on disk, `None` if it doesn't exist
+pub fn provider_hash(portal_dir: &Path, name: &str) -> Result<Option<String>> {
+    let path = provider::provider_config_path(portal_dir, name);
+    match fsutil::read(&path) {
+        Ok(bytes) => Ok(Some(hash::sha256_hex(&bytes))),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(e) => {
+            Err(e).with_context(|| format!("Failed to read provider config: {}", path.display()))
+        }
+    }
+}
+
+impl Policy {
+    /// Providers kiosk mode allows: the kiosk list, else the pinned ones
+    pub fn kiosk_providers(&self) -> Vec<String> {
+        match self
+            .kiosk
+            .as_ref()
+            .and_then(|kiosk| kiosk.providers.clone())
+        {
+            Some(providers) => providers,
+            None => self.providers.keys().cloned().collect(),
+        }
+    }
+
+    /// Fail unless the provider file for `name` is allowed by this policy.
+    /// Built-in providers without a file are always allowed.
+    pub fn verify(&self, portal_dir: &Path, name: &str) -> Result<()> {
+        if self.kiosk.is_some() && !self.kiosk_providers().iter().any(|p| p == name) {
+            return Err(kiosk_refusal(name, &self.kiosk_providers()));
+        }
+        let actual = provider_hash(portal_dir, name)?;
+        match (self.providers.get(name), actual) {
+            (Some(pin), Some(actual)) if pin.sha256 == actual => Ok(()),
+            (Some(pin), Some(actual)) => Err(anyhow!(
+                "Provider '{}' doesn't match its approved definition in {}\n  approved: {}\n  found:    {}\n\n\
+                 Review {} and run `portal provider approve {}` to re-approve it.",
+                name,
+                POLICY_FILE,
+                pin.sha256,
+                actual,
+                provider::provider_config_path(portal_dir, name).display(),
+                name
+            )),
+            (Some(_), None) => Err(anyhow!(
+                "Provider '{}' is pinned in {} but its file is missing: {}",
+                name,
+                POLICY_FILE,
+                provider::provider_config_path(portal_dir, name).display()
+            )),
+            (None, Some(_)) if self.require_pinned => Err(anyhow!(
+                "Provider '{}' isn't approved ({} requires every provider to be pinned). \
+                 Review it and run `portal provider approve {}`.",
+                name,
+                POLICY_FILE,
+                name
+            )),
+            (None, _) => Ok(()),
+        }
+    }
+}
+
+fn kiosk_refusal(name: &str, allowed: &[String]) -> anyhow::Error {
+    anyhow!(
+        "Provider '{}' isn't available in kiosk mode (allowed: {})",
+        name,
+        if allowed.is_empty() {
+            "none".to_string()
+        } else {
+            allowed.join(", ")
+        }
+    )
+}
+
+/// Whether portal runs in kiosk mode, and if so the providers it allows.
+/// `forced` (the `kiosk` build) turns it on even without a `kiosk` section.
+pub fn kiosk_mode(portal_dir: &Path, forced: bool) -> Result<Option<Vec<String>>> {
+    let policy = read_policy(portal_dir)?;
+    Ok(match policy {
+        Some(policy) if forced || policy.kiosk.is_some() => Some(policy.kiosk_providers()),
+        Some(_) => None,
+        None if forced => Some(Vec::new()),
+        None => None,
+    })
+}
+
+/// Fail unless kiosk mode `allowed` lets users switch to `name`
+pub fn check_kiosk_provider(allowed: &[String], name: &str) -> Result<()> {
+    if allowed.iter().any(|p| p == name) {
+        Ok(())
+    } else {
+        Err(kiosk_refusal(name, allowed))
+    }
+}
+
+/// Pin the current contents of a provider file, returning the hash
+pub fn approve(portal_dir: &Path, name: &str) -> Result<String> {
+    let sha256 = provider_hash(portal_dir, name)?.ok_or_else(|| {
+        anyhow!(
+            "Provider file not found: {}",
+            provider::provider_config_path(portal_dir, name).display()
+        )
+    })?;
+    let mut policy = read_policy(portal_dir)?.unwrap_or_default();
+    policy.providers.insert(
+        name.to_string(),
+        ProviderPin {
+            sha256: sha256.clone(),
+        },
+    );
+    write_policy(portal_dir, &policy)?;
+    Ok(sha256)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scratch(name: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("portal-policy-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn hashes_the_provider_file_as_it_is_on_disk() {
+        let dir = scratch("hash");
+        assert_eq!(provider_hash(&dir, "work").unwrap(), None);
+        fs::write(dir.join("work.json"), "{\"agents\": {}}\n").unwrap();
+        assert_eq!(
+            provider_hash(&dir, "work").unwrap().as_deref(),
+            Some(hash::sha256_hex(b"{\"agents\": {}}\n").as_str())
+        );
+        // Whitespace counts: the pin is of the bytes, not of the JSON
+        fs::write(dir.join("work.json"), "{\"agents\":{}}\n").unwrap();
+        assert_ne!(
+            provider_hash(&dir, "work").unwrap(),
+            Some(hash::sha256_hex(b"{\"agents\": {}}\n"))
+        );
+    }
+
+    #[test]
+    fn verify_accepts_approved_files_and_refuses_edited_ones() {
+        let dir = scratch("verify");
+        fs::write(dir.join("work.json"), r#"{"agents": {}}"#).unwrap();
+        let sha256 = approve(&dir, "work").unwrap();
+        let policy = read_policy(&dir).unwrap().unwrap();
+        assert_eq!(policy.providers["work"].sha256, sha256);
+        policy.verify(&dir, "work").unwrap();
+
+        fs::write(
+            dir.join("work.json"),
+            r#"{"agents": {"oracle": {"model": "x/y"}}}"#,
+        )
+        .unwrap();
+        let err = policy.verify(&dir, "work").unwrap_err().to_string();
+        assert!(err.starts_with("Provider 'work' doesn't match its approved definition"));
+
+        fs::remove_file(dir.join("work.json")).unwrap();
+        let err = policy.verify(&dir, "work").unwrap_err().to_string();
+        assert!(
+            err.starts_with("Provider 'work' is pinned in policy.json but its file is missing")
+        );
+    }
+
+    #[test]
+    fn require_pinned_refuses_unpinned_custom_providers_only() {
+        let dir = scratch("require");
+        fs::write(dir.join("other.json"), r#"{"agents": {}}"#).unwrap();
+        let policy = Policy {
+            require_pinned: true,
+            ..Policy::default()
+        };
+        assert!(policy.verify(&dir, "other").is_err());
+        policy.verify(&dir, "copilot").unwrap();
+        Policy::default().verify(&dir, "other").unwrap();
+    }
+
+    #[test]
+    fn kiosk_falls_back_to_the_pinned_providers() {
+        let policy: Policy =
+            serde_json::from_str(r#"{"providers": {"work": {"sha256": "00"}}, "kiosk": {}}"#)
+                .unwrap();
+        assert_eq!(policy.kiosk_providers(), ["work"]);
+        let err = policy
+            .verify(Path::new("/nonexistent"), "copilot")
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Provider 'copilot' isn't available in kiosk mode (allowed: work)"
+        );
+    }
+}
@@ -0,0 +1,2104 @@
+use anyhow::{Context, Result, anyhow};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::backup;
+use crate::batch::{self, BatchOutcome, Step};
+use crate::changes::{self, ConfigChange};
+use crate::clock::{self, ClockStamp};
+use crate::compat::{self, Compatibility};
+use crate::config::{self, OhMyOpenCodeConfig};
+use crate::diff::{self, ModelChange};
+use crate::error::PortalError;
+use crate::events::{Event, EventKind};
+use crate::fsutil;
+use crate::graph::{self, Graph};
+use crate::hash;
+use crate::hooks::{self, Hook, HookContext};
+use crate::journal::{Journal, JournalEntry};
+use crate::jsonc;
+use crate::keychain::{self, SecretPaths};
+use crate::lock::{self, FileLock};
+use crate::mapping::{MappingTable, MappingsFile, Tier};
+use crate::notify;
+use crate::paths;
+use crate::policy;
+use crate::pricing::PricingTable;
+use crate::project;
+use crate::provider::{self, AgentModelConfig, ProviderConfig, ProviderMerge};
+use crate::rendered::{self, SwitchMode};
+use crate::secrets;
+use crate::settings;
+use crate::sources::{self, Refresh, RemoteProvider};
+use crate::storage::{self, EventStore};
+use crate::switch::{self, DetectedProvider, SwitchContext, SwitchReport};
+use crate::temporary::{self, Expired, Expiry, TemporarySwitch};
+
+/// Name of [`Portal::current_provider_path`] in the state directory
+const CURRENT_PROVIDER_FILE: &str = "current-provider";
+
+/// Entry point for embedding portal: owns the config and provider locations
+/// and exposes the same operations as the CLI.
+#[derive(Debug, Clone)]
+pub struct Portal {
+    config_path: PathBuf,
+    portal_dir: PathBuf,
+    state_dir: PathBuf,
+    /// Applied in order over `mappings.json`
+    mapping_overrides: Vec<MappingsFile>,
+}
+
+/// Options for [`Portal::switch`]
+#[derive(Debug, Clone, Default)]
+pub struct SwitchOptions {
+    /// Create a backup even if one already exists
+    pub force_backup: bool,
+    /// Only switch these agents; `None` switches all of them
+    pub agents: Option<Vec<String>>,
+    /// Write the switched config here instead of over the original, which
+    /// is left untouched (no backup is made)
+    pub output: Option<PathBuf>,
+    /// Leave top-level model fields (`model`, `small_model`) alone
+    pub skip_top_level: bool,
+    /// Refuse to write unless the config still has this SHA-256, e.g. the
+    /// [`SwitchPreview::config_hash`] of a plan the user reviewed
+    pub expect_hash: Option<String>,
+    /// Write client options even if they look like they hold raw API keys
+    pub allow_inline_secrets: bool,
+    /// Models to write for these agents (or `subagents.<name>`, or
+    /// top-level fields) once the provider's switch is done, over whatever
+    /// it gave them
+    pub overrides: BTreeMap<String, String>,
+}
+
+/// A switch computed in memory but not yet written
+#[derive(Debug, Clone)]
+pub struct SwitchPreview {
+    /// The switched config document
+    pub document: Value,
+    pub report: SwitchReport,
+    /// Set when the config was only partially understood and just the
+    /// agent model fields were changed
+    pub compatibility: Compatibility,
+    /// SHA-256 of the config the preview was computed from
+    pub config_hash: String,
+}
+
+impl SwitchPreview {
+    pub fn agent_models(&self) -> Vec<(String, String)> {
+        config::agent_models(&self.document)
+    }
+}
+
+/// Result of a switch that was written to disk
+#[derive(Debug, Clone)]
+pub struct SwitchOutcome {
+    pub report: SwitchReport,
+    pub compatibility: Compatibility,
+    /// Per-agent models before and after, including unchanged agents
+    pub changes: Vec<ModelChange>,
+    /// Backup created before writing, if any
+    pub backup: Option<PathBuf>,
+    /// Problems after the write, such as a failing post-switch hook
+    pub warnings: Vec<String>,
+}
+
+/// Every agent moved to one tier on the current provider, as
+/// [`Portal::tier_plan`] works it out
+#[derive(Debug, Clone, Serialize)]
+pub struct TierPlan {
+    pub provider: String,
+    pub tier: Tier,
+    /// New models by agent, `subagents.<name>`, or top-level field; agents
+    /// already on the tier aren't here
+    pub models: BTreeMap<String, String>,
+    /// Agents left as they are because the provider has no model of the tier
+    pub unmatched: Vec<String>,
+    /// Agents left as they are because they're pinned
+    pub pinned: Vec<String>,
+}
+
+/// Edits to a config since portal last wrote it, as [`Portal::drift`]
+/// finds them before a switch
+#[derive(Debug, Clone)]
+pub struct Drift {
+    pub changes: Vec<ConfigChange>,
+    /// Dotted paths of the edited model fields the switch changes again
+    pub overwritten: Vec<String>,
+}
+
+/// Snapshot of the current configuration
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Status {
+    pub config_path: PathBuf,
+    /// The provider all agents are on, if they agree
+    pub provider: Option<String>,
+    /// Each agent's provider
+    pub providers: BTreeMap<String, DetectedProvider>,
+    /// Each agent's model; `None` for an agent without a string `model`
+    pub agents: BTreeMap<String, Option<String>>,
+    pub compatibility: Compatibility,
+}
+
+impl Portal {
+    pub fn new(config_path: impl Into<PathBuf>, portal_dir: impl Into<PathBuf>) -> Self {
+        Self {
+            config_path: config_path.into(),
+            portal_dir: portal_dir.into(),
+            state_dir: paths::state_dir(),
+            mapping_overrides: Vec::new(),
+        }
+    }
+
+    /// Keep runtime state (event log, journals) somewhere other than the
+    /// default XDG state directory
+    pub fn with_state_dir(mut self, state_dir: impl Into<PathBuf>) -> Self {
+        self.state_dir = state_dir.into();
+        self
+    }
+
+    /// Layer mapping rows over `mappings.json` and any overrides already
+    /// added, e.g. an environment's and then a project's
+    pub fn with_mapping_overrides(mut self, overrides: MappingsFile) -> Self {
+        self.mapping_overrides.push(overrides);
+        self
+    }
+
+    /// A portal for another config sharing this one's directories and
+    /// mapping overrides
+    pub fn for_config(&self, config_path: impl Into<PathBuf>) -> Self {
+        Self {
+            config_path: config_path.into(),
+            ..self.clone()
+        }
+    }
+
+    /// Use the standard XDG locations, optionally overriding the config path
+    pub fn discover(config_path: Option<PathBuf>) -> Self {
+        Self::new(
+            config_path.unwrap_or_else(paths::default_config_path),
+            paths::portal_dir(),
+        )
+    }
+
+    pub fn config_path(&self) -> &Path {
+        &self.config_path
+    }
+
+    pub fn portal_dir(&self) -> &Path {
+        &self.portal_dir
+    }
+
+    pub fn state_dir(&self) -> &Path {
+        &self.state_dir
+    }
+
+    /// The event history, in the backend settings.json picks (the JSONL
+    /// log if it can't be read)
+    pub fn events(&self) -> Box<dyn EventStore> {
+        let storage = settings::read_settings(&self.portal_dir)
+            .ok()
+            .flatten()
+            .map(|settings| settings.storage)
+            .unwrap_or_default();
+        storage::open(&self.state_dir, storage)
+    }
+
+    /// Append to the event log. The log is best-effort: a write that already
+    /// succeeded is never reported as failed because of it.
+    fn record(&self, kind: EventKind) {
+        let _ = self.events().append(&Event::now(kind));
+    }
+
+    /// Copy of the config as portal last wrote it, kept so later edits by
+    /// other tools or by hand can be told apart from portal's own
+    pub fn last_written_path(&self) -> PathBuf {
+        self.state_dir
+            .join("last-written")
+            .join(format!("{}.json", self.state_key()))
+    }
+
+    /// File holding the default config's provider as of portal's last
+    /// write (`mixed` when its agents disagree), for shell prompts and
+    /// window titles that can't afford to run portal
+    pub fn current_provider_path(&self) -> PathBuf {
+        self.state_dir.join(CURRENT_PROVIDER_FILE)
+    }
+
+    /// Where the config holds secrets portal filled in from the keychain
+    /// on its last write, so they can be kept out of the journal, backups
+    /// and the last-written copy
+    fn secrets_path(&self) -> PathBuf {
+        self.state_dir
+            .join("secrets")
+            .join(format!("{}.json", self.state_key()))
+    }
+
+    /// Undo/redo history of portal's writes to this config
+    pub fn journal_path(&self) -> PathBuf {
+        self.state_dir
+            .join("journal")
+            .join(format!("{}.json", self.state_key()))
+    }
+
+    /// Lock file held while this config is checked and written
+    pub fn lock_path(&self) -> PathBuf {
+        self.state_dir
+            .join("locks")
+            .join(format!("{}.lock", self.state_key()))
+    }
+
+    /// Keep other portal processes from writing this config until the
+    /// lock is dropped
+    fn lock(&self) -> Result<FileLock> {
+        lock::acquire(&self.lock_path(), &self.config_path, lock::LOCK_TIMEOUT)
+    }
+
+    /// Temporary switch of this config waiting to be reverted, if any
+    pub fn temporary_path(&self) -> PathBuf {
+        temporary::record_path(&self.state_dir, &self.state_key())
+    }
+
+    /// State of an in-progress `portal bisect` of this config
+    pub fn bisect_path(&self) -> PathBuf {
+        self.state_dir
+            .join("bisect")
+            .join(format!("{}.json", self.state_key()))
+    }
+
+    /// This config's backup channel: `global` for the default config,
+    /// `project-<dir>-<key>` for a config inside a project with a
+    /// `.portal.json` or `.portal/portal.json`, and `config-<dir>-<key>`
+    /// for anything else
+    pub fn backups(&self) -> backup::Channel {
+        let config = self.canonical_config();
+        let name = if self.is_default_config() {
+            "global".to_string()
+        } else {
+            let parent = config.parent().unwrap_or(Path::new("."));
+            let (kind, dir) = match project::find_project_file(parent) {
+                Some(project_file) => ("project", project::root_of(&project_file).to_path_buf()),
+                None => ("config", parent.to_path_buf()),
+            };
+            let dir = dir
+                .file_name()
+                .map(|n| n.to_string_lossy().to_string())
+                .unwrap_or_else(|| "root".to_string());
+            format!("{}-{}-{}", kind, dir, &self.state_key()[..8])
+        };
+        backup::Channel::new(name, &self.config_path, &self.backup_store())
+    }
+
+    /// Whether this is the config portal uses without `--config`
+    fn is_default_config(&self) -> bool {
+        rendered::canonical_config(&paths::default_config_path(), &self.portal_dir)
+            == self.canonical_config()
+    }
+
+    /// The config's path with symlinks resolved, other than a link portal
+    /// repoints in symlink mode
+    fn canonical_config(&self) -> PathBuf {
+        rendered::canonical_config(&self.config_path, &self.portal_dir)
+    }
+
+    /// Managed store holding every config's backup channel
+    pub fn backup_store(&self) -> PathBuf {
+        self.state_dir.join(backup::STORE_DIR)
+    }
+
+    /// Name for per-config state files, stable for a given config location
+    fn state_key(&self) -> String {
+        let config = self.canonical_config();
+        let key = hash::sha256_hex(config.to_string_lossy().as_bytes());
+        key[..16].to_string()
+    }
+
+    /// Refresh the last-written copy after a write. Best-effort, like
+    /// [`Self::record`].
+    fn remember_written(&self) {
+        let path = self.last_written_path();
+        if let Some(parent) = path.parent() {
+            let _ = fs::create_dir_all(parent);
+        }
+        if let Ok(Some(content)) = self.redacted_config() {
+            let _ = fsutil::write_atomic(&path, content.as_bytes());
+        }
+        if self.is_default_config() {
+            self.remember_provider();
+        }
+    }
+
+    /// Bring [`Self::current_provider_path`] up to date, removing it when
+    /// no agent's provider is known
+    fn remember_provider(&self) {
+        let path = self.current_provider_path();
+        let detected = fsutil::read_to_string(&self.config_path)
+            .ok()
+            .and_then(|text| jsonc::parse(&text).ok())
+            .map(|document| switch::detect_document_providers(&document))
+            .unwrap_or_default();
+        let provider = match switch::common_provider(&detected) {
+            Some(provider) => provider,
+            None if switch::mixed_providers(&detected).is_empty() => {
+                let _ = fs::remove_file(&path);
+                return;
+            }
+            None => "mixed".to_string(),
+        };
+        let _ = fsutil::write_atomic(&path, format!("{}\n", provider).as_bytes());
+    }
+
+    /// Where portal's last write left secrets from the keychain in the
+    /// config. Empty if it left none, or the record can't be read.
+    pub fn secret_paths(&self) -> SecretPaths {
+        fsutil::read(&self.secrets_path())
+            .ok()
+            .and_then(|bytes| serde_json::from_slice(&bytes).ok())
+            .unwrap_or_default()
+    }
+
+    /// Record where a write left secrets. Best-effort, like
+    /// [`Self::record`].
+    fn remember_secrets(&self, paths: &SecretPaths) {
+        let path = self.secrets_path();
+        if paths.is_empty() {
+            let _ = fs::remove_file(&path);
+            return;
+        }
+        if let Some(parent) = path.parent() {
+            let _ = fs::create_dir_all(parent);
+        }
+        if let Ok(json) = serde_json::to_string_pretty(paths) {
+            let _ = fsutil::write_atomic(&path, json.as_bytes());
+        }
+    }
+
+    /// The config's text with the secrets portal filled in put back to
+    /// their `{secret:NAME}` placeholders, as the journal, backups and the
+    /// last-written copy keep it. `None` if there is no config.
+    pub fn redacted_config(&self) -> Result<Option<String>> {
+        match fsutil::read_to_string(&self.config_path) {
+            Ok(text) => Ok(Some(keychain::redact_config(&text, &self.secret_paths()))),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e)
+                .with_context(|| format!("Failed to read config: {}", self.config_path.display())),
+        }
+    }
+
+    /// `document` as it is written, with its `{secret:NAME}` placeholders
+    /// filled in from the keychain, and where the config then holds
+    /// secrets: those, and the ones an earlier write filled in that
+    /// `document` keeps as they were
+    fn fill_secrets(&self, document: &Value) -> Result<(Value, SecretPaths)> {
+        let mut document = document.clone();
+        let mut paths = keychain::resolve_document(&mut document)?;
+        warn_in_clear(&self.config_path, &paths);
+        let previous = self.secret_paths();
+        if !previous.is_empty()
+            && let Ok(current) = config::read_document(&self.config_path)
+        {
+            for (pointer, text) in previous {
+                let kept = document.pointer(&pointer);
+                if kept.is_some_and(Value::is_string) && kept == current.pointer(&pointer) {
+                    paths.entry(pointer).or_insert(text);
+                }
+            }
+        }
+        Ok((document, paths))
+    }
+
+    /// [`Self::fill_secrets`] for config text, such as a journal entry or
+    /// a backup
+    fn fill_secrets_text(&self, text: &str) -> Result<(String, SecretPaths)> {
+        let (text, paths) = keychain::resolve_config(text)?;
+        warn_in_clear(&self.config_path, &paths);
+        Ok((text, paths))
+    }
+
+    /// Run `write`, which leaves secrets from the keychain at `secrets` in
+    /// the config, then bring the last-written copy and the journal up to
+    /// date. Both keep placeholders in place of the secrets. The journal is
+    /// best-effort, like [`Self::record`].
+    fn write_tracked(
+        &self,
+        action: &str,
+        secrets: &SecretPaths,
+        write: impl FnOnce() -> Result<Option<PathBuf>>,
+    ) -> Result<Option<PathBuf>> {
+        let before = self.redacted_config().ok().flatten();
+        let backup = write()?;
+        self.remember_secrets(secrets);
+        self.remember_written();
+        let after = self.redacted_config().ok().flatten();
+        match (&before, &after) {
+            (Some(before), Some(after)) if before == after => {
+                crate::portal_info!("{}: {} unchanged", action, self.config_path.display())
+            }
+            _ => crate::portal_info!("{}: wrote {}", action, self.config_path.display()),
+        }
+        if let (Some(before), Some(after)) = (before, after)
+            && before != after
+        {
+            let path = self.journal_path();
+            if let Ok(mut journal) = Journal::load(&path) {
+                journal.push(JournalEntry {
+                    timestamp: chrono::Utc::now(),
+                    action: action.to_string(),
+                    before,
+                    after,
+                });
+                let _ = journal.save(&path);
+            }
+        }
+        Ok(backup)
+    }
+
+    pub fn journal(&self) -> Result<Journal> {
+        Journal::load(&self.journal_path())
+    }
+
+    /// Put back the config as it was before portal's most recent write.
+    /// `None` if there is nothing left to undo.
+    pub fn undo(&self) -> Result<Option<JournalEntry>> {
+        self.step(false)
+    }
+
+    /// Reapply the write most recently undone. `None` if there is nothing
+    /// to redo.
+    pub fn redo(&self) -> Result<Option<JournalEntry>> {
+        self.step(true)
+    }
+
+    fn step(&self, forward: bool) -> Result<Option<JournalEntry>> {
+        let path = self.journal_path();
+        let mut journal = Journal::load(&path)?;
+        let entry = match if forward {
+            journal.redo_entry()
+        } else {
+            journal.undo_entry()
+        } {
+            Some(entry) => entry.clone(),
+            None => return Ok(None),
+        };
+        let (expected, target) = if forward {
+            (&entry.before, &entry.after)
+        } else {
+            (&entry.after, &entry.before)
+        };
+
+        let _lock = self.lock()?;
+        if self.redacted_config()?.as_deref() != Some(expected.as_str()) {
+            return Err(anyhow!(
+                "Config file was modified after portal's last write: {}\n\n\
+                 {} would discard those edits, so nothing was written. \
+                 Run `portal changes` to see them.",
+                self.config_path.display(),
+                if forward { "Redo" } else { "Undo" }
+            ));
+        }
+        let (target, secrets) = self.fill_secrets_text(target)?;
+        fsutil::write_atomic(&self.config_path, target.as_bytes())
+            .with_context(|| format!("Failed to write config: {}", self.config_path.display()))?;
+        self.remember_secrets(&secrets);
+        self.remember_written();
+
+        if forward {
+            journal.position += 1;
+        } else {
+            journal.position -= 1;
+        }
+        journal.save(&path)?;
+
+        let config = self.config_path.clone();
+        let action = entry.action.clone();
+        self.record(if forward {
+            EventKind::Redo { config, action }
+        } else {
+            EventKind::Undo { config, action }
+        });
+        Ok(Some(entry))
+    }
+
+    /// Whether the config differs from the copy portal last wrote, e.g.
+    /// because another tool rewrote it. `true` if portal never wrote it.
+    pub fn modified_since_write(&self) -> Result<bool> {
+        let last = config::fingerprint(&self.last_written_path())?;
+        let current = self
+            .redacted_config()?
+            .map(|text| hash::sha256_hex(text.as_bytes()));
+        Ok(last.is_none() || current != last)
+    }
+
+    /// Provider this config was most recently switched to, per the event log
+    pub fn last_provider(&self) -> Result<Option<String>> {
+        // As state_key does, so a config linked into rendered/ still matches
+        let canonical = |p: &Path| rendered::canonical_config(p, &self.portal_dir);
+        let config = self.canonical_config();
+        Ok(self
+            .events()
+            .read_all()?
+            .into_iter()
+            .rev()
+            .find_map(|event| match event.kind {
+                EventKind::Switch {
+                    config: switched,
+                    provider,
+                    ..
+                } if canonical(&switched) == config => Some(Some(provider)),
+                // Back to whatever was there before the temporary switch
+                EventKind::Expire {
+                    config: expired,
+                    restored_provider,
+                    ..
+                } if canonical(&expired) == config => Some(restored_provider),
+                _ => None,
+            })
+            .flatten())
+    }
+
+    /// Switch back to `provider` if something other than portal changed the
+    /// config since portal last wrote it and undid part of the switch.
+    /// Agents in `except` are left alone. Returns the agents that were (or,
+    /// with `dry_run`, would be) changed; `None` when nothing needed doing.
+    pub fn reapply(
+        &self,
+        provider: &str,
+        options: &SwitchOptions,
+        except: &[String],
+        dry_run: bool,
+    ) -> Result<Option<Vec<String>>> {
+        if !self.config_path.exists() || !self.modified_since_write()? {
+            return Ok(None);
+        }
+        let mut options = options.clone();
+        if !except.is_empty() {
+            let agents = match &options.agents {
+                Some(agents) => agents.clone(),
+                None => {
+                    let document = config::read_document(&self.config_path)?;
+                    let mut names = config::agent_models(&document);
+                    names.extend(config::subagent_models(&document));
+                    names.extend(config::top_level_models(&document));
+                    names.into_iter().map(|(name, _)| name).collect()
+                }
+            };
+            options.agents = Some(
+                agents
+                    .into_iter()
+                    .filter(|agent| !except.contains(agent))
+                    .collect(),
+            );
+        }
+
+        let (preview, changes) = self.diff(provider, &options)?;
+        let changed: Vec<String> = changes
+            .into_iter()
+            .filter(|c| c.is_changed())
+            .map(|c| c.agent)
+            .collect();
+        if changed.is_empty() && preview.report.options.is_empty() {
+            return Ok(None);
+        }
+        if !dry_run {
+            options.expect_hash = Some(preview.config_hash);
+            self.switch(provider, &options)?;
+        }
+        Ok(Some(changed))
+    }
+
+    /// Edits made to the config since portal last wrote it, and which of
+    /// them switching to `provider` would overwrite. `None` if there are
+    /// none, or portal hasn't written the config yet.
+    pub fn drift(&self, provider: &str, options: &SwitchOptions) -> Result<Option<Drift>> {
+        if !self.modified_since_write()? {
+            return Ok(None);
+        }
+        let Some(changes) = self.changes_since_write()?.filter(|c| !c.is_empty()) else {
+            return Ok(None);
+        };
+        let (_, switch) = self.diff(provider, options)?;
+        let overwritten = switch
+            .iter()
+            .filter(|c| c.is_changed())
+            .map(ModelChange::path)
+            .filter(|path| changes.iter().any(|change| change.path == *path))
+            .collect();
+        Ok(Some(Drift {
+            changes,
+            overwritten,
+        }))
+    }
+
+    /// Differences between the config as portal last wrote it and as it is
+    /// now. `None` if portal hasn't written this config yet.
+    pub fn changes_since_write(&self) -> Result<Option<Vec<ConfigChange>>> {
+        let last = self.last_written_path();
+        if !last.exists() {
+            return Ok(None);
+        }
+        self.ensure_exists()?;
+        let before = config::read_document(&last)?;
+        let after = config::read_document(&self.config_path)?;
+        // The last-written copy keeps placeholders for secrets
+        let after = self.redacted_document(after);
+        Ok(Some(changes::config_changes(&before, &after)))
+    }
+
+    /// `document`, read from the config, with the secrets portal filled in
+    /// put back to their placeholders
+    pub fn redacted_document(&self, mut document: Value) -> Value {
+        keychain::redact(&mut document, &self.secret_paths());
+        document
+    }
+
+    fn ensure_exists(&self) -> Result<()> {
+        if !self.config_path.exists() {
+            return Err(PortalError::ConfigNotFound(self.config_path.clone()).into());
+        }
+        Ok(())
+    }
+
+    /// Read and parse the oh-my-opencode config
+    pub fn load(&self) -> Result<OhMyOpenCodeConfig> {
+        self.ensure_exists()?;
+        config::read_config(&self.config_path)
+    }
+
+    /// A provider's config: its file in the portal directory, else its
+    /// definition cached from a provider source, with what it leaves out
+    /// taken from the providers it `extends`. A built-in provider can be
+    /// extended without a file of its own, and ends the chain.
+    pub fn provider_config(&self, provider: &str) -> Result<Option<ProviderConfig>> {
+        let Some(mut config) = self.own_provider_config(provider)? else {
+            return Ok(None);
+        };
+        let mut chain = vec![provider.to_string()];
+        let mut next = config.extends.clone();
+        while let Some(parent) = next {
+            if chain.contains(&parent) {
+                chain.push(parent);
+                return Err(anyhow!(
+                    "Provider inheritance cycle: {}",
+                    chain.join(" extends ")
+                ));
+            }
+            match self.own_provider_config(&parent)? {
+                Some(parent_config) => {
+                    config.inherit(&parent_config);
+                    next = parent_config.extends;
+                }
+                None if provider::is_builtin_name(&parent) => next = None,
+                None => {
+                    return Err(anyhow!(
+                        "Provider '{}' extends unknown provider '{}'",
+                        chain.last().map(String::as_str).unwrap_or(provider),
+                        parent
+                    ));
+                }
+            }
+            config.ancestors.push(parent.clone());
+            chain.push(parent);
+        }
+        Ok(Some(config))
+    }
+
+    /// A provider's own config, before what it extends is filled in
+    fn own_provider_config(&self, provider: &str) -> Result<Option<ProviderConfig>> {
+        if let Some(config) = provider::read_provider_config(&self.portal_dir, provider)? {
+            return Ok(Some(config));
+        }
+        match self.remote_providers()?.get(provider) {
+            Some(remote) => sources::read_cached(remote).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    /// Fail if `policy.json` doesn't allow switching to `provider`
+    pub fn verify_provider(&self, provider: &str) -> Result<()> {
+        let Some(policy) = policy::read_policy(&self.portal_dir)? else {
+            return Ok(());
+        };
+        // Pins are of files in the portal directory, which a provider
+        // from a source doesn't have
+        let path = provider::provider_config_path(&self.portal_dir, provider);
+        if (policy.require_pinned || policy.providers.contains_key(provider))
+            && !path.exists()
+            && let Some(remote) = self.remote_providers()?.get(provider)
+        {
+            return Err(anyhow!(
+                "Provider '{}' comes from source '{}', and {} only allows pinned provider \
+                 files. Copy {} to {} and run `portal provider approve {}`.",
+                provider,
+                remote.source,
+                policy::POLICY_FILE,
+                remote.path.display(),
+                path.display(),
+                provider
+            ));
+        }
+        policy.verify(&self.portal_dir, provider)
+    }
+
+    /// Built-in mappings merged with the user's `mappings.json` and any
+    /// mapping overrides
+    pub fn mappings(&self) -> Result<MappingTable> {
+        let mut table = MappingTable::load(&self.portal_dir)?;
+        for overrides in &self.mapping_overrides {
+            table.merge(overrides.clone());
+        }
+        Ok(table)
+    }
+
+    /// Providers with a file in the portal directory
+    pub fn custom_providers(&self) -> Result<Vec<String>> {
+        provider::list_custom_providers(&self.portal_dir)
+    }
+
+    /// Provider sources from `settings.json`, name to location
+    pub fn sources(&self) -> Result<BTreeMap<String, String>> {
+        Ok(settings::read_settings(&self.portal_dir)?
+            .unwrap_or_default()
+            .sources)
+    }
+
+    /// Providers cached from the provider sources, leaving out any with a
+    /// file in the portal directory, which wins
+    pub fn remote_providers(&self) -> Result<BTreeMap<String, RemoteProvider>> {
+        let mut remote = sources::cached_providers(&self.state_dir, &self.sources()?);
+        remote.retain(|name, _| !provider::provider_config_path(&self.portal_dir, name).exists());
+        Ok(remote)
+    }
+
+    /// Fetch every provider source again
+    pub fn refresh_sources(&self) -> Result<Vec<Refresh>> {
+        Ok(sources::refresh(&self.state_dir, &self.sources()?))
+    }
+
+    /// How every agent in the config would be routed on each of
+    /// `providers`
+    pub fn graph(&self, providers: &[String]) -> Result<Graph> {
+        self.ensure_exists()?;
+        let document = config::read_document(&self.config_path)?;
+        let mappings = self.mappings()?;
+        let providers = providers
+            .iter()
+            .map(|name| Ok((name.clone(), self.provider_config(name)?)))
+            .collect::<Result<Vec<_>>>()?;
+        Ok(graph::build(&document, &providers, &mappings))
+    }
+
+    /// Provider aliases from `settings.json`, alias to provider
+    pub fn aliases(&self) -> Result<BTreeMap<String, String>> {
+        Ok(settings::read_settings(&self.portal_dir)?
+            .unwrap_or_default()
+            .aliases)
+    }
+
+    /// The provider a name given by the user stands for: the target of
+    /// its alias, or the name itself. Resolve names with this before
+    /// handing them to the other methods.
+    pub fn resolve_provider(&self, name: &str) -> Result<String> {
+        let settings = settings::read_settings(&self.portal_dir)?.unwrap_or_default();
+        Ok(settings.resolve_alias(name).to_string())
+    }
+
+    /// Agents every switch leaves alone, from `pins` in `settings.json`
+    pub fn pins(&self) -> Result<Vec<String>> {
+        let settings = settings::read_settings(&self.portal_dir)?.unwrap_or_default();
+        Ok(settings.pins)
+    }
+
+    /// Pin `agent` (or `subagents.<name>`, or a top-level field), or with
+    /// `pinned` unset unpin it; `false` if it already was (or wasn't)
+    pub fn set_pinned(&self, agent: &str, pinned: bool) -> Result<bool> {
+        let mut settings = settings::read_settings(&self.portal_dir)?.unwrap_or_default();
+        if settings.pins.iter().any(|p| p == agent) == pinned {
+            return Ok(false);
+        }
+        if pinned {
+            settings.pins.push(agent.to_string());
+            settings.pins.sort();
+        } else {
+            settings.pins.retain(|p| p != agent);
+        }
+        settings::write_settings(&self.portal_dir, &settings)?;
+        Ok(true)
+    }
+
+    /// Bundled prices with `pricing` from `settings.json` on top
+    pub fn pricing(&self) -> Result<PricingTable> {
+        let settings = settings::read_settings(&self.portal_dir)?.unwrap_or_default();
+        Ok(PricingTable::with_overrides(&settings.pricing))
+    }
+
+    /// The config's agents and providers, read from the document as it is,
+    /// so a config portal only partly understands still has one
+    pub fn status(&self) -> Result<Status> {
+        self.ensure_exists()?;
+        let document = config::read_document(&self.config_path)?;
+        let agents = document
+            .get("agents")
+            .and_then(Value::as_object)
+            .map(|agents| {
+                agents
+                    .keys()
+                    .map(|name| {
+                        let model = config::model_slot(&document, name).map(str::to_string);
+                        (name.clone(), model)
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+        let providers = switch::detect_document_providers(&document);
+        Ok(Status {
+            config_path: self.config_path.clone(),
+            provider: switch::common_provider(&providers),
+            providers,
+            agents,
+            compatibility: compat::assess(&document),
+        })
+    }
+
+    /// Fail if the config no longer has the contents (by hash) that were
+    /// read at the start of an operation, so a concurrent edit isn't lost
+    fn ensure_unchanged(&self, expected: Option<&str>) -> Result<()> {
+        let current = config::fingerprint(&self.config_path)?;
+        if current.as_deref() != expected {
+            return Err(self.changed_error());
+        }
+        Ok(())
+    }
+
+    fn changed_error(&self) -> anyhow::Error {
+        PortalError::ConfigChanged(self.config_path.clone()).into()
+    }
+
+    /// The config's current per-agent, per-subagent, and top-level models
+    /// as a provider, so switching to it restores them exactly. Where a subagent
+    /// shares an agent's name, the agent's model is kept.
+    pub fn capture(&self) -> Result<ProviderConfig> {
+        self.ensure_exists()?;
+        let document = config::read_document(&self.config_path)?;
+        let mut captured = ProviderConfig::default();
+        for (name, model) in config::top_level_models(&document)
+            .into_iter()
+            .chain(config::subagent_models(&document))
+            .chain(config::agent_models(&document))
+        {
+            captured.agents.insert(name, AgentModelConfig { model });
+        }
+        Ok(captured)
+    }
+
+    /// The models that move every agent to `tier` while staying on the
+    /// provider the config was last switched to (or, when portal didn't
+    /// switch it, the one its models are on), per [`switch::tier_model`].
+    /// Pinned agents are left alone. Apply them with [`Self::apply_models`].
+    pub fn tier_plan(&self, tier: Tier) -> Result<TierPlan> {
+        self.ensure_exists()?;
+        let document = config::read_document(&self.config_path)?;
+        let provider = match self.last_provider()? {
+            Some(provider) => provider,
+            None => self.status()?.provider.ok_or_else(|| {
+                anyhow!(
+                    "Can't tell which provider {} is on; switch it to one first",
+                    self.config_path.display()
+                )
+            })?,
+        };
+        let provider_config = self.provider_config(&provider)?;
+        let mappings = self.mappings()?;
+        let ctx = SwitchContext {
+            provider_config: provider_config.as_ref(),
+            ..SwitchContext::new(&provider, &mappings)
+        };
+
+        let pins = self.pins()?;
+        let mut models = BTreeMap::new();
+        let mut unmatched = Vec::new();
+        let mut pinned = Vec::new();
+        for (agent, current) in config::top_level_models(&document)
+            .into_iter()
+            .chain(config::agent_models(&document))
+            .chain(
+                config::subagent_models(&document)
+                    .into_iter()
+                    .map(|(name, model)| (format!("subagents.{}", name), model)),
+            )
+        {
+            if pins.contains(&agent) {
+                pinned.push(agent);
+                continue;
+            }
+            if mappings.tier(&current) == Some(tier) {
+                continue;
+            }
+            match switch::tier_model(&current, tier, &ctx) {
+                Some(model) if model != current => {
+                    models.insert(agent, model);
+                }
+                Some(_) => {}
+                None => unmatched.push(agent),
+            }
+        }
+        Ok(TierPlan {
+            provider,
+            tier,
+            models,
+            unmatched,
+            pinned,
+        })
+    }
+
+    /// [`Portal::capture`] merged into `provider`'s file, without writing it
+    pub fn capture_into(&self, provider: &str) -> Result<ProviderMerge> {
+        provider::merge_provider_config(&self.portal_dir, provider, &self.capture()?)
+    }
+
+    /// Whether `model` is one portal knows: its base model is in the mapping
+    /// table, or some custom provider file uses it verbatim
+    pub fn is_known_model(&self, model: &str) -> Result<bool> {
+        let mappings = self.mappings()?;
+        if mappings.find(&mappings.base_model(model)).is_some() {
+            return Ok(true);
+        }
+        for name in self.custom_providers()? {
+            if let Some(config) = self.provider_config(&name)?
+                && config.agents.values().any(|a| a.model == model)
+            {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+
+    /// Fail unless `model` is a `provider/model` string that
+    /// [`Self::is_known_model`], as `portal set` requires without `--force`
+    pub fn ensure_known_model(&self, model: &str) -> Result<()> {
+        if !model.contains('/') {
+            return Err(anyhow!(
+                "'{}' isn't a provider/model string (e.g. github-copilot/{}). \
+                 Pass --force to set it anyway.",
+                model,
+                model
+            ));
+        }
+        if !self.is_known_model(model)? {
+            return Err(anyhow!(
+                "'{}' isn't in portal's mappings or provider files; check the name, \
+                 or pass --force to set it anyway.",
+                model
+            ));
+        }
+        Ok(())
+    }
+
+    /// Compute the switched config without writing anything
+    pub fn preview_switch(&self, provider: &str, options: &SwitchOptions) -> Result<SwitchPreview> {
+        self.ensure_exists()?;
+        let source = config::read_source(&self.config_path)?;
+        self.preview_document(source.document, &source.hash, provider, options)
+    }
+
+    fn preview_document(
+        &self,
+        mut document: Value,
+        hash: &str,
+        provider: &str,
+        options: &SwitchOptions,
+    ) -> Result<SwitchPreview> {
+        if let Some(agents) = &options.agents {
+            let mut known = config::agent_models(&document);
+            known.extend(config::subagent_models(&document));
+            known.extend(config::top_level_models(&document));
+            for agent in agents {
+                if !known.iter().any(|(name, _)| name == agent) {
+                    return Err(anyhow!(
+                        "Agent '{}' not found in config: {}",
+                        agent,
+                        self.config_path.display()
+                    ));
+                }
+            }
+        }
+
+        self.verify_provider(provider)?;
+        // `{secret:NAME}` placeholders stay until the write fills them in
+        let provider_config = self.provider_config(provider)?;
+        let mappings = self.mappings()?;
+        // Agents named with --agent are switched even when pinned
+        let pins = match options.agents {
+            Some(_) => Vec::new(),
+            None => self.pins()?,
+        };
+        let ctx = SwitchContext {
+            provider_config: provider_config.as_ref(),
+            agents: options.agents.as_deref(),
+            top_level: !options.skip_top_level,
+            pinned: &pins,
+            ..SwitchContext::new(provider, &mappings)
+        };
+
+        crate::portal_info!(
+            "Switching {} to '{}' ({})",
+            self.config_path.display(),
+            provider,
+            if provider_config.is_some() {
+                "with its provider file"
+            } else {
+                "no provider file, mappings only"
+            }
+        );
+        let compatibility = compat::assess(&document);
+        // A config portal doesn't fully understand only has its models changed
+        let mut report = if compatibility.is_conservative() {
+            switch::switch_document(&mut document, &ctx)
+        } else {
+            let mut config: OhMyOpenCodeConfig = serde_json::from_value(document)
+                .map_err(PortalError::parse("config file", &self.config_path))?;
+            let mut report = switch::switch_to_provider(&mut config, &ctx);
+            document = serde_json::to_value(&config)?;
+            report.options = switch::apply_provider_options(&mut document, &ctx, &report.unmapped);
+            report
+        };
+        for (agent, model) in &options.overrides {
+            let slot = config::model_slot_mut(&mut document, agent).ok_or_else(|| {
+                anyhow!(
+                    "Agent '{}' not found in config: {}",
+                    agent,
+                    self.config_path.display()
+                )
+            })?;
+            *slot = Value::String(model.clone());
+            report.unmapped.retain(|a| a != agent);
+            report.pinned.retain(|a| a != agent);
+        }
+
+        Ok(SwitchPreview {
+            document,
+            report,
+            compatibility,
+            config_hash: hash.to_string(),
+        })
+    }
+
+    /// Run `hook` for a switch of this config
+    fn run_hook(
+        &self,
+        hook: Hook,
+        action: &str,
+        provider: Option<&str>,
+        previous_provider: Option<&str>,
+    ) -> Result<()> {
+        hooks::run(
+            &self.portal_dir,
+            hook,
+            &HookContext {
+                action,
+                provider,
+                previous_provider,
+                config: &self.config_path,
+            },
+        )
+    }
+
+    /// Run the post-switch hook; the write already happened, so a failure
+    /// is only a warning
+    fn post_switch_hook(
+        &self,
+        action: &str,
+        provider: Option<&str>,
+        previous_provider: Option<&str>,
+    ) -> Option<String> {
+        self.run_hook(Hook::PostSwitch, action, provider, previous_provider)
+            .err()
+            .map(|e| format!("{:#}", e))
+    }
+
+    /// Refuse to write `provider`'s client options if they hold what look
+    /// like raw API keys, unless the options allow it
+    fn check_secrets(&self, provider: &str, options: &SwitchOptions) -> Result<()> {
+        match self.provider_config(provider)? {
+            Some(config) => {
+                secrets::check_options(provider, &config.options, options.allow_inline_secrets)
+            }
+            None => Ok(()),
+        }
+    }
+
+    /// Per-agent model changes switching to `provider` would make
+    pub fn diff(
+        &self,
+        provider: &str,
+        options: &SwitchOptions,
+    ) -> Result<(SwitchPreview, Vec<ModelChange>)> {
+        let preview = self.preview_switch(provider, options)?;
+        let current = config::read_document(&self.config_path)?;
+        let changes = diff::agent_changes(&current, &preview.document);
+        Ok((preview, changes))
+    }
+
+    /// Switch to `provider` and write the result, backing up the original
+    /// the first time (or always, with `force_backup`). Aborts without
+    /// writing if the config changes on disk while the switch is computed.
+    pub fn switch(&self, provider: &str, options: &SwitchOptions) -> Result<SwitchOutcome> {
+        self.ensure_exists()?;
+        let source = config::read_source(&self.config_path)?;
+        let preview =
+            self.preview_document(source.document.clone(), &source.hash, provider, options)?;
+        self.check_secrets(provider, options)?;
+        let previous_provider = switch::detect_document_provider(&source.document);
+        // Hooks are told the portal provider, not the model prefix
+        let switched_from = self.last_provider()?;
+        if options.output.is_none() {
+            self.run_hook(
+                Hook::PreSwitch,
+                "switch",
+                Some(provider),
+                switched_from.as_deref(),
+            )?;
+        }
+        let action = format!("switch to {}", provider);
+        let (changes, backup) =
+            self.commit(&source, &preview.document, options, &action, Some(provider))?;
+
+        let mut warnings = Vec::new();
+        if options.output.is_none() {
+            // An ordinary switch is sticky: it replaces any temporary one
+            temporary::remove_record(&self.temporary_path())?;
+            self.record(EventKind::Switch {
+                config: self.config_path.clone(),
+                provider: provider.to_string(),
+                previous_provider,
+                agents: changed_agents(&changes),
+            });
+            warnings.extend(self.post_switch_hook(
+                "switch",
+                Some(provider),
+                switched_from.as_deref(),
+            ));
+        }
+
+        Ok(SwitchOutcome {
+            report: preview.report,
+            compatibility: preview.compatibility,
+            changes,
+            backup,
+            warnings,
+        })
+    }
+
+    /// Switch like [`Self::switch`], but only until `expiry`, when
+    /// [`Self::expire_temporary`] puts the config back. Switching again
+    /// before then extends the switch; the config still goes back to how
+    /// it was before the first one.
+    pub fn switch_temporarily(
+        &self,
+        provider: &str,
+        options: &SwitchOptions,
+        expiry: &Expiry,
+    ) -> Result<SwitchOutcome> {
+        self.temporarily(provider, options, expiry, || self.switch(provider, options))
+    }
+
+    /// [`Self::switch_fields`] until `expiry`, like [`Self::switch_temporarily`]
+    pub fn switch_fields_temporarily(
+        &self,
+        provider: &str,
+        fields: &[String],
+        options: &SwitchOptions,
+        expiry: &Expiry,
+    ) -> Result<SwitchOutcome> {
+        self.temporarily(provider, options, expiry, || {
+            self.switch_fields(provider, fields, options)
+        })
+    }
+
+    fn temporarily(
+        &self,
+        provider: &str,
+        options: &SwitchOptions,
+        expiry: &Expiry,
+        switch: impl FnOnce() -> Result<SwitchOutcome>,
+    ) -> Result<SwitchOutcome> {
+        if options.output.is_some() {
+            return Err(anyhow!(
+                "A temporary switch can't write to another file; drop --out"
+            ));
+        }
+        let boot_id = match expiry {
+            Expiry::At(_) => None,
+            Expiry::Reboot => Some(clock::current_boot_id().ok_or_else(|| {
+                anyhow!(
+                    "Can't tell when this machine last booted, so --until-reboot is unavailable"
+                )
+            })?),
+        };
+        self.ensure_exists()?;
+        let current = fsutil::read_to_string(&self.config_path)
+            .with_context(|| format!("Failed to read config: {}", self.config_path.display()))?;
+        let pending = self
+            .temporary_switch()?
+            .filter(|record| record.after_hash == hash::sha256_hex(current.as_bytes()));
+        let previous_provider = match &pending {
+            Some(record) => record.previous_provider.clone(),
+            // After a hand edit, the log no longer says what is in the config
+            None if self.modified_since_write()? => None,
+            None => self.last_provider()?,
+        };
+
+        let secrets = self.secret_paths();
+        let outcome = switch()?;
+        let after = fsutil::read_to_string(&self.config_path)
+            .with_context(|| format!("Failed to read config: {}", self.config_path.display()))?;
+        // Kept with placeholders for secrets, like the journal
+        let before = pending
+            .map(|record| record.before)
+            .unwrap_or_else(|| keychain::redact_config(&current, &secrets));
+        if before != keychain::redact_config(&after, &self.secret_paths()) {
+            temporary::write_record(
+                &self.temporary_path(),
+                &TemporarySwitch {
+                    config: self.canonical_config(),
+                    provider: provider.to_string(),
+                    previous_provider,
+                    switched_at: chrono::Utc::now(),
+                    until: match expiry {
+                        Expiry::At(until) => Some(*until),
+                        Expiry::Reboot => None,
+                    },
+                    boot_id,
+                    clock: ClockStamp::now(),
+                    before,
+                    after_hash: hash::sha256_hex(after.as_bytes()),
+                },
+            )?;
+        }
+        Ok(outcome)
+    }
+
+    /// The temporary switch of this config that hasn't run out yet, if any
+    pub fn temporary_switch(&self) -> Result<Option<TemporarySwitch>> {
+        temporary::read_record(&self.temporary_path())
+    }
+
+    /// Keep the current temporary switch for good. `false` if there was none.
+    pub fn make_sticky(&self) -> Result<bool> {
+        let path = self.temporary_path();
+        let existed = path.exists();
+        temporary::remove_record(&path)?;
+        Ok(existed)
+    }
+
+    /// Put the config back if its temporary switch has run out. A config
+    /// changed since the switch is left as it is and the switch forgotten.
+    pub fn expire_temporary(&self) -> Result<Option<Expired>> {
+        self.expire_record(&self.temporary_path())
+    }
+
+    fn expire_record(&self, path: &Path) -> Result<Option<Expired>> {
+        let Some(record) = temporary::read_record(path)? else {
+            return Ok(None);
+        };
+        if !record.is_due(chrono::Utc::now()) {
+            return Ok(None);
+        }
+        let lock = self.lock()?;
+        // Another process may have ended it while this one waited
+        let Some(record) = temporary::read_record(path)? else {
+            return Ok(None);
+        };
+        let current = config::fingerprint(&self.config_path)?;
+        let reverted = current.as_deref() == Some(record.after_hash.as_str());
+        if reverted {
+            let action = format!("end temporary switch to {}", record.provider);
+            let (before, secrets) = self.fill_secrets_text(&record.before)?;
+            self.write_tracked(&action, &secrets, || {
+                fsutil::write_atomic(&self.config_path, before.as_bytes()).with_context(|| {
+                    format!("Failed to write config: {}", self.config_path.display())
+                })?;
+                Ok(None)
+            })?;
+            self.record(EventKind::Expire {
+                config: self.config_path.clone(),
+                provider: record.provider.clone(),
+                restored_provider: record.previous_provider.clone(),
+            });
+        }
+        temporary::remove_record(path)?;
+        // A hook may well run portal itself
+        drop(lock);
+        let warnings = if reverted {
+            self.post_switch_hook(
+                "expire",
+                record.previous_provider.as_deref(),
+                Some(&record.provider),
+            )
+            .into_iter()
+            .collect()
+        } else {
+            Vec::new()
+        };
+        Ok(Some(Expired {
+            record,
+            reverted,
+            warnings,
+        }))
+    }
+
+    /// [`Self::expire_temporary`] for every config with a temporary switch,
+    /// not just this one
+    pub fn expire_all_temporary(&self) -> Vec<Result<Expired>> {
+        temporary::record_paths(&self.state_dir)
+            .into_iter()
+            .filter_map(|path| {
+                let record = match temporary::read_record(&path) {
+                    Ok(record) => record?,
+                    Err(e) => return Some(Err(e)),
+                };
+                self.for_config(&record.config)
+                    .expire_record(&path)
+                    .transpose()
+            })
+            .collect()
+    }
+
+    /// More config files a switch of this config should update, from
+    /// `settings.json`, each as a portal for that file and its model fields
+    pub fn extra_targets(&self) -> Result<Vec<(Portal, Vec<String>)>> {
+        let settings = settings::read_settings(&self.portal_dir)?.unwrap_or_default();
+        Ok(settings
+            .targets
+            .into_iter()
+            .map(|target| {
+                (
+                    self.for_config(paths::expand_home(&target.path)),
+                    target.fields,
+                )
+            })
+            .collect())
+    }
+
+    /// Compute what [`Self::switch_fields`] would change without writing
+    pub fn preview_fields(
+        &self,
+        provider: &str,
+        fields: &[String],
+    ) -> Result<(SwitchReport, Vec<ModelChange>)> {
+        self.ensure_exists()?;
+        let mut document = config::read_document(&self.config_path)?;
+        self.switch_fields_in(&mut document, provider, fields)
+    }
+
+    fn switch_fields_in(
+        &self,
+        document: &mut Value,
+        provider: &str,
+        fields: &[String],
+    ) -> Result<(SwitchReport, Vec<ModelChange>)> {
+        self.verify_provider(provider)?;
+        let provider_config = self.provider_config(provider)?;
+        let mappings = self.mappings()?;
+        let ctx = SwitchContext {
+            provider_config: provider_config.as_ref(),
+            ..SwitchContext::new(provider, &mappings)
+        };
+        Ok(switch::switch_fields(document, fields, &ctx))
+    }
+
+    /// Switch the model strings at the dotted `fields` paths of a config
+    /// that isn't an oh-my-opencode config, with the same backup,
+    /// concurrent-edit, and journal handling as [`Self::switch`]
+    pub fn switch_fields(
+        &self,
+        provider: &str,
+        fields: &[String],
+        options: &SwitchOptions,
+    ) -> Result<SwitchOutcome> {
+        self.ensure_exists()?;
+        let source = config::read_source(&self.config_path)?;
+        let mut document = source.document.clone();
+        let (report, changes) = self.switch_fields_in(&mut document, provider, fields)?;
+        let action = format!("switch to {}", provider);
+        let backup = if changes.iter().any(ModelChange::is_changed) {
+            self.commit(&source, &document, options, &action, None)?.1
+        } else {
+            None
+        };
+
+        if options.output.is_none() {
+            temporary::remove_record(&self.temporary_path())?;
+            self.record(EventKind::Switch {
+                config: self.config_path.clone(),
+                provider: provider.to_string(),
+                previous_provider: None,
+                agents: changed_agents(&changes),
+            });
+        }
+
+        Ok(SwitchOutcome {
+            report,
+            compatibility: Compatibility::default(),
+            changes,
+            backup,
+            warnings: Vec::new(),
+        })
+    }
+
+    /// Set specific models directly, keyed by agent name or
+    /// `subagents.<name>`, with the same backup and concurrent-edit checks
+    /// as [`Self::switch`]. With `provider`, its client options are merged
+    /// in too, unless the config is one portal only partly understands, and
+    /// the write is logged as a switch to it; otherwise it is a manual edit.
+    pub fn apply_models(
+        &self,
+        models: &BTreeMap<String, String>,
+        provider: Option<&str>,
+        options: &SwitchOptions,
+    ) -> Result<SwitchOutcome> {
+        self.ensure_exists()?;
+        let source = config::read_source(&self.config_path)?;
+        let mut document = source.document.clone();
+        for (agent, model) in models {
+            let slot = config::model_slot_mut(&mut document, agent).ok_or_else(|| {
+                anyhow!(
+                    "Agent '{}' not found in config: {}",
+                    agent,
+                    self.config_path.display()
+                )
+            })?;
+            *slot = Value::String(model.clone());
+        }
+        let compatibility = compat::assess(&source.document);
+        let mut report = SwitchReport::default();
+        if let Some(provider) = provider
+            && !compatibility.is_conservative()
+        {
+            self.check_secrets(provider, options)?;
+            let provider_config = self.provider_config(provider)?;
+            let mappings = self.mappings()?;
+            let agents: Vec<String> = models.keys().cloned().collect();
+            let ctx = SwitchContext {
+                provider_config: provider_config.as_ref(),
+                agents: Some(&agents),
+                ..SwitchContext::new(provider, &mappings)
+            };
+            report.options = switch::apply_provider_options(&mut document, &ctx, &[]);
+        }
+        let action = match provider {
+            Some(provider) => format!("switch to {}", provider),
+            None => format!(
+                "set {}",
+                models
+                    .iter()
+                    .map(|(agent, model)| format!("{}={}", agent, model))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+        };
+        let hooked = provider.is_some() && options.output.is_none();
+        let switched_from = if hooked { self.last_provider()? } else { None };
+        if hooked {
+            self.run_hook(
+                Hook::PreSwitch,
+                "switch",
+                provider,
+                switched_from.as_deref(),
+            )?;
+        }
+        let (changes, backup) = self.commit(&source, &document, options, &action, None)?;
+
+        let mut warnings = Vec::new();
+        if options.output.is_none() {
+            let config = self.config_path.clone();
+            let agents = changed_agents(&changes);
+            self.record(match provider {
+                Some(provider) => EventKind::Switch {
+                    config,
+                    provider: provider.to_string(),
+                    previous_provider: switch::detect_document_provider(&source.document),
+                    agents,
+                },
+                None => EventKind::Edit { config, agents },
+            });
+        }
+        if hooked {
+            warnings.extend(self.post_switch_hook("switch", provider, switched_from.as_deref()));
+        }
+
+        Ok(SwitchOutcome {
+            report,
+            compatibility,
+            changes,
+            backup,
+            warnings,
+        })
+    }
+
+    /// Run a batch's steps in memory and report what writing the result
+    /// would change, without writing anything
+    pub fn preview_batch(
+        &self,
+        lines: &[batch::Line],
+        options: &SwitchOptions,
+    ) -> Result<BatchOutcome> {
+        self.ensure_exists()?;
+        let source = config::read_source(&self.config_path)?;
+        let (document, switches) = self.batch_document(&source, lines, options)?;
+        Ok(BatchOutcome {
+            switches,
+            compatibility: compat::assess(&source.document),
+            changes: diff::agent_changes(&source.document, &document),
+            backup: None,
+            warnings: Vec::new(),
+        })
+    }
+
+    /// Run a batch's steps in memory, each on the result of the one before,
+    /// then write the config once: one lock, at most one backup, one
+    /// journal entry. The write is logged as a switch to the last step's
+    /// provider, or as a manual edit if no step switches.
+    pub fn apply_batch(
+        &self,
+        lines: &[batch::Line],
+        options: &SwitchOptions,
+    ) -> Result<BatchOutcome> {
+        self.ensure_exists()?;
+        let source = config::read_source(&self.config_path)?;
+        let (document, switches) = self.batch_document(&source, lines, options)?;
+        let provider = switches.last().map(|(provider, _)| provider.clone());
+        let hooked = provider.is_some() && options.output.is_none();
+        let switched_from = if hooked { self.last_provider()? } else { None };
+        if hooked {
+            self.run_hook(
+                Hook::PreSwitch,
+                "switch",
+                provider.as_deref(),
+                switched_from.as_deref(),
+            )?;
+        }
+        let action = format!(
+            "batch: {}",
+            lines
+                .iter()
+                .map(|line| line.text.as_str())
+                .collect::<Vec<_>>()
+                .join("; ")
+        );
+        let (changes, backup) = self.commit(&source, &document, options, &action, None)?;
+
+        let mut warnings = Vec::new();
+        if options.output.is_none() {
+            let config = self.config_path.clone();
+            let agents = changed_agents(&changes);
+            if provider.is_some() {
+                // Like an ordinary switch, this one is sticky
+                temporary::remove_record(&self.temporary_path())?;
+            }
+            self.record(match &provider {
+                Some(provider) => EventKind::Switch {
+                    config,
+                    provider: provider.clone(),
+                    previous_provider: switch::detect_document_provider(&source.document),
+                    agents,
+                },
+                None => EventKind::Edit { config, agents },
+            });
+        }
+        if hooked {
+            warnings.extend(self.post_switch_hook(
+                "switch",
+                provider.as_deref(),
+                switched_from.as_deref(),
+            ));
+        }
+
+        Ok(BatchOutcome {
+            switches,
+            compatibility: compat::assess(&source.document),
+            changes,
+            backup,
+            warnings,
+        })
+    }
+
+    /// The config after every step of a batch, with each switch's report
+    fn batch_document(
+        &self,
+        source: &config::Source,
+        lines: &[batch::Line],
+        options: &SwitchOptions,
+    ) -> Result<(Value, Vec<(String, SwitchReport)>)> {
+        let mut document = source.document.clone();
+        let mut switches = Vec::new();
+        for line in lines {
+            let context = || format!("Line {}: {}", line.number, line.text);
+            match &line.step {
+                Step::Switch {
+                    provider,
+                    agents,
+                    skip_top_level,
+                } => {
+                    let provider = self.resolve_provider(provider).with_context(context)?;
+                    self.check_secrets(&provider, options)
+                        .with_context(context)?;
+                    let step_options = SwitchOptions {
+                        agents: agents.clone(),
+                        skip_top_level: *skip_top_level,
+                        ..options.clone()
+                    };
+                    let preview = self
+                        .preview_document(document, &source.hash, &provider, &step_options)
+                        .with_context(context)?;
+                    document = preview.document;
+                    switches.push((provider, preview.report));
+                }
+                Step::Set {
+                    agent,
+                    model,
+                    force,
+                } => {
+                    if !force {
+                        self.ensure_known_model(model).with_context(context)?;
+                    }
+                    let slot = config::model_slot_mut(&mut document, agent)
+                        .ok_or_else(|| anyhow!("Agent '{}' not found in config", agent))
+                        .with_context(context)?;
+                    *slot = Value::String(model.clone());
+                }
+            }
+        }
+        Ok((document, switches))
+    }
+
+    /// Write `document` as the new config (or to `options.output`), as an
+    /// edit of `source`. Refuses if the config on disk is no longer
+    /// `source`, or isn't the version `options.expect_hash` names. The
+    /// write is journaled as `action`. `provider` is set for a switch to
+    /// it, which in symlink mode links the config to its rendering instead.
+    fn commit(
+        &self,
+        source: &config::Source,
+        document: &Value,
+        options: &SwitchOptions,
+        action: &str,
+        provider: Option<&str>,
+    ) -> Result<(Vec<ModelChange>, Option<PathBuf>)> {
+        let changes = diff::agent_changes(&source.document, document);
+
+        if let Some(output) = &options.output {
+            let (document, _) = self.fill_secrets(document)?;
+            config::write_rendered(output, Some(&source.text), &document)?;
+            return Ok((changes, None));
+        }
+
+        if options
+            .expect_hash
+            .as_ref()
+            .is_some_and(|expected| *expected != source.hash)
+        {
+            return Err(self.changed_error());
+        }
+        let _lock = self.lock()?;
+        self.ensure_unchanged(Some(&source.hash))?;
+        let backup = match provider {
+            Some(provider) if self.switch_mode()? == SwitchMode::Symlink => self.write_linked(
+                &source.text,
+                document,
+                options.force_backup,
+                action,
+                provider,
+            )?,
+            _ => self.write_document_as(document, options.force_backup, action)?,
+        };
+        Ok((changes, backup))
+    }
+
+    /// Announce a switch portal made on its own with a desktop
+    /// notification, if settings.json asks for them
+    pub fn notify(&self, message: &str) {
+        let wanted = settings::read_settings(&self.portal_dir)
+            .map(|settings| settings.unwrap_or_default().notify)
+            .unwrap_or(false);
+        if wanted && let Err(e) = notify::send(message) {
+            crate::portal_warn!("{:#}", e);
+        }
+    }
+
+    fn switch_mode(&self) -> Result<SwitchMode> {
+        Ok(settings::read_settings(&self.portal_dir)?
+            .unwrap_or_default()
+            .switch_mode)
+    }
+
+    /// The rendering the config links to, if it is a symlink into
+    /// `rendered/`
+    pub fn linked_rendering(&self) -> Option<PathBuf> {
+        rendered::link_target(&self.config_path, &self.portal_dir)
+    }
+
+    /// Where `provider`'s rendering of this config goes in symlink mode
+    pub fn rendered_path(&self, provider: &str) -> PathBuf {
+        rendered::rendered_path(&self.portal_dir, self.backups().name(), provider)
+    }
+
+    /// Symlink mode's write: render `document` as an edit of `original`
+    /// into the provider's file under `rendered/`, then point the config at
+    /// it. A config that is still a plain file is backed up first, as it
+    /// would be before being rewritten.
+    fn write_linked(
+        &self,
+        original: &str,
+        document: &Value,
+        force_backup: bool,
+        action: &str,
+        provider: &str,
+    ) -> Result<Option<PathBuf>> {
+        let target = self.rendered_path(provider);
+        let (document, secrets) = self.fill_secrets(document)?;
+        self.write_tracked(action, &secrets, || {
+            let backup = self.backup_if_needed(force_backup)?;
+            if let Some(dir) = target.parent() {
+                fs::create_dir_all(dir).with_context(|| {
+                    format!(
+                        "Failed to create rendered config directory: {}",
+                        dir.display()
+                    )
+                })?;
+            }
+            config::write_rendered(&target, Some(original), &document)?;
+            // The rendering may hold API keys, like the config did
+            if let Ok(metadata) = fs::metadata(&self.config_path) {
+                let _ = fs::set_permissions(&target, metadata.permissions());
+            }
+            fsutil::symlink_atomic(&self.config_path, &target).with_context(|| {
+                format!(
+                    "Failed to link {} to {}",
+                    self.config_path.display(),
+                    target.display()
+                )
+            })?;
+            Ok(backup)
+        })
+    }
+
+    /// Write `config`, creating a backup first if needed
+    pub fn write(
+        &self,
+        config: &OhMyOpenCodeConfig,
+        force_backup: bool,
+    ) -> Result<Option<PathBuf>> {
+        let _lock = self.lock()?;
+        self.write_document_as(&serde_json::to_value(config)?, force_backup, "write")
+    }
+
+    /// Write an untyped config document, creating a backup first if needed
+    pub fn write_document(&self, document: &Value, force_backup: bool) -> Result<Option<PathBuf>> {
+        let _lock = self.lock()?;
+        self.write_document_as(document, force_backup, "write")
+    }
+
+    /// [`Self::write_document`], journaled under `action`
+    fn write_document_as(
+        &self,
+        document: &Value,
+        force_backup: bool,
+        action: &str,
+    ) -> Result<Option<PathBuf>> {
+        let (document, secrets) = self.fill_secrets(document)?;
+        self.write_tracked(action, &secrets, || {
+            let backup = self.backup_if_needed(force_backup)?;
+            config::write_document(&self.config_path, &document)?;
+            Ok(backup)
+        })
+    }
+
+    fn backup_if_needed(&self, force_backup: bool) -> Result<Option<PathBuf>> {
+        let channel = self.backups();
+        let should_backup = force_backup || !backup::has_existing_backup(&channel);
+        if should_backup && let Some(contents) = self.redacted_config()? {
+            let settings = settings::read_settings(&self.portal_dir)?
+                .unwrap_or_default()
+                .backup;
+            Ok(Some(backup::create_backup(
+                &channel,
+                &settings,
+                contents.as_bytes(),
+            )?))
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Move the backups older versions left next to the config into its
+    /// channel in the store, `jobs` at a time, as
+    /// [`backup::migrate_legacy`] does
+    pub fn migrate_backups(
+        &self,
+        jobs: usize,
+        progress: impl Fn(usize, usize) + Sync,
+    ) -> Result<Vec<backup::Migrated>> {
+        let _lock = self.lock()?;
+        let settings = settings::read_settings(&self.portal_dir)?
+            .unwrap_or_default()
+            .backup;
+        backup::migrate_legacy(&self.backups(), &settings, jobs, progress)
+    }
+
+    /// Resolve which backup a revert would restore
+    pub fn resolve_backup(&self, backup_path: Option<PathBuf>) -> Result<PathBuf> {
+        match backup_path {
+            Some(p) => {
+                if !p.exists() {
+                    return Err(anyhow!("Backup file not found: {}", p.display()));
+                }
+                // Another config's channel is off limits
+                let channel = self.backups();
+                let store = fs::canonicalize(self.backup_store()).ok();
+                let dir = fs::canonicalize(&p)
+                    .ok()
+                    .and_then(|p| p.parent().map(Path::to_path_buf));
+                if let (Some(store), Some(dir)) = (store, dir)
+                    && dir.starts_with(&store)
+                    && fs::canonicalize(channel.dir()).ok() != Some(dir)
+                {
+                    return Err(anyhow!(
+                        "{} belongs to another config's backup channel, not '{}'",
+                        p.display(),
+                        channel.name()
+                    ));
+                }
+                Ok(p)
+            }
+            None => backup::find_latest_backup(&self.backups())
+                .ok_or_else(|| anyhow!("No backup files found")),
+        }
+    }
+
+    /// Restore the config from a backup (latest if `backup_path` is `None`).
+    /// Aborts without writing if the config changes on disk in the meantime.
+    pub fn revert(&self, backup_path: Option<PathBuf>) -> Result<PathBuf> {
+        let expected = config::fingerprint(&self.config_path)?;
+        let backup = self.resolve_backup(backup_path)?;
+        let _lock = self.lock()?;
+        self.ensure_unchanged(expected.as_deref())?;
+        let (content, secrets) = match String::from_utf8(backup::read_backup(&backup)?) {
+            Ok(text) => {
+                let (text, secrets) = self.fill_secrets_text(&text)?;
+                (text.into_bytes(), secrets)
+            }
+            Err(e) => (e.into_bytes(), SecretPaths::new()),
+        };
+        let action = format!(
+            "revert to {}",
+            backup.file_name().unwrap_or_default().to_string_lossy()
+        );
+        self.write_tracked(&action, &secrets, || {
+            fsutil::write_atomic(&self.config_path, &content)
+                .with_context(|| format!("Failed to restore from backup: {}", backup.display()))?;
+            Ok(None)
+        })?;
+        self.record(EventKind::Revert {
+            config: self.config_path.clone(),
+            backup: backup.clone(),
+        });
+        Ok(backup)
+    }
+
+    /// The config as it is now, to [`Self::roll_back`] to
+    pub fn snapshot(&self) -> Result<Snapshot> {
+        let contents = fsutil::read(&self.config_path)
+            .with_context(|| format!("Failed to read config: {}", self.config_path.display()))?;
+        Ok(Snapshot {
+            contents,
+            secrets: self.secret_paths(),
+        })
+    }
+
+    /// Put the config back to `original`, what it held before a switch
+    /// that has to be taken back, e.g. because another config in the same
+    /// [`crate::fleet`] switch failed. `action` names that switch, for the
+    /// journal and the event log.
+    pub fn roll_back(&self, original: &Snapshot, action: &str) -> Result<()> {
+        let _lock = self.lock()?;
+        self.write_tracked(&format!("roll back {}", action), &original.secrets, || {
+            fsutil::write_atomic(&self.config_path, &original.contents).with_context(|| {
+                format!("Failed to roll back config: {}", self.config_path.display())
+            })?;
+            Ok(None)
+        })?;
+        self.record(EventKind::Undo {
+            config: self.config_path.clone(),
+            action: action.to_string(),
+        });
+        Ok(())
+    }
+}
+
+/// A config's contents, and where they hold secrets from the keychain
+pub struct Snapshot {
+    contents: Vec<u8>,
+    secrets: SecretPaths,
+}
+
+/// Say that a write leaves the secrets at `paths` in clear in `config`
+fn warn_in_clear(config: &Path, paths: &SecretPaths) {
+    if paths.is_empty() {
+        return;
+    }
+    crate::portal_warn!(
+        "{} holds {} from the keychain in clear: {}. Portal's journal and backups keep the placeholders.",
+        config.display(),
+        if paths.len() == 1 {
+            "a secret"
+        } else {
+            "secrets"
+        },
+        paths.keys().cloned().collect::<Vec<_>>().join(", ")
+    );
+}
+
+fn changed_agents(changes: &[ModelChange]) -> Vec<String> {
+    changes
+        .iter()
+        .filter(|c| c.is_changed())
+        .map(|c| c.agent.clone())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A portal for `config` in a fresh directory under the system's
+    /// temporary one
+    fn scratch(name: &str, config: &str) -> Portal {
+        let dir =
+            std::env::temp_dir().join(format!("portal-status-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("oh-my-opencode.json"), config).unwrap();
+        Portal::new(dir.join("oh-my-opencode.json"), dir.join("portal"))
+            .with_state_dir(dir.join("state"))
+    }
+
+    #[test]
+    fn status_lists_an_agent_without_a_model() {
+        let portal = scratch(
+            "no-model",
+            r#"{
+                "agents": {
+                    "oracle": {"model": "openrouter/openai/gpt-5.2"},
+                    "oracle": {"model": "openrouter/openai/gpt-5.2", "temperature": 0.1},
+                    "explore": {"temperature": 0.2}
+                }
+            }"#,
+        );
+        let status = portal.status().unwrap();
+        assert_eq!(
+            status.agents,
+            BTreeMap::from([
+                ("explore".to_string(), None),
+                (
+                    "oracle".to_string(),
+                    Some("openrouter/openai/gpt-5.2".to_string())
+                ),
+            ])
+        );
+        assert_eq!(status.provider.as_deref(), Some("openrouter"));
+        assert!(status.compatibility.is_conservative());
+    }
+
+    #[test]
+    fn conservative_switch_leaves_provider_options_out() {
+        let portal = scratch(
+            "conservative",
+            r#"{"agents": {"oracle": {"model": "github-copilot/gpt-5.2"}, "explore": {}}}"#,
+        );
+        fs::create_dir_all(portal.portal_dir()).unwrap();
+        fs::write(
+            portal.portal_dir().join("gateway.json"),
+            r#"{"agents": {"oracle": {"model": "gateway/gpt-5.2"}}, "options": {"timeout": 5}}"#,
+        )
+        .unwrap();
+
+        let preview = portal
+            .preview_switch("gateway", &SwitchOptions::default())
+            .unwrap();
+        assert!(preview.compatibility.is_conservative());
+        assert_eq!(
+            preview.document["agents"]["oracle"]["model"],
+            "gateway/gpt-5.2"
+        );
+        assert!(preview.document.get("provider").is_none());
+        assert!(preview.report.options.is_empty());
+
+        let models = BTreeMap::from([("oracle".to_string(), "gateway/gpt-5.2".to_string())]);
+        let outcome = portal
+            .apply_models(&models, Some("gateway"), &SwitchOptions::default())
+            .unwrap();
+        assert!(outcome.report.options.is_empty());
+        assert!(
+            config::read_document(portal.config_path())
+                .unwrap()
+                .get("provider")
+                .is_none()
+        );
+    }
+
+    #[test]
+    fn provider_config_fills_in_what_it_extends() {
+        let portal = scratch("extends", "{}");
+        fs::create_dir_all(portal.portal_dir()).unwrap();
+        fs::write(
+            portal.portal_dir().join("base.json"),
+            r#"{"extends": "openrouter", "agents": {"oracle": {"model": "base/oracle"}, "explore": {"model": "base/explore"}}, "options": {"timeout": 5}}"#,
+        )
+        .unwrap();
+        fs::write(
+            portal.portal_dir().join("team.json"),
+            r#"{"extends": "base", "agents": {"oracle": {"model": "team/oracle"}}}"#,
+        )
+        .unwrap();
+
+        let config = portal.provider_config("team").unwrap().unwrap();
+        assert_eq!(config.ancestors, ["base", "openrouter"]);
+        assert_eq!(config.agents["oracle"].model, "team/oracle");
+        assert_eq!(config.agents["explore"].model, "base/explore");
+        assert_eq!(config.options.timeout, Some(5));
+    }
+
+    #[test]
+    fn provider_config_refuses_cycles_and_unknown_parents() {
+        let portal = scratch("extends-cycle", "{}");
+        fs::create_dir_all(portal.portal_dir()).unwrap();
+        fs::write(portal.portal_dir().join("a.json"), r#"{"extends": "b"}"#).unwrap();
+        fs::write(portal.portal_dir().join("b.json"), r#"{"extends": "a"}"#).unwrap();
+        fs::write(
+            portal.portal_dir().join("c.json"),
+            r#"{"extends": "nowhere"}"#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            portal.provider_config("a").unwrap_err().to_string(),
+            "Provider inheritance cycle: a extends b extends a"
+        );
+        assert_eq!(
+            portal.provider_config("c").unwrap_err().to_string(),
+            "Provider 'c' extends unknown provider 'nowhere'"
+        );
+    }
+
+    #[test]
+    fn status_of_a_config_without_agents() {
+        let portal = scratch("no-agents", r#"{"model": "github-copilot/gpt-5.2"}"#);
+        let status = portal.status().unwrap();
+        assert!(status.agents.is_empty());
+        assert_eq!(status.provider, None);
+        assert!(status.compatibility.is_conservative());
+    }
+}
@@ -0,0 +1,106 @@
+//! Approximate prices per million tokens, to weigh up a switch. Bundled
+//! prices are list prices keyed by base model; `pricing` in settings.json
+//! adds or overrides entries, keyed by base model or by full model string.
+
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+use crate::mapping::MappingTable;
+use crate::switch;
+
+/// US dollars per million tokens
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Price {
+    pub input: f64,
+    pub output: f64,
+}
+
+/// Bundled list prices as (base model, input, output). They drift; override
+/// them in settings.json rather than relying on them for billing.
+const BUILTIN_PRICES: &[(&str, f64, f64)] = &[
+    ("claude-opus-4.5", 5.0, 25.0),
+    ("claude-sonnet-4.5", 3.0, 15.0),
+    ("claude-sonnet-4", 3.0, 15.0),
+    ("gpt-5.2", 1.75, 14.0),
+    ("gpt-4.1", 2.0, 8.0),
+    ("o3", 2.0, 8.0),
+    ("o4-mini", 1.1, 4.4),
+    ("gemini-3-flash", 0.5, 3.0),
+    ("gemini-3-pro", 2.0, 12.0),
+];
+
+/// What a model costs to use
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Cost {
+    PerToken {
+        input: f64,
+        output: f64,
+    },
+    /// Covered by a GitHub Copilot plan rather than billed per token
+    Plan,
+    Unknown,
+}
+
+impl Cost {
+    /// `$3.00 in / $15.00 out`, `Copilot plan`, or `unknown`
+    pub fn describe(&self) -> String {
+        match self {
+            Cost::PerToken { input, output } => format!("${:.2} in / ${:.2} out", input, output),
+            Cost::Plan => "Copilot plan".to_string(),
+            Cost::Unknown => "unknown".to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct PricingTable {
+    prices: BTreeMap<String, Price>,
+}
+
+impl PricingTable {
+    pub fn builtin() -> Self {
+        Self {
+            prices: BUILTIN_PRICES
+                .iter()
+                .map(|(base, input, output)| {
+                    (
+                        base.to_string(),
+                        Price {
+                            input: *input,
+                            output: *output,
+                        },
+                    )
+                })
+                .collect(),
+        }
+    }
+
+    /// The bundled prices with `overrides` (from settings.json) on top
+    pub fn with_overrides(overrides: &BTreeMap<String, Price>) -> Self {
+        let mut table = Self::builtin();
+        table
+            .prices
+            .extend(overrides.iter().map(|(key, price)| (key.clone(), *price)));
+        table
+    }
+
+    /// Price of `model`: an entry for the full model string first, then
+    /// Copilot's flat plan, then an entry for its base model
+    pub fn cost(&self, model: &str, mappings: &MappingTable) -> Cost {
+        let per_token = |price: &Price| Cost::PerToken {
+            input: price.input,
+            output: price.output,
+        };
+        if let Some(price) = self.prices.get(model) {
+            return per_token(price);
+        }
+        if model.starts_with("github-copilot/") {
+            return Cost::Plan;
+        }
+        match self.prices.get(&switch::canonical_base(model, mappings)) {
+            Some(price) => per_token(price),
+            None => Cost::Unknown,
+        }
+    }
+}
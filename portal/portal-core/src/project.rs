@@ -0,0 +1,116 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use crate::error::PortalError;
+use crate::fsutil;
+use crate::jsonc;
+use crate::mapping::{MappingOverride, MappingsFile};
+
+/// Name of the project-level portal file
+pub const PROJECT_FILE: &str = ".portal.json";
+
+/// Directory that can hold the project file instead, as `.portal/portal.json`
+pub const PROJECT_DIR: &str = ".portal";
+
+/// Name of the project file inside [`PROJECT_DIR`]
+pub const PROJECT_DIR_FILE: &str = "portal.json";
+
+/// Project-level settings from `.portal.json`
+///
+/// ```json
+/// {
+///   "agents": ["sisyphus", "oracle"],
+///   "config": "opencode/oh-my-opencode.json",
+///   "provider": "openrouter",
+///   "mappings": { "claude-opus-4.5": { "openrouter": "anthropic/claude-opus-4.5:beta" } }
+/// }
+/// ```
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ProjectConfig {
+    /// Agents relevant to this project
+    #[serde(default)]
+    pub agents: Vec<String>,
+    /// Project oh-my-opencode config, relative to the project root. When
+    /// set, portal works on it instead of the global config anywhere in
+    /// the project.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub config: Option<PathBuf>,
+    /// Provider `portal switch` uses when none is named
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub provider: Option<String>,
+    /// Mapping rows layered over `mappings.json` (and an environment's), in
+    /// its format
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub mappings: BTreeMap<String, MappingOverride>,
+}
+
+impl ProjectConfig {
+    pub fn mapping_overrides(&self) -> MappingsFile {
+        MappingsFile {
+            mappings: self.mappings.clone(),
+            ..MappingsFile::default()
+        }
+    }
+}
+
+/// A loaded `.portal.json` together with where it was found
+#[derive(Debug, Clone)]
+pub struct Project {
+    pub path: PathBuf,
+    pub config: ProjectConfig,
+}
+
+impl Project {
+    /// Directory containing the `.portal.json` or `.portal/`
+    pub fn root(&self) -> &Path {
+        root_of(&self.path)
+    }
+
+    /// Project config path resolved against the project root
+    pub fn config_path(&self) -> Option<PathBuf> {
+        self.config.config.as_ref().map(|p| self.root().join(p))
+    }
+}
+
+/// The project root a project file belongs to
+pub fn root_of(project_file: &Path) -> &Path {
+    let parent = project_file.parent().unwrap_or(Path::new("."));
+    if parent.file_name().is_some_and(|name| name == PROJECT_DIR) {
+        parent.parent().unwrap_or(Path::new("."))
+    } else {
+        parent
+    }
+}
+
+/// Walk up from `start` looking for a `.portal.json`, or a
+/// `.portal/portal.json` in a directory without one
+pub fn find_project_file(start: &Path) -> Option<PathBuf> {
+    start.ancestors().find_map(|dir| {
+        [
+            dir.join(PROJECT_FILE),
+            dir.join(PROJECT_DIR).join(PROJECT_DIR_FILE),
+        ]
+        .into_iter()
+        .find(|candidate| candidate.is_file())
+    })
+}
+
+pub fn read_project(path: &Path) -> Result<Project> {
+    let content = fsutil::read_to_string(path)
+        .with_context(|| format!("Failed to read project file: {}", path.display()))?;
+    let config = serde_json::from_str(&jsonc::strip(&content))
+        .map_err(PortalError::parse("project file", path))?;
+    Ok(Project {
+        path: path.to_path_buf(),
+        config,
+    })
+}
+
+/// Find and load the nearest project file above `start`
+pub fn discover(start: &Path) -> Result<Option<Project>> {
+    find_project_file(start)
+        .map(|path| read_project(&path))
+        .transpose()
+}
@@ -0,0 +1,489 @@
+use anyhow::{Context, Result, anyhow};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::error::PortalError;
+use crate::{config, fsutil, jsonc};
+
+/// Provider config format - matches oh-my-opencode structure
+/// Example: { "agents": { "sisyphus": { "model": "github-copilot/claude-opus-4.5" } } }
+///
+/// Agent names may be glob patterns (`test-*`, `*`), see
+/// [`ProviderConfig::agent`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Default)]
+pub struct ProviderConfig {
+    /// One line saying what the provider is, shown by `portal list`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    /// Anything worth remembering: what it routes through, when to use it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub notes: Option<String>,
+    /// Provider this one builds on: its agents, rules, options, and mapping
+    /// table names apply wherever this file doesn't say otherwise
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub extends: Option<String>,
+    /// The providers `extends` leads to, nearest first, once
+    /// [`crate::Portal::provider_config`] has filled them in
+    #[serde(skip)]
+    pub ancestors: Vec<String>,
+    #[serde(default)]
+    pub agents: BTreeMap<String, AgentModelConfig>,
+    /// Models built from the base model, for agents without an entry in
+    /// `agents`; the first matching rule wins
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub rules: Vec<ModelRule>,
+    /// Client options merged into `provider.<id>.options` for each opencode
+    /// provider the switched models use
+    #[serde(default, skip_serializing_if = "ProviderOptions::is_empty")]
+    pub options: ProviderOptions,
+}
+
+/// Provider-level client settings, named as opencode expects them
+/// Example: { "timeout": 600000, "maxRetries": 5, "parallelToolCalls": false }
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ProviderOptions {
+    /// Request timeout in milliseconds
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timeout: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_retries: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub parallel_tool_calls: Option<bool>,
+    /// Any other option, passed through as-is
+    #[serde(flatten)]
+    pub extra: BTreeMap<String, Value>,
+}
+
+impl ProviderConfig {
+    /// The entry for `agent`: its exact name first, then the glob pattern
+    /// matching it with the most literal characters, then the `*`
+    /// catch-all. Patterns that tie go by name.
+    pub fn agent(&self, agent: &str) -> Option<&AgentModelConfig> {
+        if let Some(entry) = self.agents.get(agent) {
+            return Some(entry);
+        }
+        self.agents
+            .iter()
+            .filter(|(pattern, _)| is_agent_pattern(pattern) && glob_matches(pattern, agent))
+            .max_by_key(|(pattern, _)| {
+                let literal = pattern.chars().filter(|c| !matches!(c, '*' | '?')).count();
+                (literal, std::cmp::Reverse(pattern.as_str()))
+            })
+            .map(|(_, entry)| entry)
+    }
+
+    /// The model the first rule matching `base` gives it
+    pub fn rule_model(&self, base: &str) -> Option<String> {
+        self.rules.iter().find_map(|rule| rule.apply(base))
+    }
+
+    /// Fill in what this config leaves out from `parent`, the config it
+    /// extends: agents it doesn't list, rules tried after its own, and
+    /// options it doesn't set
+    pub fn inherit(&mut self, parent: &ProviderConfig) {
+        if self.description.is_none() {
+            self.description = parent.description.clone();
+        }
+        if self.notes.is_none() {
+            self.notes = parent.notes.clone();
+        }
+        for (agent, entry) in &parent.agents {
+            self.agents
+                .entry(agent.clone())
+                .or_insert_with(|| entry.clone());
+        }
+        self.rules.extend(parent.rules.iter().cloned());
+        self.options.inherit(&parent.options);
+    }
+}
+
+/// A rule building the model for every base model it matches
+/// Example: { "match": "claude-*", "template": "bedrock/anthropic.{model}-v1:0" }
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ModelRule {
+    /// Glob pattern for the base model, e.g. `claude-*`
+    #[serde(rename = "match")]
+    pub pattern: String,
+    /// The model to write; `{model}` stands for the base model
+    pub template: String,
+}
+
+impl ModelRule {
+    /// The model this rule gives `base`, if it matches
+    pub fn apply(&self, base: &str) -> Option<String> {
+        glob_matches(&self.pattern, base).then(|| self.template.replace("{model}", base))
+    }
+}
+
+/// Whether an `agents` key is a glob pattern rather than an agent name
+pub fn is_agent_pattern(name: &str) -> bool {
+    name.contains(['*', '?'])
+}
+
+/// Match `name` against `pattern`, where `*` is any run of characters and
+/// `?` any one character
+pub fn glob_matches(pattern: &str, name: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let name: Vec<char> = name.chars().collect();
+    let (mut p, mut n) = (0, 0);
+    // Where the last `*` was, and how much of the name it has taken
+    let mut star: Option<(usize, usize)> = None;
+    while n < name.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                star = Some((p, n));
+                p += 1;
+            }
+            Some(&c) if c == '?' || c == name[n] => {
+                p += 1;
+                n += 1;
+            }
+            _ => match star {
+                Some((star_p, star_n)) => {
+                    star = Some((star_p, star_n + 1));
+                    p = star_p + 1;
+                    n = star_n + 1;
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+impl ProviderOptions {
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// Take each option this doesn't set from `parent`
+    fn inherit(&mut self, parent: &ProviderOptions) {
+        self.timeout = self.timeout.or(parent.timeout);
+        self.max_retries = self.max_retries.or(parent.max_retries);
+        self.parallel_tool_calls = self.parallel_tool_calls.or(parent.parallel_tool_calls);
+        for (key, value) in &parent.extra {
+            self.extra
+                .entry(key.clone())
+                .or_insert_with(|| value.clone());
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AgentModelConfig {
+    pub model: String,
+}
+
+/// Built-in providers as (name, description) pairs
+pub const BUILTIN_PROVIDERS: &[(&str, &str)] = &[
+    ("copilot", "GitHub Copilot (github-copilot/model)"),
+    ("openrouter", "OpenRouter (openrouter/provider/model)"),
+];
+
+/// Files in the portal directory that are not provider configs
+pub const RESERVED_NAMES: &[&str] = &["daemon", "mappings", "policy", "settings"];
+
+pub fn is_reserved_name(name: &str) -> bool {
+    RESERVED_NAMES.contains(&name)
+}
+
+/// Description of a built-in provider
+pub fn builtin_description(name: &str) -> Option<&'static str> {
+    BUILTIN_PROVIDERS
+        .iter()
+        .find(|(builtin, _)| *builtin == name)
+        .map(|(_, description)| *description)
+}
+
+pub fn is_builtin_name(name: &str) -> bool {
+    BUILTIN_PROVIDERS
+        .iter()
+        .any(|(builtin, _)| *builtin == name)
+}
+
+/// Check that `name` can be used for a new provider file
+pub fn validate_name(name: &str) -> Result<()> {
+    if name.is_empty() || name.contains(['/', '\\']) || name.starts_with('.') {
+        return Err(anyhow!("Invalid provider name: '{}'", name));
+    }
+    if is_reserved_name(name) || is_builtin_name(name) {
+        return Err(anyhow!(
+            "'{}' is a built-in or reserved name; pick another provider name",
+            name
+        ));
+    }
+    Ok(())
+}
+
+pub fn provider_config_path(portal_dir: &Path, provider: &str) -> PathBuf {
+    portal_dir.join(format!("{}.json", provider))
+}
+
+pub fn read_provider_config(portal_dir: &Path, provider: &str) -> Result<Option<ProviderConfig>> {
+    if is_reserved_name(provider) {
+        return Err(anyhow!("'{}' is a reserved name, not a provider", provider));
+    }
+    let path = provider_config_path(portal_dir, provider);
+    if !path.exists() {
+        return Ok(None);
+    }
+    let content = fsutil::read_to_string(&path)
+        .with_context(|| format!("Failed to read provider config: {}", path.display()))?;
+    let config: ProviderConfig = serde_json::from_str(&jsonc::strip(&content))
+        .map_err(PortalError::parse("provider config", &path))?;
+    Ok(Some(config))
+}
+
+/// Names of custom providers defined as `*.json` files in the portal directory
+pub fn list_custom_providers(portal_dir: &Path) -> Result<Vec<String>> {
+    if !portal_dir.exists() {
+        return Ok(Vec::new());
+    }
+    let mut providers: Vec<_> = fs::read_dir(portal_dir)
+        .with_context(|| format!("Failed to read portal directory: {}", portal_dir.display()))?
+        .flatten()
+        .filter(|e| e.path().extension().is_some_and(|ext| ext == "json"))
+        .filter_map(|e| Some(e.path().file_stem()?.to_string_lossy().to_string()))
+        .filter(|name| !is_reserved_name(name))
+        .collect();
+    providers.sort();
+    Ok(providers)
+}
+
+/// Write a provider config as `<portal_dir>/<provider>.json`, creating the
+/// directory. Comments and layout of a file it replaces are kept where the
+/// contents agree.
+pub fn write_provider_config(
+    portal_dir: &Path,
+    provider: &str,
+    config: &ProviderConfig,
+) -> Result<PathBuf> {
+    if is_reserved_name(provider) {
+        return Err(anyhow!("'{}' is a reserved name, not a provider", provider));
+    }
+    fs::create_dir_all(portal_dir).with_context(|| {
+        format!(
+            "Failed to create portal directory: {}",
+            portal_dir.display()
+        )
+    })?;
+    let path = provider_config_path(portal_dir, provider);
+    let original = fsutil::read_to_string(&path).ok();
+    let content = config::render_document(original.as_deref(), &serde_json::to_value(config)?)?;
+    fsutil::write_atomic(&path, content.as_bytes())
+        .with_context(|| format!("Failed to write provider config: {}", path.display()))?;
+    Ok(path)
+}
+
+/// Captured models merged into a provider file, ready to write
+#[derive(Debug, Clone)]
+pub struct ProviderMerge {
+    pub path: PathBuf,
+    /// New file content, keeping the comments and layout of the old one
+    pub content: String,
+    /// Agents the file didn't have
+    pub added: Vec<String>,
+    /// Agents the file gave another model until now, with that model
+    pub updated: Vec<(String, String)>,
+    /// Agents the file already gives the captured model
+    pub unchanged: usize,
+}
+
+impl ProviderMerge {
+    /// Whether writing would change the file
+    pub fn is_changed(&self) -> bool {
+        !self.added.is_empty() || !self.updated.is_empty()
+    }
+
+    pub fn write(&self) -> Result<()> {
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent)
+                .with_context(|| format!("Failed to create directory: {}", parent.display()))?;
+        }
+        fsutil::write_atomic(&self.path, self.content.as_bytes())
+            .with_context(|| format!("Failed to write provider config: {}", self.path.display()))
+    }
+}
+
+/// Write `captured`'s agents into `<portal_dir>/<provider>.json`, keeping
+/// the file's other agents and options. Built-in names are allowed: their
+/// files add models on top of the mapping table.
+pub fn merge_provider_config(
+    portal_dir: &Path,
+    provider: &str,
+    captured: &ProviderConfig,
+) -> Result<ProviderMerge> {
+    if !is_builtin_name(provider) {
+        validate_name(provider)?;
+    }
+    let path = provider_config_path(portal_dir, provider);
+    let original = match fsutil::read_to_string(&path) {
+        Ok(text) => Some(text),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => None,
+        Err(e) => {
+            return Err(e)
+                .with_context(|| format!("Failed to read provider config: {}", path.display()));
+        }
+    };
+    let mut document = match &original {
+        Some(text) => jsonc::parse(text).map_err(PortalError::parse("provider config", &path))?,
+        None => Value::Object(Default::default()),
+    };
+    let agents = document
+        .as_object_mut()
+        .ok_or_else(|| anyhow!("Provider config is not an object: {}", path.display()))?
+        .entry("agents")
+        .or_insert_with(|| Value::Object(Default::default()))
+        .as_object_mut()
+        .ok_or_else(|| anyhow!("'agents' is not an object in {}", path.display()))?;
+
+    let (mut added, mut updated, mut unchanged) = (Vec::new(), Vec::new(), 0);
+    for (agent, model) in &captured.agents {
+        let previous = agents
+            .get(agent)
+            .and_then(|a| a.get("model"))
+            .and_then(Value::as_str)
+            .map(str::to_string);
+        match previous {
+            Some(previous) if previous == model.model => {
+                unchanged += 1;
+                continue;
+            }
+            Some(previous) => updated.push((agent.clone(), previous)),
+            None => added.push(agent.clone()),
+        }
+        match agents.get_mut(agent).and_then(Value::as_object_mut) {
+            Some(entry) => {
+                entry.insert("model".to_string(), Value::String(model.model.clone()));
+            }
+            None => {
+                agents.insert(agent.clone(), serde_json::json!({ "model": model.model }));
+            }
+        }
+    }
+
+    // What comes out must still read as a provider config
+    serde_json::from_value::<ProviderConfig>(document.clone())
+        .map_err(PortalError::parse("provider config", &path))?;
+    let content = config::render_document(original.as_deref(), &document)?;
+    Ok(ProviderMerge {
+        path,
+        content,
+        added,
+        updated,
+        unchanged,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn with_agents(names: &[&str]) -> ProviderConfig {
+        ProviderConfig {
+            agents: names
+                .iter()
+                .map(|name| {
+                    (
+                        name.to_string(),
+                        AgentModelConfig {
+                            model: format!("gw/{}", name),
+                        },
+                    )
+                })
+                .collect(),
+            ..ProviderConfig::default()
+        }
+    }
+
+    #[test]
+    fn glob_matches_stars_and_question_marks() {
+        assert!(glob_matches("claude-*", "claude-opus-4.5"));
+        assert!(glob_matches("*-4.5", "claude-opus-4.5"));
+        assert!(glob_matches("claude-*-4.?", "claude-opus-4.5"));
+        assert!(glob_matches("*", ""));
+        assert!(glob_matches("a*b*c", "aXbYbZc"));
+        assert!(!glob_matches("claude-*", "gpt-5.2"));
+        assert!(!glob_matches("gpt-?", "gpt-5.2"));
+        assert!(!glob_matches("a*b", "aXbY"));
+    }
+
+    #[test]
+    fn agent_prefers_exact_then_most_literal_pattern_then_catch_all() {
+        let config = with_agents(&["*", "test-*", "test-unit-*", "test-unit-fast"]);
+        let model = |agent: &str| config.agent(agent).map(|entry| entry.model.as_str());
+        assert_eq!(model("test-unit-fast"), Some("gw/test-unit-fast"));
+        assert_eq!(model("test-unit-slow"), Some("gw/test-unit-*"));
+        assert_eq!(model("test-e2e"), Some("gw/test-*"));
+        assert_eq!(model("oracle"), Some("gw/*"));
+        assert_eq!(with_agents(&["test-*"]).agent("oracle"), None);
+    }
+
+    #[test]
+    fn agent_patterns_that_tie_go_by_name() {
+        let config = with_agents(&["*-fast", "test-*"]);
+        assert_eq!(
+            config.agent("test-fast").map(|entry| entry.model.as_str()),
+            Some("gw/*-fast")
+        );
+    }
+
+    #[test]
+    fn first_matching_rule_wins() {
+        let config = ProviderConfig {
+            rules: vec![
+                ModelRule {
+                    pattern: "claude-opus-*".to_string(),
+                    template: "bedrock/opus.{model}".to_string(),
+                },
+                ModelRule {
+                    pattern: "claude-*".to_string(),
+                    template: "bedrock/{model}".to_string(),
+                },
+            ],
+            ..ProviderConfig::default()
+        };
+        assert_eq!(
+            config.rule_model("claude-opus-4.5").as_deref(),
+            Some("bedrock/opus.claude-opus-4.5")
+        );
+        assert_eq!(
+            config.rule_model("claude-sonnet-4.5").as_deref(),
+            Some("bedrock/claude-sonnet-4.5")
+        );
+        assert_eq!(config.rule_model("gpt-5.2"), None);
+    }
+
+    #[test]
+    fn inherit_keeps_own_agents_and_tries_own_rules_first() {
+        let mut child = with_agents(&["oracle"]);
+        child.rules.push(ModelRule {
+            pattern: "claude-*".to_string(),
+            template: "child/{model}".to_string(),
+        });
+        let mut parent = with_agents(&["oracle", "explore"]);
+        parent.agents.get_mut("oracle").unwrap().model = "parent/oracle".to_string();
+        parent.rules.push(ModelRule {
+            pattern: "*".to_string(),
+            template: "parent/{model}".to_string(),
+        });
+        parent.description = Some("Parent".to_string());
+
+        child.inherit(&parent);
+        assert_eq!(child.agents["oracle"].model, "gw/oracle");
+        assert_eq!(child.agents["explore"].model, "gw/explore");
+        assert_eq!(
+            child.rule_model("claude-opus-4.5").as_deref(),
+            Some("child/claude-opus-4.5")
+        );
+        assert_eq!(
+            child.rule_model("gpt-5.2").as_deref(),
+            Some("parent/gpt-5.2")
+        );
+        assert_eq!(child.description.as_deref(), Some("Parent"));
+    }
+}
@@ -0,0 +1,372 @@
+//! Quota tracking for provider accounts, polled politely: accounts that
+//! share a key share one request, polls to the same provider are spaced
+//! out and jittered, and results are cached in the state directory so the
+//! CLI can read them without calling the provider at all.
+
+use anyhow::{Context, Result, anyhow};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime};
+
+use crate::clock;
+use crate::error::PortalError;
+use crate::fsutil;
+use crate::http;
+
+/// File in the state directory caching the latest quota of each account
+pub const QUOTA_CACHE: &str = "quota.json";
+
+/// OpenRouter endpoint describing the calling key's usage and limit
+pub const OPENROUTER_KEY_URL: &str = "https://openrouter.ai/api/v1/key";
+
+/// Poll interval when an account doesn't set one
+pub const DEFAULT_INTERVAL_SECS: u64 = 300;
+
+/// Shortest interval each provider is polled at, whatever an account asks
+const MIN_INTERVAL_SECS: &[(&str, u64)] = &[("openrouter", 60)];
+
+/// Least time between two requests to the same provider
+const PROVIDER_GAP: Duration = Duration::from_secs(5);
+
+/// Polls are moved randomly by up to this fraction of their interval
+const JITTER: f64 = 0.1;
+
+/// One account to track, listed under `quotas` in `daemon.json`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct QuotaAccount {
+    pub name: String,
+    /// API the account belongs to; only `openrouter` so far
+    #[serde(default = "default_provider")]
+    pub provider: String,
+    /// Environment variable holding the account's API key
+    pub key_env: String,
+    /// Endpoint to poll instead of the provider's default, e.g. a gateway
+    /// that proxies OpenRouter's
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub interval_secs: Option<u64>,
+}
+
+fn default_provider() -> String {
+    "openrouter".to_string()
+}
+
+/// Accounts with the same key at the same endpoint share a poll
+type PollKey = (String, String);
+
+impl QuotaAccount {
+    /// Fail if portal can't poll this account
+    pub fn check(&self) -> Result<()> {
+        if self.provider != "openrouter" {
+            return Err(anyhow!(
+                "quota account '{}': unsupported provider '{}' (only openrouter is supported)",
+                self.name,
+                self.provider
+            ));
+        }
+        Ok(())
+    }
+
+    pub fn url(&self) -> &str {
+        self.url.as_deref().unwrap_or(OPENROUTER_KEY_URL)
+    }
+
+    /// Shortest interval the provider is polled at
+    pub fn min_interval(&self) -> Duration {
+        Duration::from_secs(
+            MIN_INTERVAL_SECS
+                .iter()
+                .find(|(provider, _)| *provider == self.provider)
+                .map_or(0, |(_, secs)| *secs),
+        )
+    }
+
+    /// Requested interval, raised to the provider's minimum
+    pub fn interval(&self) -> Duration {
+        Duration::from_secs(self.interval_secs.unwrap_or(DEFAULT_INTERVAL_SECS))
+            .max(self.min_interval())
+    }
+
+    fn poll_key(&self) -> PollKey {
+        (self.url().to_string(), self.key_env.clone())
+    }
+}
+
+/// An account's quota as of the last poll
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct QuotaSnapshot {
+    pub provider: String,
+    /// When the account was last polled
+    pub fetched_at: DateTime<Utc>,
+    /// Credits used so far
+    pub usage: Option<f64>,
+    /// Credit limit of the key, `None` for unlimited
+    pub limit: Option<f64>,
+    pub remaining: Option<f64>,
+    /// Set when the last poll failed; the figures are then from the poll
+    /// before it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// Latest snapshot of each account, by account name
+pub type QuotaCache = BTreeMap<String, QuotaSnapshot>;
+
+pub fn cache_path(state_dir: &Path) -> PathBuf {
+    state_dir.join(QUOTA_CACHE)
+}
+
+pub fn read_cache(state_dir: &Path) -> Result<QuotaCache> {
+    let path = cache_path(state_dir);
+    match fsutil::read_to_string(&path) {
+        Ok(content) => {
+            Ok(serde_json::from_str(&content).map_err(PortalError::parse("quota cache", &path))?)
+        }
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(QuotaCache::new()),
+        Err(e) => Err(e).with_context(|| format!("Failed to read quota cache: {}", path.display())),
+    }
+}
+
+pub fn write_cache(state_dir: &Path, cache: &QuotaCache) -> Result<()> {
+    fs::create_dir_all(state_dir)
+        .with_context(|| format!("Failed to create state directory: {}", state_dir.display()))?;
+    let path = cache_path(state_dir);
+    fsutil::write_atomic(&path, serde_json::to_string_pretty(cache)?.as_bytes())
+        .with_context(|| format!("Failed to write quota cache: {}", path.display()))
+}
+
+/// `(usage, limit, remaining)` from OpenRouter's `/key` response
+fn parse_openrouter(text: &str) -> Result<(Option<f64>, Option<f64>, Option<f64>)> {
+    let value: Value = serde_json::from_str(text).context("Failed to parse quota response")?;
+    let data = value
+        .get("data")
+        .ok_or_else(|| anyhow!("Quota response has no `data`"))?;
+    let number = |key: &str| data.get(key).and_then(Value::as_f64);
+    Ok((number("usage"), number("limit"), number("limit_remaining")))
+}
+
+/// Poll one account now
+fn fetch(account: &QuotaAccount) -> Result<(Option<f64>, Option<f64>, Option<f64>)> {
+    let key = std::env::var(&account.key_env)
+        .ok()
+        .filter(|key| !key.is_empty())
+        .ok_or_else(|| anyhow!("{} is not set", account.key_env))?;
+    parse_openrouter(&http::get_with_bearer(account.url(), &key)?)
+}
+
+/// Poll `accounts` (sharing one key and endpoint) and record the result
+/// for each in `cache`. Returns the error, if the poll failed.
+fn poll_group(accounts: &[&QuotaAccount], cache: &mut QuotaCache) -> Option<String> {
+    let result = fetch(accounts[0]);
+    let fetched_at = Utc::now();
+    let error = result.as_ref().err().map(|e| format!("{:#}", e));
+    for account in accounts {
+        let previous = cache.get(&account.name);
+        let (usage, limit, remaining) = match &result {
+            Ok(figures) => *figures,
+            Err(_) => previous.map_or((None, None, None), |p| (p.usage, p.limit, p.remaining)),
+        };
+        cache.insert(
+            account.name.clone(),
+            QuotaSnapshot {
+                provider: account.provider.clone(),
+                fetched_at,
+                usage,
+                limit,
+                remaining,
+                error: error.clone(),
+            },
+        );
+    }
+    error
+}
+
+fn groups(accounts: &[QuotaAccount]) -> BTreeMap<PollKey, Vec<&QuotaAccount>> {
+    let mut groups: BTreeMap<PollKey, Vec<&QuotaAccount>> = BTreeMap::new();
+    for account in accounts {
+        groups.entry(account.poll_key()).or_default().push(account);
+    }
+    groups
+}
+
+/// Poll the accounts whose cached snapshot is older than their interval,
+/// or with `force` older than their provider's minimum interval, and
+/// update the cache. Returns the errors of failed polls.
+pub fn refresh(state_dir: &Path, accounts: &[QuotaAccount], force: bool) -> Result<Vec<String>> {
+    let mut cache = read_cache(state_dir)?;
+    let now = Utc::now();
+    let mut errors = Vec::new();
+    for group in groups(accounts).into_values() {
+        let stale = group.iter().any(|account| {
+            let wait = if force {
+                account.min_interval()
+            } else {
+                account.interval()
+            };
+            // A snapshot from the future (the clock was set back since)
+            // is as good as none
+            cache.get(&account.name).is_none_or(|snapshot| {
+                clock::age(snapshot.fetched_at, now).is_none_or(|age| age >= wait)
+            })
+        });
+        if stale && let Some(error) = poll_group(&group, &mut cache) {
+            errors.push(format!("{}: {}", group[0].name, error));
+        }
+    }
+    write_cache(state_dir, &cache)?;
+    Ok(errors)
+}
+
+/// Schedules quota polls for the daemon
+#[derive(Debug)]
+pub struct Poller {
+    accounts: Vec<QuotaAccount>,
+    due: BTreeMap<PollKey, Instant>,
+    /// Last request to each provider, to keep them [`PROVIDER_GAP`] apart
+    last_request: BTreeMap<String, Instant>,
+    /// `Instant` and time since boot when polls were last checked, to
+    /// notice the machine sleeping in between
+    last_check: Option<(Instant, Duration)>,
+    seed: u64,
+}
+
+impl Poller {
+    pub fn new(accounts: Vec<QuotaAccount>, state_dir: &Path) -> Self {
+        let seed = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map_or(0x9e37_79b9, |d| d.as_nanos() as u64)
+            | 1;
+        let mut poller = Self {
+            accounts: Vec::new(),
+            due: BTreeMap::new(),
+            last_request: BTreeMap::new(),
+            last_check: None,
+            seed,
+        };
+        let cache = read_cache(state_dir).unwrap_or_default();
+        poller.schedule(accounts, &cache);
+        poller
+    }
+
+    /// Switch to a new account list, keeping the schedule of polls that
+    /// are still wanted
+    pub fn reconfigure(&mut self, accounts: Vec<QuotaAccount>, state_dir: &Path) {
+        let cache = read_cache(state_dir).unwrap_or_default();
+        self.schedule(accounts, &cache);
+    }
+
+    fn schedule(&mut self, accounts: Vec<QuotaAccount>, cache: &QuotaCache) {
+        let now = Instant::now();
+        let keys: Vec<PollKey> = groups(&accounts).into_keys().collect();
+        self.due.retain(|key, _| keys.contains(key));
+        let mut stagger = Duration::ZERO;
+        for key in keys {
+            if self.due.contains_key(&key) {
+                continue;
+            }
+            let group = accounts.iter().filter(|a| a.poll_key() == key);
+            // A fresh cached snapshot (say, from before a restart) pushes
+            // the first poll back
+            let wait = group
+                .filter_map(|account| {
+                    let age = clock::age(cache.get(&account.name)?.fetched_at, Utc::now())?;
+                    account.interval().checked_sub(age)
+                })
+                .min();
+            let when = match wait {
+                Some(wait) => now + wait,
+                None => {
+                    // Spread first polls out instead of firing them at once
+                    let when = now + stagger;
+                    stagger += PROVIDER_GAP;
+                    when
+                }
+            };
+            self.due.insert(key, when);
+        }
+        self.accounts = accounts;
+    }
+
+    /// `Instant` stands still while the machine sleeps, which would make
+    /// every poll late by however long it slept; bring them forward by that
+    fn catch_up_after_sleep(&mut self, now: Instant) {
+        let Some(uptime) = clock::uptime() else {
+            return;
+        };
+        let Some((then, then_uptime)) = self.last_check.replace((now, uptime)) else {
+            return;
+        };
+        let slept = uptime
+            .saturating_sub(then_uptime)
+            .saturating_sub(now.duration_since(then));
+        if slept > Duration::from_secs(1) {
+            for when in self.due.values_mut() {
+                *when = when.checked_sub(slept).unwrap_or(now);
+            }
+        }
+    }
+
+    /// A number in `[-1, 1)`
+    fn jitter(&mut self) -> f64 {
+        // xorshift64
+        self.seed ^= self.seed << 13;
+        self.seed ^= self.seed >> 7;
+        self.seed ^= self.seed << 17;
+        (self.seed >> 11) as f64 / (1u64 << 52) as f64 - 1.0
+    }
+
+    /// Poll everything that is due, at most one request per provider per
+    /// [`PROVIDER_GAP`], and update the cache. Returns the errors of
+    /// failed polls.
+    pub fn poll_due(&mut self, state_dir: &Path) -> Vec<String> {
+        let now = Instant::now();
+        self.catch_up_after_sleep(now);
+        let due: Vec<PollKey> = self
+            .due
+            .iter()
+            .filter(|(_, when)| **when <= now)
+            .map(|(key, _)| key.clone())
+            .collect();
+        if due.is_empty() {
+            return Vec::new();
+        }
+
+        let mut cache = read_cache(state_dir).unwrap_or_default();
+        let mut errors = Vec::new();
+        let accounts = self.accounts.clone();
+        for key in due {
+            let group: Vec<&QuotaAccount> =
+                accounts.iter().filter(|a| a.poll_key() == key).collect();
+            let Some(first) = group.first() else {
+                continue;
+            };
+            let provider = first.provider.clone();
+            if self
+                .last_request
+                .get(&provider)
+                .is_some_and(|last| now.duration_since(*last) < PROVIDER_GAP)
+            {
+                // Try again once the gap has passed
+                self.due.insert(key, now + PROVIDER_GAP);
+                continue;
+            }
+            self.last_request.insert(provider, now);
+            if let Some(error) = poll_group(&group, &mut cache) {
+                errors.push(format!("quota {}: {}", first.name, error));
+            }
+            let interval = group.iter().map(|a| a.interval()).min().unwrap_or_default();
+            let offset = interval.as_secs_f64() * JITTER * self.jitter();
+            let next = Duration::from_secs_f64((interval.as_secs_f64() + offset).max(1.0));
+            self.due.insert(key, now + next);
+        }
+        if let Err(e) = write_cache(state_dir, &cache) {
+            errors.push(format!("{:#}", e));
+        }
+        errors
+    }
+}
@@ -0,0 +1,204 @@
+//! Falling back to another provider when the current one rate-limits:
+//! the daemon tails opencode's log files for rate-limit errors and, when
+//! one turns up while the config is on the limited provider, switches to
+//! the fallback, for good or only for a cooldown.
+
+use anyhow::{Context, Result, anyhow};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::io::{Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use crate::paths;
+use crate::portal::Portal;
+
+/// What a rate-limit error looks like in opencode's logs, matched without
+/// regard to case and only as whole words
+pub const DEFAULT_PATTERNS: &[&str] = &["429", "rate limit", "rate_limit", "too many requests"];
+
+/// Most of a log file read in one go; older output past this is skipped
+const MAX_READ: u64 = 1024 * 1024;
+
+/// When and where to fall back, under `fallback` in `daemon.json`
+///
+/// ```json
+/// {
+///   "fallback": { "from": "copilot", "to": "openrouter", "cooldown_secs": 1800 }
+/// }
+/// ```
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Fallback {
+    /// Config to switch; the default (or `--config`) config when absent
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub config: Option<PathBuf>,
+    /// Only fall back while the config is on this provider; on any but
+    /// `to` when absent
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub from: Option<String>,
+    /// Provider to fall back to
+    pub to: String,
+    /// Log files, or directories of them, to watch; opencode's log
+    /// directory when empty
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub logs: Vec<PathBuf>,
+    /// Text marking a rate-limit error; [`DEFAULT_PATTERNS`] when empty
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub patterns: Vec<String>,
+    /// Switch back after this long; the fallback is sticky when absent
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cooldown_secs: Option<u64>,
+}
+
+/// A fallback resolved against the filesystem and checked
+#[derive(Debug, Clone)]
+pub struct FallbackTarget {
+    pub portal: Portal,
+    pub from: Option<String>,
+    pub to: String,
+    pub logs: Vec<PathBuf>,
+    pub patterns: Vec<String>,
+    pub cooldown: Option<Duration>,
+}
+
+impl Fallback {
+    /// Resolve the fallback against `portal`, failing if its providers or
+    /// policy don't hold up
+    pub fn resolve(&self, portal: &Portal) -> Result<FallbackTarget> {
+        let portal = match &self.config {
+            Some(path) => portal.for_config(paths::expand_home(path)),
+            None => portal.clone(),
+        };
+        let to = portal.resolve_provider(&self.to)?;
+        let from = self
+            .from
+            .as_deref()
+            .map(|from| portal.resolve_provider(from))
+            .transpose()?;
+        if from.as_deref() == Some(to.as_str()) {
+            return Err(anyhow!("fallback: 'from' and 'to' are both '{}'", to));
+        }
+        portal.verify_provider(&to)?;
+        portal.provider_config(&to)?;
+        let logs = if self.logs.is_empty() {
+            vec![paths::opencode_data_dir().join("log")]
+        } else {
+            self.logs.iter().map(|p| paths::expand_home(p)).collect()
+        };
+        let patterns = if self.patterns.is_empty() {
+            DEFAULT_PATTERNS.iter().map(|p| p.to_string()).collect()
+        } else {
+            self.patterns.iter().map(|p| p.to_lowercase()).collect()
+        };
+        Ok(FallbackTarget {
+            portal,
+            from,
+            to,
+            logs,
+            patterns,
+            cooldown: self.cooldown_secs.map(Duration::from_secs),
+        })
+    }
+}
+
+impl FallbackTarget {
+    /// Whether a rate limit should move the config on `current` to the
+    /// fallback
+    pub fn applies_to(&self, current: Option<&str>) -> bool {
+        match (&self.from, current) {
+            (_, Some(current)) if current == self.to => false,
+            (Some(from), current) => current == Some(from.as_str()),
+            (None, _) => true,
+        }
+    }
+}
+
+/// Whether `line` holds one of `patterns` (lowercase) as a whole word
+pub fn is_rate_limit(line: &str, patterns: &[String]) -> bool {
+    let line = line.to_lowercase();
+    let is_word = |c: Option<char>| c.is_some_and(|c| c.is_alphanumeric());
+    patterns.iter().any(|pattern| {
+        line.match_indices(pattern.as_str()).any(|(at, _)| {
+            !is_word(line[..at].chars().next_back())
+                && !is_word(line[at + pattern.len()..].chars().next())
+        })
+    })
+}
+
+/// Reads what was appended to a set of log files since last time. Files
+/// already there when it starts are read from their end, files that show
+/// up later from the start, and a file that shrinks (rotated or truncated)
+/// from the start again.
+#[derive(Debug, Default)]
+pub struct LogTail {
+    paths: Vec<PathBuf>,
+    offsets: BTreeMap<PathBuf, u64>,
+}
+
+impl LogTail {
+    pub fn new(paths: &[PathBuf]) -> Self {
+        let mut tail = Self {
+            paths: paths.to_vec(),
+            offsets: BTreeMap::new(),
+        };
+        for file in tail.files() {
+            let size = fs::metadata(&file).map(|m| m.len()).unwrap_or_default();
+            tail.offsets.insert(file, size);
+        }
+        tail
+    }
+
+    /// Log files under the watched paths
+    fn files(&self) -> Vec<PathBuf> {
+        let mut files = Vec::new();
+        for path in &self.paths {
+            if path.is_dir() {
+                let Ok(entries) = fs::read_dir(path) else {
+                    continue;
+                };
+                files.extend(
+                    entries
+                        .flatten()
+                        .map(|entry| entry.path())
+                        .filter(|path| path.is_file()),
+                );
+            } else if path.is_file() {
+                files.push(path.clone());
+            }
+        }
+        files
+    }
+
+    /// Complete lines appended since the last call, as (file, line)
+    pub fn read_new(&mut self) -> Vec<(PathBuf, String)> {
+        let mut lines = Vec::new();
+        for file in self.files() {
+            let offset = self.offsets.get(&file).copied().unwrap_or_default();
+            if let Ok((next, text)) = read_from(&file, offset) {
+                lines.extend(text.lines().map(|line| (file.clone(), line.to_string())));
+                self.offsets.insert(file, next);
+            }
+        }
+        lines
+    }
+}
+
+/// Complete lines in `path` from `offset` on, and the offset after them
+fn read_from(path: &Path, offset: u64) -> Result<(u64, String)> {
+    let mut file =
+        File::open(path).with_context(|| format!("Failed to open {}", path.display()))?;
+    let size = file.metadata()?.len();
+    let offset = if size < offset { 0 } else { offset };
+    let start = offset.max(size.saturating_sub(MAX_READ));
+    file.seek(SeekFrom::Start(start))?;
+    let mut bytes = Vec::new();
+    file.take(size - start).read_to_end(&mut bytes)?;
+    // A line still being written is read next time
+    let end = bytes.iter().rposition(|&b| b == b'\n').map_or(0, |i| i + 1);
+    bytes.truncate(end);
+    Ok((
+        start + end as u64,
+        String::from_utf8_lossy(&bytes).into_owned(),
+    ))
+}
@@ -0,0 +1,76 @@
+//! Symlink switching, with `"switch_mode": "symlink"` in settings.json:
+//! instead of rewriting the config, `portal switch` renders the whole
+//! switched config into `~/.config/portal/rendered/<provider>.json` and
+//! repoints the config, a symlink, at it in one step. Every provider's
+//! config stays on disk, so going back is a matter of repointing the link.
+
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Directory in the portal directory holding the rendered configs
+pub const RENDERED_DIR: &str = "rendered";
+
+/// How `portal switch` puts a provider's config in place
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SwitchMode {
+    /// Rewrite the config file in place
+    #[default]
+    Rewrite,
+    /// Render into `rendered/` and point the config at it
+    Symlink,
+}
+
+impl SwitchMode {
+    pub fn is_default(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+pub fn rendered_dir(portal_dir: &Path) -> PathBuf {
+    portal_dir.join(RENDERED_DIR)
+}
+
+/// Where `provider`'s rendering of a config goes: straight in
+/// `rendered/` for the default config, and in a directory named after its
+/// backup channel for any other
+pub fn rendered_path(portal_dir: &Path, channel: &str, provider: &str) -> PathBuf {
+    let dir = rendered_dir(portal_dir);
+    let dir = if channel == "global" {
+        dir
+    } else {
+        dir.join(channel)
+    };
+    dir.join(format!("{}.json", provider))
+}
+
+/// Where the config at `path` is rendered to, if it is a symlink into
+/// `rendered/`
+pub fn link_target(path: &Path, portal_dir: &Path) -> Option<PathBuf> {
+    let target = fs::read_link(path).ok()?;
+    let target = match path.parent() {
+        Some(parent) => parent.join(target),
+        None => target,
+    };
+    let target = fs::canonicalize(target).ok()?;
+    let dir = fs::canonicalize(rendered_dir(portal_dir)).ok()?;
+    target.starts_with(dir).then_some(target)
+}
+
+/// `path` with symlinks resolved, except a last one into `rendered/`:
+/// portal repoints that link on every switch, so the config is the link
+/// itself, and its backups and state stay put
+pub fn canonical_config(path: &Path, portal_dir: &Path) -> PathBuf {
+    let canonical = |p: &Path| fs::canonicalize(p).unwrap_or_else(|_| p.to_path_buf());
+    if link_target(path, portal_dir).is_some()
+        && let Some(name) = path.file_name()
+    {
+        let parent = match path.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent,
+            _ => Path::new("."),
+        };
+        return canonical(parent).join(name);
+    }
+    canonical(path)
+}
@@ -0,0 +1,280 @@
+//! A digest of what portal did over a period, built only from local state:
+//! the event log and the quota cache.
+
+use anyhow::Result;
+use chrono::{DateTime, Duration, Local, Utc};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+use crate::events::{Event, EventKind};
+use crate::portal::Portal;
+use crate::quota::{self, QuotaSnapshot};
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Report {
+    pub start: DateTime<Utc>,
+    pub end: DateTime<Utc>,
+    /// Every switch in the period, oldest first
+    pub switches: Vec<SwitchEntry>,
+    /// Time each provider was in effect, summed over all configs
+    pub providers: Vec<ProviderTime>,
+    pub activity: Activity,
+    /// Latest snapshot of each quota account
+    pub quotas: BTreeMap<String, QuotaSnapshot>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SwitchEntry {
+    pub timestamp: DateTime<Utc>,
+    pub config: PathBuf,
+    pub provider: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub previous_provider: Option<String>,
+    /// How many agents changed model
+    pub agents: usize,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ProviderTime {
+    pub provider: String,
+    pub seconds: i64,
+    /// Switches to it in the period
+    pub switches: usize,
+}
+
+/// Counts of everything else in the event log
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct Activity {
+    pub reverts: usize,
+    pub edits: usize,
+    pub undos: usize,
+    pub redos: usize,
+    /// Temporary switches that ran out
+    pub expired: usize,
+}
+
+/// Which provider a config is on after `event`, for the events that say.
+/// `Some(None)` means it is no longer known.
+fn provider_after(event: &Event) -> Option<(&PathBuf, Option<&str>)> {
+    match &event.kind {
+        EventKind::Switch {
+            config, provider, ..
+        } => Some((config, Some(provider.as_str()))),
+        EventKind::Expire {
+            config,
+            restored_provider,
+            ..
+        } => Some((config, restored_provider.as_deref())),
+        _ => None,
+    }
+}
+
+/// Summarize `events` between `start` and `end`
+pub fn build(
+    events: &[Event],
+    quotas: BTreeMap<String, QuotaSnapshot>,
+    start: DateTime<Utc>,
+    end: DateTime<Utc>,
+) -> Report {
+    let mut switches = Vec::new();
+    let mut activity = Activity::default();
+    let mut seconds: BTreeMap<String, i64> = BTreeMap::new();
+    let mut counts: BTreeMap<String, usize> = BTreeMap::new();
+    // Per config: the provider in effect and since when (clamped to start)
+    let mut current: BTreeMap<PathBuf, (Option<String>, DateTime<Utc>)> = BTreeMap::new();
+
+    let mut close = |provider: &Option<String>, from: DateTime<Utc>, to: DateTime<Utc>| {
+        if let Some(provider) = provider
+            && to > from
+        {
+            *seconds.entry(provider.clone()).or_default() += (to - from).num_seconds();
+        }
+    };
+
+    for event in events.iter().filter(|e| e.timestamp <= end) {
+        let at = event.timestamp.max(start);
+        if let Some((config, provider)) = provider_after(event) {
+            if let Some((previous, since)) = current.get(config) {
+                close(previous, *since, at);
+            }
+            current.insert(config.clone(), (provider.map(str::to_string), at));
+        }
+        if event.timestamp < start {
+            continue;
+        }
+        match &event.kind {
+            EventKind::Switch {
+                config,
+                provider,
+                previous_provider,
+                agents,
+            } => {
+                *counts.entry(provider.clone()).or_default() += 1;
+                switches.push(SwitchEntry {
+                    timestamp: event.timestamp,
+                    config: config.clone(),
+                    provider: provider.clone(),
+                    previous_provider: previous_provider.clone(),
+                    agents: agents.len(),
+                });
+            }
+            EventKind::Revert { .. } => activity.reverts += 1,
+            EventKind::Edit { .. } => activity.edits += 1,
+            EventKind::Undo { .. } => activity.undos += 1,
+            EventKind::Redo { .. } => activity.redos += 1,
+            EventKind::Expire { .. } => activity.expired += 1,
+        }
+    }
+    for (provider, since) in current.values() {
+        close(provider, *since, end);
+    }
+
+    let mut names: Vec<String> = seconds.keys().chain(counts.keys()).cloned().collect();
+    names.sort();
+    names.dedup();
+    let mut providers: Vec<ProviderTime> = names
+        .into_iter()
+        .map(|provider| ProviderTime {
+            seconds: seconds.get(&provider).copied().unwrap_or(0),
+            switches: counts.get(&provider).copied().unwrap_or(0),
+            provider,
+        })
+        .collect();
+    providers.sort_by(|a, b| b.seconds.cmp(&a.seconds).then(a.provider.cmp(&b.provider)));
+
+    Report {
+        start,
+        end,
+        switches,
+        providers,
+        activity,
+        quotas,
+    }
+}
+
+/// The report for the `days` up to now, from `portal`'s state directory
+pub fn for_days(portal: &Portal, days: i64) -> Result<Report> {
+    let end = Utc::now();
+    let start = end - Duration::days(days);
+    // What each config was on at the start, then everything since
+    let store = portal.events();
+    let mut events = store.latest_before(start, &["switch", "expire"])?;
+    events.extend(store.read_since(Some(start))?);
+    let quotas = quota::read_cache(portal.state_dir())?;
+    Ok(build(&events, quotas, start, end))
+}
+
+/// `3d 4h`, `2h 5m`, `40m`
+pub fn format_duration(seconds: i64) -> String {
+    let (days, hours, minutes) = (
+        seconds / 86_400,
+        seconds % 86_400 / 3_600,
+        seconds % 3_600 / 60,
+    );
+    if days > 0 {
+        format!("{}d {}h", days, hours)
+    } else if hours > 0 {
+        format!("{}h {}m", hours, minutes)
+    } else {
+        format!("{}m", minutes)
+    }
+}
+
+fn local(time: DateTime<Utc>) -> String {
+    time.with_timezone(&Local)
+        .format("%Y-%m-%d %H:%M")
+        .to_string()
+}
+
+fn amount(value: Option<f64>) -> String {
+    value.map_or("?".to_string(), |v| format!("{:.2}", v))
+}
+
+/// Markdown digest to paste into notes
+pub fn render_markdown(report: &Report) -> String {
+    let mut out = format!(
+        "## portal: {} to {}\n\n",
+        report.start.with_timezone(&Local).format("%Y-%m-%d"),
+        report.end.with_timezone(&Local).format("%Y-%m-%d")
+    );
+    let period = (report.end - report.start).num_seconds().max(1);
+
+    out.push_str("### Providers\n\n");
+    if report.providers.is_empty() {
+        out.push_str("No provider was recorded in this period.\n\n");
+    } else {
+        out.push_str("| Provider | Time in use | Share | Switches |\n");
+        out.push_str("| --- | --- | --- | --- |\n");
+        for provider in &report.providers {
+            out.push_str(&format!(
+                "| {} | {} | {}% | {} |\n",
+                provider.provider,
+                format_duration(provider.seconds),
+                provider.seconds * 100 / period,
+                provider.switches
+            ));
+        }
+        out.push_str(
+            "\nTime is summed over configs, so it can add up to more than the period.\n\n",
+        );
+    }
+
+    out.push_str("### Switches\n\n");
+    if report.switches.is_empty() {
+        out.push_str("None.\n\n");
+    } else {
+        for switch in &report.switches {
+            let from = switch
+                .previous_provider
+                .as_deref()
+                .map(|p| format!(" from {}", p))
+                .unwrap_or_default();
+            out.push_str(&format!(
+                "- {}: **{}**{} in `{}` ({} agent(s))\n",
+                local(switch.timestamp),
+                switch.provider,
+                from,
+                switch.config.display(),
+                switch.agents
+            ));
+        }
+        out.push('\n');
+    }
+
+    let activity = &report.activity;
+    out.push_str("### Other activity\n\n");
+    out.push_str(&format!(
+        "- Reverts to a backup: {}\n- Manual model edits: {}\n- Undos / redos: {} / {}\n\
+         - Temporary switches that ran out: {}\n\n",
+        activity.reverts, activity.edits, activity.undos, activity.redos, activity.expired
+    ));
+
+    out.push_str("### Quotas\n\n");
+    if report.quotas.is_empty() {
+        out.push_str("No quota accounts have been polled.\n");
+    } else {
+        out.push_str("| Account | Provider | Used | Limit | Remaining | As of |\n");
+        out.push_str("| --- | --- | --- | --- | --- | --- |\n");
+        for (name, snapshot) in &report.quotas {
+            let as_of = match &snapshot.error {
+                Some(error) => format!(
+                    "{} (last poll failed: {})",
+                    local(snapshot.fetched_at),
+                    error
+                ),
+                None => local(snapshot.fetched_at),
+            };
+            out.push_str(&format!(
+                "| {} | {} | {} | {} | {} | {} |\n",
+                name,
+                snapshot.provider,
+                amount(snapshot.usage),
+                amount(snapshot.limit),
+                amount(snapshot.remaining),
+                as_of.replace('|', "/")
+            ));
+        }
+    }
+    out
+}
@@ -0,0 +1,186 @@
+//! Just enough JSON Schema to check a config against the schema it names:
+//! `type`, `enum`, `const`, `properties`, `required`,
+//! `additionalProperties`, `items`, `minLength`, local `$ref`, and the
+//! `allOf`/`anyOf`/`oneOf` combinators (`oneOf` is as lenient as `anyOf`).
+//! Other keywords are ignored.
+
+use anyhow::{Context, Result};
+use serde_json::Value;
+use std::path::Path;
+
+use crate::fsutil;
+use crate::http;
+
+/// Load the schema a `$schema` reference points at: an `http(s)://` URL
+/// (fetched with curl), a `file://` URL, or a path relative to `base_dir`
+pub fn load(reference: &str, base_dir: &Path) -> Result<Value> {
+    let content = if reference.starts_with("http://") || reference.starts_with("https://") {
+        http::get(reference)?
+    } else {
+        let path = base_dir.join(reference.strip_prefix("file://").unwrap_or(reference));
+        fsutil::read_to_string(&path)
+            .with_context(|| format!("Failed to read schema: {}", path.display()))?
+    };
+    serde_json::from_str(&content).with_context(|| format!("Failed to parse schema: {}", reference))
+}
+
+/// A place where the instance doesn't match the schema
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Violation {
+    /// Dotted path into the instance, empty for the root
+    pub path: String,
+    pub message: String,
+}
+
+/// Check `instance` against `schema`
+pub fn validate(schema: &Value, instance: &Value) -> Vec<Violation> {
+    validate_at(schema, schema, instance)
+}
+
+fn join(path: &str, key: &str) -> String {
+    if path.is_empty() {
+        key.to_string()
+    } else {
+        format!("{}.{}", path, key)
+    }
+}
+
+/// Resolve a `#/...` JSON pointer into the root schema
+fn resolve<'a>(root: &'a Value, reference: &str) -> Option<&'a Value> {
+    let pointer = reference.strip_prefix('#')?;
+    if pointer.is_empty() {
+        return Some(root);
+    }
+    root.pointer(pointer)
+}
+
+fn type_matches(expected: &str, instance: &Value) -> bool {
+    match expected {
+        "object" => instance.is_object(),
+        "array" => instance.is_array(),
+        "string" => instance.is_string(),
+        "boolean" => instance.is_boolean(),
+        "null" => instance.is_null(),
+        "number" => instance.is_number(),
+        "integer" => instance.as_f64().is_some_and(|n| n.fract() == 0.0),
+        _ => true,
+    }
+}
+
+fn check(root: &Value, schema: &Value, instance: &Value, path: &str, out: &mut Vec<Violation>) {
+    let Some(schema) = schema.as_object() else {
+        // `false` rejects everything, `true` accepts everything
+        if schema == &Value::Bool(false) {
+            out.push(Violation {
+                path: path.to_string(),
+                message: "not allowed here".to_string(),
+            });
+        }
+        return;
+    };
+    let mut fail = |message: String| {
+        out.push(Violation {
+            path: path.to_string(),
+            message,
+        })
+    };
+
+    if let Some(reference) = schema.get("$ref").and_then(Value::as_str) {
+        match resolve(root, reference) {
+            Some(target) => check(root, target, instance, path, out),
+            None => out.push(Violation {
+                path: path.to_string(),
+                message: format!("schema reference {} not found", reference),
+            }),
+        }
+        return;
+    }
+
+    if let Some(expected) = schema.get("type") {
+        let types: Vec<&str> = match expected {
+            Value::String(t) => vec![t.as_str()],
+            Value::Array(ts) => ts.iter().filter_map(Value::as_str).collect(),
+            _ => Vec::new(),
+        };
+        if !types.is_empty() && !types.iter().any(|t| type_matches(t, instance)) {
+            fail(format!("expected {}", types.join(" or ")));
+            return;
+        }
+    }
+    if let Some(allowed) = schema.get("enum").and_then(Value::as_array)
+        && !allowed.contains(instance)
+    {
+        let names: Vec<String> = allowed.iter().map(Value::to_string).collect();
+        fail(format!("must be one of {}", names.join(", ")));
+    }
+    if let Some(expected) = schema.get("const")
+        && expected != instance
+    {
+        fail(format!("must be {}", expected));
+    }
+    if let (Some(min), Some(s)) = (
+        schema.get("minLength").and_then(Value::as_u64),
+        instance.as_str(),
+    ) && (s.chars().count() as u64) < min
+    {
+        fail(format!("must be at least {} character(s)", min));
+    }
+
+    if let Some(object) = instance.as_object() {
+        let properties = schema.get("properties").and_then(Value::as_object);
+        if let Some(required) = schema.get("required").and_then(Value::as_array) {
+            for key in required.iter().filter_map(Value::as_str) {
+                if !object.contains_key(key) {
+                    out.push(Violation {
+                        path: path.to_string(),
+                        message: format!("missing required property '{}'", key),
+                    });
+                }
+            }
+        }
+        for (key, value) in object {
+            let child = join(path, key);
+            match properties.and_then(|p| p.get(key)) {
+                Some(property) => check(root, property, value, &child, out),
+                None => match schema.get("additionalProperties") {
+                    Some(Value::Bool(false)) => out.push(Violation {
+                        path: child,
+                        message: "unknown property".to_string(),
+                    }),
+                    Some(additional) => check(root, additional, value, &child, out),
+                    None => {}
+                },
+            }
+        }
+    }
+
+    if let (Some(items), Some(array)) = (schema.get("items"), instance.as_array()) {
+        for (i, item) in array.iter().enumerate() {
+            check(root, items, item, &join(path, &i.to_string()), out);
+        }
+    }
+
+    if let Some(all) = schema.get("allOf").and_then(Value::as_array) {
+        for sub in all {
+            check(root, sub, instance, path, out);
+        }
+    }
+    for keyword in ["anyOf", "oneOf"] {
+        if let Some(options) = schema.get(keyword).and_then(Value::as_array)
+            && !options
+                .iter()
+                .any(|sub| validate_at(root, sub, instance).is_empty())
+        {
+            out.push(Violation {
+                path: path.to_string(),
+                message: format!("doesn't match any allowed form ({})", keyword),
+            });
+        }
+    }
+}
+
+fn validate_at(root: &Value, schema: &Value, instance: &Value) -> Vec<Violation> {
+    let mut violations = Vec::new();
+    check(root, schema, instance, "", &mut violations);
+    violations
+}
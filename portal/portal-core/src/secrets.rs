@@ -0,0 +1,107 @@
+//! Spotting API keys written straight into a provider's client options.
+//! Options are copied into the opencode config, which is often synced or
+//! committed, so keys belong in environment variables that opencode reads
+//! through a `{env:NAME}` reference instead, or in the keychain as
+//! `{secret:NAME}`.
+
+use anyhow::{Result, anyhow};
+use serde_json::Value;
+
+use crate::provider::ProviderOptions;
+
+/// Option names (lowercase, without `-` and `_`) whose values are secrets
+const SECRET_NAMES: &[&str] = &[
+    "apikey",
+    "authorization",
+    "password",
+    "secret",
+    "token",
+    "accesskey",
+];
+
+/// Prefixes of well-known API key formats, wherever they turn up
+const KEY_PREFIXES: &[&str] = &[
+    "sk-",
+    "ghp_",
+    "gho_",
+    "ghu_",
+    "ghs_",
+    "github_pat_",
+    "glpat-",
+    "xoxb-",
+    "xoxp-",
+    "AIza",
+];
+
+/// Shortest string taken for a key by its prefix alone
+const MIN_KEY_LENGTH: usize = 20;
+
+/// Whether opencode (or portal, from the keychain) fills the value in, so
+/// it holds no secret itself
+fn is_reference(value: &str) -> bool {
+    value.contains("{env:") || value.contains("{file:") || value.contains("{secret:")
+}
+
+fn is_secret_name(name: &str) -> bool {
+    let name: String = name
+        .chars()
+        .filter(|c| !matches!(c, '-' | '_'))
+        .collect::<String>()
+        .to_lowercase();
+    name == "key" || SECRET_NAMES.iter().any(|secret| name.contains(secret))
+}
+
+fn looks_like_key(value: &str) -> bool {
+    let value = value.strip_prefix("Bearer ").unwrap_or(value).trim();
+    value.len() >= MIN_KEY_LENGTH
+        && !value.contains(char::is_whitespace)
+        && KEY_PREFIXES.iter().any(|prefix| value.starts_with(prefix))
+}
+
+/// Dotted paths (`options.apiKey`, `options.headers.Authorization`) of the
+/// values in `options` that look like raw secrets
+pub fn inline_secrets(options: &ProviderOptions) -> Vec<String> {
+    let mut found = Vec::new();
+    if let Ok(value) = serde_json::to_value(options) {
+        walk(&value, "options", false, &mut found);
+    }
+    found
+}
+
+fn walk(value: &Value, path: &str, secret_name: bool, found: &mut Vec<String>) {
+    match value {
+        Value::String(text)
+            if !text.is_empty() && !is_reference(text) && (secret_name || looks_like_key(text)) =>
+        {
+            found.push(path.to_string());
+        }
+        Value::Object(map) => {
+            for (key, value) in map {
+                let path = format!("{}.{}", path, key);
+                walk(value, &path, secret_name || is_secret_name(key), found);
+            }
+        }
+        Value::Array(items) => {
+            for (i, value) in items.iter().enumerate() {
+                walk(value, &format!("{}[{}]", path, i), secret_name, found);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Refuse `provider`'s options if they hold raw secrets, unless `allow`
+pub fn check_options(provider: &str, options: &ProviderOptions, allow: bool) -> Result<()> {
+    let found = inline_secrets(options);
+    if allow || found.is_empty() {
+        return Ok(());
+    }
+    Err(anyhow!(
+        "Provider '{}' has what looks like a raw secret in {}, which a switch would \
+         copy into the config. Reference an environment variable instead \
+         (\"{{env:OPENROUTER_API_KEY}}\") or a keychain secret (\"{{secret:NAME}}\"), \
+         or pass --allow-inline-secrets",
+        provider,
+        found.join(", ")
+    ))
+}
@@ -0,0 +1,232 @@
+//! `portal verify-install`: the whole pipeline run against a throwaway
+//! config in a temporary directory, so an upgrade or a new build can be
+//! checked without touching real files. Each check builds on the one
+//! before, so after a failure the rest are skipped.
+
+use anyhow::{Context, Result, anyhow};
+use serde::Serialize;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::backup;
+use crate::portal::{Portal, SwitchOptions};
+use crate::provider::{self, AgentModelConfig, ProviderConfig};
+
+/// Config the checks start from: two agents on Copilot
+const SANDBOX_CONFIG: &str = r#"{
+  // Written by portal verify-install
+  "agents": {
+    "sisyphus": { "model": "github-copilot/claude-opus-4.5" },
+    "oracle": { "model": "github-copilot/gpt-5.2" }
+  }
+}
+"#;
+
+/// Custom provider the checks switch to
+const SANDBOX_PROVIDER: &str = "verify-install";
+const SANDBOX_MODEL: &str = "openrouter/openai/gpt-5.2";
+
+/// Outcome of checking one subsystem
+#[derive(Debug, Clone, Serialize)]
+pub struct Check {
+    pub subsystem: &'static str,
+    pub outcome: Outcome,
+    pub detail: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Outcome {
+    Pass,
+    Fail,
+    /// Not run because an earlier check failed
+    Skip,
+}
+
+impl Outcome {
+    pub fn label(self) -> &'static str {
+        match self {
+            Outcome::Pass => "PASS",
+            Outcome::Fail => "FAIL",
+            Outcome::Skip => "SKIP",
+        }
+    }
+}
+
+#[derive(Default)]
+struct Checks {
+    done: Vec<Check>,
+    failed: bool,
+}
+
+impl Checks {
+    fn check(&mut self, subsystem: &'static str, step: impl FnOnce() -> Result<String>) {
+        let (outcome, detail) = if self.failed {
+            (Outcome::Skip, "an earlier check failed".to_string())
+        } else {
+            match step() {
+                Ok(detail) => (Outcome::Pass, detail),
+                Err(e) => {
+                    self.failed = true;
+                    (Outcome::Fail, format!("{:#}", e))
+                }
+            }
+        };
+        self.done.push(Check {
+            subsystem,
+            outcome,
+            detail,
+        });
+    }
+}
+
+/// Run every check in a new directory under the system's temporary
+/// directory, removed again afterwards
+pub fn run() -> Vec<Check> {
+    let dir = sandbox_dir();
+    let mut checks = Checks::default();
+    let portal = Portal::new(dir.join("opencode.json"), dir.join("portal"))
+        .with_state_dir(dir.join("state"));
+
+    checks.check("sandbox", || {
+        fs::create_dir_all(portal.portal_dir())
+            .with_context(|| format!("Failed to create {}", portal.portal_dir().display()))?;
+        fs::write(portal.config_path(), SANDBOX_CONFIG)
+            .with_context(|| format!("Failed to write {}", portal.config_path().display()))?;
+        let mut pinned = ProviderConfig::default();
+        pinned.agents.insert(
+            "sisyphus".to_string(),
+            AgentModelConfig {
+                model: SANDBOX_MODEL.to_string(),
+            },
+        );
+        provider::write_provider_config(portal.portal_dir(), SANDBOX_PROVIDER, &pinned)?;
+        Ok(dir.display().to_string())
+    });
+    checks.check("config", || {
+        let status = portal.status()?;
+        expect(
+            status.provider.as_deref() == Some("copilot"),
+            "the sandbox config didn't read back as Copilot",
+        )?;
+        Ok(format!("parsed {} agent(s)", status.agents.len()))
+    });
+    checks.check("mappings", || {
+        let table = portal.mappings()?;
+        let model = table.transform("claude-opus-4.5", "openrouter");
+        expect(
+            model.as_deref() == Some("openrouter/anthropic/claude-opus-4.5"),
+            "claude-opus-4.5 didn't map to OpenRouter",
+        )?;
+        Ok(format!("{} row(s)", table.rows().len()))
+    });
+    checks.check("switch", || {
+        let outcome = portal.switch("openrouter", &SwitchOptions::default())?;
+        expect(
+            portal.status()?.provider.as_deref() == Some("openrouter"),
+            "the config isn't on OpenRouter after switching",
+        )?;
+        expect(
+            outcome.report.unmapped.is_empty(),
+            "some agents had no OpenRouter mapping",
+        )?;
+        Ok("switched every agent to openrouter".to_string())
+    });
+    checks.check("custom provider", || {
+        // A second backup, for pruning to remove
+        let options = SwitchOptions {
+            force_backup: true,
+            ..SwitchOptions::default()
+        };
+        portal.switch(SANDBOX_PROVIDER, &options)?;
+        let model = portal
+            .load()?
+            .agents
+            .get("sisyphus")
+            .map(|agent| agent.model.clone());
+        expect(
+            model.as_deref() == Some(SANDBOX_MODEL),
+            "the provider file's pin wasn't applied",
+        )?;
+        Ok(format!("sisyphus pinned to {}", SANDBOX_MODEL))
+    });
+    checks.check("events", || {
+        let last = portal.last_provider()?;
+        expect(
+            last.as_deref() == Some(SANDBOX_PROVIDER),
+            "the event log doesn't show the last switch",
+        )?;
+        Ok(format!(
+            "{} event(s) in {}",
+            portal.events().read_all()?.len(),
+            portal.events().path().display()
+        ))
+    });
+    checks.check("backups", || {
+        let count = backup::list_backup_info(&portal.backups()).len();
+        expect(count >= 2, "the switches didn't each leave a backup")?;
+        Ok(format!("{} backup(s)", count))
+    });
+    checks.check("revert", || {
+        let backup = portal.revert(None)?;
+        let config = fs::read_to_string(portal.config_path())?;
+        let restored = backup::read_backup_to_string(&backup)?;
+        expect(config == restored, "the config doesn't match the backup")?;
+        Ok(format!(
+            "restored {}",
+            backup.file_name().unwrap_or_default().to_string_lossy()
+        ))
+    });
+    checks.check("undo", || {
+        let entry = portal
+            .undo()?
+            .ok_or_else(|| anyhow!("there was nothing to undo after the revert"))?;
+        portal
+            .redo()?
+            .ok_or_else(|| anyhow!("the undo couldn't be redone"))?;
+        Ok(format!("undid and redid {}", entry.action))
+    });
+    checks.check("prune", || {
+        let channel = portal.backups();
+        let before = backup::list_backup_info(&channel).len();
+        let pruned = backup::prune_backups(&channel, Some(1), None)?;
+        let after = backup::list_backup_info(&channel).len();
+        expect(
+            after == 1 && pruned.len() == before - 1,
+            "pruning didn't keep exactly the newest backup",
+        )?;
+        Ok(format!("pruned {} backup(s), kept {}", pruned.len(), after))
+    });
+    // Cleaning up runs whatever happened before
+    checks.failed = false;
+    checks.check("cleanup", || remove_sandbox(&dir));
+    checks.done
+}
+
+fn expect(condition: bool, problem: &str) -> Result<()> {
+    if condition {
+        Ok(())
+    } else {
+        Err(anyhow!("{}", problem))
+    }
+}
+
+fn sandbox_dir() -> PathBuf {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.subsec_nanos())
+        .unwrap_or_default();
+    std::env::temp_dir().join(format!(
+        "portal-verify-install-{}-{}",
+        std::process::id(),
+        nanos
+    ))
+}
+
+fn remove_sandbox(dir: &Path) -> Result<String> {
+    if dir.exists() {
+        fs::remove_dir_all(dir).with_context(|| format!("Failed to remove {}", dir.display()))?;
+    }
+    Ok(format!("removed {}", dir.display()))
+}
@@ -0,0 +1,177 @@
+//! Service definitions that run `portal daemon run` in the background: a
+//! systemd user unit on Linux, a launchd agent on macOS.
+
+use std::path::{Path, PathBuf};
+
+use crate::paths;
+
+/// systemd unit name
+pub const SYSTEMD_UNIT: &str = "portal.service";
+
+/// launchd job label, also the plist's file stem
+pub const LAUNCHD_LABEL: &str = "com.github.marzvrover.portal";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ServiceManager {
+    Systemd,
+    Launchd,
+}
+
+impl ServiceManager {
+    /// The one this platform uses
+    pub fn native() -> Self {
+        if cfg!(target_os = "macos") {
+            Self::Launchd
+        } else {
+            Self::Systemd
+        }
+    }
+
+    /// Where the definition is installed for the current user
+    pub fn unit_path(self) -> PathBuf {
+        match self {
+            Self::Systemd => paths::config_home()
+                .join("systemd")
+                .join("user")
+                .join(SYSTEMD_UNIT),
+            Self::Launchd => dirs::home_dir()
+                .expect("Could not determine home directory")
+                .join("Library")
+                .join("LaunchAgents")
+                .join(format!("{}.plist", LAUNCHD_LABEL)),
+        }
+    }
+}
+
+/// What the service runs
+#[derive(Debug, Clone)]
+pub struct ServiceSpec {
+    pub program: PathBuf,
+    pub args: Vec<String>,
+    /// Environment the daemon needs to find the same files as the shell
+    /// that installed it
+    pub env: Vec<(String, String)>,
+    /// Where launchd sends the daemon's output; systemd uses the journal
+    pub log_path: PathBuf,
+}
+
+/// The service definition file for `manager`
+pub fn render(manager: ServiceManager, spec: &ServiceSpec) -> String {
+    match manager {
+        ServiceManager::Systemd => render_systemd(spec),
+        ServiceManager::Launchd => render_launchd(spec),
+    }
+}
+
+/// Quote one ExecStart/Environment word for systemd, which also expands
+/// `%` specifiers
+fn systemd_quote(word: &str) -> String {
+    let escaped = word
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('%', "%%");
+    if escaped.is_empty() || escaped.contains(char::is_whitespace) || escaped != word {
+        format!("\"{}\"", escaped)
+    } else {
+        escaped
+    }
+}
+
+fn render_systemd(spec: &ServiceSpec) -> String {
+    let mut command = vec![systemd_quote(&spec.program.to_string_lossy())];
+    command.extend(spec.args.iter().map(|arg| systemd_quote(arg)));
+    let mut unit = String::from(
+        "[Unit]\n\
+         Description=portal: keep oh-my-opencode providers applied\n\
+         After=default.target\n\
+         \n\
+         [Service]\n\
+         Type=simple\n",
+    );
+    unit.push_str(&format!("ExecStart={}\n", command.join(" ")));
+    unit.push_str("ExecReload=/bin/kill -HUP $MAINPID\n");
+    for (key, value) in &spec.env {
+        unit.push_str(&format!(
+            "Environment={}\n",
+            systemd_quote(&format!("{}={}", key, value))
+        ));
+    }
+    unit.push_str(
+        "Restart=on-failure\n\
+         RestartSec=5\n\
+         \n\
+         [Install]\n\
+         WantedBy=default.target\n",
+    );
+    unit
+}
+
+fn xml_escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn plist_string(text: &str) -> String {
+    format!("<string>{}</string>", xml_escape(text))
+}
+
+fn render_launchd(spec: &ServiceSpec) -> String {
+    let log = spec.log_path.to_string_lossy();
+    let mut lines = vec![
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>".to_string(),
+        "<!DOCTYPE plist PUBLIC \"-//Apple//DTD PLIST 1.0//EN\" \
+         \"http://www.apple.com/DTDs/PropertyList-1.0.dtd\">"
+            .to_string(),
+        "<plist version=\"1.0\">".to_string(),
+        "<dict>".to_string(),
+        "  <key>Label</key>".to_string(),
+        format!("  {}", plist_string(LAUNCHD_LABEL)),
+        "  <key>ProgramArguments</key>".to_string(),
+        "  <array>".to_string(),
+        format!("    {}", plist_string(&spec.program.to_string_lossy())),
+    ];
+    lines.extend(
+        spec.args
+            .iter()
+            .map(|arg| format!("    {}", plist_string(arg))),
+    );
+    lines.push("  </array>".to_string());
+    if !spec.env.is_empty() {
+        lines.push("  <key>EnvironmentVariables</key>".to_string());
+        lines.push("  <dict>".to_string());
+        for (key, value) in &spec.env {
+            lines.push(format!("    <key>{}</key>", xml_escape(key)));
+            lines.push(format!("    {}", plist_string(value)));
+        }
+        lines.push("  </dict>".to_string());
+    }
+    lines.extend(
+        [
+            "  <key>RunAtLoad</key>",
+            "  <true/>",
+            // Restart after a crash, not after a clean stop
+            "  <key>KeepAlive</key>",
+            "  <dict>",
+            "    <key>SuccessfulExit</key>",
+            "    <false/>",
+            "  </dict>",
+            "  <key>ThrottleInterval</key>",
+            "  <integer>5</integer>",
+            "  <key>StandardOutPath</key>",
+        ]
+        .map(String::from),
+    );
+    lines.push(format!("  {}", plist_string(&log)));
+    lines.push("  <key>StandardErrorPath</key>".to_string());
+    lines.push(format!("  {}", plist_string(&log)));
+    lines.push("</dict>".to_string());
+    lines.push("</plist>".to_string());
+    lines.join("\n") + "\n"
+}
+
+/// Log file for the launchd agent, in the state directory
+pub fn log_path(state_dir: &Path) -> PathBuf {
+    state_dir.join("daemon.log")
+}
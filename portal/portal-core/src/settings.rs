@@ -0,0 +1,313 @@
+use anyhow::{Context, Result, anyhow};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::backup::BackupSettings;
+use crate::config;
+use crate::error::PortalError;
+use crate::fsutil;
+use crate::jsonc;
+use crate::mapping::{MappingOverride, MappingsFile};
+use crate::paths;
+use crate::pricing::Price;
+use crate::rendered::SwitchMode;
+use crate::storage::Storage;
+
+/// Environment variable naming the environment to use, ahead of the one
+/// `portal env` activated
+pub const ENV_VAR: &str = "PORTAL_ENV";
+
+/// File in the state directory naming the environment `portal env` activated
+pub const ACTIVE_ENV_FILE: &str = "environment";
+
+/// File in the portal directory holding general settings
+pub const SETTINGS_FILE: &str = "settings.json";
+
+/// Portal-wide settings
+///
+/// ```json
+/// {
+///   "targets": [
+///     {
+///       "path": "~/.config/opencode/opencode.json",
+///       "fields": ["model", "small_model", "agent.build.model"]
+///     }
+///   ],
+///   "aliases": { "or": "openrouter", "cp": "copilot" },
+///   "commands": { "work": "switch work-openrouter --until 18:00" },
+///   "pins": ["librarian", "subagents.indexer"],
+///   "pricing": {
+///     "claude-opus-4.5": { "input": 5.0, "output": 25.0 },
+///     "work-gateway/claude-opus-4.5": { "input": 0.0, "output": 0.0 }
+///   },
+///   "git_commit": true,
+///   "notify": true,
+///   "storage": "sqlite",
+///   "sources": { "team": "gist:8f2c5a1e9b7d4c3a", "gateway": "https://example.com/portal.json" },
+///   "backup": { "compress": true, "encrypt": true },
+///   "hooks": { "post-switch": "tmux display-message \"portal: $PORTAL_PROVIDER\"" },
+///   "environments": {
+///     "work": {
+///       "provider": "work-openrouter",
+///       "config": "~/work/.opencode/oh-my-opencode.json",
+///       "mappings": { "claude-opus-4.5": { "work-openrouter": "proxy/claude-opus-4.5" } }
+///     },
+///     "home": { "provider": "copilot" }
+///   }
+/// }
+/// ```
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Settings {
+    /// More config files a switch updates after oh-my-opencode.json
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub targets: Vec<ConfigTarget>,
+    /// Named lists of configs `portal switch --fleet` switches together;
+    /// `~/` is expanded
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub fleets: BTreeMap<String, Vec<PathBuf>>,
+    /// Short names for providers, e.g. `or` for `openrouter`
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub aliases: BTreeMap<String, String>,
+    /// Shortcuts for portal command lines: `portal work` runs the command
+    /// line `work` stands for, with any further arguments appended
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub commands: BTreeMap<String, String>,
+    /// Agents (or `subagents.<name>`, or top-level fields) every switch
+    /// leaves on the model they have, as `portal pin` sets them
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub pins: Vec<String>,
+    /// Dollars per million tokens by base model or full model string, over
+    /// the bundled prices
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub pricing: BTreeMap<String, Price>,
+    /// Commit each config portal writes to the git repository it is in,
+    /// as `--git-commit` does
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub git_commit: bool,
+    /// Show a desktop notification when portal switches on its own: in
+    /// watch mode or the daemon, on a rate-limit fallback, or when a
+    /// temporary switch ends
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub notify: bool,
+    /// Write a crash report when portal panics, as `PORTAL_CRASH_REPORTS=1`
+    /// does
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub crash_reports: bool,
+    /// Backend keeping the event history: `jsonl` (the default) or `sqlite`
+    #[serde(default, skip_serializing_if = "Storage::is_default")]
+    pub storage: Storage,
+    /// Remote provider definitions by source name: `gist:<id>`, a gist
+    /// page, or an HTTPS URL, fetched by `portal list --refresh`
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub sources: BTreeMap<String, String>,
+    /// How backups are written: `{"compress": true}` compresses them,
+    /// `{"encrypt": true}` encrypts them
+    #[serde(default, skip_serializing_if = "BackupSettings::is_default")]
+    pub backup: BackupSettings,
+    /// How `portal switch` writes the config: `rewrite` (the default)
+    /// rewrites it in place, `symlink` links it to a rendering per provider
+    #[serde(default, skip_serializing_if = "SwitchMode::is_default")]
+    pub switch_mode: SwitchMode,
+    /// Command lines run by the shell around a switch, by hook name
+    /// (`pre-switch`, `post-switch`), after any executable of the same name
+    /// in the hooks directory
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub hooks: BTreeMap<String, String>,
+    /// Named setups selected with `portal env` or `PORTAL_ENV`
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub environments: BTreeMap<String, Environment>,
+}
+
+/// A provider, config, and mapping overrides that belong together, e.g.
+/// for one machine
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Environment {
+    /// Provider `portal env <name>` switches to
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub provider: Option<String>,
+    /// Config to use instead of the default; `~/` is expanded
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub config: Option<PathBuf>,
+    /// Mapping rows layered over `mappings.json`, in its format
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub mappings: BTreeMap<String, MappingOverride>,
+}
+
+impl Environment {
+    pub fn mapping_overrides(&self) -> MappingsFile {
+        MappingsFile {
+            mappings: self.mappings.clone(),
+            ..MappingsFile::default()
+        }
+    }
+}
+
+/// Another JSON file with model fields in it
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ConfigTarget {
+    /// `~/` is expanded
+    pub path: PathBuf,
+    /// Dotted paths to the model strings in the file, e.g. `agent.build.model`
+    pub fields: Vec<String>,
+}
+
+pub fn settings_file_path(portal_dir: &Path) -> PathBuf {
+    portal_dir.join(SETTINGS_FILE)
+}
+
+pub fn read_settings(portal_dir: &Path) -> Result<Option<Settings>> {
+    let path = settings_file_path(portal_dir);
+    if !path.exists() {
+        return Ok(None);
+    }
+    let content = fsutil::read_to_string(&path)
+        .with_context(|| format!("Failed to read settings file: {}", path.display()))?;
+    let settings = serde_json::from_str(&jsonc::strip(&content))
+        .map_err(PortalError::parse("settings file", &path))?;
+    Ok(Some(settings))
+}
+
+/// Write `settings.json`, keeping the comments and layout of the file it
+/// replaces where the contents agree
+pub fn write_settings(portal_dir: &Path, settings: &Settings) -> Result<PathBuf> {
+    fs::create_dir_all(portal_dir).with_context(|| {
+        format!(
+            "Failed to create portal directory: {}",
+            portal_dir.display()
+        )
+    })?;
+    let path = settings_file_path(portal_dir);
+    let original = fsutil::read_to_string(&path).ok();
+    let content = config::render_document(original.as_deref(), &serde_json::to_value(settings)?)?;
+    fsutil::write_atomic(&path, content.as_bytes())
+        .with_context(|| format!("Failed to write settings file: {}", path.display()))?;
+    Ok(path)
+}
+
+impl Settings {
+    /// The provider `name` stands for. Aliases don't chain: the target of
+    /// an alias is always a provider name.
+    pub fn resolve_alias<'a>(&'a self, name: &'a str) -> &'a str {
+        self.aliases.get(name).map(String::as_str).unwrap_or(name)
+    }
+
+    /// The arguments command alias `name` stands for, `None` if there is no
+    /// such alias
+    pub fn command_alias(&self, name: &str) -> Option<Result<Vec<String>>> {
+        let line = self.commands.get(name)?;
+        Some(split_words(line).with_context(|| {
+            format!(
+                "Invalid command alias '{}' in {}: {}",
+                name, SETTINGS_FILE, line
+            )
+        }))
+    }
+
+    pub fn environment(&self, name: &str) -> Result<&Environment> {
+        self.environments.get(name).ok_or_else(|| {
+            let known: Vec<&str> = self.environments.keys().map(String::as_str).collect();
+            anyhow!(
+                "Unknown environment '{}' (defined in {}: {})",
+                name,
+                SETTINGS_FILE,
+                if known.is_empty() {
+                    "none".to_string()
+                } else {
+                    known.join(", ")
+                }
+            )
+        })
+    }
+
+    /// The configs of fleet `name`, with `~/` expanded
+    pub fn fleet(&self, name: &str) -> Result<Vec<PathBuf>> {
+        let configs = self.fleets.get(name).ok_or_else(|| {
+            let known: Vec<&str> = self.fleets.keys().map(String::as_str).collect();
+            anyhow!(
+                "Unknown fleet '{}' (defined in {}: {})",
+                name,
+                SETTINGS_FILE,
+                if known.is_empty() {
+                    "none".to_string()
+                } else {
+                    known.join(", ")
+                }
+            )
+        })?;
+        Ok(configs
+            .iter()
+            .map(|path| paths::expand_home(path))
+            .collect())
+    }
+}
+
+/// Split a command line into words the way a shell would for the simple
+/// cases: whitespace separates words, and single or double quotes keep
+/// spaces within one
+fn split_words(line: &str) -> Result<Vec<String>> {
+    let mut words = Vec::new();
+    let mut word: Option<String> = None;
+    let mut quote = None;
+    for c in line.chars() {
+        match (quote, c) {
+            (Some(q), c) if c == q => quote = None,
+            (Some(_), c) => word.get_or_insert_default().push(c),
+            (None, '\'' | '"') => {
+                quote = Some(c);
+                word.get_or_insert_default();
+            }
+            (None, c) if c.is_whitespace() => words.extend(word.take()),
+            (None, c) => word.get_or_insert_default().push(c),
+        }
+    }
+    if quote.is_some() {
+        return Err(anyhow!("Unterminated quote"));
+    }
+    words.extend(word);
+    if words.is_empty() {
+        return Err(anyhow!("Empty command"));
+    }
+    Ok(words)
+}
+
+fn active_env_path(state_dir: &Path) -> PathBuf {
+    state_dir.join(ACTIVE_ENV_FILE)
+}
+
+/// The environment in effect: `PORTAL_ENV` if set, else the one
+/// `portal env` activated
+pub fn active_environment(state_dir: &Path) -> Result<Option<String>> {
+    if let Ok(name) = std::env::var(ENV_VAR)
+        && !name.is_empty()
+    {
+        return Ok(Some(name));
+    }
+    let path = active_env_path(state_dir);
+    match fsutil::read_to_string(&path) {
+        Ok(content) => Ok(Some(content.trim().to_string()).filter(|name| !name.is_empty())),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e).with_context(|| format!("Failed to read {}", path.display())),
+    }
+}
+
+/// Remember `name` as the active environment, or forget it with `None`
+pub fn set_active_environment(state_dir: &Path, name: Option<&str>) -> Result<()> {
+    let path = active_env_path(state_dir);
+    match name {
+        Some(name) => {
+            fs::create_dir_all(state_dir).with_context(|| {
+                format!("Failed to create state directory: {}", state_dir.display())
+            })?;
+            fsutil::write_atomic(&path, format!("{}\n", name).as_bytes())
+                .with_context(|| format!("Failed to write {}", path.display()))
+        }
+        None => match fs::remove_file(&path) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+                Err(e).with_context(|| format!("Failed to remove {}", path.display()))
+            }
+            _ => Ok(()),
+        },
+    }
+}
@@ -0,0 +1,259 @@
+//! Remote provider sources: provider definitions a team publishes once,
+//! in a GitHub gist or at an HTTPS URL, named under `"sources"` in
+//! settings.json. `portal list --refresh` fetches each source into the
+//! state directory, and its providers can then be switched to like local
+//! ones. A provider file of the same name in the portal directory wins.
+//!
+//! A gist holds one provider per `<name>.json` file. A URL serves every
+//! provider in one object, by name:
+//!
+//! ```json
+//! {
+//!   "work-gateway": {
+//!     "description": "Company gateway",
+//!     "agents": { "oracle": { "model": "work-gateway/gpt-5.2" } }
+//!   }
+//! }
+//! ```
+
+use anyhow::{Context, Result, anyhow};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::provider::{self, ProviderConfig};
+use crate::{fsutil, http};
+
+/// Directory in portal's state directory caching every source
+pub const SOURCES_DIR: &str = "sources";
+
+/// File in each source's cache saying where and when it was fetched
+const SOURCE_FILE: &str = ".source.json";
+
+/// Where a source's providers are fetched from
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Location {
+    /// A GitHub gist, by id
+    Gist(String),
+    /// An HTTPS URL serving providers by name
+    Url(String),
+}
+
+impl Location {
+    /// `gist:<id>`, a `https://gist.github.com/...` page, or any other
+    /// HTTPS URL
+    pub fn parse(source: &str) -> Result<Self> {
+        if let Some(id) = source.strip_prefix("gist:") {
+            return Ok(Self::Gist(id.to_string()));
+        }
+        if let Some(path) = source.strip_prefix("https://gist.github.com/") {
+            let id = path
+                .trim_end_matches('/')
+                .rsplit('/')
+                .next()
+                .unwrap_or_default();
+            if !id.is_empty() {
+                return Ok(Self::Gist(id.to_string()));
+            }
+        }
+        if source.starts_with("https://") {
+            return Ok(Self::Url(source.to_string()));
+        }
+        Err(anyhow!(
+            "Unsupported provider source '{}': use an https:// URL or gist:<id>",
+            source
+        ))
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct SourceMeta {
+    url: String,
+    fetched: DateTime<Utc>,
+}
+
+/// What refreshing one source came to
+#[derive(Debug, Clone, Serialize)]
+pub struct Refresh {
+    pub source: String,
+    pub url: String,
+    /// Providers now cached from it
+    pub providers: Vec<String>,
+    /// Why it couldn't be fetched; the providers cached before stay
+    pub error: Option<String>,
+}
+
+/// A provider cached from a source
+#[derive(Debug, Clone, Serialize)]
+pub struct RemoteProvider {
+    pub source: String,
+    pub path: PathBuf,
+}
+
+pub fn cache_dir(state_dir: &Path, source: &str) -> PathBuf {
+    state_dir.join(SOURCES_DIR).join(source)
+}
+
+/// Fetch every source in `sources`, by name, into the cache
+pub fn refresh(state_dir: &Path, sources: &BTreeMap<String, String>) -> Vec<Refresh> {
+    sources
+        .iter()
+        .map(|(source, url)| match refresh_one(state_dir, source, url) {
+            Ok(providers) => Refresh {
+                source: source.clone(),
+                url: url.clone(),
+                providers,
+                error: None,
+            },
+            Err(e) => Refresh {
+                source: source.clone(),
+                url: url.clone(),
+                providers: cached_names(&cache_dir(state_dir, source)),
+                error: Some(format!("{:#}", e)),
+            },
+        })
+        .collect()
+}
+
+fn refresh_one(state_dir: &Path, source: &str, url: &str) -> Result<Vec<String>> {
+    provider::validate_name(source).context("Invalid provider source name")?;
+    let providers = fetch(&Location::parse(url)?)?;
+
+    // Staged next to the cache and swapped in whole, so a failure halfway
+    // leaves the old one
+    let dir = cache_dir(state_dir, source);
+    let staging = dir.with_file_name(format!(".{}.new", source));
+    let _ = fs::remove_dir_all(&staging);
+    fs::create_dir_all(&staging)
+        .with_context(|| format!("Failed to create {}", staging.display()))?;
+    for (name, config) in &providers {
+        let path = staging.join(format!("{}.json", name));
+        fs::write(&path, serde_json::to_string_pretty(config)?)
+            .with_context(|| format!("Failed to write {}", path.display()))?;
+    }
+    let meta = SourceMeta {
+        url: url.to_string(),
+        fetched: Utc::now(),
+    };
+    fs::write(
+        staging.join(SOURCE_FILE),
+        serde_json::to_string_pretty(&meta)?,
+    )?;
+    if dir.exists() {
+        fs::remove_dir_all(&dir).with_context(|| format!("Failed to replace {}", dir.display()))?;
+    }
+    fs::rename(&staging, &dir).with_context(|| format!("Failed to write {}", dir.display()))?;
+    crate::portal_info!(
+        "Fetched {} provider(s) from source '{}'",
+        providers.len(),
+        source
+    );
+    Ok(providers.into_keys().collect())
+}
+
+/// Every provider `location` serves, checked to be valid
+fn fetch(location: &Location) -> Result<BTreeMap<String, ProviderConfig>> {
+    let documents: BTreeMap<String, Value> = match location {
+        Location::Url(url) => {
+            let body = http::get(url)?;
+            serde_json::from_str(&body)
+                .with_context(|| format!("{} doesn't serve an object of providers", url))?
+        }
+        Location::Gist(id) => gist_files(id)?,
+    };
+    documents
+        .into_iter()
+        .map(|(name, document)| {
+            provider::validate_name(&name)?;
+            let config: ProviderConfig = serde_json::from_value(document)
+                .with_context(|| format!("Provider '{}' isn't a valid provider config", name))?;
+            Ok((name, config))
+        })
+        .collect()
+}
+
+/// The `<name>.json` files of a gist, parsed, by name
+fn gist_files(id: &str) -> Result<BTreeMap<String, Value>> {
+    let url = format!("https://api.github.com/gists/{}", id);
+    let gist: Value = serde_json::from_str(&http::get(&url)?)
+        .with_context(|| format!("Unexpected response from {}", url))?;
+    let files = gist
+        .get("files")
+        .and_then(Value::as_object)
+        .ok_or_else(|| anyhow!("Gist {} has no files", id))?;
+    let mut documents = BTreeMap::new();
+    for (file, details) in files {
+        let Some(name) = file.strip_suffix(".json") else {
+            continue;
+        };
+        // Large files are left out of the API response
+        let content = match details.get("content").and_then(Value::as_str) {
+            Some(content) if details.get("truncated") != Some(&Value::Bool(true)) => {
+                content.to_string()
+            }
+            _ => {
+                let raw = details
+                    .get("raw_url")
+                    .and_then(Value::as_str)
+                    .ok_or_else(|| anyhow!("Gist {} gives no contents for {}", id, file))?;
+                http::get(raw)?
+            }
+        };
+        let document = crate::jsonc::parse(&content)
+            .with_context(|| format!("{} in gist {} isn't valid JSON", file, id))?;
+        documents.insert(name.to_string(), document);
+    }
+    Ok(documents)
+}
+
+fn cached_names(dir: &Path) -> Vec<String> {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    let mut names: Vec<String> = entries
+        .flatten()
+        .filter_map(|e| {
+            let name = e.file_name().to_string_lossy().into_owned();
+            let stem = name.strip_suffix(".json")?;
+            (!stem.starts_with('.')).then(|| stem.to_string())
+        })
+        .collect();
+    names.sort();
+    names
+}
+
+/// Providers cached from `sources`, by name; when two sources have the
+/// same provider, the first source by name wins
+pub fn cached_providers(
+    state_dir: &Path,
+    sources: &BTreeMap<String, String>,
+) -> BTreeMap<String, RemoteProvider> {
+    let mut providers = BTreeMap::new();
+    for source in sources.keys() {
+        let dir = cache_dir(state_dir, source);
+        for name in cached_names(&dir) {
+            let path = dir.join(format!("{}.json", name));
+            providers.entry(name).or_insert(RemoteProvider {
+                source: source.clone(),
+                path,
+            });
+        }
+    }
+    providers
+}
+
+/// A cached provider's config
+pub fn read_cached(remote: &RemoteProvider) -> Result<ProviderConfig> {
+    let content = fsutil::read_to_string(&remote.path).with_context(|| {
+        format!(
+            "Failed to read provider from source '{}': {}",
+            remote.source,
+            remote.path.display()
+        )
+    })?;
+    serde_json::from_str(&content)
+        .map_err(|e| crate::error::PortalError::parse("provider config", &remote.path)(e).into())
+}
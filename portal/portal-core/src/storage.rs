@@ -0,0 +1,299 @@
+//! Where the event history is kept. The JSONL log in the state directory
+//! is the default; with `"storage": "sqlite"` in settings.json events go to
+//! an SQLite database instead, indexed by time so reports over years of
+//! history don't read all of it. The database is driven through the
+//! `sqlite3` command-line shell, which must be installed.
+
+use anyhow::{Context, Result, anyhow};
+use chrono::{DateTime, SecondsFormat, Utc};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::thread;
+use std::time::Duration;
+
+use crate::events::{Event, EventLog};
+
+/// Database file in the state directory for the SQLite backend
+pub const SQLITE_FILE: &str = "events.sqlite3";
+
+/// How often `follow` checks the database for new events
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+const SCHEMA: &str = "\
+CREATE TABLE IF NOT EXISTS events (
+    id INTEGER PRIMARY KEY,
+    timestamp TEXT NOT NULL,
+    event TEXT NOT NULL,
+    config TEXT NOT NULL,
+    json TEXT NOT NULL
+);
+CREATE INDEX IF NOT EXISTS events_timestamp ON events (timestamp);
+CREATE INDEX IF NOT EXISTS events_config ON events (config);
+";
+
+/// Which backend holds the event history
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Storage {
+    #[default]
+    Jsonl,
+    Sqlite,
+}
+
+impl Storage {
+    pub fn is_default(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+/// Event history, whichever backend keeps it
+pub trait EventStore {
+    /// Where the events are kept
+    fn path(&self) -> &Path;
+
+    fn append(&self, event: &Event) -> Result<()>;
+
+    /// Events at or after `since`, oldest first; all of them with `None`.
+    /// Entries that don't parse are skipped.
+    fn read_since(&self, since: Option<DateTime<Utc>>) -> Result<Vec<Event>>;
+
+    /// Every event, oldest first
+    fn read_all(&self) -> Result<Vec<Event>> {
+        self.read_since(None)
+    }
+
+    /// For each config, its latest event before `before` of one of
+    /// `kinds` (as named in the `event` field), oldest first
+    fn latest_before(&self, before: DateTime<Utc>, kinds: &[&str]) -> Result<Vec<Event>> {
+        let mut latest: Vec<Event> = Vec::new();
+        for event in self.read_all()? {
+            if event.timestamp >= before || !kinds.contains(&kind_name(&event)?.as_str()) {
+                continue;
+            }
+            latest.retain(|e| e.kind.config() != event.kind.config());
+            latest.push(event);
+        }
+        Ok(latest)
+    }
+
+    /// Call `on_line` with every event as a JSON line, then keep waiting
+    /// for new ones. Only returns on error.
+    fn follow(&self, on_line: &mut dyn FnMut(&str) -> Result<()>) -> Result<()>;
+
+    /// Rewrite the events naming provider `from` to name `to` instead,
+    /// returning how many there were
+    fn rename_provider(&self, from: &str, to: &str) -> Result<usize>;
+}
+
+/// The event history in the state directory, kept by `storage`
+pub fn open(state_dir: &Path, storage: Storage) -> Box<dyn EventStore> {
+    match storage {
+        Storage::Jsonl => Box::new(EventLog::new(state_dir)),
+        Storage::Sqlite => Box::new(SqliteStore::new(state_dir)),
+    }
+}
+
+impl EventStore for EventLog {
+    fn path(&self) -> &Path {
+        EventLog::path(self)
+    }
+
+    fn append(&self, event: &Event) -> Result<()> {
+        EventLog::append(self, event)
+    }
+
+    fn read_since(&self, since: Option<DateTime<Utc>>) -> Result<Vec<Event>> {
+        let mut events = EventLog::read_all(self)?;
+        if let Some(since) = since {
+            events.retain(|event| event.timestamp >= since);
+        }
+        Ok(events)
+    }
+
+    fn follow(&self, on_line: &mut dyn FnMut(&str) -> Result<()>) -> Result<()> {
+        EventLog::follow(self, on_line)
+    }
+
+    fn rename_provider(&self, from: &str, to: &str) -> Result<usize> {
+        EventLog::rename_provider(self, from, to)
+    }
+}
+
+/// Events in an SQLite database, one row each with the event's JSON and
+/// indexed columns to query by
+#[derive(Debug, Clone)]
+pub struct SqliteStore {
+    path: PathBuf,
+    /// JSONL log imported when the database is first created
+    legacy: EventLog,
+}
+
+impl SqliteStore {
+    pub fn new(state_dir: &Path) -> Self {
+        Self {
+            path: state_dir.join(SQLITE_FILE),
+            legacy: EventLog::new(state_dir),
+        }
+    }
+
+    /// Run `sql` in the database and return what it printed. The first
+    /// use creates the database, bringing over the events already in the
+    /// JSONL log.
+    fn run(&self, sql: &str) -> Result<String> {
+        let mut script = String::new();
+        if !self.path.exists() {
+            if let Some(parent) = self.path.parent() {
+                fs::create_dir_all(parent).with_context(|| {
+                    format!("Failed to create state directory: {}", parent.display())
+                })?;
+            }
+            script.push_str(SCHEMA);
+            script.push_str("BEGIN;\n");
+            for event in self.legacy.read_all()? {
+                script.push_str(&insert(&event)?);
+            }
+            script.push_str("COMMIT;\n");
+        }
+        script.push_str(sql);
+
+        let mut child = Command::new("sqlite3")
+            .args(["-batch", "-bail", "-noheader", "-list"])
+            .arg(&self.path)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .context("Failed to run sqlite3, which the SQLite storage backend needs")?;
+        child
+            .stdin
+            .take()
+            .expect("stdin is piped")
+            .write_all(script.as_bytes())
+            .context("Failed to send SQL to sqlite3")?;
+        let output = child.wait_with_output().context("Failed to run sqlite3")?;
+        if !output.status.success() {
+            return Err(anyhow!(
+                "sqlite3 failed on {}: {}",
+                self.path.display(),
+                String::from_utf8_lossy(&output.stderr).trim()
+            ));
+        }
+        Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+    }
+}
+
+impl EventStore for SqliteStore {
+    fn path(&self) -> &Path {
+        &self.path
+    }
+
+    fn append(&self, event: &Event) -> Result<()> {
+        self.run(&insert(event)?).map(|_| ())
+    }
+
+    fn read_since(&self, since: Option<DateTime<Utc>>) -> Result<Vec<Event>> {
+        let filter = since
+            .map(|since| format!(" WHERE timestamp >= {}", quote(&timestamp(since))))
+            .unwrap_or_default();
+        let output = self.run(&format!(
+            "SELECT json FROM events{} ORDER BY timestamp, id;\n",
+            filter
+        ))?;
+        Ok(output
+            .lines()
+            .filter_map(|line| serde_json::from_str(line).ok())
+            .collect())
+    }
+
+    fn latest_before(&self, before: DateTime<Utc>, kinds: &[&str]) -> Result<Vec<Event>> {
+        let kinds: Vec<String> = kinds.iter().map(|kind| quote(kind)).collect();
+        let output = self.run(&format!(
+            "SELECT json FROM events WHERE id IN (\
+             SELECT MAX(id) FROM events WHERE timestamp < {} AND event IN ({}) GROUP BY config\
+             ) ORDER BY timestamp, id;\n",
+            quote(&timestamp(before)),
+            kinds.join(", ")
+        ))?;
+        Ok(output
+            .lines()
+            .filter_map(|line| serde_json::from_str(line).ok())
+            .collect())
+    }
+
+    fn follow(&self, on_line: &mut dyn FnMut(&str) -> Result<()>) -> Result<()> {
+        let mut last = 0i64;
+        loop {
+            let output = self.run(&format!(
+                "SELECT id, json FROM events WHERE id > {} ORDER BY id;\n",
+                last
+            ))?;
+            for line in output.lines() {
+                // The ID is a number, so the first separator is the column break
+                let Some((id, json)) = line.split_once('|') else {
+                    continue;
+                };
+                last = id.parse().unwrap_or(last);
+                on_line(json)?;
+            }
+            thread::sleep(POLL_INTERVAL);
+        }
+    }
+
+    fn rename_provider(&self, from: &str, to: &str) -> Result<usize> {
+        let output = self.run("SELECT id, json FROM events ORDER BY id;\n")?;
+        let mut updates = String::new();
+        let mut renamed = 0;
+        for line in output.lines() {
+            let Some((id, json)) = line.split_once('|') else {
+                continue;
+            };
+            let Ok(mut event) = serde_json::from_str::<Event>(json) else {
+                continue;
+            };
+            if event.kind.rename_provider(from, to) {
+                updates.push_str(&format!(
+                    "UPDATE events SET json = {} WHERE id = {};\n",
+                    quote(&serde_json::to_string(&event)?),
+                    id
+                ));
+                renamed += 1;
+            }
+        }
+        if renamed > 0 {
+            self.run(&format!("BEGIN;\n{}COMMIT;\n", updates))?;
+        }
+        Ok(renamed)
+    }
+}
+
+/// Timestamps as stored: fixed width in UTC, so they sort as text
+fn timestamp(time: DateTime<Utc>) -> String {
+    time.to_rfc3339_opts(SecondsFormat::Micros, true)
+}
+
+fn quote(text: &str) -> String {
+    format!("'{}'", text.replace('\'', "''"))
+}
+
+/// The event's `event` field: `switch`, `revert`, ...
+fn kind_name(event: &Event) -> Result<String> {
+    Ok(serde_json::to_value(event)?
+        .get("event")
+        .and_then(|kind| kind.as_str().map(str::to_string))
+        .unwrap_or_default())
+}
+
+fn insert(event: &Event) -> Result<String> {
+    let json = serde_json::to_string(event)?;
+    let kind = kind_name(event)?;
+    Ok(format!(
+        "INSERT INTO events (timestamp, event, config, json) VALUES ({}, {}, {}, {});\n",
+        quote(&timestamp(event.timestamp)),
+        quote(&kind),
+        quote(&event.kind.config().to_string_lossy()),
+        quote(&json)
+    ))
+}
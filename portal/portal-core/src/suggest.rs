@@ -0,0 +1,74 @@
+//! What to offer when a switch fails: the provider most recently switched
+//! to that would still switch cleanly now.
+
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use std::fs;
+use std::path::Path;
+
+use crate::events::EventKind;
+use crate::portal::{Portal, SwitchOptions};
+use crate::quota;
+
+/// A provider that worked before
+#[derive(Debug, Clone)]
+pub struct Suggestion {
+    pub provider: String,
+    /// When portal last switched the config to it
+    pub switched_at: DateTime<Utc>,
+}
+
+/// Providers the config was switched to, newest first, each once
+pub fn recent_providers(portal: &Portal) -> Result<Vec<(String, DateTime<Utc>)>> {
+    let canonical = |p: &Path| fs::canonicalize(p).unwrap_or_else(|_| p.to_path_buf());
+    let config = canonical(portal.config_path());
+    let mut providers: Vec<(String, DateTime<Utc>)> = Vec::new();
+    for event in portal.events().read_all()?.into_iter().rev() {
+        if let EventKind::Switch {
+            config: switched,
+            provider,
+            ..
+        } = event.kind
+            && canonical(&switched) == config
+            && !providers.iter().any(|(p, _)| *p == provider)
+        {
+            providers.push((provider, event.timestamp));
+        }
+    }
+    Ok(providers)
+}
+
+/// The most recent provider other than `failed` and the current one that
+/// `accept` allows, that switches now without an error or unmapped agents,
+/// and whose quota account of the same name (if any) hasn't run out
+pub fn suggest(
+    portal: &Portal,
+    failed: &str,
+    options: &SwitchOptions,
+    accept: impl Fn(&str) -> bool,
+) -> Result<Option<Suggestion>> {
+    let current = portal.last_provider()?;
+    let quotas = quota::read_cache(portal.state_dir())?;
+    for (provider, switched_at) in recent_providers(portal)? {
+        if provider == failed || current.as_deref() == Some(provider.as_str()) || !accept(&provider)
+        {
+            continue;
+        }
+        if quotas
+            .get(&provider)
+            .is_some_and(|q| q.remaining.is_some_and(|r| r <= 0.0))
+        {
+            continue;
+        }
+        match portal.preview_switch(&provider, options) {
+            Ok(preview) if preview.report.unmapped.is_empty() => {
+                return Ok(Some(Suggestion {
+                    provider,
+                    switched_at,
+                }));
+            }
+            _ => continue,
+        }
+    }
+    Ok(None)
+}
@@ -0,0 +1,588 @@
+use crate::config::{self, OhMyOpenCodeConfig};
+use crate::diff::ModelChange;
+use crate::mapping::{MappingTable, Tier, infer_openrouter_model};
+use crate::provider::ProviderConfig;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::collections::{BTreeMap, BTreeSet};
+
+/// Outcome of applying a provider switch to a config in memory
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct SwitchReport {
+    /// Agents that had no mapping for the provider and kept their current model
+    pub unmapped: Vec<String>,
+    /// opencode provider IDs whose `provider.<id>.options` were updated
+    pub options: Vec<String>,
+    /// Agents pinned in settings.json, which kept their current model
+    pub pinned: Vec<String>,
+}
+
+/// How sure detection is of an agent's provider
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Confidence {
+    /// The model's prefix is a built-in provider's
+    Exact,
+    /// The model has some other prefix, taken to be the provider's name
+    Heuristic,
+    /// The model has no provider prefix
+    Unknown,
+}
+
+/// An agent's provider as worked out from its model
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DetectedProvider {
+    /// Provider name as `portal switch` takes it, if there is one
+    pub provider: Option<String>,
+    pub confidence: Confidence,
+}
+
+/// The provider a model string is on: `github-copilot/...` is `copilot`,
+/// `openrouter/...` is `openrouter`, and other prefixes are passed through
+pub fn detect_model_provider(model: &str) -> DetectedProvider {
+    let (provider, confidence) = match model.split_once('/') {
+        Some(("github-copilot", _)) => (Some("copilot"), Confidence::Exact),
+        Some(("openrouter", _)) => (Some("openrouter"), Confidence::Exact),
+        Some((prefix, _)) if !prefix.is_empty() => (Some(prefix), Confidence::Heuristic),
+        _ => (None, Confidence::Unknown),
+    };
+    DetectedProvider {
+        provider: provider.map(str::to_string),
+        confidence,
+    }
+}
+
+/// Each agent's provider, by agent name
+pub fn detect_providers(config: &OhMyOpenCodeConfig) -> BTreeMap<String, DetectedProvider> {
+    config
+        .agents
+        .iter()
+        .map(|(name, agent)| (name.clone(), detect_model_provider(&agent.model)))
+        .collect()
+}
+
+/// [`detect_providers`] for a config document
+pub fn detect_document_providers(document: &Value) -> BTreeMap<String, DetectedProvider> {
+    config::agent_models(document)
+        .into_iter()
+        .map(|(name, model)| (name, detect_model_provider(&model)))
+        .collect()
+}
+
+/// The provider every agent with a known one is on; `None` when they
+/// disagree or none is known
+pub fn common_provider(detected: &BTreeMap<String, DetectedProvider>) -> Option<String> {
+    let known: BTreeSet<&str> = detected
+        .values()
+        .filter_map(|d| d.provider.as_deref())
+        .collect();
+    match known.len() {
+        1 => known.into_iter().next().map(str::to_string),
+        _ => None,
+    }
+}
+
+/// Distinct providers the agents are on, when they don't agree on one
+pub fn mixed_providers(detected: &BTreeMap<String, DetectedProvider>) -> Vec<String> {
+    let known: BTreeSet<String> = detected
+        .values()
+        .filter_map(|d| d.provider.clone())
+        .collect();
+    if known.len() > 1 {
+        known.into_iter().collect()
+    } else {
+        Vec::new()
+    }
+}
+
+pub fn detect_current_provider(config: &OhMyOpenCodeConfig) -> Option<String> {
+    common_provider(&detect_providers(config))
+}
+
+/// [`detect_current_provider`] for a config document
+pub fn detect_document_provider(document: &Value) -> Option<String> {
+    common_provider(&detect_document_providers(document))
+}
+
+/// Everything the transform needs besides the config itself
+#[derive(Debug, Clone, Copy)]
+pub struct SwitchContext<'a> {
+    pub provider: &'a str,
+    pub provider_config: Option<&'a ProviderConfig>,
+    pub mappings: &'a MappingTable,
+    /// Only rewrite these agents; `None` rewrites all of them
+    pub agents: Option<&'a [String]>,
+    /// Also rewrite top-level model fields such as `model` and `small_model`
+    pub top_level: bool,
+    /// Leave these alone, named as [`SwitchReport::unmapped`] names them
+    pub pinned: &'a [String],
+}
+
+impl<'a> SwitchContext<'a> {
+    pub fn new(provider: &'a str, mappings: &'a MappingTable) -> Self {
+        Self {
+            provider,
+            provider_config: None,
+            mappings,
+            agents: None,
+            top_level: true,
+            pinned: &[],
+        }
+    }
+
+    fn includes(&self, agent_name: &str) -> bool {
+        self.agents
+            .is_none_or(|agents| agents.iter().any(|a| a == agent_name))
+    }
+
+    /// Whether `reported` (as [`SwitchReport`] names it) is pinned, noting
+    /// it in `report` if so
+    fn is_pinned(&self, reported: &str, report: &mut SwitchReport) -> bool {
+        let pinned = self.pinned.iter().any(|p| p == reported);
+        if pinned {
+            report.pinned.push(reported.to_string());
+        }
+        pinned
+    }
+}
+
+/// Where a new model came from
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Resolution {
+    /// The provider file names a model for this agent
+    ProviderFile,
+    /// One of the provider file's rules built the model from the base model
+    Rule,
+    /// The mapping table has a column for the provider
+    Mapped,
+    /// Neither does; the model was guessed from the base model
+    Guessed,
+}
+
+/// New model for one agent, preferring an explicit entry from the provider
+/// config, then the config's rules, and falling back to the mapping table.
+/// `None` means no mapping.
+pub fn resolve_model(agent_name: &str, current: &str, ctx: &SwitchContext) -> Option<String> {
+    explain_model(agent_name, current, ctx).map(|(model, _)| model)
+}
+
+/// [`resolve_model`], also saying how the model was arrived at
+pub fn explain_model(
+    agent_name: &str,
+    current: &str,
+    ctx: &SwitchContext,
+) -> Option<(String, Resolution)> {
+    let decision = decide_model(agent_name, current, ctx);
+    match &decision {
+        Some((model, resolution)) => crate::portal_debug!(
+            "{}: '{}' -> '{}' on '{}': {}",
+            agent_name,
+            current,
+            model,
+            ctx.provider,
+            match resolution {
+                Resolution::ProviderFile => "named in the provider file".to_string(),
+                Resolution::Rule => format!(
+                    "provider file rule on base model '{}'",
+                    canonical_base(current, ctx.mappings)
+                ),
+                Resolution::Mapped => format!(
+                    "mapping table row '{}'",
+                    canonical_base(current, ctx.mappings)
+                ),
+                Resolution::Guessed => format!(
+                    "guessed from base model '{}'",
+                    canonical_base(current, ctx.mappings)
+                ),
+            }
+        ),
+        None => crate::portal_debug!(
+            "{}: '{}' has no mapping for '{}' (base model '{}'), left as is",
+            agent_name,
+            current,
+            ctx.provider,
+            canonical_base(current, ctx.mappings)
+        ),
+    }
+    decision
+}
+
+fn decide_model(
+    agent_name: &str,
+    current: &str,
+    ctx: &SwitchContext,
+) -> Option<(String, Resolution)> {
+    // Check if provider config has explicit mapping for this agent
+    if let Some(pc) = ctx.provider_config
+        && let Some(agent_override) = pc.agent(agent_name)
+    {
+        return Some((agent_override.model.clone(), Resolution::ProviderFile));
+    }
+
+    decide_base_model(&canonical_base(current, ctx.mappings), ctx)
+}
+
+/// What the provider's rules, the mapping table, or a guess make of a
+/// canonical base model, leaving out the provider file's per-agent pins.
+/// The mapping table and the guess go by the provider's name, then by the
+/// names of the providers it extends.
+fn decide_base_model(base: &str, ctx: &SwitchContext) -> Option<(String, Resolution)> {
+    if let Some(model) = ctx.provider_config.and_then(|pc| pc.rule_model(base)) {
+        return Some((model, Resolution::Rule));
+    }
+
+    let names = || {
+        std::iter::once(ctx.provider).chain(
+            ctx.provider_config
+                .into_iter()
+                .flat_map(|pc| pc.ancestors.iter().map(String::as_str)),
+        )
+    };
+
+    // Fall back to the mapping table
+    if let Some(new_model) = names().find_map(|name| ctx.mappings.transform(base, name)) {
+        return Some((new_model, Resolution::Mapped));
+    }
+
+    // Custom provider without explicit config - best effort
+    names()
+        .find_map(|name| match name {
+            p if p.contains("openrouter") => Some(infer_openrouter_model(base)),
+            p if p.contains("copilot") => Some(format!("github-copilot/{}", base)),
+            _ => None,
+        })
+        .map(|guess| (guess, Resolution::Guessed))
+}
+
+/// The model of `tier` on `ctx.provider` for an agent now on `current`:
+/// the first mapping table row of the tier from the same vendor as
+/// `current`, or else from any vendor, that the provider has a model for.
+/// The provider file's per-agent pins don't say which tier they are, so
+/// they're left out; its rules still apply.
+pub fn tier_model(current: &str, tier: Tier, ctx: &SwitchContext) -> Option<String> {
+    let vendor = ctx
+        .mappings
+        .find(&canonical_base(current, ctx.mappings))
+        .map(|m| m.openrouter_provider.as_str());
+    let rows = ctx.mappings.rows().iter().filter(|m| m.tier == Some(tier));
+    let (same, other): (Vec<_>, Vec<_>) =
+        rows.partition(|m| Some(m.openrouter_provider.as_str()) == vendor);
+    same.into_iter()
+        .chain(other)
+        .find_map(|m| decide_base_model(&m.base, ctx))
+        .map(|(model, _)| model)
+}
+
+/// The mapping table's base model for a full model string, or the bare
+/// model name if the table doesn't know it
+pub fn canonical_base(model: &str, mappings: &MappingTable) -> String {
+    let base = mappings.base_model(model);
+    mappings.find(&base).map(|m| m.base.clone()).unwrap_or(base)
+}
+
+/// Rewrite the string `model` of each selected subagent entry. Unmapped
+/// and pinned subagents are reported as `subagents.<name>`.
+fn switch_subagents<'v>(
+    subagents: impl Iterator<Item = (&'v String, &'v mut Value)>,
+    ctx: &SwitchContext,
+    report: &mut SwitchReport,
+) {
+    for (name, subagent) in subagents {
+        if !ctx.includes(name) || ctx.is_pinned(&format!("subagents.{}", name), report) {
+            continue;
+        }
+        let Some(model) = subagent.get_mut("model") else {
+            continue;
+        };
+        let Some(current) = model.as_str() else {
+            continue;
+        };
+        match resolve_model(name, current, ctx) {
+            Some(new_model) => *model = Value::String(new_model),
+            None => report.unmapped.push(format!("subagents.{}", name)),
+        }
+    }
+}
+
+/// Rewrite the top-level default model fields among `fields`. With an
+/// agent filter, only fields named in it are touched.
+fn switch_top_level<'v>(
+    fields: impl Iterator<Item = (&'v String, &'v mut Value)>,
+    ctx: &SwitchContext,
+    report: &mut SwitchReport,
+) {
+    if !ctx.top_level {
+        return;
+    }
+    for (name, model) in fields {
+        if !config::TOP_LEVEL_MODEL_FIELDS.contains(&name.as_str())
+            || !ctx.includes(name)
+            || ctx.is_pinned(name, report)
+        {
+            continue;
+        }
+        let Some(current) = model.as_str() else {
+            continue;
+        };
+        match resolve_model(name, current, ctx) {
+            Some(new_model) => *model = Value::String(new_model),
+            None => report.unmapped.push(name.clone()),
+        }
+    }
+}
+
+/// Rewrite each selected agent's and subagent's model for the provider,
+/// plus the top-level model fields unless `ctx.top_level` is off
+pub fn switch_to_provider(config: &mut OhMyOpenCodeConfig, ctx: &SwitchContext) -> SwitchReport {
+    let mut report = SwitchReport::default();
+
+    for (agent_name, agent_config) in config.agents.iter_mut() {
+        if !ctx.includes(agent_name) || ctx.is_pinned(agent_name, &mut report) {
+            continue;
+        }
+        match resolve_model(agent_name, &agent_config.model, ctx) {
+            Some(model) => agent_config.model = model,
+            None => report.unmapped.push(agent_name.clone()),
+        }
+    }
+    if let Some(subagents) = config.subagents.as_mut() {
+        switch_subagents(subagents.iter_mut(), ctx, &mut report);
+    }
+    switch_top_level(config.extra.iter_mut(), ctx, &mut report);
+
+    report.unmapped.sort();
+    report.pinned.sort();
+    report
+}
+
+/// Conservative variant of [`switch_to_provider`] for configs portal doesn't
+/// fully understand: only string `model` fields under `agents` and
+/// `subagents` (and the top-level model fields) are touched, everything else
+/// in the document is left as-is.
+pub fn switch_document(document: &mut Value, ctx: &SwitchContext) -> SwitchReport {
+    let mut report = SwitchReport::default();
+
+    if let Some(fields) = document.as_object_mut() {
+        switch_top_level(fields.iter_mut(), ctx, &mut report);
+    }
+
+    if let Some(subagents) = document.get_mut("subagents").and_then(Value::as_object_mut) {
+        switch_subagents(subagents.iter_mut(), ctx, &mut report);
+    }
+    let Some(agents) = document.get_mut("agents").and_then(Value::as_object_mut) else {
+        report.unmapped.sort();
+        report.pinned.sort();
+        return report;
+    };
+    for (agent_name, agent) in agents.iter_mut() {
+        if !ctx.includes(agent_name) || ctx.is_pinned(agent_name, &mut report) {
+            continue;
+        }
+        let Some(model) = agent.get_mut("model") else {
+            continue;
+        };
+        let Some(current) = model.as_str() else {
+            continue;
+        };
+        match resolve_model(agent_name, current, ctx) {
+            Some(new_model) => *model = Value::String(new_model),
+            None => report.unmapped.push(agent_name.clone()),
+        }
+    }
+
+    report.unmapped.sort();
+    report.pinned.sort();
+    report
+}
+
+/// Rewrite the model strings at the dotted `fields` paths of an arbitrary
+/// JSON document through the mapping table, the way top-level model fields
+/// are. Fields that are missing or not strings are skipped; ones with no
+/// mapping keep their model and are reported as unmapped. Returns the
+/// before and after model of every field found.
+pub fn switch_fields(
+    document: &mut Value,
+    fields: &[String],
+    ctx: &SwitchContext,
+) -> (SwitchReport, Vec<ModelChange>) {
+    let mut report = SwitchReport::default();
+    let mut changes = Vec::new();
+    for field in fields {
+        let Some(model) = field
+            .split('.')
+            .try_fold(&mut *document, |value, key| value.get_mut(key))
+        else {
+            continue;
+        };
+        let Some(current) = model.as_str().map(str::to_string) else {
+            continue;
+        };
+        let after = match resolve_model(field, &current, ctx) {
+            Some(new_model) => {
+                *model = Value::String(new_model.clone());
+                new_model
+            }
+            None => {
+                report.unmapped.push(field.clone());
+                current.clone()
+            }
+        };
+        changes.push(ModelChange {
+            agent: field.clone(),
+            before: Some(current),
+            after: Some(after),
+        });
+    }
+    (report, changes)
+}
+
+/// Merge the provider config's client options into `provider.<id>.options`
+/// for every opencode provider ID the switched models use. Entries named in
+/// `unmapped` (as [`SwitchReport::unmapped`] names them) and pinned ones
+/// kept their old provider and don't count. Options the provider config
+/// doesn't set are left alone, and no `provider` object is added unless an
+/// ID is written into it. Returns the updated IDs.
+pub fn apply_provider_options(
+    document: &mut Value,
+    ctx: &SwitchContext,
+    unmapped: &[String],
+) -> Vec<String> {
+    let Some(options) = ctx.provider_config.map(|pc| &pc.options) else {
+        return Vec::new();
+    };
+    if options.is_empty() {
+        return Vec::new();
+    }
+    let Ok(Value::Object(options)) = serde_json::to_value(options) else {
+        return Vec::new();
+    };
+
+    let mut models: Vec<(String, String, String)> = config::agent_models(document)
+        .into_iter()
+        .map(|(name, model)| (name.clone(), name, model))
+        .collect();
+    models.extend(
+        config::subagent_models(document)
+            .into_iter()
+            .map(|(name, model)| (format!("subagents.{}", name), name, model)),
+    );
+    if ctx.top_level {
+        models.extend(
+            config::top_level_models(document)
+                .into_iter()
+                .map(|(name, model)| (name.clone(), name, model)),
+        );
+    }
+    let mut ids: Vec<String> = models
+        .iter()
+        .filter(|(reported, name, _)| {
+            ctx.includes(name) && !unmapped.contains(reported) && !ctx.pinned.contains(reported)
+        })
+        .filter_map(|(_, _, model)| model.split_once('/').map(|(id, _)| id.to_string()))
+        .collect();
+    ids.sort();
+    ids.dedup();
+    if ids.is_empty() {
+        return Vec::new();
+    }
+
+    let Some(root) = document.as_object_mut() else {
+        return Vec::new();
+    };
+    let Some(providers) = root
+        .entry("provider")
+        .or_insert_with(|| Value::Object(Map::new()))
+        .as_object_mut()
+    else {
+        return Vec::new();
+    };
+
+    let mut updated = Vec::new();
+    for id in ids {
+        let Some(current) = providers
+            .entry(id.as_str())
+            .or_insert_with(|| Value::Object(Map::new()))
+            .as_object_mut()
+            .and_then(|entry| {
+                entry
+                    .entry("options")
+                    .or_insert_with(|| Value::Object(Map::new()))
+                    .as_object_mut()
+            })
+        else {
+            continue;
+        };
+        let mut changed = false;
+        for (key, value) in &options {
+            if current.get(key) != Some(value) {
+                current.insert(key.clone(), value.clone());
+                changed = true;
+            }
+        }
+        if changed {
+            updated.push(id);
+        }
+    }
+    updated
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::provider::{ProviderConfig, ProviderOptions};
+    use serde_json::json;
+
+    fn with_timeout() -> ProviderConfig {
+        ProviderConfig {
+            options: ProviderOptions {
+                timeout: Some(600000),
+                ..ProviderOptions::default()
+            },
+            ..ProviderConfig::default()
+        }
+    }
+
+    #[test]
+    fn writes_options_for_the_switched_providers() {
+        let mappings = MappingTable::builtin();
+        let config = with_timeout();
+        let ctx = SwitchContext {
+            provider_config: Some(&config),
+            ..SwitchContext::new("work", &mappings)
+        };
+        let mut document = json!({
+            "agents": { "oracle": { "model": "openrouter/openai/gpt-5.2" } },
+        });
+        let updated = apply_provider_options(&mut document, &ctx, &[]);
+        assert_eq!(updated, ["openrouter"]);
+        assert_eq!(
+            document["provider"]["openrouter"]["options"],
+            json!({ "timeout": 600000 })
+        );
+        // Nothing left to change the second time
+        assert!(apply_provider_options(&mut document, &ctx, &[]).is_empty());
+    }
+
+    #[test]
+    fn adds_no_provider_object_when_no_id_qualifies() {
+        let mappings = MappingTable::builtin();
+        let config = with_timeout();
+        let pinned = ["oracle".to_string()];
+        let ctx = SwitchContext {
+            provider_config: Some(&config),
+            pinned: &pinned,
+            ..SwitchContext::new("work", &mappings)
+        };
+        let mut document = json!({
+            "agents": {
+                "oracle": { "model": "openrouter/openai/gpt-5.2" },
+                "librarian": { "model": "anthropic/claude-sonnet-4.5" },
+            },
+        });
+        let original = document.clone();
+        let unmapped = ["librarian".to_string()];
+        assert!(apply_provider_options(&mut document, &ctx, &unmapped).is_empty());
+        assert_eq!(document, original);
+    }
+}
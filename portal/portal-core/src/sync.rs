@@ -0,0 +1,297 @@
+//! Keeping the portal directory in sync across machines through git: once
+//! it is a repository (`portal sync init`), portal fast-forwards it before
+//! a switch and commits and pushes whatever a command changed in it.
+//! Merges that conflict are taken back and reported, so provider files
+//! never hold conflict markers.
+
+use anyhow::{Context, Result, anyhow};
+use serde::Serialize;
+use std::fs;
+use std::path::Path;
+use std::process::Output;
+
+use crate::git::git;
+use crate::rendered::RENDERED_DIR;
+
+/// Ignored in every synced portal directory: renderings are per machine,
+/// and staged writes are only ever there mid-write
+const GITIGNORE: &str = "rendered/\n.*.tmp.*\n";
+
+/// What a pull did
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "result", content = "files", rename_all = "snake_case")]
+pub enum Pull {
+    /// The branch has no upstream to pull from
+    NoUpstream,
+    UpToDate,
+    /// Merged, changing these files
+    Updated(Vec<String>),
+    /// Both sides have commits, and only a fast-forward was allowed
+    Diverged,
+    /// The merge conflicted in these files, so it was taken back
+    Conflicts(Vec<String>),
+}
+
+/// Where a synced portal directory stands against its upstream
+#[derive(Debug, Clone, Serialize)]
+pub struct Status {
+    /// Upstream branch, e.g. `origin/main`
+    pub upstream: Option<String>,
+    /// Local commits not pushed yet
+    pub ahead: usize,
+    /// Upstream commits not pulled yet
+    pub behind: usize,
+    /// Files changed but not committed, as `git status --short` shows them
+    pub changed: Vec<String>,
+}
+
+/// Whether `portal_dir` is a repository of its own
+pub fn is_repo(portal_dir: &Path) -> bool {
+    portal_dir.join(".git").exists()
+}
+
+/// Make `portal_dir` a repository, pushing to (and merging in) `remote` if
+/// given: cloned when there is nothing here yet, otherwise merged with
+/// what is here.
+pub fn init(portal_dir: &Path, remote: Option<&str>) -> Result<()> {
+    let empty = fs::read_dir(portal_dir).map_or(true, |mut entries| entries.next().is_none());
+    if let Some(remote) = remote
+        && empty
+        && !is_repo(portal_dir)
+    {
+        let parent = portal_dir.parent().unwrap_or(Path::new("."));
+        fs::create_dir_all(parent)
+            .with_context(|| format!("Failed to create {}", parent.display()))?;
+        let target = portal_dir.to_string_lossy();
+        checked(
+            git(parent, &["clone", "--quiet", remote, &target])?,
+            "clone",
+        )?;
+        ensure_gitignore(portal_dir)?;
+        return commit_all(portal_dir, "portal: ignore per-machine files").map(drop);
+    }
+
+    fs::create_dir_all(portal_dir)
+        .with_context(|| format!("Failed to create {}", portal_dir.display()))?;
+    if !is_repo(portal_dir) {
+        checked(git(portal_dir, &["init", "--quiet"])?, "init")?;
+    }
+    ensure_gitignore(portal_dir)?;
+    commit_all(portal_dir, "portal: start syncing")?;
+    let Some(remote) = remote else {
+        return Ok(());
+    };
+
+    let remotes = run(portal_dir, &["remote"])?;
+    let verb = if remotes.lines().any(|name| name == "origin") {
+        "set-url"
+    } else {
+        "add"
+    };
+    checked(
+        git(portal_dir, &["remote", verb, "origin", remote])?,
+        "remote",
+    )?;
+    checked(git(portal_dir, &["fetch", "--quiet", "origin"])?, "fetch")?;
+    // A remote that already has a setup: join its history
+    if let Some(branch) = remote_default_branch(portal_dir)? {
+        let upstream = format!("origin/{}", branch);
+        if let Some(conflicts) = merge(portal_dir, &upstream, true)? {
+            return Err(conflict_error(portal_dir, &conflicts));
+        }
+        checked(git(portal_dir, &["branch", "-M", &branch])?, "branch")?;
+        checked(
+            git(portal_dir, &["branch", "--set-upstream-to", &upstream])?,
+            "branch",
+        )?;
+    }
+    push(portal_dir).map(drop)
+}
+
+/// Stage everything and commit it as `message`; `false` if nothing changed
+pub fn commit_all(portal_dir: &Path, message: &str) -> Result<bool> {
+    checked(git(portal_dir, &["add", "--all"])?, "add")?;
+    if git(portal_dir, &["diff", "--cached", "--quiet"])?
+        .status
+        .success()
+    {
+        return Ok(false);
+    }
+    checked(
+        git(portal_dir, &["commit", "--quiet", "-m", message])?,
+        "commit",
+    )?;
+    crate::portal_info!("Committed {}: {}", portal_dir.display(), message);
+    Ok(true)
+}
+
+/// Fetch and merge the upstream; with `merge_diverged` unset, only a
+/// fast-forward
+pub fn pull(portal_dir: &Path, merge_diverged: bool) -> Result<Pull> {
+    let Some(upstream) = upstream(portal_dir)? else {
+        return Ok(Pull::NoUpstream);
+    };
+    checked(git(portal_dir, &["fetch", "--quiet"])?, "fetch")?;
+    if count(portal_dir, "HEAD..@{u}")? == 0 {
+        return Ok(Pull::UpToDate);
+    }
+    let before = run(portal_dir, &["rev-parse", "HEAD"])?;
+    let before = before.trim();
+    if !git(portal_dir, &["merge", "--quiet", "--ff-only", &upstream])?
+        .status
+        .success()
+    {
+        if !merge_diverged {
+            return Ok(Pull::Diverged);
+        }
+        if let Some(conflicts) = merge(portal_dir, &upstream, false)? {
+            return Ok(Pull::Conflicts(conflicts));
+        }
+    }
+    let changed = run(portal_dir, &["diff", "--name-only", before, "HEAD"])?;
+    Ok(Pull::Updated(changed.lines().map(str::to_string).collect()))
+}
+
+/// Push to the upstream, setting it up on the first push to a remote;
+/// `false` when there is no remote
+pub fn push(portal_dir: &Path) -> Result<bool> {
+    if upstream(portal_dir)?.is_some() {
+        checked(git(portal_dir, &["push", "--quiet"])?, "push")?;
+        return Ok(true);
+    }
+    let remotes = run(portal_dir, &["remote"])?;
+    let Some(remote) = remotes.lines().next() else {
+        return Ok(false);
+    };
+    checked(
+        git(
+            portal_dir,
+            &["push", "--quiet", "--set-upstream", remote, "HEAD"],
+        )?,
+        "push",
+    )?;
+    Ok(true)
+}
+
+/// Where the repository stands, after fetching if there is an upstream
+pub fn status(portal_dir: &Path) -> Result<Status> {
+    let upstream = upstream(portal_dir)?;
+    let (mut ahead, mut behind) = (0, 0);
+    if upstream.is_some() {
+        if let Err(e) = checked(git(portal_dir, &["fetch", "--quiet"])?, "fetch") {
+            crate::portal_warn!("couldn't fetch, so the counts may be stale: {:#}", e);
+        }
+        ahead = count(portal_dir, "@{u}..HEAD")?;
+        behind = count(portal_dir, "HEAD..@{u}")?;
+    }
+    let changed = run(portal_dir, &["status", "--short"])?
+        .lines()
+        .map(str::to_string)
+        .collect();
+    Ok(Status {
+        upstream,
+        ahead,
+        behind,
+        changed,
+    })
+}
+
+/// The error for a merge that was taken back
+pub fn conflict_error(portal_dir: &Path, files: &[String]) -> anyhow::Error {
+    anyhow!(
+        "Sync conflicts in: {}\n\nNothing was merged. Resolve them with git in {} \
+         (`git pull`, fix the files, `git commit`), then run `portal sync` again.",
+        files.join(", "),
+        portal_dir.display()
+    )
+}
+
+/// Merge `upstream`; on conflicts take the merge back and return the
+/// conflicting files
+fn merge(portal_dir: &Path, upstream: &str, unrelated: bool) -> Result<Option<Vec<String>>> {
+    let mut args = vec!["merge", "--quiet", "--no-edit"];
+    if unrelated {
+        args.push("--allow-unrelated-histories");
+    }
+    args.push(upstream);
+    let merged = git(portal_dir, &args)?;
+    if merged.status.success() {
+        return Ok(None);
+    }
+    let conflicts: Vec<String> = run(portal_dir, &["diff", "--name-only", "--diff-filter=U"])?
+        .lines()
+        .map(str::to_string)
+        .collect();
+    if conflicts.is_empty() {
+        return Err(git_failure("merge", &merged));
+    }
+    checked(git(portal_dir, &["merge", "--abort"])?, "merge --abort")?;
+    Ok(Some(conflicts))
+}
+
+fn upstream(portal_dir: &Path) -> Result<Option<String>> {
+    let output = git(
+        portal_dir,
+        &["rev-parse", "--abbrev-ref", "--symbolic-full-name", "@{u}"],
+    )?;
+    Ok(output
+        .status
+        .success()
+        .then(|| String::from_utf8_lossy(&output.stdout).trim().to_string()))
+}
+
+/// The branch `origin/HEAD` points at, if the remote has any branches
+fn remote_default_branch(portal_dir: &Path) -> Result<Option<String>> {
+    let listing = run(portal_dir, &["ls-remote", "--symref", "origin", "HEAD"])?;
+    Ok(listing.lines().find_map(|line| {
+        let target = line.strip_prefix("ref: refs/heads/")?;
+        Some(target.split_whitespace().next()?.to_string())
+    }))
+}
+
+fn count(portal_dir: &Path, range: &str) -> Result<usize> {
+    let counted = run(portal_dir, &["rev-list", "--count", range])?;
+    counted
+        .trim()
+        .parse()
+        .with_context(|| format!("Unexpected output from git rev-list: {}", counted.trim()))
+}
+
+fn ensure_gitignore(portal_dir: &Path) -> Result<()> {
+    let path = portal_dir.join(".gitignore");
+    let existing = fs::read_to_string(&path).unwrap_or_default();
+    if existing
+        .lines()
+        .any(|line| line.trim_end_matches('/') == RENDERED_DIR)
+    {
+        return Ok(());
+    }
+    let mut contents = existing;
+    if !contents.is_empty() && !contents.ends_with('\n') {
+        contents.push('\n');
+    }
+    contents.push_str(GITIGNORE);
+    fs::write(&path, contents).with_context(|| format!("Failed to write {}", path.display()))
+}
+
+/// Stdout of a git command that has to succeed
+fn run(portal_dir: &Path, args: &[&str]) -> Result<String> {
+    let output = checked(git(portal_dir, args)?, args[0])?;
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+fn checked(output: Output, action: &str) -> Result<Output> {
+    if output.status.success() {
+        Ok(output)
+    } else {
+        Err(git_failure(action, &output))
+    }
+}
+
+fn git_failure(action: &str, output: &Output) -> anyhow::Error {
+    anyhow!(
+        "git {} failed: {}",
+        action,
+        String::from_utf8_lossy(&output.stderr).trim()
+    )
+}
@@ -0,0 +1,269 @@
+//! Switches that only last until a given time or the next reboot, after
+//! which the daemon, or the next portal command, puts the config back.
+
+use anyhow::{Context, Result, anyhow};
+use chrono::{DateTime, Duration, Local, NaiveDate, NaiveDateTime, NaiveTime, TimeZone, Utc};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::clock::{self, ClockStamp};
+use crate::error::PortalError;
+use crate::fsutil;
+
+/// Directory in the state directory holding one record per config with a
+/// temporary switch in effect
+pub const TEMPORARY_DIR: &str = "temporary";
+
+/// When a temporary switch ends
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Expiry {
+    At(DateTime<Utc>),
+    Reboot,
+}
+
+impl Expiry {
+    /// "until 2026-01-31 17:30" / "until reboot", for messages
+    pub fn describe(&self) -> String {
+        match self {
+            Self::At(until) => format!(
+                "until {}",
+                until.with_timezone(&Local).format("%Y-%m-%d %H:%M")
+            ),
+            Self::Reboot => "until reboot".to_string(),
+        }
+    }
+}
+
+/// A temporary switch waiting to be reverted
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TemporarySwitch {
+    pub config: PathBuf,
+    pub provider: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub previous_provider: Option<String>,
+    pub switched_at: DateTime<Utc>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub until: Option<DateTime<Utc>>,
+    /// Boot the switch was made in, for `--until-reboot`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub boot_id: Option<String>,
+    /// When the switch was made by the time since boot, so a wall clock
+    /// changed since (by hand, or on waking from sleep) doesn't end it
+    /// early or keep it going
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub clock: Option<ClockStamp>,
+    /// Config text to put back
+    pub before: String,
+    /// SHA-256 of the config right after the switch; if it no longer
+    /// matches, something else has written the config since and it is not
+    /// reverted
+    pub after_hash: String,
+}
+
+impl TemporarySwitch {
+    pub fn expiry(&self) -> Expiry {
+        match self.until {
+            Some(until) => Expiry::At(until),
+            None => Expiry::Reboot,
+        }
+    }
+
+    pub fn is_due(&self, now: DateTime<Utc>) -> bool {
+        match (&self.until, &self.boot_id) {
+            (Some(until), _) => {
+                *until <= clock::trusted_now(self.switched_at, self.clock.as_ref(), now)
+            }
+            // Without a boot ID to compare, a reboot can't be told apart
+            (None, Some(boot_id)) => clock::current_boot_id().is_some_and(|id| id != *boot_id),
+            (None, None) => false,
+        }
+    }
+}
+
+/// What became of a temporary switch whose time was up
+#[derive(Debug, Clone)]
+pub struct Expired {
+    pub record: TemporarySwitch,
+    /// `false` if the config had been changed since, so it was left alone
+    pub reverted: bool,
+    /// Problems after putting the config back, such as a failing
+    /// post-switch hook
+    pub warnings: Vec<String>,
+}
+
+pub fn record_path(state_dir: &Path, key: &str) -> PathBuf {
+    state_dir.join(TEMPORARY_DIR).join(format!("{}.json", key))
+}
+
+pub fn read_record(path: &Path) -> Result<Option<TemporarySwitch>> {
+    match fsutil::read_to_string(path) {
+        Ok(content) => Ok(Some(
+            serde_json::from_str(&content).map_err(PortalError::parse("temporary switch", path))?,
+        )),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(e) => {
+            Err(e).with_context(|| format!("Failed to read temporary switch: {}", path.display()))
+        }
+    }
+}
+
+pub fn write_record(path: &Path, record: &TemporarySwitch) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)
+            .with_context(|| format!("Failed to create directory: {}", parent.display()))?;
+    }
+    let content = serde_json::to_string_pretty(record)?;
+    fsutil::write_atomic(path, content.as_bytes())
+        .with_context(|| format!("Failed to write temporary switch: {}", path.display()))
+}
+
+pub fn remove_record(path: &Path) -> Result<()> {
+    match fs::remove_file(path) {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+            Err(e).with_context(|| format!("Failed to remove temporary switch: {}", path.display()))
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Every record in the state directory, whichever config it is for
+pub fn record_paths(state_dir: &Path) -> Vec<PathBuf> {
+    let Ok(entries) = fs::read_dir(state_dir.join(TEMPORARY_DIR)) else {
+        return Vec::new();
+    };
+    let mut paths: Vec<PathBuf> = entries
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter(|path| path.extension().is_some_and(|ext| ext == "json"))
+        .collect();
+    paths.sort();
+    paths
+}
+
+/// Parse an `--until` value: a duration from now (`90m`, `2h`, `1h30m`,
+/// `1d`), a local time of day (`17:30`, the next one to come), a local
+/// date and time (`2026-01-31 17:30`), or an RFC 3339 timestamp
+pub fn parse_until(text: &str, now: DateTime<Local>) -> Result<DateTime<Utc>> {
+    let text = text.trim();
+    let at = if let Some(duration) = parse_duration(text) {
+        now + duration
+    } else if let Ok(time) = DateTime::parse_from_rfc3339(text) {
+        time.with_timezone(&Local)
+    } else if let Ok(time) = NaiveTime::parse_from_str(text, "%H:%M") {
+        let today = local(now.date_naive().and_time(time))?;
+        if today > now {
+            today
+        } else {
+            local((now.date_naive() + Duration::days(1)).and_time(time))?
+        }
+    } else if let Some(datetime) = ["%Y-%m-%d %H:%M", "%Y-%m-%dT%H:%M"]
+        .iter()
+        .find_map(|format| NaiveDateTime::parse_from_str(text, format).ok())
+    {
+        local(datetime)?
+    } else if let Ok(date) = NaiveDate::parse_from_str(text, "%Y-%m-%d") {
+        local(date.and_time(NaiveTime::MIN))?
+    } else {
+        return Err(anyhow!(
+            "Can't read '{}' as a time: use a duration (90m, 2h, 1d), \
+             a time of day (17:30), a date and time (2026-01-31 17:30), \
+             or an RFC 3339 timestamp",
+            text
+        ));
+    };
+    if at <= now {
+        return Err(anyhow!("'{}' is in the past", text));
+    }
+    Ok(at.with_timezone(&Utc))
+}
+
+fn local(datetime: NaiveDateTime) -> Result<DateTime<Local>> {
+    Local
+        .from_local_datetime(&datetime)
+        .earliest()
+        .ok_or_else(|| anyhow!("{} doesn't exist in the local time zone", datetime))
+}
+
+/// `90m`, `2h`, `1h30m`, `1d`, `45s`
+fn parse_duration(text: &str) -> Option<Duration> {
+    let mut total = Duration::zero();
+    let mut digits = String::new();
+    for c in text.chars() {
+        if c.is_ascii_digit() {
+            digits.push(c);
+            continue;
+        }
+        let n: i64 = digits.parse().ok()?;
+        digits.clear();
+        total += match c {
+            's' => Duration::seconds(n),
+            'm' => Duration::minutes(n),
+            'h' => Duration::hours(n),
+            'd' => Duration::days(n),
+            _ => return None,
+        };
+    }
+    (digits.is_empty() && !text.is_empty()).then_some(total)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn noon() -> DateTime<Local> {
+        Local.with_ymd_and_hms(2026, 1, 15, 12, 0, 0).unwrap()
+    }
+
+    fn at(day: u32, hour: u32, minute: u32) -> DateTime<Utc> {
+        Local
+            .with_ymd_and_hms(2026, 1, day, hour, minute, 0)
+            .unwrap()
+            .with_timezone(&Utc)
+    }
+
+    #[test]
+    fn durations_count_from_now() {
+        assert_eq!(parse_until("90m", noon()).unwrap(), at(15, 13, 30));
+        assert_eq!(parse_until("1h30m", noon()).unwrap(), at(15, 13, 30));
+        assert_eq!(parse_until(" 1d ", noon()).unwrap(), at(16, 12, 0));
+        assert!(parse_until("0m", noon()).is_err());
+        assert!(parse_until("5", noon()).is_err());
+        assert!(parse_until("5w", noon()).is_err());
+    }
+
+    #[test]
+    fn a_time_of_day_is_the_next_one_to_come() {
+        assert_eq!(parse_until("17:30", noon()).unwrap(), at(15, 17, 30));
+        assert_eq!(parse_until("09:00", noon()).unwrap(), at(16, 9, 0));
+        assert_eq!(parse_until("12:00", noon()).unwrap(), at(16, 12, 0));
+    }
+
+    #[test]
+    fn dates_and_timestamps() {
+        assert_eq!(
+            parse_until("2026-01-31 17:30", noon()).unwrap(),
+            at(31, 17, 30)
+        );
+        assert_eq!(
+            parse_until("2026-01-31T17:30", noon()).unwrap(),
+            at(31, 17, 30)
+        );
+        assert_eq!(parse_until("2026-01-20", noon()).unwrap(), at(20, 0, 0));
+        assert_eq!(
+            parse_until("2026-01-31T17:30:00Z", noon()).unwrap(),
+            Utc.with_ymd_and_hms(2026, 1, 31, 17, 30, 0).unwrap()
+        );
+    }
+
+    #[test]
+    fn rejects_the_past_and_the_unreadable() {
+        let past = parse_until("2026-01-01 09:00", noon()).unwrap_err();
+        assert_eq!(past.to_string(), "'2026-01-01 09:00' is in the past");
+        let unreadable = parse_until("tomorrow", noon()).unwrap_err();
+        assert!(
+            unreadable
+                .to_string()
+                .starts_with("Can't read 'tomorrow' as a time")
+        );
+    }
+}
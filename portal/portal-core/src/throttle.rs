@@ -0,0 +1,88 @@
+//! Keeping long-running modes (`watch`, the daemon) from logging the same
+//! warning over and over. The first occurrence is logged; repeats within
+//! the window are only counted, and summed up in one line when it ends.
+
+use std::collections::BTreeMap;
+use std::time::{Duration, Instant};
+
+/// How long repeats of a warning are held back
+pub const REPEAT_WINDOW: Duration = Duration::from_secs(10 * 60);
+
+#[derive(Debug, Clone)]
+struct Seen {
+    since: Instant,
+    repeats: u32,
+}
+
+/// Warnings logged recently, with how often each came up again since
+#[derive(Debug, Clone)]
+pub struct WarningThrottle {
+    window: Duration,
+    seen: BTreeMap<String, Seen>,
+}
+
+impl Default for WarningThrottle {
+    fn default() -> Self {
+        Self::new(REPEAT_WINDOW)
+    }
+}
+
+impl WarningThrottle {
+    pub fn new(window: Duration) -> Self {
+        Self {
+            window,
+            seen: BTreeMap::new(),
+        }
+    }
+
+    /// Whether `message` should be logged now. Repeats within the window
+    /// are counted instead.
+    pub fn admit(&mut self, message: &str) -> bool {
+        let now = Instant::now();
+        match self.seen.get_mut(message) {
+            Some(seen) if now.duration_since(seen.since) < self.window => {
+                seen.repeats += 1;
+                false
+            }
+            _ => {
+                self.seen.insert(
+                    message.to_string(),
+                    Seen {
+                        since: now,
+                        repeats: 0,
+                    },
+                );
+                true
+            }
+        }
+    }
+
+    /// One line for each warning whose window just ended after it came up
+    /// again. Those stay held back for another window; the others are
+    /// forgotten, so they are logged in full the next time.
+    pub fn summaries(&mut self) -> Vec<String> {
+        let now = Instant::now();
+        let window = self.window;
+        let mut lines = Vec::new();
+        self.seen.retain(|message, seen| {
+            if now.duration_since(seen.since) < window {
+                return true;
+            }
+            if seen.repeats == 0 {
+                return false;
+            }
+            lines.push(format!(
+                "Repeated {} more time(s) in the last {}m: {}",
+                seen.repeats,
+                window.as_secs().div_ceil(60),
+                message
+            ));
+            *seen = Seen {
+                since: now,
+                repeats: 0,
+            };
+            true
+        });
+        lines
+    }
+}
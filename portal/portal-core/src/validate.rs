@@ -0,0 +1,261 @@
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
+
+use crate::config;
+use crate::doctor::Severity;
+use crate::portal::Portal;
+use crate::provider;
+use crate::schema;
+
+/// A structural problem in the config or a provider file
+#[derive(Debug, Clone, Serialize)]
+pub struct Issue {
+    pub severity: Severity,
+    pub file: PathBuf,
+    /// Dotted path to the offending value, e.g. `agents.oracle.model`
+    pub path: String,
+    pub message: String,
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ValidateOptions {
+    /// Also check the config against the schema its `$schema` names, which
+    /// may mean fetching it
+    pub schema: bool,
+}
+
+/// What's wrong with a `provider/model` string, if anything
+pub fn model_problem(model: &str) -> Option<&'static str> {
+    if model.trim().is_empty() {
+        return Some("model is empty");
+    }
+    if model.chars().any(char::is_whitespace) {
+        return Some("model contains whitespace");
+    }
+    if !model.contains('/') {
+        return Some("model has no provider prefix (expected provider/model)");
+    }
+    if model.split('/').any(str::is_empty) {
+        return Some("model has an empty provider or model segment");
+    }
+    None
+}
+
+fn issue(severity: Severity, file: &Path, path: String, message: impl Into<String>) -> Issue {
+    Issue {
+        severity,
+        file: file.to_path_buf(),
+        path,
+        message: message.into(),
+    }
+}
+
+/// Every model in a config document, keyed by its dotted path
+fn config_models(document: &Value) -> Vec<(String, &Value)> {
+    let mut models = Vec::new();
+    for field in config::TOP_LEVEL_MODEL_FIELDS {
+        if let Some(model) = document.get(*field) {
+            models.push((field.to_string(), model));
+        }
+    }
+    for section in ["agents", "subagents"] {
+        let Some(entries) = document.get(section).and_then(Value::as_object) else {
+            continue;
+        };
+        for (name, entry) in entries {
+            if let Some(model) = entry.get("model") {
+                models.push((format!("{}.{}.model", section, name), model));
+            }
+        }
+    }
+    models
+}
+
+fn check_model(file: &Path, path: String, model: &Value, issues: &mut Vec<Issue>) {
+    match model.as_str() {
+        Some(model) => {
+            if let Some(problem) = model_problem(model) {
+                issues.push(issue(Severity::Error, file, path, problem));
+            }
+        }
+        None => issues.push(issue(Severity::Error, file, path, "model is not a string")),
+    }
+}
+
+/// Check the config and every custom provider file
+pub fn validate(portal: &Portal, options: ValidateOptions) -> Vec<Issue> {
+    let mut issues = Vec::new();
+    let config_path = portal.config_path();
+
+    let document = match config::read_document(config_path) {
+        Ok(document) => Some(document),
+        Err(e) => {
+            issues.push(issue(
+                Severity::Error,
+                config_path,
+                String::new(),
+                format!("{:#}", e),
+            ));
+            None
+        }
+    };
+
+    if let Some(document) = &document {
+        for section in ["agents", "subagents"] {
+            let Some(entries) = document.get(section).and_then(Value::as_object) else {
+                continue;
+            };
+            for (name, entry) in entries {
+                if !entry.is_object() {
+                    issues.push(issue(
+                        Severity::Error,
+                        config_path,
+                        format!("{}.{}", section, name),
+                        "agent entry is not an object",
+                    ));
+                } else if section == "agents" && entry.get("model").is_none() {
+                    issues.push(issue(
+                        Severity::Error,
+                        config_path,
+                        format!("{}.{}", section, name),
+                        "agent has no model",
+                    ));
+                }
+            }
+        }
+        for (path, model) in config_models(document) {
+            check_model(config_path, path, model, &mut issues);
+        }
+
+        if options.schema {
+            match document.get("$schema").and_then(Value::as_str) {
+                Some(reference) => {
+                    let base = config_path.parent().unwrap_or(Path::new("."));
+                    match schema::load(reference, base) {
+                        Ok(schema) => {
+                            for violation in schema::validate(&schema, document) {
+                                issues.push(issue(
+                                    Severity::Error,
+                                    config_path,
+                                    violation.path,
+                                    format!("schema: {}", violation.message),
+                                ));
+                            }
+                        }
+                        Err(e) => issues.push(issue(
+                            Severity::Warning,
+                            config_path,
+                            "$schema".to_string(),
+                            format!("{:#}", e),
+                        )),
+                    }
+                }
+                None => issues.push(issue(
+                    Severity::Warning,
+                    config_path,
+                    "$schema".to_string(),
+                    "config declares no $schema to validate against",
+                )),
+            }
+        }
+    }
+
+    // Names a provider file may set: whatever the config has a model slot for
+    let known: Option<BTreeSet<String>> = document.as_ref().map(|document| {
+        config::agent_models(document)
+            .into_iter()
+            .chain(config::subagent_models(document))
+            .chain(config::top_level_models(document))
+            .map(|(name, _)| name)
+            .collect()
+    });
+
+    let names = match provider::list_custom_providers(portal.portal_dir()) {
+        Ok(names) => names,
+        Err(e) => {
+            issues.push(issue(
+                Severity::Error,
+                portal.portal_dir(),
+                String::new(),
+                format!("{:#}", e),
+            ));
+            Vec::new()
+        }
+    };
+    for name in names {
+        let path = provider::provider_config_path(portal.portal_dir(), &name);
+        let provider_config = match provider::read_provider_config(portal.portal_dir(), &name) {
+            Ok(Some(provider_config)) => provider_config,
+            Ok(None) => continue,
+            Err(e) => {
+                issues.push(issue(
+                    Severity::Error,
+                    &path,
+                    String::new(),
+                    format!("{:#}", e),
+                ));
+                continue;
+            }
+        };
+        if provider_config.extends.is_some()
+            && let Err(e) = portal.provider_config(&name)
+        {
+            issues.push(issue(
+                Severity::Error,
+                &path,
+                "extends".to_string(),
+                format!("{:#}", e),
+            ));
+        }
+        for (index, rule) in provider_config.rules.iter().enumerate() {
+            let problem = if rule.pattern.trim().is_empty() {
+                Some("rule has an empty match pattern")
+            } else {
+                model_problem(&rule.template.replace("{model}", "model"))
+            };
+            if let Some(problem) = problem {
+                issues.push(issue(
+                    Severity::Error,
+                    &path,
+                    format!("rules[{}]", index),
+                    problem,
+                ));
+            }
+        }
+        for (agent, agent_config) in &provider_config.agents {
+            if let Some(problem) = model_problem(&agent_config.model) {
+                issues.push(issue(
+                    Severity::Error,
+                    &path,
+                    format!("agents.{}.model", agent),
+                    problem,
+                ));
+            }
+            if let Some(known) = &known
+                && provider::is_agent_pattern(agent)
+            {
+                if !known.iter().any(|name| provider::glob_matches(agent, name)) {
+                    issues.push(issue(
+                        Severity::Warning,
+                        &path,
+                        format!("agents.{}", agent),
+                        "pattern matches no agent in the config, so switching ignores it",
+                    ));
+                }
+            } else if let Some(known) = &known
+                && !known.contains(agent)
+            {
+                issues.push(issue(
+                    Severity::Warning,
+                    &path,
+                    format!("agents.{}", agent),
+                    "unknown agent: not in the config, so switching ignores it",
+                ));
+            }
+        }
+    }
+
+    issues
+}
@@ -0,0 +1,108 @@
+//! Zstandard compression by shelling out to `zstd`, as [`crate::http`]
+//! does with curl, plus the skippable frames that carry data `zstd -d`
+//! passes over.
+
+use anyhow::{Context, Result, anyhow};
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+/// First bytes of a compressed frame
+const FRAME_MAGIC: [u8; 4] = [0x28, 0xb5, 0x2f, 0xfd];
+
+/// First bytes of the skippable frames written here; decoders ignore
+/// anything from `0x184D2A50` to `0x184D2A5F`
+const SKIPPABLE_MAGIC: [u8; 4] = [0x50, 0x2a, 0x4d, 0x18];
+
+/// Whether `data` starts with a zstd frame, skippable or not
+pub fn is_compressed(data: &[u8]) -> bool {
+    data.get(..4).is_some_and(|magic| {
+        magic == FRAME_MAGIC || (magic[1..] == SKIPPABLE_MAGIC[1..] && magic[0] & 0xf0 == 0x50)
+    })
+}
+
+pub fn compress(data: &[u8]) -> Result<Vec<u8>> {
+    run(&["-q", "-c", "-19"], data, "compress")
+}
+
+/// `data` decompressed, skipping skippable frames
+pub fn decompress(data: &[u8]) -> Result<Vec<u8>> {
+    run(&["-q", "-d", "-c"], data, "decompress")
+}
+
+/// A skippable frame holding `payload`
+pub fn skippable_frame(payload: &[u8]) -> Vec<u8> {
+    let mut frame = SKIPPABLE_MAGIC.to_vec();
+    frame.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+    frame.extend_from_slice(payload);
+    frame
+}
+
+/// The payload of the skippable frame `data` starts with, if it does
+pub fn leading_skippable_frame(data: &[u8]) -> Option<&[u8]> {
+    let rest = data.strip_prefix(&SKIPPABLE_MAGIC[..])?;
+    let (len, rest) = rest.split_at_checked(4)?;
+    let len = u32::from_le_bytes(len.try_into().ok()?) as usize;
+    rest.get(..len)
+}
+
+fn run(args: &[&str], input: &[u8], action: &str) -> Result<Vec<u8>> {
+    let mut child = Command::new("zstd")
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .with_context(|| format!("Failed to run zstd to {}", action))?;
+    let mut stdin = child.stdin.take().expect("stdin is piped");
+    let input = input.to_vec();
+    // Fed from another thread, so a full stdout pipe can't stall both sides
+    let writer = std::thread::spawn(move || stdin.write_all(&input));
+    let output = child
+        .wait_with_output()
+        .with_context(|| format!("Failed to run zstd to {}", action))?;
+    let written = writer.join().expect("zstd input thread panicked");
+    if !output.status.success() {
+        return Err(anyhow!(
+            "zstd failed to {}: {}",
+            action,
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    written.with_context(|| format!("Failed to pass data to zstd to {}", action))?;
+    Ok(output.stdout)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_back_a_skippable_frame() {
+        let frame = skippable_frame(b"{\"provider\":\"work\"}");
+        assert_eq!(&frame[4..8], &19u32.to_le_bytes());
+        assert!(is_compressed(&frame));
+        let mut data = frame.clone();
+        data.extend_from_slice(&FRAME_MAGIC);
+        assert_eq!(
+            leading_skippable_frame(&data),
+            Some(&b"{\"provider\":\"work\"}"[..])
+        );
+    }
+
+    #[test]
+    fn recognises_frames() {
+        assert!(is_compressed(&[0x28, 0xb5, 0x2f, 0xfd, 0x00]));
+        // Any skippable magic number, not just the one written here
+        assert!(is_compressed(&[0x5f, 0x2a, 0x4d, 0x18]));
+        assert!(!is_compressed(b"{}"));
+        assert!(!is_compressed(&[0x28, 0xb5]));
+    }
+
+    #[test]
+    fn ignores_truncated_frames() {
+        let frame = skippable_frame(b"payload");
+        assert_eq!(leading_skippable_frame(&frame[..frame.len() - 1]), None);
+        assert_eq!(leading_skippable_frame(&frame[..6]), None);
+        assert_eq!(leading_skippable_frame(&FRAME_MAGIC), None);
+    }
+}
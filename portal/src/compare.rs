@@ -0,0 +1,184 @@
+//! `portal compare`: two providers side by side, agent by agent.
+
+use anyhow::{Result, anyhow};
+use portal_core::Portal;
+use portal_core::graph;
+use portal_core::provider;
+use portal_core::switch::Resolution;
+use serde_json::json;
+
+use crate::style::{self, Color, paint};
+use crate::{Cli, Format, print_json};
+
+/// Print what switching to each provider would give each agent, only for
+/// the agents they disagree on with `differences`
+pub fn run(
+    cli: &Cli,
+    portal: &Portal,
+    provider_a: &str,
+    provider_b: &str,
+    differences: bool,
+) -> Result<()> {
+    let mut providers = Vec::new();
+    for name in [provider_a, provider_b] {
+        let provider = portal.resolve_provider(name)?;
+        if !provider::is_builtin_name(&provider) && portal.provider_config(&provider)?.is_none() {
+            return Err(anyhow!("Unknown provider: '{}'", provider));
+        }
+        providers.push(provider);
+    }
+    let graph = portal.graph(&providers)?;
+    let (a, b) = (&providers[0], &providers[1]);
+
+    // Each agent's current model and its route on each provider
+    let rows: Vec<_> = graph
+        .agents
+        .iter()
+        .map(|agent| {
+            let route = |provider: &str| {
+                graph
+                    .routes
+                    .iter()
+                    .find(|r| r.agent == agent.name && r.provider == provider)
+            };
+            (agent, route(a), route(b))
+        })
+        .collect();
+    let model = |route: Option<&graph::Route>| route.and_then(|r| r.model.clone());
+    let differ =
+        |(_, ra, rb): &&(_, Option<&graph::Route>, Option<&graph::Route>)| model(*ra) != model(*rb);
+    let shown: Vec<_> = rows
+        .iter()
+        .filter(|row| !differences || differ(row))
+        .collect();
+    // Agents one provider maps and the other doesn't
+    let only = |of: usize| -> Vec<String> {
+        rows.iter()
+            .filter(|(_, ra, rb)| {
+                let (this, other) = if of == 0 { (ra, rb) } else { (rb, ra) };
+                model(*this).is_some() && model(*other).is_none()
+            })
+            .map(|(agent, _, _)| agent.name.clone())
+            .collect()
+    };
+    let (only_a, only_b) = (only(0), only(1));
+
+    if cli.format == Format::Json {
+        let route_json = |route: Option<&graph::Route>| {
+            json!({
+                "model": route.and_then(|r| r.model.as_deref()),
+                "resolution": route.and_then(|r| r.resolution),
+            })
+        };
+        return print_json(&json!({
+            "providers": [a, b],
+            "agents": shown
+                .iter()
+                .map(|(agent, ra, rb)| json!({
+                    "agent": agent.name,
+                    "model": agent.model,
+                    "base": agent.base,
+                    a.as_str(): route_json(*ra),
+                    b.as_str(): route_json(*rb),
+                    "same": model(*ra) == model(*rb),
+                }))
+                .collect::<Vec<_>>(),
+            "differences": rows.iter().filter(differ).count(),
+            "only_mapped_by": { a.as_str(): only_a, b.as_str(): only_b },
+        }));
+    }
+
+    let cell = |route: Option<&graph::Route>| match route.and_then(|r| r.model.as_deref()) {
+        Some(model) => match route.and_then(|r| r.resolution) {
+            Some(Resolution::ProviderFile) => format!("{} (provider file)", model),
+            Some(Resolution::Rule) => format!("{} (rule)", model),
+            Some(Resolution::Guessed) => format!("{} (guessed)", model),
+            Some(Resolution::Mapped) | None => model.to_string(),
+        },
+        None => "(no mapping)".to_string(),
+    };
+    let table: Vec<[String; 4]> = shown
+        .iter()
+        .map(|(agent, ra, rb)| {
+            [
+                agent.name.clone(),
+                agent.model.clone(),
+                cell(*ra),
+                cell(*rb),
+            ]
+        })
+        .collect();
+    let header = [
+        "Agent".to_string(),
+        "Current".to_string(),
+        a.clone(),
+        b.clone(),
+    ];
+
+    if cli.format == Format::Markdown {
+        println!("| {} |", header.join(" | "));
+        println!("|---|---|---|---|");
+        for row in &table {
+            println!("| {} |", row.join(" | "));
+        }
+    } else if style::is_plain() {
+        for (row, (_, ra, rb)) in table.iter().zip(&shown) {
+            let same = if model(*ra) == model(*rb) {
+                "same"
+            } else {
+                "differs"
+            };
+            println!("{} ({}), {}:", row[0], row[1], same);
+            println!("  {}: {}", a, row[2]);
+            println!("  {}: {}", b, row[3]);
+        }
+    } else {
+        let mut widths = header.clone().map(|h| h.len());
+        for row in &table {
+            for (width, value) in widths.iter_mut().zip(row) {
+                *width = (*width).max(value.len());
+            }
+        }
+        let line = |row: &[String; 4], marker: &str| {
+            format!(
+                "{} {:<w0$}  {:<w1$}  {:<w2$}  {}",
+                marker,
+                row[0],
+                row[1],
+                row[2],
+                row[3],
+                w0 = widths[0],
+                w1 = widths[1],
+                w2 = widths[2],
+            )
+        };
+        println!("{}", paint(line(&header, " ").trim_end(), Color::Dim));
+        for (row, shown_row) in table.iter().zip(&shown) {
+            if differ(shown_row) {
+                println!("{}", line(row, "*").trim_end());
+            } else {
+                println!("{}", line(row, " ").trim_end());
+            }
+        }
+    }
+
+    if cli.format != Format::Markdown {
+        println!();
+        println!(
+            "{} of {} agents differ.",
+            rows.iter().filter(differ).count(),
+            rows.len()
+        );
+        for (provider, other, agents) in [(a, b, &only_a), (b, a, &only_b)] {
+            if !agents.is_empty() {
+                println!(
+                    "Only {} maps: {} ({} leaves them unchanged)",
+                    provider,
+                    agents.join(", "),
+                    other
+                );
+            }
+        }
+    }
+    Ok(())
+}
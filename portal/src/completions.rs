@@ -0,0 +1,519 @@
+//! Shell completion scripts generated from the clap command tree.
+//!
+//! Provider arguments complete dynamically by calling `portal list --names`,
+//! so custom providers in ~/.config/portal/ show up without regenerating.
+
+use clap::builder::PossibleValue;
+use clap::{Arg, Command, ValueEnum, ValueHint};
+use std::fmt::Write;
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum Shell {
+    Bash,
+    Zsh,
+    Fish,
+}
+
+/// Positional arguments that take a provider name
+fn is_provider_arg(arg: &Arg) -> bool {
+    let id = arg.get_id().as_str();
+    id == "provider" || id.starts_with("provider_")
+}
+
+fn takes_value(arg: &Arg) -> bool {
+    arg.get_action().takes_values()
+}
+
+fn is_path(arg: &Arg) -> bool {
+    matches!(
+        arg.get_value_hint(),
+        ValueHint::AnyPath | ValueHint::FilePath | ValueHint::DirPath
+    )
+}
+
+fn possible_values(arg: &Arg) -> Vec<String> {
+    arg.get_possible_values()
+        .iter()
+        .filter(|v| !v.is_hide_set())
+        .map(PossibleValue::get_name)
+        .map(str::to_string)
+        .collect()
+}
+
+fn help(arg: &Arg) -> String {
+    arg.get_help()
+        .map(|h| h.to_string())
+        .unwrap_or_default()
+        .replace('\n', " ")
+}
+
+fn about(cmd: &Command) -> String {
+    cmd.get_about()
+        .map(|h| h.to_string())
+        .unwrap_or_default()
+        .replace('\n', " ")
+}
+
+fn options(cmd: &Command) -> impl Iterator<Item = &Arg> {
+    cmd.get_arguments()
+        .filter(|a| !a.is_positional() && !a.is_hide_set())
+}
+
+fn positionals(cmd: &Command) -> impl Iterator<Item = &Arg> {
+    cmd.get_positionals().filter(|a| !a.is_hide_set())
+}
+
+fn subcommands(cmd: &Command) -> impl Iterator<Item = &Command> {
+    cmd.get_subcommands()
+        .filter(|c| !c.is_hide_set() && c.get_name() != "help")
+}
+
+/// Every command with its path from the root, parents before children
+fn walk<'a>(cmd: &'a Command, path: Vec<&'a str>, out: &mut Vec<(Vec<&'a str>, &'a Command)>) {
+    out.push((path.clone(), cmd));
+    for sub in subcommands(cmd) {
+        let mut sub_path = path.clone();
+        sub_path.push(sub.get_name());
+        walk(sub, sub_path, out);
+    }
+}
+
+pub fn generate(shell: Shell, mut cmd: Command) -> String {
+    cmd.build();
+    let mut commands = Vec::new();
+    let name = cmd.get_name().to_string();
+    walk(&cmd, vec![name.as_str()], &mut commands);
+
+    match shell {
+        Shell::Bash => bash(&name, &commands),
+        Shell::Zsh => zsh(&name, &commands),
+        Shell::Fish => fish(&name, &commands),
+    }
+}
+
+// ============================================================================
+// Bash
+// ============================================================================
+
+fn bash(name: &str, commands: &[(Vec<&str>, &Command)]) -> String {
+    let func = format!("_{}", name.replace('-', "_"));
+    let mut out = String::new();
+
+    writeln!(out, "{}() {{", func).unwrap();
+    writeln!(out, "    local cur prev cmd i").unwrap();
+    writeln!(out, "    cur=\"${{COMP_WORDS[COMP_CWORD]}}\"").unwrap();
+    writeln!(out, "    prev=\"${{COMP_WORDS[COMP_CWORD-1]}}\"").unwrap();
+    writeln!(out, "    cmd=\"{}\"", name).unwrap();
+    writeln!(out).unwrap();
+    writeln!(out, "    for ((i = 1; i < COMP_CWORD; i++)); do").unwrap();
+    writeln!(out, "        case \"${{cmd}}__${{COMP_WORDS[i]}}\" in").unwrap();
+    for (path, _) in commands.iter().skip(1) {
+        let parent = path[..path.len() - 1].join("__");
+        let this = path.join("__");
+        writeln!(
+            out,
+            "            {}__{}) cmd=\"{}\" ;;",
+            parent,
+            path[path.len() - 1],
+            this
+        )
+        .unwrap();
+    }
+    writeln!(out, "        esac").unwrap();
+    writeln!(out, "    done").unwrap();
+    writeln!(out).unwrap();
+    writeln!(out, "    case \"${{cmd}}\" in").unwrap();
+
+    for (path, cmd) in commands {
+        writeln!(out, "        {})", path.join("__")).unwrap();
+
+        // Values for the option just typed
+        let valued: Vec<_> = options(cmd).filter(|a| takes_value(a)).collect();
+        if !valued.is_empty() {
+            writeln!(out, "            case \"${{prev}}\" in").unwrap();
+            for arg in valued {
+                let mut flags = Vec::new();
+                if let Some(long) = arg.get_long() {
+                    flags.push(format!("--{}", long));
+                }
+                if let Some(short) = arg.get_short() {
+                    flags.push(format!("-{}", short));
+                }
+                let values = possible_values(arg);
+                let action = if !values.is_empty() {
+                    format!(
+                        "COMPREPLY=($(compgen -W \"{}\" -- \"${{cur}}\"))",
+                        values.join(" ")
+                    )
+                } else if is_path(arg) {
+                    "COMPREPLY=($(compgen -f -- \"${cur}\"))".to_string()
+                } else {
+                    "COMPREPLY=()".to_string()
+                };
+                writeln!(
+                    out,
+                    "                {}) {}; return 0 ;;",
+                    flags.join("|"),
+                    action
+                )
+                .unwrap();
+            }
+            writeln!(out, "            esac").unwrap();
+        }
+
+        let mut words: Vec<String> = subcommands(cmd).map(|c| c.get_name().to_string()).collect();
+        for arg in options(cmd) {
+            if let Some(long) = arg.get_long() {
+                words.push(format!("--{}", long));
+            }
+        }
+        writeln!(
+            out,
+            "            if [[ \"${{cur}}\" == -* ]]; then COMPREPLY=($(compgen -W \"{}\" -- \"${{cur}}\")); return 0; fi",
+            words.iter().filter(|w| w.starts_with('-')).cloned().collect::<Vec<_>>().join(" ")
+        )
+        .unwrap();
+
+        let subs: Vec<_> = words
+            .iter()
+            .filter(|w| !w.starts_with('-'))
+            .cloned()
+            .collect();
+        if !subs.is_empty() {
+            writeln!(
+                out,
+                "            COMPREPLY=($(compgen -W \"{}\" -- \"${{cur}}\"))",
+                subs.join(" ")
+            )
+            .unwrap();
+        } else if positionals(cmd).any(is_provider_arg) {
+            writeln!(
+                out,
+                "            COMPREPLY=($(compgen -W \"$({} list --names 2>/dev/null)\" -- \"${{cur}}\"))",
+                name
+            )
+            .unwrap();
+        } else if let Some(arg) = positionals(cmd).next() {
+            let values = possible_values(arg);
+            if !values.is_empty() {
+                writeln!(
+                    out,
+                    "            COMPREPLY=($(compgen -W \"{}\" -- \"${{cur}}\"))",
+                    values.join(" ")
+                )
+                .unwrap();
+            } else if is_path(arg) {
+                writeln!(out, "            COMPREPLY=($(compgen -f -- \"${{cur}}\"))").unwrap();
+            }
+        }
+        writeln!(out, "            ;;").unwrap();
+    }
+
+    writeln!(out, "    esac").unwrap();
+    writeln!(out, "}}").unwrap();
+    writeln!(out).unwrap();
+    writeln!(
+        out,
+        "complete -F {} -o bashdefault -o default {}",
+        func, name
+    )
+    .unwrap();
+    out
+}
+
+// ============================================================================
+// Zsh
+// ============================================================================
+
+fn zsh_escape(s: &str) -> String {
+    s.replace('\'', "'\\''")
+        .replace('[', "\\[")
+        .replace(']', "\\]")
+        .replace(':', "\\:")
+}
+
+fn zsh_action(name: &str, arg: &Arg) -> String {
+    let values = possible_values(arg);
+    if is_provider_arg(arg) {
+        format!("_{}_providers", name)
+    } else if !values.is_empty() {
+        format!("({})", values.join(" "))
+    } else if is_path(arg) {
+        "_files".to_string()
+    } else {
+        String::new()
+    }
+}
+
+fn zsh(name: &str, commands: &[(Vec<&str>, &Command)]) -> String {
+    let mut out = String::new();
+    writeln!(out, "#compdef {}", name).unwrap();
+    writeln!(out).unwrap();
+    writeln!(out, "_{}_providers() {{", name).unwrap();
+    writeln!(out, "    local -a providers").unwrap();
+    writeln!(
+        out,
+        "    providers=(${{(f)\"$({} list --names 2>/dev/null)\"}})",
+        name
+    )
+    .unwrap();
+    writeln!(out, "    _describe 'provider' providers").unwrap();
+    writeln!(out, "}}").unwrap();
+
+    // Children are emitted before use, so walk in reverse
+    for (path, cmd) in commands.iter().rev() {
+        let func = format!("_{}", path.join("_").replace('-', "_"));
+        writeln!(out).unwrap();
+        writeln!(out, "{}() {{", func).unwrap();
+        writeln!(out, "    local context state state_descr line").unwrap();
+        writeln!(out, "    typeset -A opt_args").unwrap();
+        write!(out, "    _arguments -C").unwrap();
+
+        for arg in options(cmd) {
+            let spec_help = zsh_escape(&help(arg));
+            let value = if takes_value(arg) {
+                format!(":{}:{}", arg.get_id(), zsh_action(name, arg))
+            } else {
+                String::new()
+            };
+            let repeat = if matches!(arg.get_action(), clap::ArgAction::Append) {
+                "*"
+            } else {
+                ""
+            };
+            match (arg.get_short(), arg.get_long()) {
+                (Some(short), Some(long)) => write!(
+                    out,
+                    " \\\n        '{repeat}(-{short} --{long})'{{-{short},--{long}}}'[{spec_help}]{value}'"
+                )
+                .unwrap(),
+                (None, Some(long)) => {
+                    write!(out, " \\\n        '{repeat}--{long}[{spec_help}]{value}'").unwrap()
+                }
+                (Some(short), None) => {
+                    write!(out, " \\\n        '{repeat}-{short}[{spec_help}]{value}'").unwrap()
+                }
+                (None, None) => {}
+            }
+        }
+
+        let subs: Vec<_> = subcommands(cmd).collect();
+        if subs.is_empty() {
+            for (i, arg) in positionals(cmd).enumerate() {
+                write!(
+                    out,
+                    " \\\n        '{}:{}:{}'",
+                    i + 1,
+                    zsh_escape(&help(arg)),
+                    zsh_action(name, arg)
+                )
+                .unwrap();
+            }
+            writeln!(out).unwrap();
+        } else {
+            writeln!(
+                out,
+                " \\\n        '1: :->command' \\\n        '*:: :->args'"
+            )
+            .unwrap();
+            writeln!(out, "    case $state in").unwrap();
+            writeln!(out, "        command)").unwrap();
+            writeln!(out, "            local -a commands").unwrap();
+            writeln!(out, "            commands=(").unwrap();
+            for sub in &subs {
+                writeln!(
+                    out,
+                    "                '{}:{}'",
+                    sub.get_name(),
+                    zsh_escape(&about(sub))
+                )
+                .unwrap();
+            }
+            writeln!(out, "            )").unwrap();
+            writeln!(out, "            _describe 'command' commands").unwrap();
+            writeln!(out, "            ;;").unwrap();
+            writeln!(out, "        args)").unwrap();
+            writeln!(out, "            case $line[1] in").unwrap();
+            for sub in &subs {
+                writeln!(
+                    out,
+                    "                {}) {}_{} ;;",
+                    sub.get_name(),
+                    func,
+                    sub.get_name().replace('-', "_")
+                )
+                .unwrap();
+            }
+            writeln!(out, "            esac").unwrap();
+            writeln!(out, "            ;;").unwrap();
+            writeln!(out, "    esac").unwrap();
+        }
+        writeln!(out, "}}").unwrap();
+    }
+
+    writeln!(out).unwrap();
+    writeln!(out, "_{} \"$@\"", name).unwrap();
+    out
+}
+
+// ============================================================================
+// Fish
+// ============================================================================
+
+fn fish_escape(s: &str) -> String {
+    s.replace('\\', "\\\\").replace('\'', "\\'")
+}
+
+/// Fish condition matching exactly the command at `path`
+fn fish_condition(path: &[&str], commands: &[(Vec<&str>, &Command)]) -> String {
+    let children = |p: &[&str]| -> Vec<String> {
+        commands
+            .iter()
+            .filter(|(c, _)| c.len() == p.len() + 1 && c.starts_with(p))
+            .map(|(c, _)| c[c.len() - 1].to_string())
+            .collect()
+    };
+
+    let mut parts = Vec::new();
+    if path.len() == 1 {
+        parts.push("__fish_use_subcommand".to_string());
+    } else {
+        for name in &path[1..] {
+            parts.push(format!("__fish_seen_subcommand_from {}", name));
+        }
+        let own = children(path);
+        if !own.is_empty() {
+            parts.push(format!("not __fish_seen_subcommand_from {}", own.join(" ")));
+        }
+    }
+    parts.join("; and ")
+}
+
+fn fish(name: &str, commands: &[(Vec<&str>, &Command)]) -> String {
+    let mut out = String::new();
+    writeln!(out, "complete -c {} -f", name).unwrap();
+
+    for (path, cmd) in commands {
+        let condition = fish_condition(path, commands);
+
+        for sub in subcommands(cmd) {
+            writeln!(
+                out,
+                "complete -c {} -n '{}' -a {} -d '{}'",
+                name,
+                condition,
+                sub.get_name(),
+                fish_escape(&about(sub))
+            )
+            .unwrap();
+        }
+
+        for arg in options(cmd) {
+            let mut line = format!("complete -c {} -n '{}'", name, condition);
+            if let Some(long) = arg.get_long() {
+                write!(line, " -l {}", long).unwrap();
+            }
+            if let Some(short) = arg.get_short() {
+                write!(line, " -s {}", short).unwrap();
+            }
+            if takes_value(arg) {
+                let values = possible_values(arg);
+                if !values.is_empty() {
+                    write!(line, " -xa '{}'", values.join(" ")).unwrap();
+                } else if is_path(arg) {
+                    line.push_str(" -rF");
+                } else {
+                    line.push_str(" -r");
+                }
+            }
+            write!(line, " -d '{}'", fish_escape(&help(arg))).unwrap();
+            writeln!(out, "{}", line).unwrap();
+        }
+
+        if subcommands(cmd).next().is_none() {
+            for arg in positionals(cmd) {
+                let values = possible_values(arg);
+                if is_provider_arg(arg) {
+                    writeln!(
+                        out,
+                        "complete -c {} -n '{}' -a '({} list --names 2>/dev/null)'",
+                        name, condition, name
+                    )
+                    .unwrap();
+                } else if !values.is_empty() {
+                    writeln!(
+                        out,
+                        "complete -c {} -n '{}' -a '{}'",
+                        name,
+                        condition,
+                        values.join(" ")
+                    )
+                    .unwrap();
+                } else if is_path(arg) {
+                    writeln!(out, "complete -c {} -n '{}' -F", name, condition).unwrap();
+                }
+            }
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn demo() -> Command {
+        Command::new("demo")
+            .arg(
+                Arg::new("format")
+                    .long("format")
+                    .value_parser(["text", "json"])
+                    .help("Output format"),
+            )
+            .subcommand(
+                Command::new("switch")
+                    .about("Switch to a provider")
+                    .arg(Arg::new("provider")),
+            )
+            .subcommand(
+                Command::new("backups")
+                    .about("Manage backups")
+                    .subcommand(Command::new("prune").about("Delete the user's old backups"))
+                    .subcommand(Command::new("secret").hide(true)),
+            )
+    }
+
+    #[test]
+    fn bash_walks_every_command() {
+        let script = generate(Shell::Bash, demo());
+        assert!(script.contains("demo__backups__prune) cmd=\"demo__backups__prune\""));
+        assert!(script.contains("$(demo list --names 2>/dev/null)"));
+        assert!(script.contains("text json"));
+        assert!(!script.contains("secret"));
+    }
+
+    #[test]
+    fn zsh_describes_commands() {
+        let script = generate(Shell::Zsh, demo());
+        assert!(script.starts_with("#compdef demo"));
+        assert!(script.contains("'switch:Switch to a provider'"));
+        assert!(script.contains("_demo_providers"));
+        assert!(script.contains("Delete the user'\\''s old backups"));
+    }
+
+    #[test]
+    fn fish_scopes_completions_to_their_command() {
+        let script = generate(Shell::Fish, demo());
+        assert!(script.contains(
+            "-n '__fish_seen_subcommand_from backups; and not __fish_seen_subcommand_from prune' \
+             -a prune -d 'Delete the user\\'s old backups'"
+        ));
+        assert!(script.contains("-n '__fish_use_subcommand' -a switch"));
+    }
+
+    #[test]
+    fn escapes_for_each_shell() {
+        assert_eq!(zsh_escape("a [b]: it's"), "a \\[b\\]\\: it'\\''s");
+        assert_eq!(fish_escape("it's a \\"), "it\\'s a \\\\");
+    }
+}
@@ -0,0 +1,127 @@
+//! Crash reports: on a panic, write what is needed to report it (command
+//! line, version, backtrace) to the state directory. Nothing is sent
+//! anywhere, and no config or provider file contents are included.
+
+use std::backtrace::Backtrace;
+use std::fmt::Write as _;
+use std::fs;
+use std::panic::PanicHookInfo;
+use std::path::PathBuf;
+
+use portal_core::{paths, settings};
+
+/// Directory in the state directory holding crash reports
+const CRASH_DIR: &str = "crashes";
+
+/// Longest panic message kept; longer ones may quote file contents
+const MAX_MESSAGE: usize = 200;
+
+/// Replace the default panic output with a crash report, if the user
+/// asked for them with `PORTAL_CRASH_REPORTS=1` or `"crash_reports": true`
+/// in settings.json. `PORTAL_CRASH_REPORTS=0` turns them off whatever the
+/// settings say. Otherwise Rust's usual panic message is kept.
+pub fn install() {
+    if !enabled() {
+        return;
+    }
+    let default = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        let message = message(info);
+        // Not a bug in portal: the reader of a pipe went away
+        if message.contains("Broken pipe") {
+            default(info);
+            return;
+        }
+        match write_report(info, &message) {
+            Some(path) => eprintln!(
+                "portal crashed: {}\n\n\
+                 A crash report was written to {}\n\
+                 It holds the command line, portal's version, and a backtrace, but \
+                 none of your files. Attach it when reporting the bug.",
+                message,
+                path.display()
+            ),
+            None => default(info),
+        }
+    }));
+}
+
+fn enabled() -> bool {
+    if let Some(value) = std::env::var_os("PORTAL_CRASH_REPORTS") {
+        return !value.is_empty() && value != "0";
+    }
+    let env = paths::Env::current();
+    // Without a home directory there is no settings file to read
+    env.home.is_some()
+        && settings::read_settings(&env.portal_dir())
+            .ok()
+            .flatten()
+            .is_some_and(|settings| settings.crash_reports)
+}
+
+fn message(info: &PanicHookInfo) -> String {
+    let payload = info.payload();
+    let text = payload
+        .downcast_ref::<&str>()
+        .map(|s| s.to_string())
+        .or_else(|| payload.downcast_ref::<String>().cloned())
+        .unwrap_or_else(|| "unknown panic".to_string());
+    let mut first = redact(text.lines().next().unwrap_or_default());
+    if first.chars().count() > MAX_MESSAGE {
+        first = first.chars().take(MAX_MESSAGE).collect::<String>() + "...";
+    }
+    first
+}
+
+/// Hide the home directory, which usually holds the user name
+fn redact(text: &str) -> String {
+    match std::env::var("HOME").ok() {
+        Some(home) if !home.is_empty() && home != "/" => text.replace(&home, "~"),
+        _ => text.to_string(),
+    }
+}
+
+/// [`portal_core::paths::state_dir`], which can't be used here: it panics
+/// without a home directory, and panicking in the hook aborts
+fn state_dir() -> Option<PathBuf> {
+    let home = std::env::var_os("XDG_STATE_HOME")
+        .map(PathBuf::from)
+        .or_else(|| {
+            if cfg!(windows) {
+                std::env::var_os("LOCALAPPDATA").map(PathBuf::from)
+            } else {
+                Some(PathBuf::from(std::env::var_os("HOME")?).join(".local/state"))
+            }
+        })?;
+    Some(home.join("portal"))
+}
+
+/// The report's path, or `None` if it couldn't be written
+fn write_report(info: &PanicHookInfo, message: &str) -> Option<PathBuf> {
+    let now = chrono::Local::now();
+    let mut report = String::new();
+    let _ = writeln!(report, "portal crash report");
+    let _ = writeln!(report, "version: {}", env!("CARGO_PKG_VERSION"));
+    let _ = writeln!(report, "time: {}", now.to_rfc3339());
+    let _ = writeln!(
+        report,
+        "platform: {} {}",
+        std::env::consts::OS,
+        std::env::consts::ARCH
+    );
+    let args: Vec<String> = std::env::args_os()
+        .map(|a| redact(&a.to_string_lossy()))
+        .collect();
+    let _ = writeln!(report, "command: {}", args.join(" "));
+    let _ = writeln!(report, "panic: {}", message);
+    if let Some(location) = info.location() {
+        let _ = writeln!(report, "location: {}:{}", location.file(), location.line());
+    }
+    let _ = writeln!(report, "\nbacktrace:\n{}", Backtrace::force_capture());
+
+    let dir = state_dir()?.join(CRASH_DIR);
+    fs::create_dir_all(&dir).ok()?;
+    let path = dir.join(format!("crash-{}.txt", now.format("%Y%m%d-%H%M%S")));
+    fs::write(&path, redact(&report)).ok()?;
+    Some(path)
+}
@@ -0,0 +1,526 @@
+//! `portal daemon`: keep providers applied to one or more configs, poll
+//! quotas, and fall back to another provider on rate limits, as described
+//! by `daemon.json`, reloading that file on SIGHUP.
+
+use anyhow::{Context, Result, anyhow};
+use portal_core::Portal;
+use portal_core::daemon::{self, DaemonConfig, Target};
+use portal_core::fsutil;
+use portal_core::ipc;
+use portal_core::log::Level;
+use portal_core::notify;
+use portal_core::portal::SwitchOptions;
+use portal_core::quota::{Poller, QuotaAccount};
+use portal_core::ratelimit::{self, FallbackTarget, LogTail};
+use portal_core::service::{self, ServiceManager, ServiceSpec};
+use portal_core::temporary::Expiry;
+use portal_core::throttle::WarningThrottle;
+use std::fs;
+use std::path::PathBuf;
+use std::process::Command;
+use std::time::Duration;
+
+use crate::Cli;
+use crate::watch::Watcher;
+
+/// Longest a signal can go unnoticed
+const SIGNAL_CHECK: Duration = Duration::from_secs(1);
+
+/// Longest stretch of a log line quoted when falling back
+const EXCERPT_CHARS: usize = 120;
+
+fn log(message: &str) {
+    if crate::exit::is_quiet() {
+        return;
+    }
+    if crate::logging::is_json() {
+        portal_core::log::record(Level::Info, module_path!(), message);
+    } else {
+        eprintln!("[{}] {}", chrono::Local::now().format("%H:%M:%S"), message);
+    }
+}
+
+/// Log `message` as a warning, unless it was logged a moment ago
+fn warn(throttle: &mut WarningThrottle, message: &str) {
+    if !throttle.admit(message) || crate::exit::is_quiet() {
+        return;
+    }
+    if crate::logging::is_json() {
+        portal_core::log::record(Level::Warn, module_path!(), message);
+    } else {
+        log(&format!("Warning: {}", message));
+    }
+}
+
+#[cfg(unix)]
+mod signals {
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    static RELOAD: AtomicBool = AtomicBool::new(false);
+    static STOP: AtomicBool = AtomicBool::new(false);
+
+    extern "C" fn on_reload(_: libc::c_int) {
+        RELOAD.store(true, Ordering::SeqCst);
+    }
+
+    extern "C" fn on_stop(_: libc::c_int) {
+        STOP.store(true, Ordering::SeqCst);
+    }
+
+    fn install(signal: libc::c_int, handler: extern "C" fn(libc::c_int)) {
+        // SAFETY: sigaction is plain data, so zeroed is a valid start; the
+        // handlers only store to atomics, which is async-signal-safe
+        unsafe {
+            let mut action: libc::sigaction = std::mem::zeroed();
+            action.sa_sigaction = handler as libc::sighandler_t;
+            // No SA_RESTART: a signal should interrupt the wait for changes
+            action.sa_flags = 0;
+            libc::sigemptyset(&mut action.sa_mask);
+            libc::sigaction(signal, &action, std::ptr::null_mut());
+        }
+    }
+
+    pub fn install_handlers() {
+        install(libc::SIGHUP, on_reload);
+        install(libc::SIGINT, on_stop);
+        install(libc::SIGTERM, on_stop);
+    }
+
+    pub fn take_reload() -> bool {
+        RELOAD.swap(false, Ordering::SeqCst)
+    }
+
+    pub fn stopping() -> bool {
+        STOP.load(Ordering::SeqCst)
+    }
+
+    pub fn is_running(pid: u32) -> bool {
+        // SAFETY: signal 0 only checks that the process exists
+        unsafe { libc::kill(pid as libc::pid_t, 0) == 0 }
+    }
+
+    pub fn send_reload(pid: u32) -> std::io::Result<()> {
+        // SAFETY: kill takes no pointers; the pid is the daemon's own
+        if unsafe { libc::kill(pid as libc::pid_t, libc::SIGHUP) } != 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+        Ok(())
+    }
+}
+
+#[cfg(not(unix))]
+mod signals {
+    pub fn install_handlers() {}
+
+    pub fn take_reload() -> bool {
+        false
+    }
+
+    pub fn stopping() -> bool {
+        false
+    }
+
+    pub fn is_running(_pid: u32) -> bool {
+        false
+    }
+
+    pub fn send_reload(_pid: u32) -> std::io::Result<()> {
+        Err(std::io::Error::new(
+            std::io::ErrorKind::Unsupported,
+            "signals are not supported on this platform",
+        ))
+    }
+}
+
+/// Removes the pid file when the daemon exits
+struct PidGuard(PathBuf);
+
+impl Drop for PidGuard {
+    fn drop(&mut self) {
+        let _ = daemon::remove_pid(&self.0);
+    }
+}
+
+/// What the daemon is currently acting on
+struct Loaded {
+    targets: Vec<Target>,
+    quotas: Vec<QuotaAccount>,
+    fallback: Option<FallbackTarget>,
+    debounce: Duration,
+}
+
+impl Loaded {
+    fn load(portal: &Portal) -> Result<Self> {
+        let path = daemon::daemon_file_path(portal.portal_dir());
+        let config: DaemonConfig =
+            daemon::read_daemon_config(portal.portal_dir())?.ok_or_else(|| {
+                anyhow!(
+                    "No daemon file: {}\n\nList the configs to keep applied there, e.g.\n  \
+                     {{ \"watch\": [{{ \"provider\": \"openrouter\" }}] }}\n\
+                     or write {{}} to only answer queries.",
+                    path.display()
+                )
+            })?;
+        config.check()?;
+        Ok(Self {
+            targets: config.targets(portal)?,
+            quotas: config.quotas.clone(),
+            fallback: config.fallback(portal)?,
+            debounce: Duration::from_millis(config.debounce_ms()),
+        })
+    }
+
+    fn files(&self) -> Vec<PathBuf> {
+        self.targets
+            .iter()
+            .map(|t| t.portal.config_path().to_path_buf())
+            .collect()
+    }
+
+    fn describe(&self) {
+        for target in &self.targets {
+            log(&format!(
+                "Keeping '{}' applied to {}",
+                target.provider,
+                target.portal.config_path().display()
+            ));
+        }
+        for account in &self.quotas {
+            log(&format!(
+                "Polling the {} quota of '{}' every {}s",
+                account.provider,
+                account.name,
+                account.interval().as_secs()
+            ));
+        }
+        if let Some(fallback) = &self.fallback {
+            let logs: Vec<String> = fallback
+                .logs
+                .iter()
+                .map(|p| p.display().to_string())
+                .collect();
+            log(&format!(
+                "Falling back from {} to '{}' on rate limits in {}{}",
+                fallback
+                    .from
+                    .as_ref()
+                    .map_or("any provider".to_string(), |from| format!("'{}'", from)),
+                fallback.to,
+                logs.join(", "),
+                fallback
+                    .cooldown
+                    .map(|c| format!(", for {}s", c.as_secs()))
+                    .unwrap_or_default()
+            ));
+        }
+    }
+
+    /// A tail of the fallback's log files, from their current end
+    fn tail(&self) -> Option<LogTail> {
+        self.fallback.as_ref().map(|f| LogTail::new(&f.logs))
+    }
+
+    /// Switch to the fallback if the logs show a rate limit since last
+    /// time and the config is on the provider it applies to
+    fn fall_back(&self, tail: &mut LogTail, dry_run: bool, throttle: &mut WarningThrottle) {
+        let Some(fallback) = &self.fallback else {
+            return;
+        };
+        let Some((file, line)) = tail
+            .read_new()
+            .into_iter()
+            .rev()
+            .find(|(_, line)| ratelimit::is_rate_limit(line, &fallback.patterns))
+        else {
+            return;
+        };
+        let config = fallback.portal.config_path().display().to_string();
+        let current = match fallback.portal.last_provider() {
+            Ok(current) => current,
+            Err(e) => return warn(throttle, &format!("{}: {:#}", config, e)),
+        };
+        if !fallback.applies_to(current.as_deref()) {
+            return;
+        }
+        let expiry = match fallback
+            .cooldown
+            .map(chrono::Duration::from_std)
+            .transpose()
+        {
+            Ok(cooldown) => cooldown.map(|c| Expiry::At(chrono::Utc::now() + c)),
+            Err(e) => return warn(throttle, &format!("fallback cooldown: {}", e)),
+        };
+        let seen = format!(
+            "Rate limited on {} ({}: {})",
+            current.as_deref().unwrap_or("the current provider"),
+            file.file_name().unwrap_or_default().to_string_lossy(),
+            line.trim().chars().take(EXCERPT_CHARS).collect::<String>()
+        );
+        let until = expiry
+            .as_ref()
+            .map(|e| format!(" {}", e.describe()))
+            .unwrap_or_default();
+        if dry_run {
+            return log(&format!(
+                "{}; dry run - would switch {} to '{}'{}",
+                seen, config, fallback.to, until
+            ));
+        }
+        let options = SwitchOptions::default();
+        let result = match &expiry {
+            Some(expiry) => fallback
+                .portal
+                .switch_temporarily(&fallback.to, &options, expiry),
+            None => fallback.portal.switch(&fallback.to, &options),
+        };
+        match result {
+            Ok(_) => {
+                log(&format!(
+                    "{}; switched {} to '{}'{}",
+                    seen, config, fallback.to, until
+                ));
+                fallback.portal.notify(&notify::switch_message(
+                    &config,
+                    current.as_deref(),
+                    &fallback.to,
+                    &format!("rate limited{}", until),
+                ));
+            }
+            Err(e) => warn(throttle, &format!("{}: {:#}", config, e)),
+        }
+    }
+
+    /// Re-apply every target whose config was rewritten. Errors are
+    /// reported and retried on the next change.
+    fn reapply(&self, dry_run: bool, throttle: &mut WarningThrottle) {
+        for target in &self.targets {
+            let result =
+                target
+                    .portal
+                    .reapply(&target.provider, &target.options, &target.except, dry_run);
+            match result {
+                Ok(Some(changed)) => {
+                    log(&format!(
+                        "{} '{}' to {}: {}",
+                        if dry_run {
+                            "Dry run - would re-apply"
+                        } else {
+                            "Re-applied"
+                        },
+                        target.provider,
+                        target.portal.config_path().display(),
+                        changed.join(", ")
+                    ));
+                    if !dry_run {
+                        target.portal.notify(&crate::reapplied_message(
+                            &target.portal,
+                            &target.provider,
+                            &changed,
+                        ));
+                    }
+                }
+                Ok(None) => {}
+                Err(e) => warn(
+                    throttle,
+                    &format!("{}: {:#}", target.portal.config_path().display(), e),
+                ),
+            }
+        }
+    }
+}
+
+/// Run in the foreground until SIGINT or SIGTERM
+pub fn run(portal: &Portal, dry_run: bool) -> Result<()> {
+    let state_dir = portal.state_dir().to_path_buf();
+    if let Some(pid) = daemon::read_pid(&state_dir)?
+        && pid != std::process::id()
+        && signals::is_running(pid)
+    {
+        return Err(anyhow!("The daemon is already running (pid {})", pid));
+    }
+
+    // Only a working daemon.json gets a daemon started; after that, a
+    // broken one is reported and the previous one kept
+    let mut loaded = Loaded::load(portal)?;
+    let mut watcher = Watcher::new(&loaded.files())?;
+    let mut poller = Poller::new(loaded.quotas.clone(), &state_dir);
+    let mut tail = loaded.tail();
+    signals::install_handlers();
+    daemon::write_pid(&state_dir, std::process::id())?;
+    let _guard = PidGuard(state_dir.clone());
+    let _server = ipc::Server::start(portal)?;
+    let mut throttle = WarningThrottle::default();
+    loaded.describe();
+    if cfg!(unix) {
+        log(&format!(
+            "Answering queries on {}",
+            ipc::socket_path(&state_dir).display()
+        ));
+    }
+
+    loop {
+        loaded.reapply(dry_run, &mut throttle);
+
+        let mut changed = false;
+        while !changed {
+            if signals::stopping() {
+                log("Stopping");
+                return Ok(());
+            }
+            if signals::take_reload() {
+                match Loaded::load(portal) {
+                    Ok(next) => match watcher.set_files(&next.files()) {
+                        Ok(()) => {
+                            log("Reloaded daemon file");
+                            loaded = next;
+                            poller.reconfigure(loaded.quotas.clone(), &state_dir);
+                            tail = loaded.tail();
+                            loaded.describe();
+                            break;
+                        }
+                        Err(e) => {
+                            log(&format!("Reload failed, keeping previous setup: {:#}", e));
+                            // Put back the watches of the setup still in use
+                            watcher.set_files(&loaded.files())?;
+                        }
+                    },
+                    Err(e) => log(&format!("Reload failed, keeping previous setup: {:#}", e)),
+                }
+            }
+            for error in poller.poll_due(&state_dir) {
+                warn(&mut throttle, &error);
+            }
+            if let Some(tail) = tail.as_mut() {
+                loaded.fall_back(tail, dry_run, &mut throttle);
+            }
+            if !dry_run {
+                crate::end_temporary_switches(portal, log, |warning| warn(&mut throttle, warning));
+            }
+            for summary in throttle.summaries() {
+                log(&summary);
+            }
+            // Wake up now and then in case a signal landed just before the
+            // wait started, to poll quotas, and to end temporary switches
+            changed = watcher.wait(Some(SIGNAL_CHECK))?;
+        }
+        // Let a burst of writes settle before looking
+        while watcher.wait(Some(loaded.debounce))? {}
+    }
+}
+
+/// Ask the running daemon to reload its daemon file
+pub fn reload(portal: &Portal) -> Result<u32> {
+    let pid = daemon::read_pid(portal.state_dir())?
+        .filter(|pid| signals::is_running(*pid))
+        .ok_or_else(|| anyhow!("The daemon isn't running"))?;
+    signals::send_reload(pid).map_err(|e| anyhow!("Failed to signal pid {}: {}", pid, e))?;
+    Ok(pid)
+}
+
+/// Variables passed through to the service so it sees the same files and
+/// tools as the shell that installed it
+const SERVICE_ENV: &[&str] = &["PATH", "XDG_CONFIG_HOME", "XDG_DATA_HOME", "XDG_STATE_HOME"];
+
+/// Write (and with `enable`, start) a service running `portal daemon run`
+pub fn install(
+    cli: &Cli,
+    portal: &Portal,
+    manager: ServiceManager,
+    enable: bool,
+    force: bool,
+) -> Result<()> {
+    let program = std::env::current_exe()
+        .and_then(fs::canonicalize)
+        .context("Failed to locate the portal executable")?;
+    let mut args = vec!["daemon".to_string(), "run".to_string()];
+    if cli.config().is_some() {
+        let config = fs::canonicalize(portal.config_path())
+            .unwrap_or_else(|_| portal.config_path().to_path_buf());
+        args.push("--config".to_string());
+        args.push(config.to_string_lossy().into_owned());
+    }
+    let spec = ServiceSpec {
+        program,
+        args,
+        env: SERVICE_ENV
+            .iter()
+            .filter_map(|key| Some((key.to_string(), std::env::var(key).ok()?)))
+            .collect(),
+        log_path: service::log_path(portal.state_dir()),
+    };
+    let content = service::render(manager, &spec);
+    let path = manager.unit_path();
+
+    if cli.dry_run {
+        println!("Dry run - would write {}:", path.display());
+        println!();
+        print!("{}", content);
+        return Ok(());
+    }
+
+    let existing = fs::read_to_string(&path).ok();
+    if existing.as_deref() == Some(content.as_str()) {
+        println!("{} is up to date", path.display());
+    } else {
+        if existing.is_some() && !force {
+            return Err(anyhow!(
+                "{} already exists with different contents. Pass --force to replace it.",
+                path.display()
+            ));
+        }
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)
+                .with_context(|| format!("Failed to create directory: {}", parent.display()))?;
+        }
+        // launchd won't create the log file's directory
+        fs::create_dir_all(portal.state_dir()).with_context(|| {
+            format!(
+                "Failed to create state directory: {}",
+                portal.state_dir().display()
+            )
+        })?;
+        fsutil::write_atomic(&path, content.as_bytes())
+            .with_context(|| format!("Failed to write service file: {}", path.display()))?;
+        println!("Wrote {}", path.display());
+    }
+
+    let commands: Vec<Vec<String>> = match manager {
+        ServiceManager::Systemd => vec![
+            vec!["systemctl", "--user", "daemon-reload"],
+            vec![
+                "systemctl",
+                "--user",
+                "enable",
+                "--now",
+                service::SYSTEMD_UNIT,
+            ],
+        ]
+        .into_iter()
+        .map(|c| c.into_iter().map(String::from).collect())
+        .collect(),
+        ServiceManager::Launchd => vec![vec![
+            "launchctl".to_string(),
+            "load".to_string(),
+            "-w".to_string(),
+            path.to_string_lossy().into_owned(),
+        ]],
+    };
+    if !enable {
+        println!("Enable it with:");
+        for command in &commands {
+            println!("  {}", command.join(" "));
+        }
+        return Ok(());
+    }
+    for command in &commands {
+        let status = Command::new(&command[0])
+            .args(&command[1..])
+            .status()
+            .with_context(|| format!("Failed to run {}", command[0]))?;
+        if !status.success() {
+            return Err(anyhow!("`{}` failed ({})", command.join(" "), status));
+        }
+    }
+    println!("Enabled and started the daemon");
+    Ok(())
+}
@@ -0,0 +1,42 @@
+//! Round-trip text through the user's `$VISUAL` / `$EDITOR`.
+
+use anyhow::{Context, Result, anyhow};
+use std::fs;
+use std::process::Command;
+
+fn editor() -> String {
+    std::env::var("VISUAL")
+        .or_else(|_| std::env::var("EDITOR"))
+        .ok()
+        .filter(|e| !e.trim().is_empty())
+        .unwrap_or_else(|| "vi".to_string())
+}
+
+/// Open `initial` in the editor and return the saved text. `name` is used
+/// for the temporary file so editors can pick a sensible mode.
+pub fn edit(initial: &str, name: &str) -> Result<String> {
+    let path = std::env::temp_dir().join(format!("portal-{}-{}", std::process::id(), name));
+    fs::write(&path, initial)
+        .with_context(|| format!("Failed to write temporary file: {}", path.display()))?;
+
+    let editor = editor();
+    // Run through the shell so EDITOR may carry arguments, e.g. "code --wait"
+    #[cfg(unix)]
+    let status = Command::new("sh")
+        .arg("-c")
+        .arg(format!("{} \"$1\"", editor))
+        .arg("sh")
+        .arg(&path)
+        .status();
+    #[cfg(not(unix))]
+    let status = Command::new(&editor).arg(&path).status();
+
+    let result = match status {
+        Ok(status) if status.success() => fs::read_to_string(&path)
+            .with_context(|| format!("Failed to read edited file: {}", path.display())),
+        Ok(status) => Err(anyhow!("Editor '{}' exited with {}", editor, status)),
+        Err(e) => Err(anyhow!("Failed to run editor '{}': {}", editor, e)),
+    };
+    let _ = fs::remove_file(&path);
+    result
+}
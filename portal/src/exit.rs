@@ -0,0 +1,115 @@
+//! How portal ends, for scripts: exit codes to branch on, and `--quiet`,
+//! which leaves nothing on the terminal but errors.
+//!
+//! | Code | Meaning                                                       |
+//! |------|---------------------------------------------------------------|
+//! | 0    | Done (switched, or any other command that succeeded)          |
+//! | 1    | Failed                                                        |
+//! | 2    | Bad command line                                              |
+//! | 3    | Already on the provider: the switch changed no agent          |
+//! | 4    | The config file doesn't exist                                 |
+//! | 5    | Switched, but some agents had no mapping and kept their model |
+
+use portal_core::PortalError;
+use std::fmt;
+use std::process::ExitCode;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+pub const SUCCESS: u8 = 0;
+pub const FAILED: u8 = 1;
+pub const ALREADY: u8 = 3;
+pub const CONFIG_MISSING: u8 = 4;
+pub const PARTIAL: u8 = 5;
+
+/// Ends portal with `code` and no error message: whatever there was to
+/// say has been printed already
+#[derive(Debug)]
+pub struct Status(pub u8);
+
+impl fmt::Display for Status {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "exit status {}", self.0)
+    }
+}
+
+impl std::error::Error for Status {}
+
+/// End with `code`: `Ok` for [`SUCCESS`], a [`Status`] otherwise
+pub fn status(code: u8) -> anyhow::Result<()> {
+    match code {
+        SUCCESS => Ok(()),
+        code => Err(Status(code).into()),
+    }
+}
+
+/// What a switch's exit code means, for `--format json`
+pub fn name(code: u8) -> &'static str {
+    match code {
+        ALREADY => "already",
+        PARTIAL => "partial",
+        _ => "switched",
+    }
+}
+
+/// Print `error`, unless it is a [`Status`], and pick the exit code for it
+pub fn report(error: &anyhow::Error) -> ExitCode {
+    if let Some(Status(code)) = error.downcast_ref::<Status>() {
+        return ExitCode::from(*code);
+    }
+    eprintln!("Error: {:?}", error);
+    if let Some(PortalError::ConfigNotFound(_)) = error.downcast_ref::<PortalError>() {
+        ExitCode::from(CONFIG_MISSING)
+    } else {
+        ExitCode::from(FAILED)
+    }
+}
+
+static QUIET: AtomicBool = AtomicBool::new(false);
+
+/// Turn on `--quiet` for the rest of the run: standard output goes
+/// nowhere, and [`is_quiet`] tells notes on stderr to keep out of the way
+pub fn set_quiet() {
+    QUIET.store(true, Ordering::Relaxed);
+    #[cfg(unix)]
+    {
+        use std::os::fd::AsRawFd;
+        if let Ok(null) = std::fs::OpenOptions::new().write(true).open("/dev/null") {
+            // SAFETY: both descriptors are open; stdout now refers to
+            // /dev/null, and `null` closing afterwards leaves that alone
+            unsafe {
+                libc::dup2(null.as_raw_fd(), libc::STDOUT_FILENO);
+            }
+        }
+    }
+    #[cfg(windows)]
+    {
+        use std::os::windows::io::IntoRawHandle;
+        // Standard output is looked up again for every write, so swapping
+        // the handle is enough
+        unsafe extern "system" {
+            fn SetStdHandle(std_handle: u32, handle: *mut std::ffi::c_void) -> i32;
+        }
+        const STD_OUTPUT_HANDLE: u32 = -11i32 as u32;
+        if let Ok(null) = std::fs::OpenOptions::new().write(true).open("NUL") {
+            // SAFETY: the handle is open and left open for the rest of the
+            // run, now that standard output refers to it
+            unsafe {
+                SetStdHandle(STD_OUTPUT_HANDLE, null.into_raw_handle());
+            }
+        }
+    }
+}
+
+pub fn is_quiet() -> bool {
+    QUIET.load(Ordering::Relaxed)
+}
+
+/// Let a closed pipe end portal quietly, like other command-line tools,
+/// instead of failing its next write (`portal status | head -1`)
+pub fn default_sigpipe() {
+    #[cfg(unix)]
+    // SAFETY: restoring the default disposition of a signal is always sound
+    unsafe {
+        libc::signal(libc::SIGPIPE, libc::SIG_DFL);
+    }
+}
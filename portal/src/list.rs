@@ -0,0 +1,201 @@
+//! `portal list`: the built-in, custom, and remote providers, and the
+//! aliases for them.
+
+use anyhow::Result;
+use portal_core::Portal;
+use portal_core::provider::{self, BUILTIN_PROVIDERS};
+use portal_core::settings;
+use serde_json::{Value, json};
+use std::collections::BTreeMap;
+
+use crate::{Cli, Format, all_providers, note, print_json, style};
+
+/// Print the providers and aliases, only their names with `names`, after
+/// fetching provider sources again with `refresh`
+pub fn run(cli: &Cli, portal: &Portal, names: bool, detailed: bool, refresh: bool) -> Result<()> {
+    let refreshed = if refresh {
+        let refreshed = portal.refresh_sources()?;
+        if refreshed.is_empty() {
+            note(format!(
+                "No provider sources in {}; add them under \"sources\"",
+                settings::SETTINGS_FILE
+            ));
+        }
+        for source in &refreshed {
+            match &source.error {
+                None => note(format!(
+                    "Fetched {} provider(s) from source '{}'",
+                    source.providers.len(),
+                    source.source
+                )),
+                Some(error) => note(format!(
+                    "Couldn't fetch source '{}', keeping {} cached provider(s): {}",
+                    source.source,
+                    source.providers.len(),
+                    error
+                )),
+            }
+        }
+        Some(refreshed)
+    } else {
+        None
+    };
+    if names {
+        let providers = all_providers(portal)?;
+        for name in &providers {
+            println!("{}", name);
+        }
+        for name in portal.aliases()?.keys() {
+            if !providers.contains(name) {
+                println!("{}", name);
+            }
+        }
+        return Ok(());
+    }
+    if cli.format == Format::Json {
+        let mut value = to_json(portal, detailed)?;
+        if let Some(refreshed) = refreshed {
+            value["refreshed"] = serde_json::to_value(refreshed)?;
+        }
+        return print_json(&value);
+    }
+    let aliases = portal.aliases()?;
+    let files = provider_files(portal)?;
+
+    let (extended_builtins, custom_providers): (Vec<String>, Vec<String>) = portal
+        .custom_providers()?
+        .into_iter()
+        .partition(|name| provider::is_builtin_name(name));
+    println!("Built-in providers:");
+    for (name, description) in BUILTIN_PROVIDERS {
+        if style::is_plain() {
+            let file = if extended_builtins.iter().any(|p| p == name) {
+                format!(", with models from {}.json", name)
+            } else {
+                String::new()
+            };
+            println!("  {}: {}{}", name, description, file);
+        } else if extended_builtins.iter().any(|p| p == name) {
+            println!(
+                "  {:<11} - {} + models from {}.json",
+                name, description, name
+            );
+        } else {
+            println!("  {:<11} - {}", name, description);
+        }
+        if detailed && let Some(config) = files.get(*name) {
+            print_provider_details(config);
+        }
+    }
+    println!();
+
+    if !custom_providers.is_empty() {
+        println!("Custom providers (from {}):", portal.portal_dir().display());
+        for p in custom_providers {
+            let config = files.get(&p);
+            match config.and_then(|c| c.description.as_deref()) {
+                Some(description) if style::is_plain() => println!("  {}: {}", p, description),
+                Some(description) => println!("  {:<11} - {}", p, description),
+                None => println!("  {}", p),
+            }
+            if detailed && let Some(config) = config {
+                print_provider_details(config);
+            }
+        }
+        println!();
+    }
+
+    let remote = portal.remote_providers()?;
+    if !remote.is_empty() {
+        println!("Remote providers (from sources):");
+        for (p, from) in &remote {
+            let config = files.get(p);
+            let description = config.and_then(|c| c.description.as_deref());
+            match description {
+                Some(description) if style::is_plain() => {
+                    println!("  {}: {}, from {}", p, description, from.source)
+                }
+                Some(description) => {
+                    println!("  {:<11} - {} [{}]", p, description, from.source)
+                }
+                None if style::is_plain() => println!("  {}, from {}", p, from.source),
+                None => println!("  {:<11} [{}]", p, from.source),
+            }
+            if detailed && let Some(config) = config {
+                print_provider_details(config);
+            }
+        }
+        println!();
+    }
+
+    if !aliases.is_empty() {
+        println!("Aliases (from {}):", settings::SETTINGS_FILE);
+        for (alias, provider) in &aliases {
+            println!("  {} -> {}", alias, provider);
+        }
+        println!();
+    }
+
+    println!("Usage: portal switch <provider>");
+    Ok(())
+}
+
+/// Custom and remote provider configs that parse; a broken one shouldn't
+/// hide the others, and validate reports it
+fn provider_files(portal: &Portal) -> Result<BTreeMap<String, provider::ProviderConfig>> {
+    Ok(portal
+        .custom_providers()?
+        .into_iter()
+        .chain(portal.remote_providers()?.into_keys())
+        .filter_map(|name| Some((name.clone(), portal.provider_config(&name).ok()??)))
+        .collect())
+}
+
+/// `list --format json`, and the MCP server's `list_providers`
+pub fn to_json(portal: &Portal, detailed: bool) -> Result<Value> {
+    let builtin: Vec<_> = BUILTIN_PROVIDERS
+        .iter()
+        .map(|(name, description)| json!({ "name": name, "description": description }))
+        .collect();
+    let mut value = json!({
+        "builtin": builtin,
+        "custom": portal.custom_providers()?,
+        "remote": portal
+            .remote_providers()?
+            .into_iter()
+            .map(|(name, remote)| json!({ "name": name, "source": remote.source }))
+            .collect::<Vec<_>>(),
+        "aliases": portal.aliases()?,
+        "portal_dir": portal.portal_dir(),
+    });
+    if detailed {
+        value["details"] = provider_files(portal)?
+            .iter()
+            .map(|(name, config)| (name.clone(), provider_details_json(config)))
+            .collect::<serde_json::Map<_, _>>()
+            .into();
+    }
+    Ok(value)
+}
+
+/// Notes and a count of agent models and rules, under a provider in
+/// `list --detailed`
+fn print_provider_details(config: &provider::ProviderConfig) {
+    for line in config.notes.iter().flat_map(|notes| notes.lines()) {
+        println!("      {}", line);
+    }
+    println!(
+        "      {} agent model(s), {} rule(s)",
+        config.agents.len(),
+        config.rules.len()
+    );
+}
+
+fn provider_details_json(config: &provider::ProviderConfig) -> Value {
+    json!({
+        "description": config.description,
+        "notes": config.notes,
+        "agents": config.agents.len(),
+        "rules": config.rules.len(),
+    })
+}
@@ -0,0 +1,67 @@
+//! The CLI's log sink: diagnostics on stderr. Warnings are shown unless
+//! quiet; `-v` adds what portal does step by step, such as backups and
+//! config writes, and `-vv` every file read and written and how each
+//! agent's model was picked. With `--log-json` each line is a JSON object
+//! instead, for scripts and log collectors:
+//!
+//! ```json
+//! {"time":"2026-10-14T07:00:34.892Z","level":"debug","target":"portal_core::switch","message":"oracle: ..."}
+//! ```
+
+use chrono::{SecondsFormat, Utc};
+use portal_core::log::{self, Level, Sink};
+use serde_json::json;
+use std::io::Write;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+static JSON: AtomicBool = AtomicBool::new(false);
+
+struct Stderr {
+    verbosity: u8,
+    quiet: bool,
+    json: bool,
+}
+
+impl Sink for Stderr {
+    fn enabled(&self, level: Level) -> bool {
+        !self.quiet && level as u8 <= self.verbosity
+    }
+
+    fn record(&self, level: Level, target: &str, message: &str) {
+        let line = if self.json {
+            json!({
+                "time": Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true),
+                "level": level.name(),
+                "target": target,
+                "message": message,
+            })
+            .to_string()
+        } else {
+            let label = match level {
+                Level::Warn => "Warning",
+                Level::Info => "Info",
+                Level::Debug => "Debug",
+            };
+            format!("{}: {}", label, message)
+        };
+        // Nowhere left to report a failure to write to stderr
+        let _ = writeln!(std::io::stderr().lock(), "{}", line);
+    }
+}
+
+/// Log to stderr for the rest of the run: 0 for warnings only, 1 (`-v`)
+/// for steps, 2 or more (`-vv`) for everything; `quiet` logs nothing at
+/// all, and `json` writes JSON lines
+pub fn init(verbosity: u8, quiet: bool, json: bool) {
+    JSON.store(json, Ordering::Relaxed);
+    log::set_sink(Stderr {
+        verbosity,
+        quiet,
+        json,
+    });
+}
+
+/// Whether log lines are JSON objects
+pub fn is_json() -> bool {
+    JSON.load(Ordering::Relaxed)
+}
@@ -1,117 +1,68 @@
-use anyhow::{anyhow, Context, Result};
-use chrono::Utc;
-use clap::{Parser, Subcommand};
-use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
-use std::fs;
-use std::path::PathBuf;
-
-// ============================================================================
-// Data Structures
-// ============================================================================
-
-/// Main oh-my-opencode configuration file structure
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct OhMyOpenCodeConfig {
-    #[serde(rename = "$schema", skip_serializing_if = "Option::is_none")]
-    pub schema: Option<String>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub google_auth: Option<bool>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub subagents: Option<HashMap<String, serde_json::Value>>,
-    pub agents: HashMap<String, AgentConfig>,
-    #[serde(flatten)]
-    pub extra: HashMap<String, serde_json::Value>,
-}
-
-/// Per-agent configuration
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct AgentConfig {
-    pub model: String,
-    #[serde(flatten)]
-    pub extra: HashMap<String, serde_json::Value>,
-}
+mod compare;
+mod completions;
+mod crash;
+mod daemon;
+mod editor;
+mod exit;
+mod list;
+mod logging;
+mod mcp;
+mod picker;
+mod prompt;
+mod serve;
+mod status;
+mod style;
+mod title;
+mod watch;
 
-/// Provider config format - matches oh-my-opencode structure
-/// Example: { "agents": { "sisyphus": { "model": "github-copilot/claude-opus-4.5" } } }
-#[derive(Debug, Clone, Serialize, Deserialize, Default)]
-pub struct ProviderConfig {
-    #[serde(default)]
-    pub agents: HashMap<String, AgentModelConfig>,
-}
-
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct AgentModelConfig {
-    pub model: String,
-}
-
-// ============================================================================
-// Default Model Mappings
-// ============================================================================
-
-struct ModelMapping {
-    base: &'static str,
-    copilot: &'static str,
-    openrouter_provider: &'static str,
-    openrouter_model: &'static str,
-}
-
-const MODEL_MAPPINGS: &[ModelMapping] = &[
-    ModelMapping {
-        base: "claude-opus-4.5",
-        copilot: "claude-opus-4.5",
-        openrouter_provider: "anthropic",
-        openrouter_model: "claude-opus-4.5",
-    },
-    ModelMapping {
-        base: "claude-sonnet-4.5",
-        copilot: "claude-sonnet-4.5",
-        openrouter_provider: "anthropic",
-        openrouter_model: "claude-sonnet-4.5",
-    },
-    ModelMapping {
-        base: "claude-sonnet-4",
-        copilot: "claude-sonnet-4",
-        openrouter_provider: "anthropic",
-        openrouter_model: "claude-sonnet-4",
-    },
-    ModelMapping {
-        base: "gpt-5.2",
-        copilot: "gpt-5.2",
-        openrouter_provider: "openai",
-        openrouter_model: "gpt-5.2",
-    },
-    ModelMapping {
-        base: "gpt-4.1",
-        copilot: "gpt-4.1",
-        openrouter_provider: "openai",
-        openrouter_model: "gpt-4.1",
-    },
-    ModelMapping {
-        base: "o3",
-        copilot: "o3",
-        openrouter_provider: "openai",
-        openrouter_model: "o3",
-    },
-    ModelMapping {
-        base: "o4-mini",
-        copilot: "o4-mini",
-        openrouter_provider: "openai",
-        openrouter_model: "o4-mini",
-    },
-    ModelMapping {
-        base: "gemini-3-flash",
-        copilot: "gemini-3-flash",
-        openrouter_provider: "google",
-        openrouter_model: "gemini-3-flash-preview",
-    },
-    ModelMapping {
-        base: "gemini-3-pro",
-        copilot: "gemini-3-pro",
-        openrouter_provider: "google",
-        openrouter_model: "gemini-3-pro-preview",
-    },
-];
+use anyhow::{Context, Result, anyhow};
+use clap::{Args, CommandFactory, Parser, Subcommand, ValueEnum};
+use completions::Shell;
+use portal_core::adopt;
+use portal_core::batch;
+use portal_core::bisect::{self, Step};
+use portal_core::bundle::{self, ImportState};
+use portal_core::catalog::{self, Catalog};
+use portal_core::changes::{self, ChangeKind, ConfigChange};
+use portal_core::compat::Compatibility;
+use portal_core::diff::ModelChange;
+use portal_core::doctor::{self, Severity};
+use portal_core::fleet::{self, FleetState};
+use portal_core::git;
+use portal_core::graph;
+use portal_core::health;
+use portal_core::init;
+use portal_core::keychain;
+use portal_core::manage;
+use portal_core::mapping::Tier;
+use portal_core::notify;
+use portal_core::portal::Drift;
+use portal_core::pricing::Cost;
+use portal_core::provider::{self, BUILTIN_PROVIDERS};
+use portal_core::quota;
+use portal_core::report;
+use portal_core::service::ServiceManager;
+use portal_core::settings::{self, Environment};
+use portal_core::suggest;
+use portal_core::switch::Resolution;
+use portal_core::sync::{self, Pull};
+use portal_core::temporary::{self, Expiry};
+use portal_core::throttle::WarningThrottle;
+use portal_core::validate::{self, ValidateOptions};
+use portal_core::{
+    Portal, PortalError, SwitchOptions, SwitchOutcome, SwitchReport, backup, config, discover,
+    fsutil, mapping, matrix, paths, policy, project, secrets, selftest,
+};
+use prompt::{PromptColor, PromptTarget};
+use serde_json::{Value, json};
+use std::collections::BTreeMap;
+use std::ffi::OsString;
+use std::fs;
+use std::io::{IsTerminal, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use style::{Color, paint};
+use watch::Watcher;
 
 // ============================================================================
 // CLI
@@ -125,9 +76,10 @@ struct Cli {
     #[command(subcommand)]
     command: Commands,
 
-    /// Path to oh-my-opencode.json config file
-    #[arg(short, long, global = true)]
-    config: Option<PathBuf>,
+    /// Path to oh-my-opencode.json config file; `switch` takes several
+    /// and switches them all or none of them
+    #[arg(short, long, global = true, value_name = "CONFIG")]
+    config: Vec<PathBuf>,
 
     /// Show what would change without writing
     #[arg(long, global = true)]
@@ -136,348 +88,4609 @@ struct Cli {
     /// Force create backup even if one exists
     #[arg(long, global = true)]
     backup: bool,
+
+    /// Output format for status, list, diff, switch, changes, backups, and
+    /// validate; graph also takes dot and mermaid, report and compare
+    /// markdown
+    #[arg(long, global = true, value_enum, default_value_t = Format::Text)]
+    format: Format,
+
+    /// Commit each config portal writes to the git repository it is in
+    /// (the default with "git_commit": true in settings.json)
+    #[arg(long, global = true, overrides_with = "no_git_commit")]
+    git_commit: bool,
+
+    /// Don't commit config writes, whatever settings.json says
+    #[arg(long, global = true)]
+    no_git_commit: bool,
+
+    /// Screen-reader friendly output: no colors or redrawn menus, one
+    /// labeled fact per line (also on with PORTAL_PLAIN set)
+    #[arg(long, global = true)]
+    plain: bool,
+
+    /// Log what portal does to stderr: -v for backups, writes, and hooks,
+    /// -vv also every file read and written and each agent's model choice
+    #[arg(short, long, global = true, action = clap::ArgAction::Count)]
+    verbose: u8,
+
+    /// Print nothing but errors, for scripts that go by the exit code
+    #[arg(short, long, global = true, conflicts_with = "verbose")]
+    quiet: bool,
+
+    /// Write warnings and log lines as JSON objects, one per line
+    #[arg(long, global = true)]
+    log_json: bool,
+}
+
+impl Cli {
+    /// The config given with `--config`, the first one if there are several
+    fn config(&self) -> Option<&PathBuf> {
+        self.config.first()
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum Format {
+    Text,
+    Json,
+    /// Graphviz (portal graph only)
+    Dot,
+    /// Mermaid flowchart (portal graph only)
+    Mermaid,
+    /// Markdown (portal report and compare only)
+    Markdown,
+}
+
+impl Format {
+    /// Whether `command` can print this format; text and JSON work everywhere
+    fn applies_to(self, command: &Commands) -> bool {
+        match self {
+            Format::Text | Format::Json => true,
+            Format::Dot | Format::Mermaid => matches!(command, Commands::Graph { .. }),
+            Format::Markdown => {
+                matches!(command, Commands::Report { .. } | Commands::Compare { .. })
+            }
+        }
+    }
 }
 
 #[derive(Subcommand)]
 enum Commands {
     /// Switch to a provider (copilot, openrouter, or custom)
     Switch {
+        /// Provider name (e.g., copilot, openrouter, work-openrouter); omit
+        /// to pick one interactively
+        provider: Option<String>,
+
+        #[command(flatten)]
+        target: TargetArgs,
+
+        #[command(flatten)]
+        args: SwitchArgs,
+    },
+    /// Re-apply a provider whenever something else rewrites the config
+    Watch {
+        /// Provider to keep applied (default: the last one switched to)
+        provider: Option<String>,
+
+        #[command(flatten)]
+        target: TargetArgs,
+
+        /// Leave this agent alone even if its model is changed (repeatable)
+        #[arg(long = "except", value_name = "AGENT")]
+        except: Vec<String>,
+
+        /// Wait this long for writes to settle before re-applying
+        #[arg(long, value_name = "MS", default_value_t = 500)]
+        debounce: u64,
+    },
+    /// Keep providers applied to the configs listed in daemon.json
+    Daemon {
+        #[command(subcommand)]
+        command: DaemonCommand,
+    },
+    /// Serve portal as an MCP server on stdin and stdout, so agents can
+    /// check and switch providers mid-session
+    Mcp,
+    /// Serve a small HTTP API so other tools can read and switch providers
+    Serve {
+        /// Address to listen on
+        #[arg(long, value_name = "ADDR", default_value = "127.0.0.1:7777")]
+        listen: String,
+
+        /// Require `Authorization: Bearer <token>`, with the token read
+        /// from this environment variable
+        #[arg(long, value_name = "VAR")]
+        token_env: Option<String>,
+    },
+    /// Activate a named environment from settings.json and switch to its
+    /// provider, or list environments
+    Env {
+        /// Environment to activate (omit to list them)
+        name: Option<String>,
+
+        /// Deactivate the environment `portal env` activated
+        #[arg(long, conflicts_with = "name")]
+        clear: bool,
+    },
+    /// Check that a provider's endpoint answers and takes its key, before
+    /// switching to it
+    Health {
+        /// Provider name (e.g., copilot, openrouter, work-gateway)
+        provider: String,
+    },
+    /// Show the cached quota of the accounts listed in daemon.json
+    Quota {
+        /// Poll accounts now instead of relying on the daemon, still no
+        /// more often than each provider allows
+        #[arg(long)]
+        refresh: bool,
+    },
+    /// Show which agent models would change when switching to a provider
+    Diff {
         /// Provider name (e.g., copilot, openrouter, work-openrouter)
         provider: String,
+
+        #[command(flatten)]
+        target: TargetArgs,
+    },
+    /// Set up ~/.config/portal/ for first use: find the config, save its
+    /// models as 'default', and write example provider files
+    Init {
+        /// Answer yes to every question, e.g. when not on a terminal
+        #[arg(long, short)]
+        yes: bool,
+    },
+    /// Save the current agent models in a provider file, keeping the
+    /// entries it already has for other agents
+    Capture {
+        /// Provider whose ~/.config/portal/<provider>.json to create or
+        /// update; built-in names add models on top of the built-in provider
+        provider: String,
+    },
+    /// Save the current agent models as a custom provider
+    Save {
+        /// Provider name to write as ~/.config/portal/<name>.json
+        name: String,
+
+        /// Overwrite an existing provider file
+        #[arg(long)]
+        force: bool,
+    },
+    /// Set one agent's model directly
+    Set {
+        /// Agent name (or subagents.<name>)
+        agent: String,
+
+        /// Full model string, e.g. openrouter/anthropic/claude-opus-4.5
+        model: String,
+
+        /// Accept a model portal doesn't know from its mappings
+        #[arg(long)]
+        force: bool,
+    },
+    /// Move every agent to the current provider's fast, balanced, or max
+    /// models
+    Tier {
+        /// fast, balanced, or max
+        #[arg(value_parser = parse_tier)]
+        tier: Tier,
+    },
+    /// Keep an agent on its model through every switch, or list the pinned
+    /// agents
+    Pin {
+        /// Agent name (or subagents.<name>)
+        agent: Option<String>,
+    },
+    /// Let switches change a pinned agent's model again
+    Unpin {
+        /// Agent name (or subagents.<name>)
+        agent: String,
+    },
+    /// Run switch and set steps from a file (or `-` for stdin) as one
+    /// write, with one lock and at most one backup
+    Batch {
+        /// File with one `switch` or `set` step per line, or `-`
+        file: PathBuf,
     },
     /// Show current provider and model configuration
-    Status,
+    Status {
+        /// Also list what changed since portal last wrote the config
+        #[arg(long)]
+        changes: bool,
+    },
+    /// Show what changed in the config since portal last wrote it
+    Changes,
     /// List available providers from ~/.config/portal/
-    List,
+    List {
+        /// Print only provider names, one per line
+        #[arg(long)]
+        names: bool,
+
+        /// Also show each provider's notes and what its file sets
+        #[arg(long, conflicts_with = "names")]
+        detailed: bool,
+
+        /// Fetch the provider sources in settings.json first
+        #[arg(long)]
+        refresh: bool,
+    },
     /// Revert to a backup
     Revert {
         /// Path to backup file (defaults to latest)
         backup_path: Option<PathBuf>,
+
+        /// Pick the backup from a list, with what restoring it would change
+        #[arg(long, short, conflicts_with = "backup_path")]
+        interactive: bool,
     },
-}
+    /// Step back through portal's writes to the config
+    Undo,
+    /// Reapply a write undone with `portal undo`
+    Redo,
+    /// Find the first backup where a problem appeared
+    Bisect {
+        #[command(subcommand)]
+        command: BisectCommand,
+    },
+    /// List or prune config backups
+    Backups {
+        #[command(subcommand)]
+        command: BackupsCommand,
+    },
+    /// Check the config, provider files, and backups for problems
+    Doctor {
+        /// Apply the available automated fixes (after confirmation)
+        #[arg(long)]
+        fix: bool,
 
-// ============================================================================
-// Config Operations
-// ============================================================================
+        /// Don't ask for confirmation before fixing
+        #[arg(short, long, requires = "fix")]
+        yes: bool,
+    },
+    /// Check the config and provider files for malformed agents and models
+    Validate {
+        /// Also validate the config against the schema its `$schema` names
+        /// (fetched with curl when it is a URL)
+        #[arg(long)]
+        schema: bool,
+    },
+    /// Generate a shell completion script
+    Completions {
+        #[arg(value_enum)]
+        shell: Shell,
+    },
+    /// Print shell code to load in your shell's startup file
+    Hook {
+        #[arg(value_enum)]
+        shell: Shell,
+        /// Show the current provider in the terminal window title
+        #[arg(long, required = true)]
+        title: bool,
+    },
+    /// Print the current provider for a shell prompt or status bar; prints
+    /// nothing when no provider is known
+    Prompt {
+        /// What to print: {provider} and {symbol} are filled in
+        #[arg(long, default_value = "{symbol} {provider}")]
+        template: String,
 
-fn get_portal_dir() -> PathBuf {
-    std::env::var("XDG_CONFIG_HOME")
-        .map(PathBuf::from)
-        .unwrap_or_else(|_| {
-            dirs::home_dir()
-                .expect("Could not determine home directory")
-                .join(".config")
-        })
-        .join("portal")
-}
+        /// Color the output
+        #[arg(long, value_enum)]
+        color: Option<PromptColor>,
 
-fn get_config_path(custom: Option<PathBuf>) -> Result<PathBuf> {
-    if let Some(path) = custom {
-        return Ok(path);
-    }
+        /// Write colors for bash's PS1, zsh's PROMPT, or tmux's status line
+        /// instead of as plain ANSI escapes
+        #[arg(long = "for", value_enum, default_value_t = PromptTarget::Ansi)]
+        target: PromptTarget,
+    },
+    /// Print portal's event log as line-delimited JSON
+    Events {
+        /// Keep running and print new events as they happen
+        #[arg(short, long)]
+        follow: bool,
+    },
+    /// Check this build end to end in a temporary sandbox, leaving real files alone
+    VerifyInstall,
+    /// Turn the sed/jq model rewrites in a switching script into provider files
+    Adopt {
+        /// Shell script that switches providers
+        script: PathBuf,
 
-    let config_dir = std::env::var("XDG_CONFIG_HOME")
-        .map(PathBuf::from)
-        .unwrap_or_else(|_| {
-            dirs::home_dir()
-                .expect("Could not determine home directory")
-                .join(".config")
-        });
-    Ok(config_dir.join("opencode").join("oh-my-opencode.json"))
+        /// Overwrite existing provider files
+        #[arg(long)]
+        force: bool,
+    },
+    /// Generate a set of provider files from a matrix definition
+    Generate {
+        /// JSON file describing providers, tiers, and accounts
+        #[arg(long, value_name = "PATH")]
+        matrix: PathBuf,
+    },
+    /// Summarize switches, provider time, and quotas from local state
+    Report {
+        /// How far back to look
+        #[arg(long, value_enum, default_value_t = Period::Week)]
+        period: Period,
+    },
+    /// Show side by side what every agent's model would be on two providers
+    Compare {
+        /// First provider
+        provider_a: String,
+        /// Second provider
+        provider_b: String,
+        /// Only list agents the two providers disagree on
+        #[arg(long)]
+        differences: bool,
+    },
+    /// Show how each agent's model maps to each provider, as a tree or a
+    /// diagram (--format dot or mermaid)
+    Graph {
+        /// Only show this provider (repeatable; default: all of them)
+        #[arg(long = "provider", value_name = "NAME")]
+        providers: Vec<String>,
+    },
+    /// Manage provider files in ~/.config/portal/
+    Provider {
+        #[command(subcommand)]
+        command: ProviderCommand,
+    },
+    /// Maintain mappings.json
+    Mappings {
+        #[command(subcommand)]
+        command: MappingsCommand,
+    },
+    /// Keep API keys in the OS keychain for `{secret:NAME}` placeholders
+    Secret {
+        #[command(subcommand)]
+        command: SecretCommand,
+    },
+    /// Pack provider files, mappings.json, and settings.json into a
+    /// .tar.gz bundle for another machine or a team
+    Export {
+        /// Bundle to write, e.g. portal-setup.tar.gz
+        bundle: PathBuf,
+        /// Overwrite the bundle if it exists
+        #[arg(long)]
+        force: bool,
+        /// Export provider files even if they hold what looks like a raw
+        /// API key
+        #[arg(long)]
+        allow_inline_secrets: bool,
+    },
+    /// Unpack a bundle made by `portal export` into ~/.config/portal/
+    Import {
+        bundle: PathBuf,
+        /// Replace files that differ from the bundle's
+        #[arg(long)]
+        force: bool,
+    },
+    /// Sync ~/.config/portal/ through git: commit local edits, merge the
+    /// upstream's, and push
+    Sync {
+        #[command(subcommand)]
+        command: Option<SyncCommand>,
+    },
 }
 
-fn get_provider_config_path(provider: &str) -> PathBuf {
-    get_portal_dir().join(format!("{}.json", provider))
+#[derive(Subcommand)]
+enum SyncCommand {
+    /// Make ~/.config/portal/ a git repository, cloning or joining REMOTE
+    /// if given
+    Init {
+        /// Repository to sync with, e.g. git@github.com:me/portal-setup.git
+        remote: Option<String>,
+    },
+    /// Show uncommitted edits and how far the upstream is ahead or behind
+    Status,
 }
 
-fn read_config(path: &PathBuf) -> Result<OhMyOpenCodeConfig> {
-    let content = fs::read_to_string(path)
-        .with_context(|| format!("Failed to read config file: {}", path.display()))?;
-    serde_json::from_str(&content)
-        .with_context(|| format!("Failed to parse config file: {}", path.display()))
+#[derive(Subcommand)]
+enum ProviderCommand {
+    /// Write a new provider file, pinning the current agent models unless
+    /// --model is given
+    Add {
+        /// Provider name to write as ~/.config/portal/<name>.json
+        name: String,
+
+        /// Pin one agent to a model (repeatable)
+        #[arg(long = "model", value_name = "AGENT=MODEL")]
+        models: Vec<String>,
+
+        /// Set options.baseURL
+        #[arg(long)]
+        base_url: Option<String>,
+
+        /// Set options.apiKey, e.g. '{env:WORK_KEY}' or '{secret:work}'
+        #[arg(long)]
+        api_key: Option<String>,
+
+        /// Overwrite an existing provider file
+        #[arg(long)]
+        force: bool,
+    },
+    /// Delete a provider file, and the aliases and approval pointing at it
+    Remove {
+        /// Provider name
+        provider: String,
+    },
+    /// Rename a provider, updating aliases, settings, and history
+    Rename {
+        /// Current provider name
+        provider: String,
+        /// New provider name
+        to: String,
+    },
+    /// Copy a provider (built-in ones included) to a new provider file
+    Copy {
+        /// Provider to copy
+        provider: String,
+        /// Name of the copy
+        to: String,
+    },
+    /// Generate provider files for opencode providers portal doesn't know yet
+    Discover {
+        /// Read a `provider/model` listing from a file instead of running `opencode models`
+        #[arg(long)]
+        from: Option<PathBuf>,
+    },
+    /// Show a provider's description, notes, agent models, and rules
+    Show {
+        /// Provider name
+        provider: String,
+    },
+    /// Pin a provider file's current contents as approved in policy.json
+    Approve {
+        /// Provider name
+        provider: String,
+    },
 }
 
-fn read_provider_config(provider: &str) -> Result<Option<ProviderConfig>> {
-    let path = get_provider_config_path(provider);
-    if !path.exists() {
-        return Ok(None);
-    }
-    let content = fs::read_to_string(&path)
-        .with_context(|| format!("Failed to read provider config: {}", path.display()))?;
-    let config: ProviderConfig = serde_json::from_str(&content)
-        .with_context(|| format!("Failed to parse provider config: {}", path.display()))?;
-    Ok(Some(config))
+#[derive(Subcommand)]
+enum MappingsCommand {
+    /// Add rows to mappings.json for OpenRouter models the table lacks
+    Sync {
+        /// Take models from this vendor (repeatable; default: anthropic,
+        /// google, openai)
+        #[arg(long = "vendor", value_name = "VENDOR")]
+        vendors: Vec<String>,
+
+        /// Read OpenRouter's model list from a file instead of fetching it
+        #[arg(long)]
+        from: Option<PathBuf>,
+    },
 }
 
-fn has_existing_backup(config_path: &PathBuf) -> bool {
-    let parent = config_path.parent().unwrap_or(config_path);
-    if let Ok(entries) = fs::read_dir(parent) {
-        for entry in entries.flatten() {
-            let name = entry.file_name();
-            let name_str = name.to_string_lossy();
-            if name_str.starts_with("oh-my-opencode.json.bak.") {
-                return true;
-            }
-        }
-    }
-    false
+#[derive(Subcommand)]
+enum SecretCommand {
+    /// Store a secret, read from the terminal without echo, or from stdin
+    Set {
+        /// Secret name, as used in `{secret:NAME}`
+        name: String,
+    },
+    /// Print a stored secret
+    Get {
+        /// Secret name
+        name: String,
+    },
+    /// Remove a stored secret
+    Delete {
+        /// Secret name
+        name: String,
+    },
 }
 
-fn find_latest_backup(config_path: &PathBuf) -> Option<PathBuf> {
-    let parent = config_path.parent()?;
-    let mut backups: Vec<_> = fs::read_dir(parent)
-        .ok()?
-        .flatten()
-        .filter(|e| {
-            e.file_name()
-                .to_string_lossy()
-                .starts_with("oh-my-opencode.json.bak.")
-        })
-        .collect();
+#[derive(Subcommand)]
+enum BisectCommand {
+    /// Start bisecting the backups of the config
+    Start {
+        /// A backup known to be good (default: test the oldest first)
+        #[arg(long, value_name = "BACKUP")]
+        good: Option<PathBuf>,
 
-    backups.sort_by_key(|e| e.file_name());
-    backups.last().map(|e| e.path())
+        /// A backup known to be bad (default: the current config)
+        #[arg(long, value_name = "BACKUP")]
+        bad: Option<PathBuf>,
+    },
+    /// The restored config works
+    Good,
+    /// The restored config has the problem
+    Bad,
+    /// Mark each candidate by running a command: exit 0 is good, anything
+    /// else is bad
+    Run {
+        #[arg(required = true, trailing_var_arg = true, allow_hyphen_values = true)]
+        command: Vec<String>,
+    },
+    /// Stop bisecting and restore the original config
+    Reset,
 }
 
-fn write_config(path: &PathBuf, config: &OhMyOpenCodeConfig, force_backup: bool) -> Result<()> {
-    let should_backup = force_backup || !has_existing_backup(path);
+#[derive(Subcommand)]
+enum DaemonCommand {
+    /// Run in the foreground, re-applying providers after external rewrites
+    /// and answering status queries
+    Run,
+    /// Make the running daemon re-read daemon.json, providers, and policy
+    Reload,
+    /// Write a systemd user unit (Linux) or launchd agent (macOS) that runs
+    /// the daemon at login and restarts it if it crashes
+    Install {
+        /// Service manager to write for (default: this platform's)
+        #[arg(long, value_enum)]
+        manager: Option<Manager>,
 
-    if should_backup && path.exists() {
-        let timestamp = Utc::now().format("%Y-%m-%dT%H-%M-%S-%3fZ");
-        let backup_path = path.with_extension(format!("json.bak.{}", timestamp));
-        fs::copy(path, &backup_path)
-            .with_context(|| format!("Failed to create backup at: {}", backup_path.display()))?;
-        eprintln!("Backup created: {}", backup_path.display());
-    }
+        /// Also enable and start the service
+        #[arg(long)]
+        enable: bool,
 
-    let content = serde_json::to_string_pretty(config)?;
-    fs::write(path, format!("{}\n", content))
-        .with_context(|| format!("Failed to write config file: {}", path.display()))?;
-    Ok(())
+        /// Replace an existing service file that differs
+        #[arg(long)]
+        force: bool,
+    },
 }
 
-// ============================================================================
-// Provider Switching Logic
-// ============================================================================
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum Manager {
+    Systemd,
+    Launchd,
+}
+
+#[derive(Subcommand)]
+enum BackupsCommand {
+    /// Show each backup's timestamp, size, and provider
+    List,
+    /// Show every backup channel in the managed store
+    Channels,
+    /// Delete old backups
+    #[command(group(clap::ArgGroup::new("rule").required(true).multiple(true)))]
+    Prune {
+        /// Keep this many of the newest backups
+        #[arg(long, value_name = "N", group = "rule")]
+        keep: Option<usize>,
 
-fn detect_current_provider(config: &OhMyOpenCodeConfig) -> Option<String> {
-    let first_model = config.agents.values().next()?.model.as_str();
-    let parts: Vec<&str> = first_model.split('/').collect();
-    parts.first().map(|s| s.to_string())
+        /// Only delete backups older than this (e.g. 30d, 12h, 2w)
+        #[arg(long, value_name = "AGE", value_parser = parse_age, group = "rule")]
+        older_than: Option<chrono::Duration>,
+    },
+    /// Move backups older versions left next to the config into the store
+    Migrate {
+        /// Backups to compress or encrypt at once (default: one per CPU)
+        #[arg(long, value_name = "N")]
+        jobs: Option<usize>,
+    },
 }
 
-fn extract_base_model(model: &str) -> String {
-    let parts: Vec<&str> = model.split('/').collect();
-    match parts.as_slice() {
-        [_, model] => model.to_string(),
-        [_, _, model] => model.to_string(),
-        [_, _, _, rest @ ..] => rest.join("/"),
-        _ => model.to_string(),
+/// Parse an age like `30d`, `12h`, `45m`, or `2w`
+fn parse_age(s: &str) -> Result<chrono::Duration, String> {
+    let split = s.len() - s.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+    let (number, unit) = s.split_at(split);
+    let n: i64 = number
+        .parse()
+        .map_err(|_| format!("expected a number followed by m, h, d, or w: {}", s))?;
+    match unit {
+        "m" => Ok(chrono::Duration::minutes(n)),
+        "h" => Ok(chrono::Duration::hours(n)),
+        "d" => Ok(chrono::Duration::days(n)),
+        "w" => Ok(chrono::Duration::weeks(n)),
+        _ => Err(format!("unknown age unit '{}' (use m, h, d, or w)", unit)),
     }
 }
 
-fn find_mapping(base_model: &str) -> Option<&'static ModelMapping> {
-    MODEL_MAPPINGS.iter().find(|m| {
-        m.base == base_model || m.copilot == base_model || m.openrouter_model == base_model
-    })
+fn parse_tier(s: &str) -> Result<Tier, String> {
+    Tier::ALL
+        .into_iter()
+        .find(|tier| tier.name() == s)
+        .ok_or_else(|| format!("unknown tier '{}' (use fast, balanced, or max)", s))
 }
 
-fn transform_to_builtin_provider(base_model: &str, provider: &str) -> Option<String> {
-    let mapping = find_mapping(base_model)?;
-
-    match provider {
-        "copilot" | "github-copilot" => Some(format!("github-copilot/{}", mapping.copilot)),
-        "openrouter" => Some(format!(
-            "openrouter/{}/{}",
-            mapping.openrouter_provider, mapping.openrouter_model
-        )),
-        _ => None,
+/// Parse an `AGENT=MODEL` override
+fn parse_override(s: &str) -> Result<(String, String), String> {
+    match s.split_once('=') {
+        Some((agent, model)) if !agent.is_empty() && !model.is_empty() => {
+            Ok((agent.to_string(), model.to_string()))
+        }
+        _ => Err(format!("expected AGENT=MODEL: {}", s)),
     }
 }
 
-fn infer_openrouter_model(base_model: &str) -> String {
-    let provider = if base_model.starts_with("claude") {
-        "anthropic"
-    } else if base_model.starts_with("gpt")
-        || base_model.starts_with("o1")
-        || base_model.starts_with("o3")
-        || base_model.starts_with("o4")
-    {
-        "openai"
-    } else if base_model.starts_with("gemini") {
-        "google"
-    } else {
-        "unknown"
-    };
-    format!("openrouter/{}/{}", provider, base_model)
+/// How `portal switch` writes, and for how long
+#[derive(Args, Default)]
+struct SwitchArgs {
+    /// Write the switched config to this file and leave the original untouched
+    #[arg(long, value_name = "PATH")]
+    out: Option<PathBuf>,
+
+    /// Review and edit the per-agent changes in $EDITOR before applying
+    #[arg(long)]
+    edit_plan: bool,
+
+    /// Check new OpenRouter models against the live OpenRouter catalog
+    /// and refuse to write unknown ones
+    #[arg(long)]
+    verify_models: bool,
+
+    /// Write the provider's client options even if they look like they
+    /// hold raw API keys
+    #[arg(long)]
+    allow_inline_secrets: bool,
+
+    /// Switch back automatically at this time: a duration (2h, 90m), a
+    /// time of day (17:30), or a date and time (2026-01-31 17:30)
+    #[arg(long, value_name = "TIME", conflicts_with_all = ["until_reboot", "out", "edit_plan"])]
+    until: Option<String>,
+
+    /// Switch back automatically after the next reboot
+    #[arg(long, conflicts_with_all = ["out", "edit_plan"])]
+    until_reboot: bool,
+
+    /// Keep the switch until the next one (the default). Without a
+    /// provider, keeps the current temporary switch for good.
+    #[arg(long, conflicts_with_all = ["until", "until_reboot"])]
+    sticky: bool,
+
+    /// Give this agent (or subagents.<name>) this model after the switch,
+    /// just this once (repeatable)
+    #[arg(long = "set", value_name = "AGENT=MODEL", value_parser = parse_override)]
+    overrides: Vec<(String, String)>,
+
+    /// Switch every config of this fleet in settings.json (and any given
+    /// with --config), all of them or none
+    #[arg(long, value_name = "NAME", conflicts_with_all = ["out", "edit_plan", "verify_models", "sticky"])]
+    fleet: Option<String>,
 }
 
-fn switch_to_provider(
-    config: &mut OhMyOpenCodeConfig,
-    provider: &str,
-    provider_config: Option<&ProviderConfig>,
-) -> Result<()> {
-    for (agent_name, agent_config) in config.agents.iter_mut() {
-        // Check if provider config has explicit mapping for this agent
-        if let Some(pc) = provider_config {
-            if let Some(agent_override) = pc.agents.get(agent_name) {
-                agent_config.model = agent_override.model.clone();
-                continue;
-            }
+impl SwitchArgs {
+    fn expiry(&self) -> Result<Option<Expiry>> {
+        if self.until_reboot {
+            return Ok(Some(Expiry::Reboot));
         }
+        self.until
+            .as_deref()
+            .map(|until| {
+                Ok(Expiry::At(temporary::parse_until(
+                    until,
+                    chrono::Local::now(),
+                )?))
+            })
+            .transpose()
+    }
+}
 
-        // Fall back to built-in transformations
-        let base = extract_base_model(&agent_config.model);
-        let canonical_base = find_mapping(&base).map(|m| m.base).unwrap_or(&base);
+/// Which config and agents a switch applies to
+#[derive(Args)]
+struct TargetArgs {
+    /// Which agents to switch: all of them, or only those listed in the
+    /// nearest .portal.json
+    #[arg(long, value_enum, default_value_t = Scope::Global)]
+    scope: Scope,
 
-        if let Some(new_model) = transform_to_builtin_provider(canonical_base, provider) {
-            agent_config.model = new_model;
-        } else {
-            // Custom provider without explicit config - best effort
-            match provider {
-                p if p.contains("openrouter") => {
-                    agent_config.model = infer_openrouter_model(canonical_base);
-                }
-                p if p.contains("copilot") => {
-                    agent_config.model = format!("github-copilot/{}", canonical_base);
-                }
-                _ => {
-                    eprintln!(
-                        "Warning: No mapping for agent '{}' with provider '{}', keeping current model",
-                        agent_name, provider
-                    );
-                }
-            }
+    /// Only switch this agent (repeatable); overrides the project's agent list
+    #[arg(long = "agent", value_name = "NAME")]
+    agents: Vec<String>,
+
+    /// Leave the top-level default models (model, small_model) unchanged
+    #[arg(long)]
+    no_global_models: bool,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum Period {
+    Day,
+    Week,
+    Month,
+}
+
+impl Period {
+    fn days(self) -> i64 {
+        match self {
+            Period::Day => 1,
+            Period::Week => 7,
+            Period::Month => 30,
         }
     }
+}
 
-    Ok(())
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum Scope {
+    Global,
+    Project,
 }
 
 // ============================================================================
 // Commands
 // ============================================================================
 
-fn cmd_switch(cli: &Cli, provider: &str) -> Result<()> {
-    let config_path = get_config_path(cli.config.clone())?;
-
-    if !config_path.exists() {
-        return Err(anyhow!(
-            "Config file not found: {}\n\nMake sure oh-my-opencode is configured.",
-            config_path.display()
+fn print_warnings(provider: &str, report: &SwitchReport, compatibility: &Compatibility) {
+    if compatibility.is_conservative() {
+        let reasons: String = compatibility
+            .reasons
+            .iter()
+            .map(|reason| format!("\n  - {}", reason))
+            .collect();
+        portal_core::portal_warn!(
+            "Portal doesn't fully understand this config; only the model strings of agents, subagents, and top-level model fields will be changed.{}\n  Consider updating portal.",
+            reasons
+        );
+    }
+    for agent_name in &report.unmapped {
+        portal_core::portal_warn!(
+            "No mapping for agent '{}' with provider '{}', keeping current model",
+            agent_name,
+            provider
+        );
+    }
+    if !report.pinned.is_empty() {
+        note(format!(
+            "Pinned, keeping their current models: {}",
+            report.pinned.join(", ")
         ));
     }
+}
 
-    let mut config = read_config(&config_path)?;
-    let provider_config = read_provider_config(provider)?;
+/// A note on stderr about what portal did, left out with `--quiet`
+fn note(message: impl std::fmt::Display) {
+    if !exit::is_quiet() {
+        eprintln!("{}", message);
+    }
+}
 
-    switch_to_provider(&mut config, provider, provider_config.as_ref())?;
+fn print_options(report: &SwitchReport, dry_run: bool) {
+    if report.options.is_empty() {
+        return;
+    }
+    println!(
+        "{} provider options for: {}",
+        if dry_run { "Would update" } else { "Updated" },
+        report.options.join(", ")
+    );
+}
 
-    if cli.dry_run {
-        println!("Dry run - would switch to '{}':", provider);
-        println!();
-        for (name, agent) in &config.agents {
-            println!("  {}: {}", name, agent.model);
+/// Resolve `--scope`/`--agent` into the portal to operate on and switch options
+fn resolve_target(
+    cli: &Cli,
+    portal: &Portal,
+    target: &TargetArgs,
+) -> Result<(Portal, SwitchOptions)> {
+    let mut portal = portal.clone();
+    let mut options = SwitchOptions {
+        force_backup: cli.backup,
+        skip_top_level: target.no_global_models,
+        ..Default::default()
+    };
+
+    if target.scope == Scope::Project {
+        let cwd = std::env::current_dir()?;
+        let project = project::discover(&cwd)?.ok_or_else(|| {
+            anyhow!(
+                "No {} found in {} or any parent directory",
+                project::PROJECT_FILE,
+                cwd.display()
+            )
+        })?;
+        // An explicit --config wins over the project's own config
+        if cli.config().is_none()
+            && let Some(path) = project.config_path()
+        {
+            portal = portal.for_config(path);
         }
-    } else {
-        write_config(&config_path, &config, cli.backup)?;
-        println!("Switched to '{}' provider.", provider);
+        options.agents = Some(project.config.agents);
+    }
+    if !target.agents.is_empty() {
+        options.agents = Some(target.agents.clone());
     }
 
-    Ok(())
+    Ok((portal, options))
 }
 
-fn cmd_status(cli: &Cli) -> Result<()> {
-    let config_path = get_config_path(cli.config.clone())?;
+/// Global options that take a value, so the word after them isn't the
+/// subcommand
+const VALUE_OPTIONS: &[&str] = &["-c", "--config", "--format"];
 
-    if !config_path.exists() {
-        return Err(anyhow!(
-            "Config file not found: {}\n\nMake sure oh-my-opencode is configured.",
-            config_path.display()
-        ));
+/// Replace the subcommand with what it stands for when it is a command
+/// alias from settings.json. Built-in commands always win, and aliases
+/// don't expand further.
+fn expand_command_alias(mut args: Vec<OsString>) -> Result<Vec<OsString>> {
+    let mut index = 1;
+    while let Some(arg) = args.get(index).and_then(|a| a.to_str()) {
+        if arg == "--" {
+            return Ok(args);
+        }
+        if VALUE_OPTIONS.contains(&arg) {
+            index += 2;
+        } else if arg.starts_with('-') {
+            index += 1;
+        } else {
+            break;
+        }
+    }
+    let Some(name) = args.get(index).and_then(|a| a.to_str()) else {
+        return Ok(args);
+    };
+    let builtin = Cli::command()
+        .get_subcommands()
+        .any(|c| c.get_name() == name || c.get_all_aliases().any(|a| a == name));
+    if builtin || name == "help" {
+        return Ok(args);
+    }
+    let Some(settings) = settings::read_settings(&paths::portal_dir())? else {
+        return Ok(args);
+    };
+    if let Some(expansion) = settings.command_alias(name) {
+        args.splice(index..=index, expansion?.into_iter().map(OsString::from));
     }
+    Ok(args)
+}
 
-    let config = read_config(&config_path)?;
-    let current = detect_current_provider(&config);
+/// The default locations, adjusted by the active environment
+/// (`PORTAL_ENV`, or the one `portal env` activated)
+fn discover_portal(cli: &Cli) -> Result<Portal> {
+    let portal = Portal::discover(cli.config().cloned());
+    let Some(name) = settings::active_environment(portal.state_dir())? else {
+        return apply_project(cli, portal);
+    };
+    let settings = settings::read_settings(portal.portal_dir())?.unwrap_or_default();
+    let environment = settings.environment(&name).with_context(|| {
+        format!(
+            "Active environment '{}' is not usable; change {} or run `portal env --clear`",
+            name,
+            settings::ENV_VAR
+        )
+    })?;
+    apply_project(cli, apply_environment(cli, portal, environment))
+}
 
-    println!("Config: {}", config_path.display());
-    println!();
-    println!(
-        "Provider: {}",
-        current.unwrap_or_else(|| "Unknown".to_string())
-    );
-    println!();
-    println!("Agents:");
-    for (name, agent) in &config.agents {
-        println!("  {}: {}", name, agent.model);
+fn current_project() -> Result<Option<project::Project>> {
+    match std::env::current_dir() {
+        Ok(cwd) => project::discover(&cwd),
+        Err(_) => Ok(None),
     }
+}
 
-    Ok(())
+/// The nearest project's mapping overrides on top, and its config unless
+/// `--config` names another
+fn apply_project(cli: &Cli, portal: Portal) -> Result<Portal> {
+    let Some(project) = current_project()? else {
+        return Ok(portal);
+    };
+    let portal = portal.with_mapping_overrides(project.config.mapping_overrides());
+    Ok(match (cli.config(), project.config_path()) {
+        (None, Some(config)) => portal.for_config(config),
+        _ => portal,
+    })
 }
 
-fn cmd_list() -> Result<()> {
-    println!("Built-in providers:");
-    println!("  copilot     - GitHub Copilot (github-copilot/model)");
-    println!("  openrouter  - OpenRouter (openrouter/provider/model)");
-    println!();
+/// The provider the nearest project switches to by default
+fn project_provider() -> Option<String> {
+    current_project().ok().flatten()?.config.provider
+}
 
-    let portal_dir = get_portal_dir();
-    if portal_dir.exists() {
-        let custom_providers: Vec<_> = fs::read_dir(&portal_dir)?
-            .flatten()
-            .filter(|e| e.path().extension().is_some_and(|ext| ext == "json"))
-            .map(|e| e.path().file_stem().unwrap().to_string_lossy().to_string())
-            .collect();
+fn apply_environment(cli: &Cli, portal: Portal, environment: &Environment) -> Portal {
+    let portal = portal.with_mapping_overrides(environment.mapping_overrides());
+    // An explicit --config wins over the environment's
+    match (cli.config(), &environment.config) {
+        (None, Some(config)) => portal.for_config(paths::expand_home(config)),
+        _ => portal,
+    }
+}
 
-        if !custom_providers.is_empty() {
-            println!("Custom providers (from {}):", portal_dir.display());
-            for p in custom_providers {
-                println!("  {}", p);
-            }
-            println!();
-        }
+fn cmd_health(cli: &Cli, portal: &Portal, provider: &str) -> Result<()> {
+    let provider = &portal.resolve_provider(provider)?;
+    let config = portal.provider_config(provider)?;
+    if config.is_none() && !provider::is_builtin_name(provider) {
+        return Err(anyhow!("Unknown provider: '{}'", provider));
     }
+    let health = health::check(provider, config.as_ref().map(|c| &c.options))?;
 
-    println!("Usage: portal switch <provider>");
+    if cli.format == Format::Json {
+        print_json(&serde_json::to_value(&health)?)?;
+    } else {
+        println!("{}: {}", health.provider, health.url);
+        match (&health.error, health.status, health.latency) {
+            (Some(error), _, _) => println!("  Reachable: no ({})", error),
+            (None, Some(status), Some(latency)) => println!(
+                "  Reachable: yes (HTTP {}, {} ms)",
+                status,
+                latency.as_millis()
+            ),
+            _ => println!("  Reachable: no"),
+        }
+        match &health.key_source {
+            Some(source) => println!("  Auth: {} (key from {})", health.auth.describe(), source),
+            None => println!("  Auth: {}", health.auth.describe()),
+        }
+    }
+    if !health.is_healthy() {
+        return Err(anyhow!(
+            "'{}' doesn't look ready to switch to",
+            health.provider
+        ));
+    }
     Ok(())
 }
 
-fn cmd_revert(cli: &Cli, backup_path: Option<PathBuf>) -> Result<()> {
-    let config_path = get_config_path(cli.config.clone())?;
-
-    let backup = match backup_path {
-        Some(p) => {
-            if !p.exists() {
-                return Err(anyhow!("Backup file not found: {}", p.display()));
-            }
-            p
+fn cmd_quota(cli: &Cli, portal: &Portal, refresh: bool) -> Result<()> {
+    let accounts = portal_core::daemon::read_daemon_config(portal.portal_dir())?
+        .map(|config| config.quotas)
+        .unwrap_or_default();
+    if refresh {
+        for account in &accounts {
+            account.check()?;
         }
-        None => find_latest_backup(&config_path).ok_or_else(|| anyhow!("No backup files found"))?,
-    };
+        for error in quota::refresh(portal.state_dir(), &accounts, true)? {
+            portal_core::portal_warn!("{}", error);
+        }
+    }
+    let cache = quota::read_cache(portal.state_dir())?;
 
-    if cli.dry_run {
-        println!("Dry run - would revert to: {}", backup.display());
+    if cli.format == Format::Json {
+        return print_json(&serde_json::to_value(&cache)?);
+    }
+    if cache.is_empty() {
+        if accounts.is_empty() {
+            println!("No quota accounts are listed in daemon.json.");
+        } else {
+            println!("No quota polled yet. Run `portal daemon run` or `portal quota --refresh`.");
+        }
         return Ok(());
     }
-
-    fs::copy(&backup, &config_path)
-        .with_context(|| format!("Failed to restore from backup: {}", backup.display()))?;
-
-    println!("Reverted to: {}", backup.display());
+    let now = chrono::Utc::now();
+    for (name, snapshot) in &cache {
+        let amount = |value: Option<f64>| value.map_or("?".to_string(), |v| format!("{:.2}", v));
+        let figures = match snapshot.limit {
+            None if snapshot.usage.is_none() => "no data".to_string(),
+            Some(limit) => format!(
+                "{} used of {:.2}, {} left",
+                amount(snapshot.usage),
+                limit,
+                amount(snapshot.remaining)
+            ),
+            None => format!("{} used, no limit", amount(snapshot.usage)),
+        };
+        let age = (now - snapshot.fetched_at).num_minutes();
+        println!(
+            "{} ({}): {}  (polled {})",
+            name,
+            snapshot.provider,
+            figures,
+            if age < 1 {
+                "just now".to_string()
+            } else {
+                format!("{}m ago", age)
+            }
+        );
+        if let Some(error) = &snapshot.error {
+            println!(
+                "  {}",
+                paint(&format!("last poll failed: {}", error), Color::Red)
+            );
+        }
+    }
     Ok(())
 }
 
-// ============================================================================
-// Main
-// ============================================================================
+fn cmd_env(cli: &Cli, portal: &Portal, name: Option<&str>, clear: bool) -> Result<()> {
+    let overridden = std::env::var(settings::ENV_VAR)
+        .ok()
+        .filter(|value| !value.is_empty());
+    if clear {
+        if !cli.dry_run {
+            settings::set_active_environment(portal.state_dir(), None)?;
+        }
+        println!("No environment active");
+        if let Some(value) = &overridden {
+            note(format!(
+                "Note: {}={} is set and still selects an environment",
+                settings::ENV_VAR,
+                value
+            ));
+        }
+        return Ok(());
+    }
 
-fn main() -> Result<()> {
-    let cli = Cli::parse();
+    let settings = settings::read_settings(portal.portal_dir())?.unwrap_or_default();
+    let Some(name) = name else {
+        let active = settings::active_environment(portal.state_dir())?;
+        if cli.format == Format::Json {
+            return print_json(&json!({
+                "active": active,
+                "environments": settings.environments,
+            }));
+        }
+        if settings.environments.is_empty() {
+            println!(
+                "No environments defined in {}",
+                settings::settings_file_path(portal.portal_dir()).display()
+            );
+            return Ok(());
+        }
+        for (env_name, environment) in &settings.environments {
+            let marker = if active.as_deref() == Some(env_name.as_str()) {
+                "*"
+            } else {
+                " "
+            };
+            let mut details = Vec::new();
+            if let Some(provider) = &environment.provider {
+                details.push(format!("provider {}", provider));
+            }
+            if let Some(config) = &environment.config {
+                details.push(format!("config {}", config.display()));
+            }
+            if !environment.mappings.is_empty() {
+                details.push(format!(
+                    "{} mapping override(s)",
+                    environment.mappings.len()
+                ));
+            }
+            let line = format!("{} {}  {}", marker, env_name, details.join(", "));
+            println!("{}", line.trim_end());
+        }
+        return Ok(());
+    };
+
+    let environment = settings.environment(name)?;
+    let env_portal = apply_environment(cli, portal.clone(), environment);
+    if !cli.dry_run {
+        settings::set_active_environment(portal.state_dir(), Some(name))?;
+        println!("Environment '{}' is active", name);
+    }
+    if let Some(value) = overridden.filter(|value| value != name) {
+        portal_core::portal_warn!(
+            "{}={} is set and takes precedence over '{}'",
+            settings::ENV_VAR,
+            value,
+            name
+        );
+    }
+    match &environment.provider {
+        Some(provider) => {
+            let target = TargetArgs {
+                scope: Scope::Global,
+                agents: Vec::new(),
+                no_global_models: false,
+            };
+            cmd_switch(
+                cli,
+                &env_portal,
+                Some(provider),
+                &target,
+                &SwitchArgs::default(),
+            )
+        }
+        None => {
+            if cli.dry_run {
+                println!("Dry run - would activate environment '{}'", name);
+            }
+            Ok(())
+        }
+    }
+}
+
+fn print_json(value: &Value) -> Result<()> {
+    println!("{}", serde_json::to_string_pretty(value)?);
+    Ok(())
+}
+
+fn agents_json(agents: impl IntoIterator<Item = (String, String)>) -> Value {
+    Value::Object(
+        agents
+            .into_iter()
+            .map(|(a, m)| (a, Value::String(m)))
+            .collect(),
+    )
+}
+
+/// All provider names: built-ins first, then custom providers, then those
+/// from provider sources. A file named after a built-in only adds models
+/// to it, so it isn't listed twice.
+fn all_providers(portal: &Portal) -> Result<Vec<String>> {
+    let mut providers: Vec<String> = BUILTIN_PROVIDERS
+        .iter()
+        .map(|(name, _)| name.to_string())
+        .collect();
+    providers.extend(
+        portal
+            .custom_providers()?
+            .into_iter()
+            .filter(|name| !provider::is_builtin_name(name)),
+    );
+    providers.extend(portal.remote_providers()?.into_keys());
+    Ok(providers)
+}
+
+/// Providers kiosk mode allows, or `None` outside kiosk mode. The `kiosk`
+/// build is always in kiosk mode.
+fn kiosk_providers(portal: &Portal) -> Result<Option<Vec<String>>> {
+    policy::kiosk_mode(portal.portal_dir(), cfg!(feature = "kiosk"))
+}
+
+/// In kiosk mode, refuse everything but switching among the allowed
+/// providers and commands that only read. Dry runs are always fine.
+fn check_kiosk(cli: &Cli, portal: &Portal) -> Result<()> {
+    let Some(allowed) = kiosk_providers(portal)? else {
+        return Ok(());
+    };
+    let permitted = match &cli.command {
+        Commands::Switch { provider, args, .. } => {
+            if let Some(provider) = provider {
+                policy::check_kiosk_provider(&allowed, &portal.resolve_provider(provider)?)?;
+            }
+            // --set could name a model from any provider
+            args.out.is_none() && !args.edit_plan && args.overrides.is_empty()
+        }
+        Commands::Status { .. }
+        | Commands::Changes
+        | Commands::List { .. }
+        | Commands::Diff { .. }
+        | Commands::Validate { .. }
+        | Commands::Graph { .. }
+        | Commands::Compare { .. }
+        | Commands::Report { .. }
+        | Commands::Quota { .. }
+        | Commands::Health { .. }
+        | Commands::VerifyInstall
+        | Commands::Events { .. }
+        | Commands::Completions { .. }
+        | Commands::Hook { .. }
+        | Commands::Prompt { .. }
+        | Commands::Pin { agent: None }
+        // Their switches keep to the kiosk providers themselves
+        | Commands::Tier { .. }
+        | Commands::Mcp
+        | Commands::Serve { .. }
+        | Commands::Provider {
+            command: ProviderCommand::Show { .. },
+        }
+        | Commands::Doctor { fix: false, .. }
+        | Commands::Env {
+            name: None,
+            clear: false,
+        }
+        | Commands::Backups {
+            command: BackupsCommand::List | BackupsCommand::Channels,
+        }
+        | Commands::Sync {
+            command: Some(SyncCommand::Status),
+        } => true,
+        _ => cli.dry_run,
+    };
+    if permitted {
+        return Ok(());
+    }
+    Err(anyhow!(
+        "Not available in kiosk mode, which only allows switching among: {}",
+        if allowed.is_empty() {
+            "(no providers)".to_string()
+        } else {
+            allowed.join(", ")
+        }
+    ))
+}
+
+/// Interactive provider choice with a per-agent preview for each option
+fn pick_provider(portal: &Portal, options: &SwitchOptions) -> Result<Option<String>> {
+    if !picker::is_interactive() {
+        return Err(anyhow!(
+            "No provider given. Run `portal switch <provider>` or use a terminal to pick one."
+        ));
+    }
+
+    let mut providers = all_providers(portal)?;
+    if let Some(allowed) = kiosk_providers(portal)? {
+        providers = allowed;
+    }
+    let mut items = Vec::new();
+    for provider in &providers {
+        // Show why a provider can't be used (e.g. not approved by policy)
+        // instead of refusing to open the picker at all
+        let changes = match portal.diff(provider, options) {
+            Ok((_, changes)) => changes,
+            Err(e) => {
+                items.push(picker::Item {
+                    label: provider.clone(),
+                    preview: format!("{:#}", e).lines().map(str::to_string).collect(),
+                });
+                continue;
+            }
+        };
+        let preview: Vec<String> = changes
+            .iter()
+            .map(|c| {
+                let before = c.before.as_deref().unwrap_or("-");
+                match &c.after {
+                    Some(after) if c.is_changed() => {
+                        format!("{}: {} -> {}", c.agent, before, after)
+                    }
+                    _ => format!("{}: {} (unchanged)", c.agent, before),
+                }
+            })
+            .collect();
+        let config = portal.provider_config(provider).ok().flatten();
+        let description = config
+            .as_ref()
+            .and_then(|c| c.description.as_deref())
+            .or_else(|| provider::builtin_description(provider));
+        let mut lines: Vec<String> = config
+            .iter()
+            .flat_map(|c| c.notes.iter())
+            .flat_map(|notes| notes.lines())
+            .map(str::to_string)
+            .collect();
+        lines.extend(preview);
+        items.push(picker::Item {
+            label: match description {
+                Some(description) => format!("{} - {}", provider, description),
+                None => provider.clone(),
+            },
+            preview: lines,
+        });
+    }
+
+    Ok(picker::pick("Switch to provider:", &items)?.map(|i| providers[i].clone()))
+}
+
+fn cmd_switch(
+    cli: &Cli,
+    portal: &Portal,
+    provider: Option<&str>,
+    target: &TargetArgs,
+    args: &SwitchArgs,
+) -> Result<()> {
+    let (portal, mut options) = resolve_target(cli, portal, target)?;
+    options.output = args.out.clone();
+    options.allow_inline_secrets = args.allow_inline_secrets;
+    options.overrides = args.overrides.iter().cloned().collect();
+    if args.sticky && provider.is_none() {
+        return cmd_keep_switch(cli, &portal);
+    }
+    let expiry = args.expiry()?;
+    let provider = match provider.map(str::to_string).or_else(project_provider) {
+        Some(provider) => {
+            let provider = portal.resolve_provider(&provider)?;
+            if let Some(allowed) = kiosk_providers(&portal)? {
+                policy::check_kiosk_provider(&allowed, &provider)?;
+            }
+            provider
+        }
+        None => match pick_provider(&portal, &options)? {
+            Some(choice) => choice,
+            None => {
+                println!("Cancelled.");
+                return Ok(());
+            }
+        },
+    };
+    let provider = provider.as_str();
+    let fleet = fleet_portals(cli, &portal, args)?;
+    if !fleet.is_empty() {
+        return cmd_switch_fleet(cli, &fleet, provider, &options, expiry.as_ref());
+    }
+    if args.edit_plan {
+        return cmd_switch_plan(cli, &portal, provider, options, args.verify_models);
+    }
+    if args.verify_models {
+        let (preview, changes) = portal.diff(provider, &options)?;
+        verify_models(
+            changes
+                .iter()
+                .filter(|c| c.is_changed())
+                .filter_map(|c| Some((c.agent.as_str(), c.after.as_deref()?))),
+        )?;
+        // Write what was verified: refuse if the config moved meanwhile
+        options.expect_hash = Some(preview.config_hash);
+    }
+
+    // Other registered config files follow a full switch of this one. Read
+    // them first so a broken settings.json stops the switch before it starts.
+    let extra = if options.output.is_none() && options.agents.is_none() {
+        portal.extra_targets()?
+    } else {
+        Vec::new()
+    };
+    let expiry = expiry.as_ref();
+    let drift = match options.output {
+        None => portal.drift(provider, &options)?,
+        Some(_) => None,
+    };
+
+    if cli.format == Format::Json {
+        let (mut value, code) = if cli.dry_run {
+            let (preview, changes) = portal.diff(provider, &options)?;
+            let code = switch_code(&preview.report, &changes);
+            let value = json!({
+                "dry_run": true,
+                "status": exit::name(code),
+                "provider": provider,
+                "config": portal.config_path(),
+                "agents": agents_json(preview.agent_models()),
+                "changes": changes,
+                "unmapped": preview.report.unmapped,
+                "pinned": preview.report.pinned,
+                "provider_options": preview.report.options,
+                "compatibility": preview.compatibility,
+                "costs": cost_changes_json(&portal, &changes)?,
+                "expires": expiry_json(expiry),
+                "drift": drift_json(drift.as_ref()),
+            });
+            (value, code)
+        } else {
+            let outcome = switch_until(&portal, provider, &options, expiry)?;
+            let code = switch_code(&outcome.report, &outcome.changes);
+            let value = json!({
+                "dry_run": false,
+                "status": exit::name(code),
+                "provider": provider,
+                "config": portal.config_path(),
+                "changes": outcome.changes,
+                "unmapped": outcome.report.unmapped,
+                "pinned": outcome.report.pinned,
+                "provider_options": outcome.report.options,
+                "compatibility": outcome.compatibility,
+                "backup": outcome.backup,
+                "output": options.output,
+                "expires": expiry_json(expiry),
+                "warnings": outcome.warnings,
+                "drift": drift_json(drift.as_ref()),
+            });
+            (value, code)
+        };
+        let failed = if extra.is_empty() {
+            0
+        } else {
+            let (entries, failed) = switch_extra_targets(cli, extra, provider, expiry);
+            value["targets"] = Value::Array(entries);
+            failed
+        };
+        print_json(&value)?;
+        extra_targets_result(failed)?;
+        return exit::status(code);
+    }
+
+    let until = expiry
+        .map(|expiry| format!(" {}", expiry.describe()))
+        .unwrap_or_default();
+    if let Some(drift) = &drift {
+        warn_drift(&portal, drift, cli.dry_run);
+    }
+    let code = if cli.dry_run {
+        let (preview, changes) = portal.diff(provider, &options)?;
+        print_warnings(provider, &preview.report, &preview.compatibility);
+        println!("Dry run - would switch to '{}'{}:", provider, until);
+        println!();
+        print_model_diff(provider, &changes, true);
+        print_cost_changes(&portal, &changes)?;
+        print_options(&preview.report, true);
+        switch_code(&preview.report, &changes)
+    } else {
+        let outcome = switch_until(&portal, provider, &options, expiry)?;
+        let code = switch_code(&outcome.report, &outcome.changes);
+        print_warnings(provider, &outcome.report, &outcome.compatibility);
+        if let Some(backup) = &outcome.backup {
+            note(format!("Backup created: {}", backup.display()));
+        }
+        match &options.output {
+            Some(out) => println!("Wrote '{}' config to {}", provider, out.display()),
+            None if code == exit::ALREADY => {
+                println!(
+                    "Already on '{}' provider{}; nothing changed.",
+                    provider, until
+                )
+            }
+            None => println!("Switched to '{}' provider{}.", provider, until),
+        }
+        print_options(&outcome.report, false);
+        for warning in &outcome.warnings {
+            portal_core::portal_warn!("{}", warning);
+        }
+        code
+    };
+    if !extra.is_empty() {
+        let (_, failed) = switch_extra_targets(cli, extra, provider, expiry);
+        extra_targets_result(failed)?;
+    }
+    exit::status(code)
+}
+
+/// The configs a switch applies to together: those given with `--config`
+/// when there are several, and those of `--fleet`. Empty for an ordinary
+/// switch of one config.
+fn fleet_portals(cli: &Cli, portal: &Portal, args: &SwitchArgs) -> Result<Vec<Portal>> {
+    let mut paths: Vec<PathBuf> = Vec::new();
+    if let Some(name) = &args.fleet {
+        paths.extend(cli.config.iter().cloned());
+        let settings = settings::read_settings(portal.portal_dir())?.unwrap_or_default();
+        paths.extend(settings.fleet(name)?);
+    } else if cli.config.len() > 1 {
+        if args.out.is_some() || args.edit_plan || args.verify_models || args.sticky {
+            return Err(anyhow!(
+                "--out, --edit-plan, --verify-models, and --sticky take a single --config"
+            ));
+        }
+        paths.extend(cli.config.iter().cloned());
+    } else {
+        return Ok(Vec::new());
+    }
+    let canonical = |p: &Path| fs::canonicalize(p).unwrap_or_else(|_| p.to_path_buf());
+    let mut seen = Vec::new();
+    let mut portals = Vec::new();
+    for path in paths {
+        if seen.contains(&canonical(&path)) {
+            continue;
+        }
+        seen.push(canonical(&path));
+        // The first --config is the one settings, environments, and
+        // projects were already applied to
+        portals.push(if cli.config().is_some_and(|first| *first == path) {
+            portal.clone()
+        } else {
+            portal.for_config(path)
+        });
+    }
+    Ok(portals)
+}
+
+/// Switch several configs together, reporting on each; if one fails, the
+/// ones already switched are rolled back
+fn cmd_switch_fleet(
+    cli: &Cli,
+    portals: &[Portal],
+    provider: &str,
+    options: &SwitchOptions,
+    expiry: Option<&Expiry>,
+) -> Result<()> {
+    let json = cli.format == Format::Json;
+    let until = expiry
+        .map(|expiry| format!(" {}", expiry.describe()))
+        .unwrap_or_default();
+
+    if cli.dry_run {
+        let mut entries = Vec::new();
+        let mut codes = Vec::new();
+        if !json {
+            println!(
+                "Dry run - would switch {} configs to '{}'{}:",
+                portals.len(),
+                provider,
+                until
+            );
+        }
+        for portal in portals {
+            let (preview, changes) = portal.diff(provider, options)?;
+            let code = switch_code(&preview.report, &changes);
+            codes.push(code);
+            if json {
+                entries.push(json!({
+                    "config": portal.config_path(),
+                    "status": exit::name(code),
+                    "changes": changes,
+                    "unmapped": preview.report.unmapped,
+                    "pinned": preview.report.pinned,
+                }));
+                continue;
+            }
+            println!();
+            println!("{}:", portal.config_path().display());
+            print_warnings(provider, &preview.report, &preview.compatibility);
+            print_model_diff(provider, &changes, false);
+        }
+        let code = fleet_code(&codes);
+        if json {
+            print_json(&json!({
+                "dry_run": true,
+                "status": exit::name(code),
+                "provider": provider,
+                "expires": expiry_json(expiry),
+                "configs": entries,
+            }))?;
+        }
+        return exit::status(code);
+    }
+
+    let outcome = fleet::switch_all(portals, provider, options, expiry);
+    let mut entries = Vec::new();
+    let mut codes = Vec::new();
+    for result in &outcome.results {
+        let path = result.config.display();
+        let (status, switched, error) = match &result.state {
+            FleetState::Switched(switched) => {
+                let code = switch_code(&switched.report, &switched.changes);
+                codes.push(code);
+                (exit::name(code), Some(switched), None)
+            }
+            FleetState::RolledBack(switched) => ("rolled_back", Some(switched), None),
+            FleetState::RollbackFailed(switched, e) => ("rollback_failed", Some(switched), Some(e)),
+            FleetState::Failed(e) => ("failed", None, Some(e)),
+            FleetState::Skipped => ("skipped", None, None),
+        };
+        if json {
+            entries.push(json!({
+                "config": result.config,
+                "status": status,
+                "changes": switched.map(|s| &s.changes),
+                "unmapped": switched.map(|s| &s.report.unmapped),
+                "pinned": switched.map(|s| &s.report.pinned),
+                "backup": switched.and_then(|s| s.backup.as_ref()),
+                "error": error.map(|e| format!("{:#}", e)),
+            }));
+            continue;
+        }
+        match &result.state {
+            FleetState::Switched(switched) => {
+                print_warnings(provider, &switched.report, &switched.compatibility);
+                if let Some(backup) = &switched.backup {
+                    note(format!("Backup created: {}", backup.display()));
+                }
+                if switch_code(&switched.report, &switched.changes) == exit::ALREADY {
+                    println!("{}: already on '{}'", path, provider);
+                } else {
+                    println!("{}: switched to '{}'{}", path, provider, until);
+                }
+                for warning in &switched.warnings {
+                    portal_core::portal_warn!("{}", warning);
+                }
+            }
+            FleetState::RolledBack(_) => println!("{}: switched, then rolled back", path),
+            FleetState::RollbackFailed(_, e) => {
+                println!("{}: switched, and rolling back failed: {:#}", path, e)
+            }
+            FleetState::Failed(e) => println!("{}: failed: {:#}", path, e),
+            FleetState::Skipped => println!("{}: not switched", path),
+        }
+    }
+    let code = fleet_code(&codes);
+    if json {
+        print_json(&json!({
+            "dry_run": false,
+            "status": if outcome.succeeded() { exit::name(code) } else { "failed" },
+            "provider": provider,
+            "expires": expiry_json(expiry),
+            "configs": entries,
+        }))?;
+    }
+    if let Some((path, _)) = outcome.failure() {
+        let stuck = outcome
+            .results
+            .iter()
+            .filter(|r| matches!(r.state, FleetState::RollbackFailed(..)))
+            .count();
+        return Err(if stuck == 0 {
+            anyhow!(
+                "Switching {} failed, so no config was switched",
+                path.display()
+            )
+        } else {
+            anyhow!(
+                "Switching {} failed, and {} config(s) could not be rolled back",
+                path.display(),
+                stuck
+            )
+        });
+    }
+    exit::status(code)
+}
+
+/// Exit code for a fleet switch, from each config's: [`exit::PARTIAL`] if
+/// any was partial, [`exit::ALREADY`] if all were already on the provider
+fn fleet_code(codes: &[u8]) -> u8 {
+    if codes.contains(&exit::PARTIAL) {
+        exit::PARTIAL
+    } else if !codes.is_empty() && codes.iter().all(|&code| code == exit::ALREADY) {
+        exit::ALREADY
+    } else {
+        exit::SUCCESS
+    }
+}
+
+/// Before a switch, say that the config was edited since portal last wrote
+/// it, and which of those edits the switch overwrites
+fn warn_drift(portal: &Portal, drift: &Drift, dry_run: bool) {
+    portal_core::portal_warn!(
+        "{} was modified since portal last wrote it ({} change(s); `portal changes` lists them)",
+        portal.config_path().display(),
+        drift.changes.len()
+    );
+    if !drift.overwritten.is_empty() {
+        portal_core::portal_warn!(
+            "This switch {} those edits to: {}",
+            if dry_run {
+                "would overwrite"
+            } else {
+                "overwrites"
+            },
+            drift.overwritten.join(", ")
+        );
+    }
+}
+
+fn drift_json(drift: Option<&Drift>) -> Value {
+    match drift {
+        Some(drift) => json!({
+            "changes": drift.changes,
+            "overwritten": drift.overwritten,
+        }),
+        None => Value::Null,
+    }
+}
+
+/// Exit code for a switch that went through: [`exit::PARTIAL`] when some
+/// agents had no mapping, [`exit::ALREADY`] when no agent's model changed
+fn switch_code(report: &SwitchReport, changes: &[ModelChange]) -> u8 {
+    if !report.unmapped.is_empty() {
+        exit::PARTIAL
+    } else if !changes.iter().any(|c| c.is_changed()) {
+        exit::ALREADY
+    } else {
+        exit::SUCCESS
+    }
+}
+
+/// Price per million tokens before and after each changed agent
+fn cost_changes(portal: &Portal, changes: &[ModelChange]) -> Result<Vec<(String, Cost, Cost)>> {
+    let pricing = portal.pricing()?;
+    let mappings = portal.mappings()?;
+    let cost = |model: &Option<String>| match model {
+        Some(model) => pricing.cost(model, &mappings),
+        None => Cost::Unknown,
+    };
+    Ok(changes
+        .iter()
+        .filter(|c| c.is_changed())
+        .map(|c| (c.agent.clone(), cost(&c.before), cost(&c.after)))
+        .filter(|(_, before, after)| *before != Cost::Unknown || *after != Cost::Unknown)
+        .collect())
+}
+
+fn cost_changes_json(portal: &Portal, changes: &[ModelChange]) -> Result<Value> {
+    Ok(cost_changes(portal, changes)?
+        .into_iter()
+        .map(|(agent, before, after)| json!({ "agent": agent, "before": before, "after": after }))
+        .collect())
+}
+
+fn print_cost_changes(portal: &Portal, changes: &[ModelChange]) -> Result<()> {
+    let costs = cost_changes(portal, changes)?;
+    if costs.is_empty() {
+        return Ok(());
+    }
+    println!();
+    println!("Approximate cost per 1M tokens:");
+    for (agent, before, after) in costs {
+        if style::is_plain() {
+            println!(
+                "  {}: from {} to {}",
+                agent,
+                before.describe(),
+                after.describe()
+            );
+        } else {
+            println!("  {}: {} -> {}", agent, before.describe(), after.describe());
+        }
+    }
+    Ok(())
+}
+
+/// After a failed switch, offer the provider that last worked and still
+/// switches cleanly. Only for a plain text switch of the config itself.
+fn offer_previous_provider(
+    cli: &Cli,
+    portal: &Portal,
+    provider: Option<&str>,
+    target: &TargetArgs,
+    args: &SwitchArgs,
+    error: anyhow::Error,
+) -> Result<()> {
+    let Some(provider) = provider else {
+        return Err(error);
+    };
+    // The switch went through, or there is no config to switch
+    if error.is::<exit::Status>()
+        || matches!(
+            error.downcast_ref::<PortalError>(),
+            Some(PortalError::ConfigNotFound(_))
+        )
+    {
+        return Err(error);
+    }
+    if cli.dry_run || cli.format == Format::Json || args.out.is_some() {
+        return Err(error);
+    }
+    let suggestion = (|| {
+        let (target_portal, options) = resolve_target(cli, portal, target)?;
+        let failed = portal.resolve_provider(provider)?;
+        let allowed = kiosk_providers(portal)?;
+        suggest::suggest(&target_portal, &failed, &options, |p| {
+            allowed.as_ref().is_none_or(|a| a.iter().any(|x| x == p))
+        })
+    })();
+    let Ok(Some(suggestion)) = suggestion else {
+        return Err(error);
+    };
+
+    let ago = report::format_duration((chrono::Utc::now() - suggestion.switched_at).num_seconds());
+    if !picker::is_interactive() {
+        return Err(anyhow!(
+            "{:#}\n\n'{}' worked {} ago: portal switch {}",
+            error,
+            suggestion.provider,
+            ago,
+            suggestion.provider
+        ));
+    }
+    eprintln!("Error: {:#}", error);
+    eprintln!();
+    if !picker::confirm(&format!(
+        "'{}' worked {} ago. Switch to it?",
+        suggestion.provider, ago
+    ))? {
+        return Err(anyhow!("Nothing was switched"));
+    }
+    cmd_switch(cli, portal, Some(&suggestion.provider), target, args)
+}
+
+fn switch_until(
+    portal: &Portal,
+    provider: &str,
+    options: &SwitchOptions,
+    expiry: Option<&Expiry>,
+) -> Result<SwitchOutcome> {
+    match expiry {
+        Some(expiry) => portal.switch_temporarily(provider, options, expiry),
+        None => portal.switch(provider, options),
+    }
+}
+
+/// A switch asked for as JSON, by the MCP server's `switch_provider` and
+/// the HTTP API's `POST /switch`: `provider`, and optionally `agents`,
+/// `until`, and `dry_run`. With `dry_run` set here as well, it only ever
+/// previews.
+fn switch_request(portal: &Portal, request: &Value, dry_run: bool) -> Result<Value> {
+    let provider = request
+        .get("provider")
+        .and_then(Value::as_str)
+        .ok_or_else(|| anyhow!("'provider' is required"))?;
+    let provider = portal.resolve_provider(provider)?;
+    let overrides = string_map(request, "set")?;
+    if let Some(allowed) = kiosk_providers(portal)? {
+        policy::check_kiosk_provider(&allowed, &provider)?;
+        if !overrides.is_empty() {
+            return Err(anyhow!("'set' is not available in kiosk mode"));
+        }
+    }
+    let options = SwitchOptions {
+        agents: string_list(request, "agents")?,
+        overrides,
+        ..SwitchOptions::default()
+    };
+    let expiry = match request.get("until") {
+        None | Some(Value::Null) => None,
+        Some(Value::String(until)) => Some(Expiry::At(temporary::parse_until(
+            until,
+            chrono::Local::now(),
+        )?)),
+        Some(_) => return Err(anyhow!("'until' must be a string")),
+    };
+    let dry_run = dry_run || bool_field(request, "dry_run")?;
+
+    if dry_run {
+        let (preview, changes) = portal.diff(&provider, &options)?;
+        let code = switch_code(&preview.report, &changes);
+        return Ok(json!({
+            "dry_run": true,
+            "status": exit::name(code),
+            "provider": provider,
+            "config": portal.config_path(),
+            "changes": changes,
+            "unmapped": preview.report.unmapped,
+            "pinned": preview.report.pinned,
+            "expires": expiry_json(expiry.as_ref()),
+        }));
+    }
+    let outcome = switch_until(portal, &provider, &options, expiry.as_ref())?;
+    let code = switch_code(&outcome.report, &outcome.changes);
+    portal_core::portal_info!(
+        "Switched {} to '{}'",
+        portal.config_path().display(),
+        provider
+    );
+    Ok(json!({
+        "dry_run": false,
+        "status": exit::name(code),
+        "provider": provider,
+        "config": portal.config_path(),
+        "changes": outcome.changes,
+        "unmapped": outcome.report.unmapped,
+        "pinned": outcome.report.pinned,
+        "backup": outcome.backup,
+        "expires": expiry_json(expiry.as_ref()),
+        "warnings": outcome.warnings,
+    }))
+}
+
+/// An optional list of strings in a JSON request
+fn string_list(request: &Value, key: &str) -> Result<Option<Vec<String>>> {
+    match request.get(key) {
+        None | Some(Value::Null) => Ok(None),
+        Some(Value::Array(items)) => items
+            .iter()
+            .map(|item| {
+                item.as_str()
+                    .map(str::to_string)
+                    .ok_or_else(|| anyhow!("'{}' must be a list of strings", key))
+            })
+            .collect::<Result<_>>()
+            .map(Some),
+        Some(_) => Err(anyhow!("'{}' must be a list of strings", key)),
+    }
+}
+
+/// An optional object of strings in a JSON request, empty when absent
+fn string_map(request: &Value, key: &str) -> Result<BTreeMap<String, String>> {
+    match request.get(key) {
+        None | Some(Value::Null) => Ok(BTreeMap::new()),
+        Some(Value::Object(items)) => items
+            .iter()
+            .map(|(name, item)| {
+                item.as_str()
+                    .map(|value| (name.clone(), value.to_string()))
+                    .ok_or_else(|| anyhow!("'{}' must map names to strings", key))
+            })
+            .collect(),
+        Some(_) => Err(anyhow!("'{}' must map names to strings", key)),
+    }
+}
+
+/// An optional flag in a JSON request, false when absent
+fn bool_field(request: &Value, key: &str) -> Result<bool> {
+    match request.get(key) {
+        None | Some(Value::Null) => Ok(false),
+        Some(Value::Bool(value)) => Ok(*value),
+        Some(_) => Err(anyhow!("'{}' must be true or false", key)),
+    }
+}
+
+fn expiry_json(expiry: Option<&Expiry>) -> Value {
+    match expiry {
+        Some(Expiry::At(until)) => json!(until),
+        Some(Expiry::Reboot) => json!("reboot"),
+        None => Value::Null,
+    }
+}
+
+/// `portal switch --sticky` without a provider: keep the temporary
+/// switches of this config and the settings.json targets
+fn cmd_keep_switch(cli: &Cli, portal: &Portal) -> Result<()> {
+    let mut portals = vec![portal.clone()];
+    portals.extend(
+        portal
+            .extra_targets()?
+            .into_iter()
+            .map(|(target, _)| target),
+    );
+    let mut kept = Vec::new();
+    for portal in portals {
+        let Some(record) = portal.temporary_switch()? else {
+            continue;
+        };
+        if !cli.dry_run {
+            portal.make_sticky()?;
+        }
+        kept.push(record);
+    }
+
+    if cli.format == Format::Json {
+        return print_json(&json!({
+            "dry_run": cli.dry_run,
+            "kept": kept
+                .iter()
+                .map(|record| json!({ "config": record.config, "provider": record.provider }))
+                .collect::<Vec<_>>(),
+        }));
+    }
+    if kept.is_empty() {
+        println!(
+            "No temporary switch of {} to keep.",
+            portal.config_path().display()
+        );
+    }
+    for record in &kept {
+        println!(
+            "{} '{}' in {} for good.",
+            if cli.dry_run {
+                "Dry run - would keep"
+            } else {
+                "Keeping"
+            },
+            record.provider,
+            record.config.display()
+        );
+    }
+    Ok(())
+}
+
+/// Revert temporary switches whose time is up, across all configs, and
+/// say what happened through `log`
+fn end_temporary_switches(portal: &Portal, log: impl Fn(&str), mut warn: impl FnMut(&str)) {
+    for result in portal.expire_all_temporary() {
+        match result {
+            Ok(expired) => {
+                if expired.reverted {
+                    let config = expired.record.config.display().to_string();
+                    let why = format!(
+                        "the temporary switch ended ({})",
+                        expired.record.expiry().describe()
+                    );
+                    portal.notify(&match &expired.record.previous_provider {
+                        Some(previous) => notify::switch_message(
+                            &config,
+                            Some(&expired.record.provider),
+                            previous,
+                            &why,
+                        ),
+                        None => format!(
+                            "Restored {} from before '{}': {}",
+                            config, expired.record.provider, why
+                        ),
+                    });
+                    log(&format!(
+                        "Temporary switch to '{}' ended ({}); restored {} in {}",
+                        expired.record.provider,
+                        expired.record.expiry().describe(),
+                        expired
+                            .record
+                            .previous_provider
+                            .as_deref()
+                            .map(|p| format!("'{}'", p))
+                            .unwrap_or_else(|| "the previous config".to_string()),
+                        expired.record.config.display()
+                    ));
+                } else {
+                    log(&format!(
+                        "Temporary switch to '{}' ended ({}), but {} was changed since; left it as it is",
+                        expired.record.provider,
+                        expired.record.expiry().describe(),
+                        expired.record.config.display()
+                    ));
+                }
+                for warning in &expired.warnings {
+                    warn(warning);
+                }
+            }
+            Err(e) => warn(&format!("{:#}", e)),
+        }
+    }
+}
+
+/// Switch the config files registered in settings.json. Text output is
+/// printed as it goes; the JSON entries are returned with how many
+/// failed. Every target is tried even if one fails.
+fn switch_extra_targets(
+    cli: &Cli,
+    targets: Vec<(Portal, Vec<String>)>,
+    provider: &str,
+    expiry: Option<&Expiry>,
+) -> (Vec<Value>, usize) {
+    let json = cli.format == Format::Json;
+    let mut entries = Vec::new();
+    let mut failed = 0;
+    for (target, fields) in targets {
+        let path = target.config_path().to_path_buf();
+        if !path.exists() {
+            if json {
+                entries.push(json!({ "config": path, "skipped": "not found" }));
+            } else {
+                note(format!("Skipping {}: not found", path.display()));
+            }
+            continue;
+        }
+        let result = if cli.dry_run {
+            target
+                .preview_fields(provider, &fields)
+                .map(|(report, changes)| (report, changes, None))
+        } else {
+            let options = SwitchOptions {
+                force_backup: cli.backup,
+                ..Default::default()
+            };
+            match expiry {
+                Some(expiry) => {
+                    target.switch_fields_temporarily(provider, &fields, &options, expiry)
+                }
+                None => target.switch_fields(provider, &fields, &options),
+            }
+            .map(|outcome| (outcome.report, outcome.changes, outcome.backup))
+        };
+        let (report, changes, backup) = match result {
+            Ok(result) => result,
+            Err(e) => {
+                failed += 1;
+                if json {
+                    entries.push(json!({ "config": path, "error": format!("{:#}", e) }));
+                } else {
+                    portal_core::portal_warn!("{}: {:#}", path.display(), e);
+                }
+                continue;
+            }
+        };
+        if json {
+            entries.push(json!({
+                "config": path,
+                "changes": changes,
+                "unmapped": report.unmapped,
+                "backup": backup,
+            }));
+            continue;
+        }
+        if let Some(backup) = &backup {
+            note(format!("Backup created: {}", backup.display()));
+        }
+        let changed: Vec<&ModelChange> = changes.iter().filter(|c| c.is_changed()).collect();
+        println!(
+            "{} {}:",
+            if cli.dry_run {
+                "Would update"
+            } else {
+                "Updated"
+            },
+            path.display()
+        );
+        if changed.is_empty() {
+            println!("  (no changes)");
+        }
+        for change in changed {
+            println!(
+                "  {}: {}",
+                change.agent,
+                change.after.as_deref().unwrap_or("-")
+            );
+        }
+        if !report.unmapped.is_empty() {
+            portal_core::portal_warn!(
+                "No '{}' mapping for {} in {}; left as is",
+                provider,
+                report.unmapped.join(", "),
+                path.display()
+            );
+        }
+    }
+    (entries, failed)
+}
+
+fn extra_targets_result(failed: usize) -> Result<()> {
+    if failed > 0 {
+        return Err(anyhow!(
+            "{} of the config files in settings.json could not be switched",
+            failed
+        ));
+    }
+    Ok(())
+}
+
+/// Render the changes of a switch as an editable plan, one `<agent> <model>`
+/// line per changed agent
+fn render_plan(provider: &str, portal: &Portal, changes: &[&ModelChange]) -> String {
+    let mut plan = format!(
+        "# Switch plan: '{}' for {}\n\
+         #\n\
+         # Each line is \"<agent> <model>\". Delete a line to leave that agent as it\n\
+         # is, or edit the model to use a different one. Lines starting with '#'\n\
+         # are ignored. Delete every line to abort.\n",
+        provider,
+        portal.config_path().display()
+    );
+    for change in changes {
+        plan.push_str(&format!(
+            "\n# was: {}\n{} {}\n",
+            change.before.as_deref().unwrap_or("-"),
+            change.agent,
+            change.after.as_deref().unwrap_or("-")
+        ));
+    }
+    plan
+}
+
+fn parse_plan(text: &str) -> Result<BTreeMap<String, String>> {
+    let mut models = BTreeMap::new();
+    for (n, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        let [agent, model] = fields[..] else {
+            return Err(anyhow!(
+                "Plan line {}: expected \"<agent> <model>\", got: {}",
+                n + 1,
+                line
+            ));
+        };
+        if models
+            .insert(agent.to_string(), model.to_string())
+            .is_some()
+        {
+            return Err(anyhow!("Plan line {}: '{}' appears twice", n + 1, agent));
+        }
+    }
+    Ok(models)
+}
+
+/// Fail if any OpenRouter model among `models` (agent, model) isn't in the
+/// live OpenRouter catalog, printing close matches for each
+fn verify_models<'a>(models: impl IntoIterator<Item = (&'a str, &'a str)>) -> Result<()> {
+    let models: Vec<_> = models
+        .into_iter()
+        .filter(|(_, model)| model.starts_with(catalog::OPENROUTER_PREFIX))
+        .collect();
+    if models.is_empty() {
+        return Ok(());
+    }
+    let catalog = Catalog::fetch_openrouter()
+        .context("Could not verify models (drop --verify-models to skip the check)")?;
+    let unknown = catalog.unknown_models(models);
+    if unknown.is_empty() {
+        return Ok(());
+    }
+    for model in &unknown {
+        eprintln!(
+            "Unknown OpenRouter model for '{}': {}",
+            model.agent, model.model
+        );
+        if !model.suggestions.is_empty() {
+            eprintln!("  did you mean: {}", model.suggestions.join(", "));
+        }
+    }
+    Err(anyhow!(
+        "{} model(s) not in the OpenRouter catalog; nothing was written",
+        unknown.len()
+    ))
+}
+
+fn cmd_switch_plan(
+    cli: &Cli,
+    portal: &Portal,
+    provider: &str,
+    mut options: SwitchOptions,
+    verify: bool,
+) -> Result<()> {
+    let (preview, changes) = portal.diff(provider, &options)?;
+    print_warnings(provider, &preview.report, &preview.compatibility);
+    let changed: Vec<_> = changes.iter().filter(|c| c.is_changed()).collect();
+    if changed.is_empty() {
+        println!("No changes - all agents already match '{}'.", provider);
+        return Ok(());
+    }
+
+    let edited = editor::edit(&render_plan(provider, portal, &changed), "plan.txt")?;
+    let models = parse_plan(&edited)?;
+    if models.is_empty() {
+        println!("Plan is empty - nothing changed.");
+        return Ok(());
+    }
+    if verify {
+        verify_models(models.iter().map(|(a, m)| (a.as_str(), m.as_str())))?;
+    }
+
+    if cli.dry_run {
+        println!("Dry run - would apply:");
+        for (agent, model) in &models {
+            println!("  {}: {}", agent, model);
+        }
+        return Ok(());
+    }
+    // Apply exactly what was reviewed: refuse if the config moved meanwhile
+    options.expect_hash = Some(preview.config_hash);
+    let outcome = portal.apply_models(&models, Some(provider), &options)?;
+    if let Some(backup) = &outcome.backup {
+        note(format!("Backup created: {}", backup.display()));
+    }
+    let applied = outcome.changes.iter().filter(|c| c.is_changed()).count();
+    match &options.output {
+        Some(out) => println!("Wrote plan ({} change(s)) to {}", applied, out.display()),
+        None => println!("Applied plan: {} agent(s) changed.", applied),
+    }
+    print_options(&outcome.report, false);
+    for warning in &outcome.warnings {
+        portal_core::portal_warn!("{}", warning);
+    }
+    Ok(())
+}
+
+/// How often `watch` checks for held-back warnings to sum up
+const SUMMARY_CHECK: std::time::Duration = std::time::Duration::from_secs(60);
+
+fn cmd_watch(
+    cli: &Cli,
+    portal: &Portal,
+    provider: Option<&str>,
+    target: &TargetArgs,
+    except: &[String],
+    debounce: u64,
+) -> Result<()> {
+    let (portal, options) = resolve_target(cli, portal, target)?;
+    let provider = match provider {
+        Some(provider) => portal.resolve_provider(provider)?,
+        None => portal.last_provider()?.ok_or_else(|| {
+            anyhow!(
+                "Portal hasn't switched {} yet, so there is no provider to re-apply. \
+                 Pass one: portal watch <provider>",
+                portal.config_path().display()
+            )
+        })?,
+    };
+
+    let mut watcher = Watcher::new(&[portal.config_path().to_path_buf()])?;
+    note(format!(
+        "Watching {} to keep '{}' applied (Ctrl-C to stop)",
+        portal.config_path().display(),
+        provider
+    ));
+    let debounce = std::time::Duration::from_millis(debounce);
+    let mut throttle = WarningThrottle::default();
+    loop {
+        // A half-written or broken config is reported, and retried on the
+        // next change
+        if let Err(e) = reapply(cli, &portal, &provider, &options, except) {
+            let warning = format!("{:#}", e);
+            if throttle.admit(&warning) {
+                portal_core::portal_warn!("{}", warning);
+            }
+        }
+        // Wake up now and then to sum up warnings held back
+        while !watcher.wait(Some(SUMMARY_CHECK))? {
+            for summary in throttle.summaries() {
+                note(summary);
+            }
+        }
+        // Let a burst of writes settle before looking
+        while watcher.wait(Some(debounce))? {}
+    }
+}
+
+fn cmd_serve(cli: &Cli, portal: &Portal, listen: &str, token_env: &Option<String>) -> Result<()> {
+    let token = match token_env {
+        Some(var) => Some(
+            std::env::var(var)
+                .ok()
+                .filter(|token| !token.trim().is_empty())
+                .ok_or_else(|| anyhow!("{} is not set", var))?
+                .trim()
+                .to_string(),
+        ),
+        None => None,
+    };
+    serve::run(
+        portal,
+        &serve::ServeOptions {
+            listen: listen.to_string(),
+            token,
+            dry_run: cli.dry_run,
+        },
+    )
+}
+
+fn cmd_daemon(cli: &Cli, portal: &Portal, command: &DaemonCommand) -> Result<()> {
+    match command {
+        DaemonCommand::Run => daemon::run(portal, cli.dry_run),
+        DaemonCommand::Reload => {
+            let pid = daemon::reload(portal)?;
+            println!("Asked the daemon (pid {}) to reload", pid);
+            Ok(())
+        }
+        DaemonCommand::Install {
+            manager,
+            enable,
+            force,
+        } => {
+            let manager = match manager {
+                Some(Manager::Systemd) => ServiceManager::Systemd,
+                Some(Manager::Launchd) => ServiceManager::Launchd,
+                None => ServiceManager::native(),
+            };
+            daemon::install(cli, portal, manager, *enable, *force)
+        }
+    }
+}
+
+/// Re-apply `provider` if the config was rewritten, and say so
+fn reapply(
+    cli: &Cli,
+    portal: &Portal,
+    provider: &str,
+    options: &SwitchOptions,
+    except: &[String],
+) -> Result<()> {
+    let Some(changed) = portal.reapply(provider, options, except, cli.dry_run)? else {
+        return Ok(());
+    };
+    let now = chrono::Local::now().format("%H:%M:%S");
+    if cli.dry_run {
+        println!(
+            "[{}] Dry run - would re-apply '{}': {}",
+            now,
+            provider,
+            changed.join(", ")
+        );
+    } else {
+        println!(
+            "[{}] Re-applied '{}': {}",
+            now,
+            provider,
+            changed.join(", ")
+        );
+        portal.notify(&reapplied_message(portal, provider, &changed));
+    }
+    Ok(())
+}
+
+/// The notification for `provider` re-applied over edits to `changed`
+fn reapplied_message(portal: &Portal, provider: &str, changed: &[String]) -> String {
+    notify::switch_message(
+        &portal.config_path().display().to_string(),
+        None,
+        provider,
+        &format!("re-applied over edits to {}", changed.join(", ")),
+    )
+}
+
+fn cmd_diff(cli: &Cli, portal: &Portal, provider: &str, target: &TargetArgs) -> Result<()> {
+    let (portal, options) = resolve_target(cli, portal, target)?;
+    let provider = &portal.resolve_provider(provider)?;
+    let (preview, changes) = portal.diff(provider, &options)?;
+    if cli.format == Format::Json {
+        return print_json(&json!({
+            "provider": provider,
+            "config": portal.config_path(),
+            "changes": changes,
+            "unmapped": preview.report.unmapped,
+            "pinned": preview.report.pinned,
+            "provider_options": preview.report.options,
+            "compatibility": preview.compatibility,
+        }));
+    }
+    print_warnings(provider, &preview.report, &preview.compatibility);
+    print_options(&preview.report, true);
+
+    let changed: Vec<_> = changes.iter().filter(|c| c.is_changed()).collect();
+    if changed.is_empty() {
+        println!("No changes - all agents already match '{}'.", provider);
+        return Ok(());
+    }
+
+    print_model_diff(provider, &changes, false);
+    Ok(())
+}
+
+/// [`print_model_diff`] without colors or `-`/`+` markers
+fn print_plain_model_diff(provider: &str, changes: &[ModelChange], unchanged: bool) {
+    println!("Switching to {}:", provider);
+    for change in changes {
+        let before = change.before.as_deref().unwrap_or("no model");
+        match &change.after {
+            Some(after) if change.is_changed() => {
+                println!("  {}: changes from {} to {}", change.agent, before, after)
+            }
+            None if unchanged => println!("  {}: stays {}, no mapping", change.agent, before),
+            _ if unchanged => println!("  {}: stays {}", change.agent, before),
+            _ => {}
+        }
+    }
+    println!(
+        "{} of {} agents would change.",
+        changes.iter().filter(|c| c.is_changed()).count(),
+        changes.len()
+    );
+}
+
+/// Per-agent before/after lines in red and green under a `---`/`+++`
+/// header, then a count. With `unchanged`, agents that keep their model
+/// are listed too, dimmed.
+fn print_model_diff(provider: &str, changes: &[ModelChange], unchanged: bool) {
+    if style::is_plain() {
+        print_plain_model_diff(provider, changes, unchanged);
+        return;
+    }
+    println!("{}", paint("--- current", Color::Red));
+    println!("{}", paint(&format!("+++ {}", provider), Color::Green));
+    for change in changes {
+        if !change.is_changed() {
+            if unchanged {
+                let model = change.before.as_deref().unwrap_or("-");
+                let note = if change.after.is_none() {
+                    "unchanged, no mapping"
+                } else {
+                    "unchanged"
+                };
+                println!(
+                    "{}",
+                    paint(
+                        &format!("  {}: {} ({})", change.agent, model, note),
+                        Color::Dim
+                    )
+                );
+            }
+            continue;
+        }
+        println!("  {}:", change.agent);
+        if let Some(before) = &change.before {
+            println!("{}", paint(&format!("-   {}", before), Color::Red));
+        }
+        if let Some(after) = &change.after {
+            println!("{}", paint(&format!("+   {}", after), Color::Green));
+        }
+    }
+    println!();
+    println!(
+        "{} of {} agents would change.",
+        changes.iter().filter(|c| c.is_changed()).count(),
+        changes.len()
+    );
+}
+
+/// Provider `portal init` saves the current models as
+const INIT_PROVIDER: &str = "default";
+
+fn cmd_init(cli: &Cli, portal: &Portal, yes: bool) -> Result<()> {
+    let dir = portal.portal_dir();
+    if dir.is_dir() {
+        println!("Using {}", dir.display());
+    } else if cli.dry_run {
+        println!("Dry run - would create {}", dir.display());
+    } else {
+        fs::create_dir_all(dir)
+            .with_context(|| format!("Failed to create portal directory: {}", dir.display()))?;
+        println!("Created {}", dir.display());
+    }
+
+    let found = portal.config_path().exists();
+    let mut agents = Vec::new();
+    if found {
+        println!("Found config: {}", portal.config_path().display());
+        agents = portal
+            .capture()?
+            .agents
+            .into_iter()
+            .map(|(agent, model)| (agent, model.model))
+            .collect();
+    } else {
+        println!(
+            "No config at {}. Run opencode with oh-my-opencode once to create it, \
+             or point portal at it with --config.",
+            portal.config_path().display()
+        );
+    }
+
+    let default_path = provider::provider_config_path(dir, INIT_PROVIDER);
+    if found && default_path.exists() {
+        println!("Kept existing {}", default_path.display());
+    } else if found {
+        let capture = if yes {
+            true
+        } else if picker::is_interactive() {
+            picker::confirm(&format!(
+                "Save the current models as the '{}' provider, to switch back to later?",
+                INIT_PROVIDER
+            ))?
+        } else {
+            println!(
+                "Not saving the current models without a terminal to ask on; \
+                 pass --yes or run `portal save {}`.",
+                INIT_PROVIDER
+            );
+            false
+        };
+        if capture && cli.dry_run {
+            println!(
+                "Dry run - would save {} agent model(s) to {}",
+                agents.len(),
+                default_path.display()
+            );
+        } else if capture {
+            let captured = portal.capture()?;
+            let path = provider::write_provider_config(dir, INIT_PROVIDER, &captured)?;
+            println!(
+                "Saved {} agent model(s) as '{}': {}",
+                captured.agents.len(),
+                INIT_PROVIDER,
+                path.display()
+            );
+        }
+    }
+
+    let mappings = portal.mappings()?;
+    for (name, _) in BUILTIN_PROVIDERS {
+        let path = provider::provider_config_path(dir, name);
+        if path.exists() {
+            println!("Kept existing {}", path.display());
+            continue;
+        }
+        let example = init::example_provider(&agents, name, &mappings);
+        if cli.dry_run {
+            println!(
+                "Dry run - would write an example {} pinning {} agent(s)",
+                path.display(),
+                example.agents.len()
+            );
+            continue;
+        }
+        fsutil::write_atomic(&path, init::render_example(name, &example)?.as_bytes())
+            .with_context(|| format!("Failed to write provider config: {}", path.display()))?;
+        println!(
+            "Wrote example {} pinning {} agent(s)",
+            path.display(),
+            example.agents.len()
+        );
+    }
+
+    println!();
+    println!("Next:");
+    println!("  portal list              # see every provider");
+    println!("  portal diff openrouter   # preview a switch");
+    println!("  portal switch openrouter");
+    Ok(())
+}
+
+fn cmd_capture(cli: &Cli, portal: &Portal, provider: &str) -> Result<()> {
+    let provider = portal.resolve_provider(provider)?;
+    let merge = portal.capture_into(&provider)?;
+    if cli.format == Format::Json {
+        if !cli.dry_run && merge.is_changed() {
+            merge.write()?;
+        }
+        let updated: Vec<Value> = merge
+            .updated
+            .iter()
+            .map(|(agent, previous)| json!({ "agent": agent, "previous": previous }))
+            .collect();
+        return print_json(&json!({
+            "provider": provider,
+            "path": merge.path,
+            "dry_run": cli.dry_run,
+            "added": merge.added,
+            "updated": updated,
+            "unchanged": merge.unchanged,
+        }));
+    }
+
+    if !merge.is_changed() {
+        println!(
+            "{} already has all {} current agent model(s)",
+            merge.path.display(),
+            merge.unchanged
+        );
+        return Ok(());
+    }
+    if cli.dry_run {
+        println!("Dry run - would update {}:", merge.path.display());
+    } else {
+        merge.write()?;
+        println!("Updated {}:", merge.path.display());
+    }
+    for agent in &merge.added {
+        println!("  {}: added", agent);
+    }
+    for (agent, previous) in &merge.updated {
+        println!("  {}: updated, was {}", agent, previous);
+    }
+    if merge.unchanged > 0 {
+        println!("  {} agent(s) already matched", merge.unchanged);
+    }
+    if !cli.dry_run
+        && policy::read_policy(portal.portal_dir())?
+            .is_some_and(|policy| policy.providers.contains_key(&provider))
+    {
+        println!();
+        println!(
+            "policy.json pins '{}', so switching to it is refused until you run \
+             `portal provider approve {}`.",
+            provider, provider
+        );
+    }
+    Ok(())
+}
+
+fn cmd_save(cli: &Cli, portal: &Portal, name: &str, force: bool) -> Result<()> {
+    provider::validate_name(name)?;
+    let path = provider::provider_config_path(portal.portal_dir(), name);
+    if path.exists() && !force {
+        return Err(anyhow!(
+            "Provider '{}' already exists: {}\n\nUse --force to overwrite it.",
+            name,
+            path.display()
+        ));
+    }
+
+    let captured = portal.capture()?;
+    if cli.dry_run {
+        println!("Dry run - would save '{}' to {}:", name, path.display());
+        for (agent, model) in &captured.agents {
+            println!("  {}: {}", agent, model.model);
+        }
+        return Ok(());
+    }
+    let path = provider::write_provider_config(portal.portal_dir(), name, &captured)?;
+    println!(
+        "Saved {} agent model(s) as '{}': {}",
+        captured.agents.len(),
+        name,
+        path.display()
+    );
+    Ok(())
+}
+
+fn cmd_set(cli: &Cli, portal: &Portal, agent: &str, model: &str, force: bool) -> Result<()> {
+    if !force {
+        portal.ensure_known_model(model)?;
+    }
+
+    let models = BTreeMap::from([(agent.to_string(), model.to_string())]);
+    let options = SwitchOptions {
+        force_backup: cli.backup,
+        ..Default::default()
+    };
+    if cli.dry_run {
+        let mut document = config::read_document(portal.config_path())?;
+        let before = config::model_slot_mut(&mut document, agent)
+            .and_then(|m| m.as_str().map(str::to_string))
+            .ok_or_else(|| anyhow!("Agent '{}' not found in config", agent))?;
+        println!("Dry run - would set {}: {} -> {}", agent, before, model);
+        return Ok(());
+    }
+
+    let outcome = portal.apply_models(&models, None, &options)?;
+    if let Some(backup) = &outcome.backup {
+        note(format!("Backup created: {}", backup.display()));
+    }
+    match outcome.changes.iter().find(|c| c.agent == agent) {
+        Some(change) if change.is_changed() => println!(
+            "Set {}: {} -> {}",
+            agent,
+            change.before.as_deref().unwrap_or("-"),
+            model
+        ),
+        _ => println!("{} already uses {}", agent, model),
+    }
+    Ok(())
+}
+
+fn cmd_tier(cli: &Cli, portal: &Portal, tier: Tier) -> Result<()> {
+    let plan = portal.tier_plan(tier)?;
+    if let Some(allowed) = kiosk_providers(portal)? {
+        policy::check_kiosk_provider(&allowed, &plan.provider)?;
+    }
+    let options = SwitchOptions {
+        force_backup: cli.backup,
+        ..Default::default()
+    };
+    let (changes, outcome) = if cli.dry_run || plan.models.is_empty() {
+        let mut document = config::read_document(portal.config_path())?;
+        let changes: Vec<ModelChange> = plan
+            .models
+            .iter()
+            .map(|(agent, model)| ModelChange {
+                agent: agent.clone(),
+                before: config::model_slot_mut(&mut document, agent)
+                    .and_then(|m| m.as_str().map(str::to_string)),
+                after: Some(model.clone()),
+            })
+            .collect();
+        (changes, None)
+    } else {
+        let outcome = portal.apply_models(&plan.models, Some(&plan.provider), &options)?;
+        let changes = outcome
+            .changes
+            .iter()
+            .filter(|c| c.is_changed())
+            .cloned()
+            .collect();
+        (changes, Some(outcome))
+    };
+
+    if cli.format == Format::Json {
+        return print_json(&json!({
+            "dry_run": cli.dry_run,
+            "config": portal.config_path(),
+            "provider": plan.provider,
+            "tier": plan.tier,
+            "changes": changes,
+            "unmatched": plan.unmatched,
+            "pinned": plan.pinned,
+            "backup": outcome.as_ref().and_then(|o| o.backup.as_ref()),
+            "warnings": outcome.as_ref().map(|o| o.warnings.as_slice()).unwrap_or_default(),
+        }));
+    }
+
+    if !plan.unmatched.is_empty() {
+        note(format!(
+            "'{}' has no {} model for {}; left as they are",
+            plan.provider,
+            tier,
+            plan.unmatched.join(", ")
+        ));
+    }
+    if !plan.pinned.is_empty() {
+        note(format!(
+            "Pinned, keeping their current models: {}",
+            plan.pinned.join(", ")
+        ));
+    }
+    if changes.is_empty() {
+        if plan.unmatched.is_empty() {
+            println!(
+                "Every agent already uses {} models on '{}'",
+                tier, plan.provider
+            );
+        }
+        return Ok(());
+    }
+    if let Some(backup) = outcome.as_ref().and_then(|o| o.backup.as_ref()) {
+        note(format!("Backup created: {}", backup.display()));
+    }
+    let verb = if outcome.is_some() {
+        "Moved"
+    } else {
+        "Dry run - would move"
+    };
+    println!(
+        "{} {} agent(s) to {} models on '{}':",
+        verb,
+        changes.len(),
+        tier,
+        plan.provider
+    );
+    for change in &changes {
+        println!(
+            "  {}: {} -> {}",
+            change.agent,
+            change.before.as_deref().unwrap_or("-"),
+            change.after.as_deref().unwrap_or("-")
+        );
+    }
+    for warning in outcome.iter().flat_map(|o| &o.warnings) {
+        portal_core::portal_warn!("{}", warning);
+    }
+    Ok(())
+}
+
+fn cmd_pin(cli: &Cli, portal: &Portal, agent: Option<&str>) -> Result<()> {
+    let Some(agent) = agent else {
+        let pins = portal.pins()?;
+        if cli.format == Format::Json {
+            return print_json(&json!({ "pins": pins }));
+        }
+        if pins.is_empty() {
+            println!("No agents are pinned");
+        }
+        for pin in &pins {
+            println!("{}", pin);
+        }
+        return Ok(());
+    };
+
+    if let Ok(mut document) = config::read_document(portal.config_path())
+        && config::model_slot_mut(&mut document, agent).is_none()
+    {
+        note(format!(
+            "'{}' isn't in {}; pinning it for the configs that have it",
+            agent,
+            portal.config_path().display()
+        ));
+    }
+    if cli.dry_run {
+        println!("Dry run - would pin {}", agent);
+        return Ok(());
+    }
+    let changed = portal.set_pinned(agent, true)?;
+    if cli.format == Format::Json {
+        return print_json(&json!({ "agent": agent, "pinned": true, "changed": changed }));
+    }
+    if changed {
+        println!("Pinned {}; switches leave its model alone", agent);
+    } else {
+        println!("{} is already pinned", agent);
+    }
+    Ok(())
+}
+
+fn cmd_unpin(cli: &Cli, portal: &Portal, agent: &str) -> Result<()> {
+    if !portal.pins()?.iter().any(|p| p == agent) {
+        return Err(anyhow!("'{}' isn't pinned", agent));
+    }
+    if cli.dry_run {
+        println!("Dry run - would unpin {}", agent);
+        return Ok(());
+    }
+    portal.set_pinned(agent, false)?;
+    if cli.format == Format::Json {
+        return print_json(&json!({ "agent": agent, "pinned": false, "changed": true }));
+    }
+    println!("Unpinned {}", agent);
+    Ok(())
+}
+
+fn cmd_batch(cli: &Cli, portal: &Portal, file: &Path) -> Result<()> {
+    let script = if file == Path::new("-") {
+        std::io::read_to_string(std::io::stdin()).context("Failed to read the batch from stdin")?
+    } else {
+        fs::read_to_string(file)
+            .with_context(|| format!("Failed to read batch file: {}", file.display()))?
+    };
+    let lines = batch::parse(&script)?;
+    if lines.is_empty() {
+        return Err(anyhow!("No steps in {}", file.display()));
+    }
+    if let Some(allowed) = kiosk_providers(portal)? {
+        for line in &lines {
+            if let batch::Step::Switch { provider, .. } = &line.step {
+                policy::check_kiosk_provider(&allowed, &portal.resolve_provider(provider)?)?;
+            }
+        }
+    }
+    let options = SwitchOptions {
+        force_backup: cli.backup,
+        ..Default::default()
+    };
+    let outcome = if cli.dry_run {
+        portal.preview_batch(&lines, &options)?
+    } else {
+        portal.apply_batch(&lines, &options)?
+    };
+
+    if cli.format == Format::Json {
+        return print_json(&json!({
+            "dry_run": cli.dry_run,
+            "config": portal.config_path(),
+            "steps": lines.iter().map(|line| &line.text).collect::<Vec<_>>(),
+            "provider": outcome.provider(),
+            "changes": outcome.changes,
+            "unmapped": outcome
+                .switches
+                .iter()
+                .map(|(provider, report)| json!({ "provider": provider, "agents": report.unmapped }))
+                .collect::<Vec<_>>(),
+            "pinned": outcome
+                .switches
+                .iter()
+                .map(|(provider, report)| json!({ "provider": provider, "agents": report.pinned }))
+                .collect::<Vec<_>>(),
+            "compatibility": outcome.compatibility,
+            "backup": outcome.backup,
+            "warnings": outcome.warnings,
+        }));
+    }
+
+    for (provider, report) in &outcome.switches {
+        print_warnings(provider, report, &Compatibility::default());
+    }
+    if outcome.compatibility.is_conservative() {
+        print_warnings("", &SwitchReport::default(), &outcome.compatibility);
+    }
+    let label = outcome.provider().unwrap_or("batch");
+    if cli.dry_run {
+        println!("Dry run - would run {} step(s) in one write:", lines.len());
+        println!();
+        print_model_diff(label, &outcome.changes, true);
+        print_cost_changes(portal, &outcome.changes)?;
+        return Ok(());
+    }
+    if let Some(backup) = &outcome.backup {
+        note(format!("Backup created: {}", backup.display()));
+    }
+    let changed = outcome.changes.iter().filter(|c| c.is_changed()).count();
+    println!(
+        "Ran {} step(s) in one write; {} agent(s) changed.",
+        lines.len(),
+        changed
+    );
+    for (_, report) in &outcome.switches {
+        print_options(report, false);
+    }
+    for warning in &outcome.warnings {
+        portal_core::portal_warn!("{}", warning);
+    }
+    Ok(())
+}
+
+fn cmd_changes(cli: &Cli, portal: &Portal) -> Result<()> {
+    let changes = portal.changes_since_write()?.ok_or_else(|| {
+        anyhow!(
+            "Portal hasn't written {} yet, so there's nothing to compare against",
+            portal.config_path().display()
+        )
+    })?;
+
+    if cli.format == Format::Json {
+        return print_json(&json!({
+            "config": portal.config_path(),
+            "changes": changes,
+        }));
+    }
+    if changes.is_empty() {
+        println!("No changes since portal last wrote the config.");
+        return Ok(());
+    }
+
+    println!(
+        "Changes to {} since portal last wrote it:",
+        portal.config_path().display()
+    );
+    print_config_changes(&changes);
+    Ok(())
+}
+
+/// Print config changes grouped by kind, each group after a blank line
+fn print_config_changes(changes: &[ConfigChange]) {
+    let mut kind = None;
+    for change in changes {
+        if kind != Some(change.kind) {
+            kind = Some(change.kind);
+            println!();
+            println!("{}:", change.kind.label());
+        }
+        println!("  {}", describe_config_change(change));
+    }
+}
+
+/// `agents.oracle.model: "a" -> "b"`, or just the path for an agent that
+/// was added or removed
+fn describe_config_change(change: &ConfigChange) -> String {
+    let show = |value: &Option<Value>| match value {
+        Some(v) => v.to_string(),
+        None => "(absent)".to_string(),
+    };
+    match change.kind {
+        ChangeKind::AgentAdded | ChangeKind::AgentRemoved => change.path.clone(),
+        _ => format!(
+            "{}: {} -> {}",
+            change.path,
+            show(&change.before),
+            show(&change.after)
+        ),
+    }
+}
+
+fn cmd_provider_show(cli: &Cli, portal: &Portal, provider: &str) -> Result<()> {
+    let provider = &portal.resolve_provider(provider)?;
+    let config = portal.provider_config(provider)?;
+    let builtin = provider::builtin_description(provider);
+    if config.is_none() && builtin.is_none() {
+        return Err(anyhow!("Unknown provider: '{}'", provider));
+    }
+    let config = config.unwrap_or_default();
+    let path = provider::provider_config_path(portal.portal_dir(), provider);
+    let path = path.exists().then_some(path);
+    let description = config.description.as_deref().or(builtin);
+    let aliases: Vec<String> = portal
+        .aliases()?
+        .into_iter()
+        .filter(|(_, target)| target == provider)
+        .map(|(alias, _)| alias)
+        .collect();
+    // Option values may hold keys, so only their names are shown
+    let mut options: Vec<String> = serde_json::to_value(&config.options)?
+        .as_object()
+        .map(|options| options.keys().cloned().collect())
+        .unwrap_or_default();
+    options.sort();
+
+    if cli.format == Format::Json {
+        return print_json(&json!({
+            "name": provider,
+            "builtin": builtin.is_some(),
+            "path": path,
+            "description": description,
+            "notes": config.notes,
+            "extends": config.ancestors,
+            "agents": config.agents,
+            "rules": config.rules,
+            "options": options,
+            "aliases": aliases,
+        }));
+    }
+
+    match description {
+        Some(description) => println!("{}: {}", provider, description),
+        None => println!("{}", provider),
+    }
+    match &path {
+        Some(path) => println!("  File: {}", path.display()),
+        None => println!("  File: none (built-in)"),
+    }
+    if !aliases.is_empty() {
+        println!("  Aliases: {}", aliases.join(", "));
+    }
+    if !config.ancestors.is_empty() {
+        println!("  Extends: {}", config.ancestors.join(" -> "));
+    }
+    if let Some(notes) = &config.notes {
+        println!("  Notes:");
+        for line in notes.lines() {
+            println!("    {}", line);
+        }
+    }
+    if !config.agents.is_empty() {
+        println!("  Agent models:");
+        for (agent, model) in &config.agents {
+            println!("    {}: {}", agent, model.model);
+        }
+    }
+    if !config.rules.is_empty() {
+        println!("  Rules:");
+        for rule in &config.rules {
+            println!("    {} -> {}", rule.pattern, rule.template);
+        }
+    }
+    if !options.is_empty() {
+        println!("  Client options: {}", options.join(", "));
+    }
+    Ok(())
+}
+
+fn cmd_report(cli: &Cli, portal: &Portal, period: Period) -> Result<()> {
+    let report = report::for_days(portal, period.days())?;
+    match cli.format {
+        Format::Json => return print_json(&serde_json::to_value(&report)?),
+        Format::Markdown => {
+            print!("{}", report::render_markdown(&report));
+            return Ok(());
+        }
+        _ => {}
+    }
+
+    let local = |time: chrono::DateTime<chrono::Utc>| {
+        time.with_timezone(&chrono::Local)
+            .format("%Y-%m-%d %H:%M")
+            .to_string()
+    };
+    println!("From {} to {}", local(report.start), local(report.end));
+    println!();
+    println!("Providers:");
+    if report.providers.is_empty() {
+        println!("  (none recorded)");
+    }
+    for provider in &report.providers {
+        if style::is_plain() {
+            println!(
+                "  {}: in use {}, {} switch(es)",
+                provider.provider,
+                report::format_duration(provider.seconds),
+                provider.switches
+            );
+            continue;
+        }
+        println!(
+            "  {:<16} {:>8}  {} switch(es)",
+            provider.provider,
+            report::format_duration(provider.seconds),
+            provider.switches
+        );
+    }
+    println!();
+    println!("Switches: {}", report.switches.len());
+    for switch in &report.switches {
+        println!(
+            "  {}  {} in {}",
+            local(switch.timestamp),
+            switch.provider,
+            switch.config.display()
+        );
+    }
+    let activity = &report.activity;
+    println!();
+    println!(
+        "Reverts: {}  Edits: {}  Undos: {}  Redos: {}  Expired: {}",
+        activity.reverts, activity.edits, activity.undos, activity.redos, activity.expired
+    );
+    if !report.quotas.is_empty() {
+        println!();
+        println!("Quotas (latest poll; `portal quota` for details):");
+        for (name, snapshot) in &report.quotas {
+            let remaining = snapshot
+                .remaining
+                .map_or("?".to_string(), |v| format!("{:.2}", v));
+            println!("  {}: {} remaining", name, remaining);
+        }
+    }
+    Ok(())
+}
+
+fn cmd_graph(cli: &Cli, portal: &Portal, providers: &[String]) -> Result<()> {
+    let providers = if providers.is_empty() {
+        all_providers(portal)?
+    } else {
+        providers
+            .iter()
+            .map(|name| portal.resolve_provider(name))
+            .collect::<Result<_>>()?
+    };
+    let graph = portal.graph(&providers)?;
+    match cli.format {
+        Format::Json => return print_json(&serde_json::to_value(&graph)?),
+        Format::Dot => {
+            print!("{}", graph::render_dot(&graph));
+            return Ok(());
+        }
+        Format::Mermaid => {
+            print!("{}", graph::render_mermaid(&graph));
+            return Ok(());
+        }
+        Format::Text | Format::Markdown => {}
+    }
+
+    for agent in &graph.agents {
+        println!("{} ({})", agent.name, agent.model);
+        println!("  base: {}", agent.base);
+        for route in graph.routes.iter().filter(|r| r.agent == agent.name) {
+            let note = match route.resolution {
+                Some(Resolution::ProviderFile) => " (provider file)",
+                Some(Resolution::Rule) => " (rule)",
+                Some(Resolution::Guessed) => " (guessed)",
+                Some(Resolution::Mapped) | None => "",
+            };
+            println!(
+                "  {}: {}{}",
+                route.provider,
+                route.model.as_deref().unwrap_or("(no mapping)"),
+                note
+            );
+        }
+    }
+    Ok(())
+}
+
+fn cmd_revert(
+    cli: &Cli,
+    portal: &Portal,
+    backup_path: Option<PathBuf>,
+    interactive: bool,
+) -> Result<()> {
+    if interactive {
+        return cmd_revert_interactive(cli, portal);
+    }
+    if cli.dry_run {
+        let backup = portal.resolve_backup(backup_path)?;
+        println!("Dry run - would revert to: {}", backup.display());
+        return Ok(());
+    }
+
+    let backup = portal.revert(backup_path)?;
+    println!("Reverted to: {}", backup.display());
+    Ok(())
+}
+
+/// Most changes shown under each backup in the picker
+const BACKUP_PREVIEW_LINES: usize = 8;
+
+/// `portal revert --interactive`: pick a backup, see what restoring it
+/// changes, then confirm
+fn cmd_revert_interactive(cli: &Cli, portal: &Portal) -> Result<()> {
+    let channel = portal.backups();
+    let mut backups = backup::list_backup_info(&channel);
+    if backups.is_empty() {
+        return Err(anyhow!("No backup files found"));
+    }
+    backups.reverse();
+    // A missing or broken config compares as empty: everything is restored
+    let current = config::read_document(portal.config_path())
+        .map(|document| portal.redacted_document(document))
+        .unwrap_or_else(|_| json!({}));
+    let diffs: Vec<Vec<ConfigChange>> = backups
+        .iter()
+        .map(|info| {
+            backup::read_backup_to_string(&info.path)
+                .ok()
+                .and_then(|text| portal_core::jsonc::parse(&text).ok())
+                .map(|document| changes::config_changes(&current, &document))
+                .unwrap_or_default()
+        })
+        .collect();
+
+    let items: Vec<picker::Item> = backups
+        .iter()
+        .zip(&diffs)
+        .map(|(info, diff)| {
+            let created = info
+                .created
+                .map(|t| {
+                    t.with_timezone(&chrono::Local)
+                        .format("%Y-%m-%d %H:%M:%S")
+                        .to_string()
+                })
+                .unwrap_or_else(|| "unknown date".to_string());
+            let mut preview: Vec<String> = diff
+                .iter()
+                .take(BACKUP_PREVIEW_LINES)
+                .map(describe_config_change)
+                .collect();
+            if diff.len() > BACKUP_PREVIEW_LINES {
+                preview.push(format!(
+                    "... and {} more",
+                    diff.len() - BACKUP_PREVIEW_LINES
+                ));
+            }
+            if diff.is_empty() {
+                preview.push("(same as the current config)".to_string());
+            }
+            picker::Item {
+                label: format!(
+                    "{}  {}",
+                    created,
+                    info.provider.as_deref().unwrap_or("unknown provider")
+                ),
+                preview,
+            }
+        })
+        .collect();
+
+    let Some(choice) = picker::pick("Revert to backup:", &items)? else {
+        println!("Nothing was reverted.");
+        return Ok(());
+    };
+    let backup = &backups[choice].path;
+    let diff = &diffs[choice];
+    if diff.is_empty() {
+        println!(
+            "{} matches the current config; nothing to revert.",
+            backup.display()
+        );
+        return Ok(());
+    }
+    println!("Restoring {} would change:", backup.display());
+    print_config_changes(diff);
+    println!();
+    if cli.dry_run {
+        println!("Dry run - would revert to: {}", backup.display());
+        return Ok(());
+    }
+    if !picker::confirm("Restore this backup?")? {
+        println!("Nothing was reverted.");
+        return Ok(());
+    }
+    let backup = portal.revert(Some(backup.clone()))?;
+    println!("Reverted to: {}", backup.display());
+    Ok(())
+}
+
+fn cmd_step(cli: &Cli, portal: &Portal, forward: bool) -> Result<()> {
+    let verb = if forward { "redo" } else { "undo" };
+    if cli.dry_run {
+        let journal = portal.journal()?;
+        let entry = if forward {
+            journal.redo_entry()
+        } else {
+            journal.undo_entry()
+        };
+        match entry {
+            Some(entry) => println!(
+                "Dry run - would {}: {} ({})",
+                verb,
+                entry.action,
+                entry.timestamp.format("%Y-%m-%d %H:%M:%S")
+            ),
+            None => println!("Nothing to {}", verb),
+        }
+        return Ok(());
+    }
+
+    let entry = if forward {
+        portal.redo()?
+    } else {
+        portal.undo()?
+    };
+    match entry {
+        Some(entry) if forward => println!("Redid: {}", entry.action),
+        Some(entry) => println!("Undid: {}", entry.action),
+        None => println!("Nothing to {}", verb),
+    }
+    Ok(())
+}
+
+/// Report a bisect step, with instructions unless `automatic`; `true`
+/// once the search is over
+fn print_step(step: &Step, automatic: bool) -> bool {
+    match step {
+        Step::Test {
+            candidate,
+            remaining,
+        } => {
+            println!("Restored {} ({} candidate(s) left).", candidate, remaining);
+            if !automatic {
+                println!("Test it, then run `portal bisect good` or `portal bisect bad`.");
+            }
+            false
+        }
+        Step::Found(finding) => {
+            println!("First bad: {}", finding.first_bad);
+            match &finding.last_good {
+                Some(good) => println!("Last good: {}", good),
+                None => println!("Even the oldest backup is bad."),
+            }
+            if !finding.changes.is_empty() {
+                println!();
+                println!("Changes it introduced:");
+                print_config_changes(&finding.changes);
+            }
+            println!();
+            println!("Original config restored.");
+            true
+        }
+    }
+}
+
+fn cmd_bisect(cli: &Cli, portal: &Portal, command: &BisectCommand) -> Result<()> {
+    if cli.dry_run {
+        let candidates = backup::list_backups(&portal.backups()).len() + 1;
+        println!(
+            "Dry run - would bisect {} candidate(s) for {}",
+            candidates,
+            portal.config_path().display()
+        );
+        return Ok(());
+    }
+    match command {
+        BisectCommand::Start { good, bad } => {
+            print_step(
+                &bisect::start(portal, good.as_deref(), bad.as_deref())?,
+                false,
+            );
+        }
+        BisectCommand::Good => {
+            print_step(&bisect::mark(portal, true)?, false);
+        }
+        BisectCommand::Bad => {
+            print_step(&bisect::mark(portal, false)?, false);
+        }
+        BisectCommand::Run { command } => {
+            let mut state = bisect::load(portal)?
+                .ok_or_else(|| anyhow!("No bisect in progress; run `portal bisect start`"))?;
+            while let Some(index) = state.testing {
+                let status = std::process::Command::new(&command[0])
+                    .args(&command[1..])
+                    .status()
+                    .with_context(|| format!("Failed to run '{}'", command[0]))?;
+                let good = status.success();
+                println!(
+                    "{}: {}",
+                    state.label(index),
+                    if good { "good" } else { "bad" }
+                );
+                if print_step(&bisect::mark(portal, good)?, true) {
+                    break;
+                }
+                state = bisect::load(portal)?.ok_or_else(|| anyhow!("Bisect state vanished"))?;
+            }
+        }
+        BisectCommand::Reset => {
+            if bisect::reset(portal)? {
+                println!("Bisect reset; original config restored.");
+            } else {
+                println!("No bisect in progress.");
+            }
+        }
+    }
+    Ok(())
+}
+
+fn format_size(bytes: u64) -> String {
+    if bytes < 1024 {
+        format!("{} B", bytes)
+    } else {
+        format!("{:.1} KiB", bytes as f64 / 1024.0)
+    }
+}
+
+fn cmd_backups_list(cli: &Cli, portal: &Portal) -> Result<()> {
+    let backups = backup::list_backup_info(&portal.backups());
+    if cli.format == Format::Json {
+        return print_json(&json!({
+            "config": portal.config_path(),
+            "channel": portal.backups().name(),
+            "backups": backups,
+        }));
+    }
+
+    if backups.is_empty() {
+        println!("No backups of {}", portal.config_path().display());
+        return Ok(());
+    }
+    for info in &backups {
+        let created = info
+            .created
+            .map(|t| t.format("%Y-%m-%d %H:%M:%S").to_string())
+            .unwrap_or_else(|| "unknown date".to_string());
+        let flags: Vec<&str> = [
+            (info.compressed, "compressed"),
+            (info.encrypted, "encrypted"),
+        ]
+        .into_iter()
+        .filter_map(|(set, flag)| set.then_some(flag))
+        .collect();
+        if style::is_plain() {
+            println!(
+                "{}: {}, provider {}, created {}{}",
+                info.path.display(),
+                format_size(info.size),
+                info.provider.as_deref().unwrap_or("unknown"),
+                created,
+                flags
+                    .iter()
+                    .map(|flag| format!(", {}", flag))
+                    .collect::<String>()
+            );
+            continue;
+        }
+        println!(
+            "{}  {:>9}  {:<14}  {}{}",
+            created,
+            format_size(info.size),
+            info.provider.as_deref().unwrap_or("unknown"),
+            info.path.display(),
+            if flags.is_empty() {
+                String::new()
+            } else {
+                format!("  ({})", flags.join(", "))
+            }
+        );
+    }
+    Ok(())
+}
+
+fn cmd_backups_channels(cli: &Cli, portal: &Portal) -> Result<()> {
+    let channels = backup::list_channels(&portal.backup_store());
+    if cli.format == Format::Json {
+        return print_json(&json!({
+            "store": portal.backup_store(),
+            "channels": channels,
+        }));
+    }
+    if channels.is_empty() {
+        println!("No backup channels in {}", portal.backup_store().display());
+        return Ok(());
+    }
+    let current = portal.backups();
+    for channel in &channels {
+        if style::is_plain() {
+            println!(
+                "{}{}: {} backup(s) of {}",
+                channel.name,
+                if channel.name == current.name() {
+                    " (current)"
+                } else {
+                    ""
+                },
+                channel.backups,
+                channel
+                    .config
+                    .as_ref()
+                    .map(|c| c.display().to_string())
+                    .unwrap_or_else(|| "unknown config".to_string())
+            );
+            continue;
+        }
+        let marker = if channel.name == current.name() {
+            "*"
+        } else {
+            " "
+        };
+        println!(
+            "{} {:<32}  {:>3} backup(s)  {}",
+            marker,
+            channel.name,
+            channel.backups,
+            channel
+                .config
+                .as_ref()
+                .map(|c| c.display().to_string())
+                .unwrap_or_else(|| "unknown config".to_string())
+        );
+    }
+    Ok(())
+}
+
+fn cmd_backups_prune(
+    cli: &Cli,
+    portal: &Portal,
+    keep: Option<usize>,
+    older_than: Option<chrono::Duration>,
+) -> Result<()> {
+    let channel = portal.backups();
+    let removed = if cli.dry_run {
+        backup::prune_candidates(&channel, keep, older_than)
+    } else {
+        backup::prune_backups(&channel, keep, older_than)?
+    };
+
+    if cli.format == Format::Json {
+        return print_json(&json!({
+            "dry_run": cli.dry_run,
+            "removed": removed,
+        }));
+    }
+    if removed.is_empty() {
+        println!("Nothing to prune.");
+        return Ok(());
+    }
+    let verb = if cli.dry_run {
+        "Would delete"
+    } else {
+        "Deleted"
+    };
+    for path in &removed {
+        println!("{}: {}", verb, path.display());
+    }
+    Ok(())
+}
+
+fn cmd_backups_migrate(cli: &Cli, portal: &Portal, jobs: Option<usize>) -> Result<()> {
+    if cli.dry_run {
+        let pending = backup::legacy_backups(&portal.backups());
+        if cli.format == Format::Json {
+            return print_json(&json!({
+                "dry_run": true,
+                "store": portal.backups().dir(),
+                "pending": pending,
+            }));
+        }
+        if pending.is_empty() {
+            println!("No backups to migrate.");
+        }
+        for path in &pending {
+            println!("Would migrate: {}", path.display());
+        }
+        return Ok(());
+    }
+
+    let jobs = jobs.unwrap_or_else(|| {
+        std::thread::available_parallelism()
+            .map(usize::from)
+            .unwrap_or(1)
+    });
+    let show = std::io::stderr().is_terminal() && !exit::is_quiet();
+    let drawn = AtomicBool::new(false);
+    let migrated = portal.migrate_backups(jobs, |done, total| {
+        if show {
+            let width = 30;
+            let filled = done * width / total;
+            eprint!(
+                "\rMigrating backups [{}{}] {}/{}",
+                "#".repeat(filled),
+                " ".repeat(width - filled),
+                done,
+                total
+            );
+            drawn.store(true, Ordering::Relaxed);
+        }
+    });
+    if drawn.into_inner() {
+        eprintln!();
+    }
+    let migrated = migrated?;
+
+    if cli.format == Format::Json {
+        return print_json(&json!({
+            "dry_run": false,
+            "store": portal.backups().dir(),
+            "migrated": migrated,
+        }));
+    }
+    if migrated.is_empty() {
+        println!("No backups to migrate.");
+    } else {
+        println!(
+            "Migrated {} backup(s) into {}",
+            migrated.len(),
+            portal.backups().dir().display()
+        );
+    }
+    Ok(())
+}
+
+fn cmd_validate(cli: &Cli, portal: &Portal, schema: bool) -> Result<()> {
+    let issues = validate::validate(portal, ValidateOptions { schema });
+    let errors = issues
+        .iter()
+        .filter(|i| i.severity == Severity::Error)
+        .count();
+
+    if cli.format == Format::Json {
+        print_json(&json!({ "issues": issues }))?;
+    } else if issues.is_empty() {
+        println!("No issues found.");
+    } else {
+        for issue in &issues {
+            let label = match issue.severity {
+                Severity::Error => paint("[error]", Color::Red),
+                Severity::Warning => "[warn] ".to_string(),
+            };
+            let location = if issue.path.is_empty() {
+                issue.file.display().to_string()
+            } else {
+                format!("{}: {}", issue.file.display(), issue.path)
+            };
+            println!("{} {}: {}", label, location, issue.message);
+        }
+    }
+
+    if errors > 0 {
+        return Err(anyhow!("validate found {} error(s)", errors));
+    }
+    Ok(())
+}
+
+fn cmd_doctor(cli: &Cli, portal: &Portal, fix: bool, yes: bool) -> Result<()> {
+    let problems = doctor::diagnose(portal);
+    if problems.is_empty() {
+        println!("No problems found.");
+        return Ok(());
+    }
+
+    for problem in &problems {
+        let label = match problem.severity {
+            Severity::Error => paint("[error]", Color::Red),
+            Severity::Warning => "[warn] ".to_string(),
+        };
+        println!("{} {}", label, problem.message);
+        if let Some(f) = &problem.fix {
+            println!("        fix: {}", f.describe());
+        }
+    }
+    println!();
+
+    let fixes: Vec<_> = problems.iter().filter_map(|p| p.fix.as_ref()).collect();
+    if !fix || fixes.is_empty() {
+        if !fixes.is_empty() {
+            println!(
+                "{} problem(s) can be fixed automatically with `portal doctor --fix`.",
+                fixes.len()
+            );
+        }
+        return Err(anyhow!("doctor found {} problem(s)", problems.len()));
+    }
+
+    if cli.dry_run {
+        println!("Dry run - would apply {} fix(es).", fixes.len());
+        return Ok(());
+    }
+    if !yes {
+        if !picker::is_interactive() {
+            return Err(anyhow!(
+                "Refusing to apply fixes without confirmation; pass --yes"
+            ));
+        }
+        if !picker::confirm(&format!("Apply {} fix(es)?", fixes.len()))? {
+            println!("Cancelled.");
+            return Ok(());
+        }
+    }
+
+    for f in &fixes {
+        f.apply(portal)?;
+        println!("Fixed: {}", f.describe());
+    }
+
+    let remaining = problems.len() - fixes.len();
+    if remaining > 0 {
+        return Err(anyhow!("{} problem(s) need manual attention", remaining));
+    }
+    Ok(())
+}
+
+fn cmd_prompt(
+    cli: &Cli,
+    portal: &Portal,
+    template: &str,
+    color: Option<PromptColor>,
+    target: PromptTarget,
+) -> Result<()> {
+    let provider = fsutil::read_to_string(&portal.current_provider_path())
+        .ok()
+        .map(|text| text.trim().to_string())
+        .filter(|provider| !provider.is_empty());
+    if cli.format == Format::Json {
+        return print_json(&json!({ "provider": provider }));
+    }
+    let Some(provider) = provider else {
+        return Ok(());
+    };
+    let color = color.filter(|_| std::env::var_os("NO_COLOR").is_none());
+    println!("{}", prompt::render(template, &provider, color, target)?);
+    Ok(())
+}
+
+fn cmd_events(portal: &Portal, follow: bool) -> Result<()> {
+    let log = portal.events();
+    if !follow {
+        for event in log.read_all()? {
+            println!("{}", serde_json::to_string(&event)?);
+        }
+        return Ok(());
+    }
+
+    let mut stdout = std::io::stdout();
+    log.follow(&mut |line| {
+        writeln!(stdout, "{}", line)?;
+        stdout.flush()?;
+        Ok(())
+    })
+}
+
+fn cmd_generate(cli: &Cli, portal: &Portal, matrix_path: &Path) -> Result<()> {
+    let matrix = matrix::read_matrix(matrix_path)?;
+    let files = matrix.expand(&portal.mappings()?)?;
+
+    for (name, generated) in &files {
+        let existing = portal.provider_config(name)?;
+        let action = match &existing {
+            Some(current) if current == generated => {
+                println!("Unchanged: {}", name);
+                continue;
+            }
+            Some(_) => "update",
+            None => "create",
+        };
+        if cli.dry_run {
+            println!("Dry run - would {} '{}':", action, name);
+            for (agent, model) in &generated.agents {
+                println!("  {}: {}", agent, model.model);
+            }
+        } else {
+            let path = provider::write_provider_config(portal.portal_dir(), name, generated)?;
+            let verb = if action == "create" {
+                "Created"
+            } else {
+                "Updated"
+            };
+            println!("{}: {}", verb, path.display());
+        }
+    }
+    Ok(())
+}
+
+fn cmd_verify_install(cli: &Cli) -> Result<()> {
+    let checks = selftest::run();
+    let failed = checks
+        .iter()
+        .filter(|check| check.outcome == selftest::Outcome::Fail)
+        .count();
+    if cli.format == Format::Json {
+        print_json(&json!({ "version": env!("CARGO_PKG_VERSION"), "checks": checks }))?;
+    } else {
+        println!("portal {}", env!("CARGO_PKG_VERSION"));
+        let width = checks.iter().map(|c| c.subsystem.len()).max().unwrap_or(0);
+        for check in &checks {
+            println!(
+                "{}  {:width$}  {}",
+                check.outcome.label(),
+                check.subsystem,
+                check.detail
+            );
+        }
+    }
+    if failed > 0 {
+        return Err(anyhow!("{} check(s) failed", failed));
+    }
+    Ok(())
+}
+
+fn cmd_adopt(cli: &Cli, portal: &Portal, script: &Path, force: bool) -> Result<()> {
+    let text = fs::read_to_string(script)
+        .with_context(|| format!("Failed to read script: {}", script.display()))?;
+    let adoption = adopt::parse_script(&text);
+    if adoption.providers.is_empty() && adoption.skipped.is_empty() {
+        return Err(anyhow!(
+            "No sed or jq model rewrites found in {}",
+            script.display()
+        ));
+    }
+    let current = if portal.config_path().exists() {
+        portal.capture()?
+    } else {
+        provider::ProviderConfig::default()
+    };
+
+    let mut results = Vec::new();
+    for (name, config) in adoption.provider_configs(&current) {
+        let path = provider::provider_config_path(portal.portal_dir(), &name);
+        let problem = if !provider::is_builtin_name(&name)
+            && let Err(e) = provider::validate_name(&name)
+        {
+            Some(e.to_string())
+        } else if config.agents.is_empty() {
+            Some("its rewrites match none of the current agent models".to_string())
+        } else if path.exists() && !force {
+            Some(format!(
+                "{} already exists (use --force to overwrite it)",
+                path.display()
+            ))
+        } else {
+            None
+        };
+        let action = match &problem {
+            Some(_) => "skip",
+            None if path.exists() => "update",
+            None => "create",
+        };
+        if problem.is_none() && !cli.dry_run {
+            provider::write_provider_config(portal.portal_dir(), &name, &config)?;
+        }
+        results.push((name, path, config, action, problem));
+    }
+
+    if cli.format == Format::Json {
+        let providers: Vec<Value> = results
+            .iter()
+            .map(|(name, path, config, action, problem)| {
+                json!({
+                    "provider": name,
+                    "path": path,
+                    "action": action,
+                    "agents": config.agents,
+                    "problem": problem,
+                })
+            })
+            .collect();
+        let skipped: Vec<Value> = adoption
+            .skipped
+            .iter()
+            .map(|(line, text)| json!({ "line": line, "text": text }))
+            .collect();
+        return print_json(&json!({
+            "script": script,
+            "dry_run": cli.dry_run,
+            "providers": providers,
+            "skipped": skipped,
+        }));
+    }
+
+    for (name, path, config, action, problem) in &results {
+        if let Some(problem) = problem {
+            println!("Skipped '{}': {}", name, problem);
+            continue;
+        }
+        if cli.dry_run {
+            println!("Dry run - would {} '{}':", action, name);
+        } else if *action == "create" {
+            println!("Created: {}", path.display());
+        } else {
+            println!("Updated: {}", path.display());
+        }
+        for (agent, model) in &config.agents {
+            println!("  {}: {}", agent, model.model);
+        }
+    }
+    if !adoption.skipped.is_empty() {
+        if !results.is_empty() {
+            println!();
+        }
+        println!("Lines portal couldn't follow; port these by hand:");
+        for (line, text) in &adoption.skipped {
+            println!("  {}:{}: {}", script.display(), line, text);
+        }
+    }
+    Ok(())
+}
+
+fn cmd_mappings_sync(
+    cli: &Cli,
+    portal: &Portal,
+    vendors: &[String],
+    from: Option<&PathBuf>,
+) -> Result<()> {
+    let catalog = match from {
+        Some(path) => Catalog::from_openrouter_json(
+            &fs::read_to_string(path)
+                .with_context(|| format!("Failed to read model list: {}", path.display()))?,
+        )?,
+        None => Catalog::fetch_openrouter()?,
+    };
+    let vendors: Vec<String> = if vendors.is_empty() {
+        catalog::DEFAULT_SYNC_VENDORS
+            .iter()
+            .map(|v| v.to_string())
+            .collect()
+    } else {
+        vendors.to_vec()
+    };
+    let candidates = catalog.mapping_candidates(&portal.mappings()?, &vendors);
+
+    if cli.format == Format::Json {
+        print_json(&serde_json::to_value(&candidates)?)?;
+    } else if candidates.is_empty() {
+        println!(
+            "The mapping table already covers every {} model OpenRouter lists.",
+            vendors.join("/")
+        );
+        return Ok(());
+    } else {
+        let path = mapping::mappings_file_path(portal.portal_dir());
+        if cli.dry_run {
+            println!(
+                "Dry run - would add {} row(s) to {}:",
+                candidates.len(),
+                path.display()
+            );
+        } else {
+            println!("Adding {} row(s) to {}:", candidates.len(), path.display());
+        }
+        for (base, row) in &candidates {
+            println!(
+                "  {}: copilot {}, openrouter {}",
+                base,
+                row.copilot.as_deref().unwrap_or_default(),
+                row.openrouter.as_deref().unwrap_or_default()
+            );
+        }
+    }
+    if cli.dry_run || candidates.is_empty() {
+        return Ok(());
+    }
+
+    let mut file = mapping::read_mappings_file(portal.portal_dir())?.unwrap_or_default();
+    file.mappings.extend(candidates);
+    mapping::write_mappings_file(portal.portal_dir(), &file)?;
+    if cli.format != Format::Json {
+        println!();
+        println!("The Copilot names are guesses; check them against the models your plan offers.");
+    }
+    Ok(())
+}
+
+fn cmd_export(
+    cli: &Cli,
+    portal: &Portal,
+    path: &Path,
+    force: bool,
+    allow_inline_secrets: bool,
+) -> Result<()> {
+    let manifest = bundle::export(
+        portal.portal_dir(),
+        path,
+        force,
+        allow_inline_secrets,
+        cli.dry_run,
+    )?;
+    if cli.format == Format::Json {
+        return print_json(&json!({
+            "dry_run": cli.dry_run,
+            "bundle": path,
+            "manifest": manifest,
+        }));
+    }
+    if cli.dry_run {
+        println!("Dry run - would export to {}:", path.display());
+    } else {
+        println!("Exported to {}:", path.display());
+    }
+    for name in &manifest.files {
+        println!("  {}", name);
+    }
+    Ok(())
+}
+
+fn cmd_import(cli: &Cli, portal: &Portal, path: &Path, force: bool) -> Result<()> {
+    let (manifest, files) = bundle::import(portal.portal_dir(), path, force, cli.dry_run)?;
+    if cli.format == Format::Json {
+        return print_json(&json!({
+            "dry_run": cli.dry_run,
+            "bundle": path,
+            "manifest": manifest,
+            "files": files,
+        }));
+    }
+    if cli.dry_run {
+        println!(
+            "Dry run - would import into {}:",
+            portal.portal_dir().display()
+        );
+    } else {
+        println!("Imported into {}:", portal.portal_dir().display());
+    }
+    for file in &files {
+        let state = match file.state {
+            ImportState::Added => "added",
+            ImportState::Replaced => "replaced",
+            ImportState::Unchanged => "unchanged",
+        };
+        println!("  {}: {}", file.name, state);
+    }
+    Ok(())
+}
+
+fn cmd_sync(cli: &Cli, portal: &Portal, command: Option<&SyncCommand>) -> Result<()> {
+    let dir = portal.portal_dir();
+    if let Some(SyncCommand::Init { remote }) = command {
+        if cli.dry_run {
+            println!("Dry run - would make {} a git repository", dir.display());
+            return Ok(());
+        }
+        sync::init(dir, remote.as_deref())?;
+        match remote {
+            Some(remote) => println!("Syncing {} with {}", dir.display(), remote),
+            None => println!(
+                "Syncing {} (add a remote with `portal sync init REMOTE`)",
+                dir.display()
+            ),
+        }
+        return Ok(());
+    }
+    if !sync::is_repo(dir) {
+        return Err(anyhow!(
+            "{} isn't synced yet; run `portal sync init [REMOTE]`",
+            dir.display()
+        ));
+    }
+    if matches!(command, Some(SyncCommand::Status)) || cli.dry_run {
+        let status = sync::status(dir)?;
+        if cli.format == Format::Json {
+            return print_json(&json!({ "dir": dir, "status": status }));
+        }
+        match &status.upstream {
+            Some(upstream) => println!(
+                "{}: {} ahead, {} behind {}",
+                dir.display(),
+                status.ahead,
+                status.behind,
+                upstream
+            ),
+            None => println!("{}: no upstream", dir.display()),
+        }
+        if !status.changed.is_empty() {
+            println!("\nNot committed yet:");
+            for line in &status.changed {
+                println!("  {}", line);
+            }
+        }
+        return Ok(());
+    }
+
+    let committed = sync::commit_all(dir, "portal: sync")?;
+    let pull = sync::pull(dir, true)?;
+    if let Pull::Conflicts(files) = &pull {
+        return Err(sync::conflict_error(dir, files));
+    }
+    let pushed = sync::push(dir)?;
+    if cli.format == Format::Json {
+        return print_json(&json!({
+            "dir": dir,
+            "committed": committed,
+            "pull": pull,
+            "pushed": pushed,
+        }));
+    }
+    if committed {
+        println!("Committed local edits");
+    }
+    match &pull {
+        Pull::Updated(files) => println!("Pulled: {}", files.join(", ")),
+        Pull::UpToDate => println!("Already up to date"),
+        Pull::NoUpstream => {
+            println!("No upstream to pull from; add one with `portal sync init REMOTE`")
+        }
+        Pull::Diverged | Pull::Conflicts(_) => {}
+    }
+    if pushed {
+        println!("Pushed");
+    }
+    Ok(())
+}
+
+fn cmd_secret(cli: &Cli, command: &SecretCommand) -> Result<()> {
+    match command {
+        SecretCommand::Set { name } => {
+            keychain::validate_name(name)?;
+            if cli.dry_run {
+                println!("Dry run - would store secret '{}'", name);
+                return Ok(());
+            }
+            let value = picker::read_hidden(&format!("Value for '{}': ", name))?;
+            if value.is_empty() {
+                return Err(anyhow!("No value given for secret '{}'", name));
+            }
+            keychain::set(name, &value)?;
+            println!("Stored secret '{}'", name);
+        }
+        SecretCommand::Get { name } => {
+            let value = keychain::get(name)?
+                .ok_or_else(|| anyhow!("Secret '{}' isn't in the keychain", name))?;
+            println!("{}", value);
+        }
+        SecretCommand::Delete { name } => {
+            if cli.dry_run {
+                keychain::validate_name(name)?;
+                println!("Dry run - would delete secret '{}'", name);
+            } else if keychain::delete(name)? {
+                println!("Deleted secret '{}'", name);
+            } else {
+                return Err(anyhow!("Secret '{}' isn't in the keychain", name));
+            }
+        }
+    }
+    Ok(())
+}
+
+fn cmd_provider_approve(cli: &Cli, portal: &Portal, name: &str) -> Result<()> {
+    let name = &portal.resolve_provider(name)?;
+    if cli.dry_run {
+        let sha256 = policy::provider_hash(portal.portal_dir(), name)?
+            .ok_or_else(|| anyhow!("No provider file for '{}'", name))?;
+        println!("Dry run - would approve '{}' at sha256 {}", name, sha256);
+        return Ok(());
+    }
+    let sha256 = policy::approve(portal.portal_dir(), name)?;
+    println!("Approved '{}' (sha256 {})", name, sha256);
+    Ok(())
+}
+
+fn cmd_provider_add(
+    cli: &Cli,
+    portal: &Portal,
+    name: &str,
+    models: &[String],
+    options: [(&str, &Option<String>); 2],
+    force: bool,
+) -> Result<()> {
+    let agents = models
+        .iter()
+        .map(|pin| {
+            pin.split_once('=')
+                .map(|(agent, model)| (agent.to_string(), model.to_string()))
+                .ok_or_else(|| anyhow!("Expected AGENT=MODEL, got '{}'", pin))
+        })
+        .collect::<Result<Vec<_>>>()?;
+    let mut provider_options = provider::ProviderOptions::default();
+    for (option, value) in options {
+        if let Some(value) = value {
+            provider_options
+                .extra
+                .insert(option.to_string(), Value::String(value.clone()));
+        }
+    }
+    if !secrets::inline_secrets(&provider_options).is_empty() {
+        return Err(anyhow!(
+            "That API key would be stored in the provider file as-is. Pass a reference \
+             instead: '{{env:NAME}}' for an environment variable, or '{{secret:NAME}}' \
+             after `portal secret set NAME`"
+        ));
+    }
+
+    let (path, config) = manage::add(portal, name, &agents, provider_options, force, cli.dry_run)?;
+    if cli.dry_run {
+        println!("Dry run - would write '{}' to {}:", name, path.display());
+    } else {
+        println!("Created '{}': {}", name, path.display());
+    }
+    for (agent, model) in &config.agents {
+        println!("  {}: {}", agent, model.model);
+    }
+    for (option, value) in &config.options.extra {
+        println!("  options.{}: {}", option, value);
+    }
+    Ok(())
+}
+
+/// Report a rename, copy, or removal: the provider file, then each
+/// reference updated and each left for the user
+fn print_changes(cli: &Cli, action: &str, done: &str, changes: &manage::Changes) -> Result<()> {
+    if cli.format == Format::Json {
+        return print_json(&json!({
+            "dry_run": cli.dry_run,
+            "file": changes.file,
+            "updated": changes.updated,
+            "remaining": changes.remaining,
+        }));
+    }
+    if cli.dry_run {
+        println!("Dry run - would {} {}", action, changes.file.display());
+    } else {
+        println!("{} {}", done, changes.file.display());
+    }
+    for reference in &changes.updated {
+        println!(
+            "  {}: {}",
+            reference
+                .path
+                .file_name()
+                .unwrap_or_default()
+                .to_string_lossy(),
+            reference.detail
+        );
+    }
+    if !changes.remaining.is_empty() {
+        println!();
+        println!("Still naming the removed provider; update these by hand:");
+        for reference in &changes.remaining {
+            println!("  {}: {}", reference.path.display(), reference.detail);
+        }
+    }
+    Ok(())
+}
+
+fn cmd_provider_discover(cli: &Cli, portal: &Portal, from: Option<&PathBuf>) -> Result<()> {
+    let listing = match from {
+        Some(path) => fs::read_to_string(path)
+            .with_context(|| format!("Failed to read model listing: {}", path.display()))?,
+        None => discover::opencode_models()?,
+    };
+
+    let config = portal.load()?;
+    let mappings = portal.mappings()?;
+    let existing = portal.custom_providers()?;
+    let mut generated = 0;
+
+    for (name, models) in discover::parse_model_list(&listing) {
+        if discover::is_builtin_provider(&name) || existing.contains(&name) {
+            continue;
+        }
+        let candidate = discover::candidate_provider(&config, &mappings, &name, &models);
+        if candidate.agents.is_empty() {
+            println!("Skipping '{}': no models match your agents", name);
+            continue;
+        }
+
+        generated += 1;
+        if cli.dry_run {
+            println!("Dry run - would create '{}':", name);
+            for (agent, model) in &candidate.agents {
+                println!("  {}: {}", agent, model.model);
+            }
+            println!();
+        } else {
+            let path = provider::write_provider_config(portal.portal_dir(), &name, &candidate)?;
+            println!(
+                "Created {} ({} of {} agents mapped)",
+                path.display(),
+                candidate.agents.len(),
+                config.agents.len()
+            );
+        }
+    }
+
+    if generated == 0 {
+        println!("No new providers found.");
+    }
+    Ok(())
+}
+
+// ============================================================================
+// Main
+// ============================================================================
+
+fn main() -> std::process::ExitCode {
+    crash::install();
+    exit::default_sigpipe();
+    match start() {
+        Ok(()) => std::process::ExitCode::SUCCESS,
+        Err(error) => exit::report(&error),
+    }
+}
+
+fn start() -> Result<()> {
+    let cli = Cli::parse_from(expand_command_alias(std::env::args_os().collect())?);
+    style::set_plain(cli.plain || std::env::var_os("PORTAL_PLAIN").is_some());
+    // `portal mcp` answers on standard output, so only its logs go quiet
+    if cli.quiet && !matches!(cli.command, Commands::Mcp) {
+        exit::set_quiet();
+    }
+    logging::init(cli.verbose, cli.quiet, cli.log_json);
+    // Runs on every prompt, so it skips finding the config, environments,
+    // projects, and ending temporary switches
+    if let Commands::Prompt {
+        template,
+        color,
+        target,
+    } = &cli.command
+    {
+        return cmd_prompt(&cli, &Portal::discover(None), template, *color, *target);
+    }
+    // `portal env` must work even when the active environment is broken
+    let portal = if matches!(cli.command, Commands::Env { .. }) {
+        Portal::discover(cli.config().cloned())
+    } else {
+        discover_portal(&cli)?
+    };
+    let snapshot = git_snapshot(&cli, &portal)?;
+    let syncing = syncing(&cli, &portal);
+    if syncing && matches!(cli.command, Commands::Switch { .. }) {
+        pull_before_switch(&portal);
+    }
+    let started = chrono::Utc::now();
+    let result = run(&cli, &portal);
+    if let Some(snapshot) = snapshot {
+        commit_changed_configs(&portal, &snapshot, started);
+    }
+    if syncing && result.is_ok() {
+        sync_changes(&portal);
+    }
+    result
+}
+
+/// Whether to keep the portal directory in sync around this command: it
+/// is a repository of its own, and the command isn't a dry run, `portal
+/// sync` itself, or one that keeps running
+fn syncing(cli: &Cli, portal: &Portal) -> bool {
+    !cli.dry_run
+        && sync::is_repo(portal.portal_dir())
+        && !matches!(
+            cli.command,
+            Commands::Sync { .. }
+                | Commands::Watch { .. }
+                | Commands::Daemon { .. }
+                | Commands::Mcp
+                | Commands::Serve { .. }
+        )
+}
+
+/// Bring in provider edits from other machines, if it takes only a
+/// fast-forward. Failures are warnings: the local files still work.
+fn pull_before_switch(portal: &Portal) {
+    match sync::pull(portal.portal_dir(), false) {
+        Ok(Pull::Updated(files)) => {
+            note(format!("Pulled portal file changes: {}", files.join(", ")))
+        }
+        Ok(Pull::Diverged) => portal_core::portal_warn!(
+            "{} and its upstream both have new commits; run `portal sync` to merge them",
+            portal.portal_dir().display()
+        ),
+        Ok(_) => {}
+        Err(e) => portal_core::portal_warn!("couldn't pull portal files before switching: {:#}", e),
+    }
+}
+
+/// Commit and push whatever the command changed in the portal directory
+fn sync_changes(portal: &Portal) {
+    let command: Vec<String> = std::env::args().skip(1).collect();
+    let message = format!("portal: {}", command.join(" "));
+    match sync::commit_all(portal.portal_dir(), &message) {
+        Ok(true) => match sync::push(portal.portal_dir()) {
+            Ok(_) => note(format!("Synced portal files: {}", message)),
+            Err(e) => portal_core::portal_warn!(
+                "committed portal files, but couldn't push them (`portal sync` retries): {:#}",
+                e
+            ),
+        },
+        Ok(false) => {}
+        Err(e) => portal_core::portal_warn!("couldn't commit portal files: {:#}", e),
+    }
+}
+
+/// Configs and their contents before a command ran
+type ConfigSnapshot = Vec<(PathBuf, Option<String>)>;
+
+/// Contents of every config a command may write, to see afterwards which
+/// ones to commit. `None` unless git commits are on; `watch`, the daemon,
+/// and the MCP and HTTP servers keep running, so their writes aren't
+/// committed.
+fn git_snapshot(cli: &Cli, portal: &Portal) -> Result<Option<ConfigSnapshot>> {
+    let setting = settings::read_settings(portal.portal_dir())?.is_some_and(|s| s.git_commit);
+    let enabled = (cli.git_commit || setting) && !cli.no_git_commit && !cli.dry_run;
+    if !enabled
+        || matches!(
+            cli.command,
+            Commands::Watch { .. }
+                | Commands::Daemon { .. }
+                | Commands::Mcp
+                | Commands::Serve { .. }
+        )
+    {
+        return Ok(None);
+    }
+    let mut paths = vec![portal.config_path().to_path_buf()];
+    paths.extend(
+        portal
+            .extra_targets()?
+            .into_iter()
+            .map(|(target, _)| target.config_path().to_path_buf()),
+    );
+    // And the rest of the configs a fleet switch writes
+    paths.extend(cli.config.iter().skip(1).cloned());
+    if let Commands::Switch { args, .. } = &cli.command
+        && let Some(name) = &args.fleet
+    {
+        let settings = settings::read_settings(portal.portal_dir())?.unwrap_or_default();
+        paths.extend(settings.fleet(name)?);
+    }
+    Ok(Some(
+        paths
+            .into_iter()
+            .map(|path| {
+                let content = fs::read_to_string(&path).ok();
+                (path, content)
+            })
+            .collect(),
+    ))
+}
+
+/// Commit each config whose contents changed, described by the latest
+/// event portal logged for it. Failures are warnings: the write itself
+/// went through.
+fn commit_changed_configs(
+    portal: &Portal,
+    snapshot: &ConfigSnapshot,
+    since: chrono::DateTime<chrono::Utc>,
+) {
+    let events = portal.events().read_all().unwrap_or_default();
+    let canonical = |p: &Path| fs::canonicalize(p).unwrap_or_else(|_| p.to_path_buf());
+    for (path, before) in snapshot {
+        if fs::read_to_string(path).ok() == *before {
+            continue;
+        }
+        let summary = events
+            .iter()
+            .rev()
+            .take_while(|e| e.timestamp >= since)
+            .find(|e| canonical(e.kind.config()) == canonical(path))
+            .map(|e| e.kind.summary())
+            .unwrap_or_else(|| {
+                format!(
+                    "update {}",
+                    path.file_name().unwrap_or_default().to_string_lossy()
+                )
+            });
+        let message = format!("portal: {}", summary);
+        match git::commit_file(path, &message) {
+            Ok(true) => note(format!("Committed {}: {}", path.display(), message)),
+            Ok(false) => {}
+            Err(e) => {
+                portal_core::portal_warn!("couldn't commit {} to git: {:#}", path.display(), e)
+            }
+        }
+    }
+}
+
+fn run(cli: &Cli, portal: &Portal) -> Result<()> {
+    // Whichever command runs, temporary switches whose time is up end first;
+    // the daemon does the same on its own
+    if !cli.dry_run && !matches!(cli.command, Commands::Daemon { .. }) {
+        end_temporary_switches(
+            portal,
+            |message| note(message),
+            |warning| portal_core::portal_warn!("{}", warning),
+        );
+    }
+
+    if !cli.format.applies_to(&cli.command) {
+        let format = cli.format.to_possible_value().expect("no skipped formats");
+        return Err(anyhow!(
+            "--format {} doesn't apply to this command",
+            format.get_name()
+        ));
+    }
+
+    if cli.config.len() > 1 && !matches!(cli.command, Commands::Switch { .. }) {
+        return Err(anyhow!("Only `portal switch` takes more than one --config"));
+    }
+    check_kiosk(cli, portal)?;
 
     match &cli.command {
-        Commands::Switch { provider } => cmd_switch(&cli, provider),
-        Commands::Status => cmd_status(&cli),
-        Commands::List => cmd_list(),
-        Commands::Revert { backup_path } => cmd_revert(&cli, backup_path.clone()),
+        Commands::Switch {
+            provider,
+            target,
+            args,
+        } => cmd_switch(cli, portal, provider.as_deref(), target, args).or_else(|error| {
+            offer_previous_provider(cli, portal, provider.as_deref(), target, args, error)
+        }),
+        Commands::Watch {
+            provider,
+            target,
+            except,
+            debounce,
+        } => cmd_watch(cli, portal, provider.as_deref(), target, except, *debounce),
+        Commands::Daemon { command } => cmd_daemon(cli, portal, command),
+        Commands::Mcp => mcp::serve(portal, cli.dry_run),
+        Commands::Serve { listen, token_env } => cmd_serve(cli, portal, listen, token_env),
+        Commands::Quota { refresh } => cmd_quota(cli, portal, *refresh),
+        Commands::Health { provider } => cmd_health(cli, portal, provider),
+        Commands::Env { name, clear } => cmd_env(cli, portal, name.as_deref(), *clear),
+        Commands::Diff { provider, target } => cmd_diff(cli, portal, provider, target),
+        Commands::Init { yes } => cmd_init(cli, portal, *yes),
+        Commands::Capture { provider } => cmd_capture(cli, portal, provider),
+        Commands::Save { name, force } => cmd_save(cli, portal, name, *force),
+        Commands::Batch { file } => cmd_batch(cli, portal, file),
+        Commands::Set {
+            agent,
+            model,
+            force,
+        } => cmd_set(cli, portal, agent, model, *force),
+        Commands::Tier { tier } => cmd_tier(cli, portal, *tier),
+        Commands::Pin { agent } => cmd_pin(cli, portal, agent.as_deref()),
+        Commands::Unpin { agent } => cmd_unpin(cli, portal, agent),
+        Commands::Status { changes } => status::run(cli, portal, *changes),
+        Commands::Changes => cmd_changes(cli, portal),
+        Commands::List {
+            names,
+            detailed,
+            refresh,
+        } => list::run(cli, portal, *names, *detailed, *refresh),
+        Commands::Revert {
+            backup_path,
+            interactive,
+        } => cmd_revert(cli, portal, backup_path.clone(), *interactive),
+        Commands::Bisect { command } => cmd_bisect(cli, portal, command),
+        Commands::Undo => cmd_step(cli, portal, false),
+        Commands::Redo => cmd_step(cli, portal, true),
+        Commands::Backups { command } => match command {
+            BackupsCommand::List => cmd_backups_list(cli, portal),
+            BackupsCommand::Channels => cmd_backups_channels(cli, portal),
+            BackupsCommand::Prune { keep, older_than } => {
+                cmd_backups_prune(cli, portal, *keep, *older_than)
+            }
+            BackupsCommand::Migrate { jobs } => cmd_backups_migrate(cli, portal, *jobs),
+        },
+        Commands::Doctor { fix, yes } => cmd_doctor(cli, portal, *fix, *yes),
+        Commands::Validate { schema } => cmd_validate(cli, portal, *schema),
+        Commands::Completions { shell } => {
+            print!("{}", completions::generate(*shell, Cli::command()));
+            Ok(())
+        }
+        Commands::Hook { shell, title: _ } => {
+            print!(
+                "{}",
+                title::generate(*shell, &portal.current_provider_path())
+            );
+            Ok(())
+        }
+        Commands::Prompt {
+            template,
+            color,
+            target,
+        } => cmd_prompt(cli, portal, template, *color, *target),
+        Commands::Events { follow } => cmd_events(portal, *follow),
+        Commands::VerifyInstall => cmd_verify_install(cli),
+        Commands::Adopt { script, force } => cmd_adopt(cli, portal, script, *force),
+        Commands::Generate { matrix } => cmd_generate(cli, portal, matrix),
+        Commands::Graph { providers } => cmd_graph(cli, portal, providers),
+        Commands::Compare {
+            provider_a,
+            provider_b,
+            differences,
+        } => compare::run(cli, portal, provider_a, provider_b, *differences),
+        Commands::Report { period } => cmd_report(cli, portal, *period),
+        Commands::Provider { command } => match command {
+            ProviderCommand::Discover { from } => cmd_provider_discover(cli, portal, from.as_ref()),
+            ProviderCommand::Show { provider } => cmd_provider_show(cli, portal, provider),
+            ProviderCommand::Approve { provider } => cmd_provider_approve(cli, portal, provider),
+            ProviderCommand::Add {
+                name,
+                models,
+                base_url,
+                api_key,
+                force,
+            } => cmd_provider_add(
+                cli,
+                portal,
+                name,
+                models,
+                [("baseURL", base_url), ("apiKey", api_key)],
+                *force,
+            ),
+            ProviderCommand::Remove { provider } => {
+                let name = portal.resolve_provider(provider)?;
+                let changes = manage::remove(portal, &name, cli.dry_run)?;
+                print_changes(cli, "remove", "Removed", &changes)
+            }
+            ProviderCommand::Rename { provider, to } => {
+                let from = portal.resolve_provider(provider)?;
+                let changes = manage::rename(portal, &from, to, cli.dry_run)?;
+                print_changes(cli, "rename to", "Renamed to", &changes)
+            }
+            ProviderCommand::Copy { provider, to } => {
+                let from = portal.resolve_provider(provider)?;
+                let changes = manage::copy(portal, &from, to, cli.dry_run)?;
+                print_changes(cli, "create", "Created", &changes)
+            }
+        },
+        Commands::Mappings { command } => match command {
+            MappingsCommand::Sync { vendors, from } => {
+                cmd_mappings_sync(cli, portal, vendors, from.as_ref())
+            }
+        },
+        Commands::Secret { command } => cmd_secret(cli, command),
+        Commands::Export {
+            bundle,
+            force,
+            allow_inline_secrets,
+        } => cmd_export(cli, portal, bundle, *force, *allow_inline_secrets),
+        Commands::Import { bundle, force } => cmd_import(cli, portal, bundle, *force),
+        Commands::Sync { command } => cmd_sync(cli, portal, command.as_ref()),
     }
 }
@@ -0,0 +1,229 @@
+//! `portal mcp`: portal as a Model Context Protocol server on stdin and
+//! stdout, so the agents themselves (or opencode) can see which provider
+//! they're on and switch to another mid-session, e.g. when they hit a
+//! quota. Messages are JSON-RPC 2.0, one per line; standard output carries
+//! nothing else, and logs go to stderr as usual.
+
+use anyhow::{Context, Result};
+use portal_core::Portal;
+use serde_json::{Value, json};
+use std::io::{BufRead, Write};
+
+/// Protocol versions understood, newest first; a client asking for
+/// another is offered the newest
+const PROTOCOL_VERSIONS: &[&str] = &["2025-06-18", "2025-03-26", "2024-11-05"];
+
+const PARSE_ERROR: i64 = -32700;
+const INVALID_REQUEST: i64 = -32600;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+
+/// Answer requests until stdin closes. With `dry_run`, `switch_provider`
+/// only ever previews.
+pub fn serve(portal: &Portal, dry_run: bool) -> Result<()> {
+    let server = Server {
+        portal: portal.clone(),
+        dry_run,
+    };
+    let stdin = std::io::stdin();
+    let mut stdout = std::io::stdout();
+    for line in stdin.lock().lines() {
+        let line = line.context("Failed to read from stdin")?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        if let Some(response) = server.handle(&line) {
+            writeln!(stdout, "{}", response).context("Failed to write to stdout")?;
+            stdout.flush().context("Failed to write to stdout")?;
+        }
+    }
+    Ok(())
+}
+
+struct Server {
+    portal: Portal,
+    dry_run: bool,
+}
+
+/// A JSON-RPC error, answered in place of a result
+struct RpcError {
+    code: i64,
+    message: String,
+}
+
+impl RpcError {
+    fn new(code: i64, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            message: message.into(),
+        }
+    }
+}
+
+impl Server {
+    /// The response to one message, or `None` for a notification
+    fn handle(&self, line: &str) -> Option<Value> {
+        let message: Value = match serde_json::from_str(line) {
+            Ok(message) => message,
+            Err(e) => {
+                return Some(error_response(
+                    Value::Null,
+                    &RpcError::new(PARSE_ERROR, e.to_string()),
+                ));
+            }
+        };
+        let id = message.get("id").cloned();
+        let Some(method) = message.get("method").and_then(Value::as_str) else {
+            // Responses to requests of ours; the server sends none
+            if message.get("result").is_some() || message.get("error").is_some() {
+                return None;
+            }
+            let error = RpcError::new(INVALID_REQUEST, "Expected a JSON-RPC request");
+            return Some(error_response(id.unwrap_or(Value::Null), &error));
+        };
+        let params = message.get("params").cloned().unwrap_or(Value::Null);
+        portal_core::portal_debug!("MCP request: {}", method);
+        let result = self.call(method, &params);
+        let id = id?;
+        Some(match result {
+            Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+            Err(error) => error_response(id, &error),
+        })
+    }
+
+    fn call(&self, method: &str, params: &Value) -> Result<Value, RpcError> {
+        match method {
+            "initialize" => Ok(initialize(params)),
+            "ping" => Ok(json!({})),
+            "tools/list" => Ok(json!({ "tools": tools() })),
+            "tools/call" => self.call_tool(params),
+            method if method.starts_with("notifications/") => Ok(Value::Null),
+            method => Err(RpcError::new(
+                METHOD_NOT_FOUND,
+                format!("Unknown method: {}", method),
+            )),
+        }
+    }
+
+    /// A tool's result; a tool that fails says so in the result, for the
+    /// agent to read, rather than as a protocol error
+    fn call_tool(&self, params: &Value) -> Result<Value, RpcError> {
+        let name = params
+            .get("name")
+            .and_then(Value::as_str)
+            .ok_or_else(|| RpcError::new(INVALID_PARAMS, "Missing tool name"))?;
+        let arguments = params.get("arguments").cloned().unwrap_or(json!({}));
+        let result = match name {
+            "get_status" => self.get_status(),
+            "list_providers" => crate::list::to_json(&self.portal, true),
+            "switch_provider" => crate::switch_request(&self.portal, &arguments, self.dry_run),
+            name => {
+                return Err(RpcError::new(
+                    INVALID_PARAMS,
+                    format!("Unknown tool: {}", name),
+                ));
+            }
+        };
+        Ok(match result {
+            Ok(value) => json!({
+                "content": [{ "type": "text", "text": pretty(&value) }],
+                "structuredContent": value,
+                "isError": false,
+            }),
+            Err(e) => {
+                portal_core::portal_info!("MCP {} failed: {:#}", name, e);
+                json!({
+                    "content": [{ "type": "text", "text": format!("Error: {:#}", e) }],
+                    "isError": true,
+                })
+            }
+        })
+    }
+
+    fn get_status(&self) -> Result<Value> {
+        let status = self.portal.status()?;
+        crate::status::to_json(&self.portal, &status)
+    }
+}
+
+fn initialize(params: &Value) -> Value {
+    let requested = params.get("protocolVersion").and_then(Value::as_str);
+    let version = PROTOCOL_VERSIONS
+        .iter()
+        .find(|version| Some(**version) == requested)
+        .unwrap_or(&PROTOCOL_VERSIONS[0]);
+    json!({
+        "protocolVersion": version,
+        "capabilities": { "tools": { "listChanged": false } },
+        "serverInfo": { "name": "portal", "version": env!("CARGO_PKG_VERSION") },
+        "instructions": "portal switches the oh-my-opencode agents between model \
+            providers. When a provider runs out of quota or keeps rate-limiting, \
+            call list_providers, then switch_provider to move to another one; \
+            the new models apply to sessions started after the switch.",
+    })
+}
+
+/// Tools offered, with a JSON Schema for each one's arguments
+fn tools() -> Value {
+    json!([
+        {
+            "name": "get_status",
+            "description": "Show the config portal manages: the provider the agents are on, \
+                each agent's model, and any temporary switch in effect.",
+            "inputSchema": { "type": "object", "properties": {} },
+        },
+        {
+            "name": "list_providers",
+            "description": "List the providers portal can switch to: built-in, custom, \
+                and aliases.",
+            "inputSchema": { "type": "object", "properties": {} },
+        },
+        {
+            "name": "switch_provider",
+            "description": "Switch the agents to another provider, writing the config \
+                (a backup is kept). Sessions started afterwards use the new models.",
+            "inputSchema": {
+                "type": "object",
+                "properties": {
+                    "provider": {
+                        "type": "string",
+                        "description": "Provider or alias, as list_providers shows it",
+                    },
+                    "agents": {
+                        "type": "array",
+                        "items": { "type": "string" },
+                        "description": "Only switch these agents (default: all of them)",
+                    },
+                    "set": {
+                        "type": "object",
+                        "additionalProperties": { "type": "string" },
+                        "description": "Give these agents these models over what the \
+                            provider gives them, e.g. {\"oracle\": \"openrouter/openai/gpt-5.2\"}",
+                    },
+                    "until": {
+                        "type": "string",
+                        "description": "Switch back after this long or at this time, \
+                            e.g. 90m, 2h, 17:30 (default: keep the switch)",
+                    },
+                    "dry_run": {
+                        "type": "boolean",
+                        "description": "Only show what would change",
+                    },
+                },
+                "required": ["provider"],
+            },
+        },
+    ])
+}
+
+fn error_response(id: Value, error: &RpcError) -> Value {
+    json!({
+        "jsonrpc": "2.0",
+        "id": id,
+        "error": { "code": error.code, "message": error.message },
+    })
+}
+
+fn pretty(value: &Value) -> String {
+    serde_json::to_string_pretty(value).unwrap_or_else(|_| value.to_string())
+}
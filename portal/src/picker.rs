@@ -0,0 +1,271 @@
+//! Minimal interactive list picker drawn on stderr.
+//!
+//! Uses raw terminal mode on unix for arrow-key navigation and falls back to
+//! a numbered prompt everywhere else (or when stdin/stderr aren't terminals).
+
+use anyhow::Result;
+use std::io::{self, BufRead, IsTerminal, Write};
+
+/// One selectable entry with optional preview lines shown while highlighted
+pub struct Item {
+    pub label: String,
+    pub preview: Vec<String>,
+}
+
+pub fn is_interactive() -> bool {
+    io::stdin().is_terminal() && io::stderr().is_terminal()
+}
+
+/// Let the user choose one of `items`. `Ok(None)` means they cancelled.
+pub fn pick(title: &str, items: &[Item]) -> Result<Option<usize>> {
+    if items.is_empty() {
+        return Ok(None);
+    }
+    // The arrow-key picker redraws the screen, which screen readers
+    // can't follow
+    #[cfg(unix)]
+    if is_interactive() && !crate::style::is_plain() {
+        return raw::pick(title, items);
+    }
+    pick_numbered(title, items)
+}
+
+fn pick_numbered(title: &str, items: &[Item]) -> Result<Option<usize>> {
+    let mut err = io::stderr();
+    writeln!(err, "{}", title)?;
+    for (i, item) in items.iter().enumerate() {
+        writeln!(err, "  {}) {}", i + 1, item.label)?;
+        for line in &item.preview {
+            writeln!(err, "       {}", line)?;
+        }
+    }
+    write!(err, "Choose [1-{}] (empty to cancel): ", items.len())?;
+    err.flush()?;
+
+    let mut input = String::new();
+    io::stdin().lock().read_line(&mut input)?;
+    let input = input.trim();
+    if input.is_empty() {
+        return Ok(None);
+    }
+    match input.parse::<usize>() {
+        Ok(n) if (1..=items.len()).contains(&n) => Ok(Some(n - 1)),
+        _ => Err(anyhow::anyhow!("Invalid choice: {}", input)),
+    }
+}
+
+/// Ask a yes/no question on stderr; anything but y/yes is "no"
+pub fn confirm(prompt: &str) -> Result<bool> {
+    let mut err = io::stderr();
+    write!(err, "{} [y/N] ", prompt)?;
+    err.flush()?;
+    let mut input = String::new();
+    io::stdin().lock().read_line(&mut input)?;
+    Ok(matches!(input.trim().to_lowercase().as_str(), "y" | "yes"))
+}
+
+/// Read a line from stdin without echoing it, e.g. a password. When stdin
+/// isn't a terminal, the whole of it is read instead, without the final
+/// newline.
+pub fn read_hidden(prompt: &str) -> Result<String> {
+    let mut input = String::new();
+    if !io::stdin().is_terminal() {
+        io::Read::read_to_string(&mut io::stdin(), &mut input)?;
+        return Ok(trim_newline(input));
+    }
+    let mut err = io::stderr();
+    write!(err, "{}", prompt)?;
+    err.flush()?;
+    {
+        #[cfg(unix)]
+        let _quiet = raw::NoEcho::enable()?;
+        io::stdin().lock().read_line(&mut input)?;
+    }
+    writeln!(err)?;
+    Ok(trim_newline(input))
+}
+
+fn trim_newline(mut text: String) -> String {
+    if text.ends_with('\n') {
+        text.pop();
+        if text.ends_with('\r') {
+            text.pop();
+        }
+    }
+    text
+}
+
+#[cfg(unix)]
+mod raw {
+    use super::Item;
+    use anyhow::Result;
+    use std::io::{self, Read, Write};
+
+    enum Key {
+        Up,
+        Down,
+        Enter,
+        Cancel,
+        Other,
+    }
+
+    /// Puts the terminal in raw mode and restores it on drop
+    struct RawMode {
+        original: libc::termios,
+    }
+
+    impl RawMode {
+        fn enable() -> io::Result<Self> {
+            // SAFETY: termios is plain data; tcgetattr fills it in.
+            let mut original: libc::termios = unsafe { std::mem::zeroed() };
+            // SAFETY: `original` is a valid termios to write to
+            if unsafe { libc::tcgetattr(libc::STDIN_FILENO, &mut original) } != 0 {
+                return Err(io::Error::last_os_error());
+            }
+            let mut raw = original;
+            raw.c_lflag &= !(libc::ICANON | libc::ECHO | libc::ISIG);
+            raw.c_cc[libc::VMIN] = 1;
+            raw.c_cc[libc::VTIME] = 0;
+            set_attrs(&raw)?;
+            Ok(Self { original })
+        }
+    }
+
+    impl Drop for RawMode {
+        fn drop(&mut self) {
+            let _ = set_attrs(&self.original);
+        }
+    }
+
+    /// Stops the terminal echoing typed input, restoring it on drop
+    pub struct NoEcho {
+        original: libc::termios,
+    }
+
+    impl NoEcho {
+        pub fn enable() -> io::Result<Self> {
+            // SAFETY: termios is plain data; tcgetattr fills it in.
+            let mut original: libc::termios = unsafe { std::mem::zeroed() };
+            // SAFETY: `original` is a valid termios to write to
+            if unsafe { libc::tcgetattr(libc::STDIN_FILENO, &mut original) } != 0 {
+                return Err(io::Error::last_os_error());
+            }
+            let mut quiet = original;
+            quiet.c_lflag &= !libc::ECHO;
+            set_attrs(&quiet)?;
+            Ok(Self { original })
+        }
+    }
+
+    impl Drop for NoEcho {
+        fn drop(&mut self) {
+            let _ = set_attrs(&self.original);
+        }
+    }
+
+    fn set_attrs(attrs: &libc::termios) -> io::Result<()> {
+        // SAFETY: `attrs` is a valid termios, only read
+        if unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, attrs) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+
+    /// Read one byte, waiting at most a tenth of a second
+    fn read_byte_timeout(stdin: &mut io::Stdin) -> io::Result<Option<u8>> {
+        // SAFETY: termios is plain data; tcgetattr fills it in.
+        let mut attrs: libc::termios = unsafe { std::mem::zeroed() };
+        // SAFETY: `attrs` is a valid termios to write to
+        if unsafe { libc::tcgetattr(libc::STDIN_FILENO, &mut attrs) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let blocking = attrs;
+        attrs.c_cc[libc::VMIN] = 0;
+        attrs.c_cc[libc::VTIME] = 1;
+        set_attrs(&attrs)?;
+        let mut buf = [0u8; 1];
+        let read = stdin.read(&mut buf);
+        set_attrs(&blocking)?;
+        Ok(if read? == 1 { Some(buf[0]) } else { None })
+    }
+
+    fn read_key(stdin: &mut io::Stdin) -> io::Result<Key> {
+        let mut buf = [0u8; 1];
+        stdin.read_exact(&mut buf)?;
+        Ok(match buf[0] {
+            b'\r' | b'\n' => Key::Enter,
+            b'k' => Key::Up,
+            b'j' => Key::Down,
+            b'q' | 3 => Key::Cancel,
+            0x1b => match read_byte_timeout(stdin)? {
+                None => Key::Cancel,
+                Some(b'[') | Some(b'O') => match read_byte_timeout(stdin)? {
+                    Some(b'A') => Key::Up,
+                    Some(b'B') => Key::Down,
+                    _ => Key::Other,
+                },
+                Some(_) => Key::Other,
+            },
+            _ => Key::Other,
+        })
+    }
+
+    fn render(
+        out: &mut impl Write,
+        title: &str,
+        items: &[Item],
+        selected: usize,
+    ) -> io::Result<usize> {
+        let mut lines = 0;
+        write!(out, "{}\r\n", title)?;
+        lines += 1;
+        for (i, item) in items.iter().enumerate() {
+            if i == selected {
+                write!(out, "\x1b[1m> {}\x1b[0m\r\n", item.label)?;
+            } else {
+                write!(out, "  {}\r\n", item.label)?;
+            }
+            lines += 1;
+        }
+        write!(out, "\r\n")?;
+        lines += 1;
+        for line in &items[selected].preview {
+            write!(out, "    {}\r\n", line)?;
+            lines += 1;
+        }
+        write!(
+            out,
+            "\x1b[2m(arrows/j/k to move, enter to select, q to cancel)\x1b[0m\r\n"
+        )?;
+        lines += 1;
+        out.flush()?;
+        Ok(lines)
+    }
+
+    pub fn pick(title: &str, items: &[Item]) -> Result<Option<usize>> {
+        let mut stdin = io::stdin();
+        let mut err = io::stderr();
+        let _raw = RawMode::enable()?;
+
+        let mut selected = 0;
+        write!(err, "\x1b[?25l")?;
+        let mut drawn = render(&mut err, title, items, selected)?;
+
+        let choice = loop {
+            match read_key(&mut stdin)? {
+                Key::Up => selected = selected.checked_sub(1).unwrap_or(items.len() - 1),
+                Key::Down => selected = (selected + 1) % items.len(),
+                Key::Enter => break Some(selected),
+                Key::Cancel => break None,
+                Key::Other => continue,
+            }
+            // Move back to the top of the previous frame and clear it
+            write!(err, "\x1b[{}A\x1b[J", drawn)?;
+            drawn = render(&mut err, title, items, selected)?;
+        };
+
+        write!(err, "\x1b[{}A\x1b[J\x1b[?25h", drawn)?;
+        err.flush()?;
+        Ok(choice)
+    }
+}
@@ -0,0 +1,103 @@
+//! `portal prompt`: the current provider as a short string for a shell
+//! prompt or status bar. It only reads the state file every write to the
+//! default config keeps up to date, so it is cheap enough to run on every
+//! prompt.
+
+use anyhow::{Result, anyhow};
+use clap::ValueEnum;
+
+/// What `{symbol}` stands for
+const SYMBOL: &str = "⇄";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum PromptColor {
+    Red,
+    Green,
+    Yellow,
+    Blue,
+    Magenta,
+    Cyan,
+}
+
+impl PromptColor {
+    fn ansi(self) -> &'static str {
+        match self {
+            Self::Red => "31",
+            Self::Green => "32",
+            Self::Yellow => "33",
+            Self::Blue => "34",
+            Self::Magenta => "35",
+            Self::Cyan => "36",
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Self::Red => "red",
+            Self::Green => "green",
+            Self::Yellow => "yellow",
+            Self::Blue => "blue",
+            Self::Magenta => "magenta",
+            Self::Cyan => "cyan",
+        }
+    }
+}
+
+/// Where the string goes, which decides how colors are written
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum PromptTarget {
+    /// Plain ANSI escapes, for starship, fish, and most status bars
+    Ansi,
+    /// Escapes wrapped in \001 and \002, so bash measures PS1 right
+    Bash,
+    /// Escapes wrapped in %{ and %}, for a PROMPT with PROMPT_SUBST
+    Zsh,
+    /// tmux style markup, #[fg=...]
+    Tmux,
+}
+
+/// Fill in `template`'s `{provider}` and `{symbol}`, colored if asked
+pub fn render(
+    template: &str,
+    provider: &str,
+    color: Option<PromptColor>,
+    target: PromptTarget,
+) -> Result<String> {
+    let mut text = String::new();
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        text.push_str(&rest[..start]);
+        let end = rest[start..]
+            .find('}')
+            .ok_or_else(|| anyhow!("Unclosed '{{' in prompt template: {}", template))?;
+        match &rest[start + 1..start + end] {
+            "provider" => text.push_str(provider),
+            "symbol" => text.push_str(SYMBOL),
+            other => {
+                return Err(anyhow!(
+                    "Unknown placeholder {{{}}} in prompt template; use {{provider}} or {{symbol}}",
+                    other
+                ));
+            }
+        }
+        rest = &rest[start + end + 1..];
+    }
+    text.push_str(rest);
+
+    let Some(color) = color else {
+        return Ok(text);
+    };
+    let (on, off) = match target {
+        PromptTarget::Ansi => (format!("\x1b[{}m", color.ansi()), "\x1b[0m".to_string()),
+        PromptTarget::Bash => (
+            format!("\x01\x1b[{}m\x02", color.ansi()),
+            "\x01\x1b[0m\x02".to_string(),
+        ),
+        PromptTarget::Zsh => (
+            format!("%{{\x1b[{}m%}}", color.ansi()),
+            "%{\x1b[0m%}".to_string(),
+        ),
+        PromptTarget::Tmux => (format!("#[fg={}]", color.name()), "#[default]".to_string()),
+    };
+    Ok(format!("{}{}{}", on, text, off))
+}
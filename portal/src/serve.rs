@@ -0,0 +1,369 @@
+//! `portal serve`: a small HTTP API on a local port, so editors, status
+//! bars, and other tools can read and switch providers without shelling
+//! out. Every response is JSON; failures are `{"error": "..."}`.
+//!
+//! | Request          | Does                                                 |
+//! |------------------|------------------------------------------------------|
+//! | `GET /status`    | What `portal status --format json` prints            |
+//! | `GET /providers` | What `portal list --detailed --format json` prints   |
+//! | `POST /switch`   | Switch, as `{"provider": "openrouter", ...}` asks    |
+//! | `POST /revert`   | Restore the latest backup, or `{"backup": "<name>"}` |
+//!
+//! Requests are answered one at a time, each on its own connection.
+
+use anyhow::{Context, Result, anyhow};
+use portal_core::{Portal, backup};
+use serde_json::{Value, json};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::time::Duration;
+
+/// Longest a client may take to send its request
+const READ_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Largest request head (request line and headers) read
+const MAX_HEAD: usize = 16 * 1024;
+
+/// Largest request body read
+const MAX_BODY: usize = 64 * 1024;
+
+/// How a server answers
+pub struct ServeOptions {
+    pub listen: String,
+    /// Bearer token every request must carry, if any
+    pub token: Option<String>,
+    pub dry_run: bool,
+}
+
+/// Listen on `options.listen` until killed
+pub fn run(portal: &Portal, options: &ServeOptions) -> Result<()> {
+    let addrs: Vec<SocketAddr> = options
+        .listen
+        .to_socket_addrs()
+        .with_context(|| format!("Invalid listen address: {}", options.listen))?
+        .collect();
+    if options.token.is_none() && !addrs.iter().all(|addr| addr.ip().is_loopback()) {
+        return Err(anyhow!(
+            "Refusing to listen on {} without a token: anyone who can reach it could \
+             switch providers. Set one with --token-env, or listen on 127.0.0.1.",
+            options.listen
+        ));
+    }
+    let listener = TcpListener::bind(&addrs[..])
+        .with_context(|| format!("Failed to listen on {}", options.listen))?;
+    let local = listener.local_addr()?;
+    crate::note(format!(
+        "Listening on http://{}{}",
+        local,
+        if options.token.is_some() {
+            " (token required)"
+        } else {
+            ""
+        }
+    ));
+
+    for stream in listener.incoming() {
+        let stream = match stream {
+            Ok(stream) => stream,
+            Err(e) => {
+                portal_core::portal_warn!("couldn't accept a connection: {}", e);
+                continue;
+            }
+        };
+        if let Err(e) = answer(portal, options, local, stream) {
+            portal_core::portal_debug!("connection failed: {:#}", e);
+        }
+    }
+    Ok(())
+}
+
+/// A request as far as the API needs it
+struct Request {
+    method: String,
+    path: String,
+    headers: Vec<(String, String)>,
+    body: Vec<u8>,
+}
+
+impl Request {
+    fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+/// A response: status code and JSON body
+struct Response {
+    status: u16,
+    body: Value,
+    allow: Option<&'static str>,
+}
+
+impl Response {
+    fn ok(body: Value) -> Self {
+        Self {
+            status: 200,
+            body,
+            allow: None,
+        }
+    }
+
+    fn error(status: u16, message: impl std::fmt::Display) -> Self {
+        Self {
+            status,
+            body: json!({ "error": message.to_string() }),
+            allow: None,
+        }
+    }
+}
+
+fn answer(
+    portal: &Portal,
+    options: &ServeOptions,
+    local: SocketAddr,
+    mut stream: TcpStream,
+) -> Result<()> {
+    stream.set_read_timeout(Some(READ_TIMEOUT))?;
+    let response = match read_request(&mut stream) {
+        Ok(request) => {
+            let response = respond(portal, options, local, &request);
+            portal_core::portal_info!("{} {} {}", request.method, request.path, response.status);
+            response
+        }
+        Err(e) => Response::error(400, format!("{:#}", e)),
+    };
+    write_response(&mut stream, &response)
+}
+
+fn read_request(stream: &mut TcpStream) -> Result<Request> {
+    let mut reader = BufReader::new(stream.take((MAX_HEAD + MAX_BODY) as u64));
+    let mut head = Vec::new();
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line)? == 0 {
+            return Err(anyhow!("Connection closed mid-request"));
+        }
+        if line.trim_end().is_empty() {
+            break;
+        }
+        head.push(line.trim_end().to_string());
+        if head.iter().map(String::len).sum::<usize>() > MAX_HEAD {
+            return Err(anyhow!("Request headers too large"));
+        }
+    }
+    let request_line = head.first().ok_or_else(|| anyhow!("Empty request"))?;
+    let mut parts = request_line.split_whitespace();
+    let (Some(method), Some(target)) = (parts.next(), parts.next()) else {
+        return Err(anyhow!("Malformed request line: {}", request_line));
+    };
+    let path = target.split('?').next().unwrap_or(target).to_string();
+    let headers: Vec<(String, String)> = head[1..]
+        .iter()
+        .filter_map(|line| line.split_once(':'))
+        .map(|(key, value)| (key.trim().to_string(), value.trim().to_string()))
+        .collect();
+    let mut request = Request {
+        method: method.to_string(),
+        path,
+        headers,
+        body: Vec::new(),
+    };
+    if let Some(length) = request.header("Content-Length") {
+        let length: usize = length
+            .parse()
+            .map_err(|_| anyhow!("Invalid Content-Length: {}", length))?;
+        if length > MAX_BODY {
+            return Err(anyhow!("Request body too large"));
+        }
+        request.body.resize(length, 0);
+        reader.read_exact(&mut request.body)?;
+    }
+    Ok(request)
+}
+
+fn respond(
+    portal: &Portal,
+    options: &ServeOptions,
+    local: SocketAddr,
+    request: &Request,
+) -> Response {
+    if let Some(token) = &options.token {
+        let given = request
+            .header("Authorization")
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .unwrap_or_default();
+        if !same_token(given.trim(), token) {
+            return Response::error(401, "Missing or wrong bearer token");
+        }
+    } else if !request
+        .header("Host")
+        .is_some_and(|host| is_local_host(host, &options.listen, local))
+    {
+        // Without a token, a page could reach the server through DNS
+        // rebinding, under a host name of its own
+        return Response::error(403, "The Host header doesn't name this server");
+    }
+    let allow = match request.path.as_str() {
+        "/status" | "/providers" => "GET",
+        "/switch" | "/revert" => "POST",
+        path => return Response::error(404, format!("No such endpoint: {}", path)),
+    };
+    if request.method != allow {
+        return Response {
+            allow: Some(allow),
+            ..Response::error(405, format!("{} takes {}", request.path, allow))
+        };
+    }
+    // A web page can only send JSON to another origin after asking first,
+    // which this server never allows; so requiring it keeps pages the
+    // browser visits from switching providers
+    if allow == "POST"
+        && !request
+            .header("Content-Type")
+            .is_some_and(|value| value.starts_with("application/json"))
+    {
+        return Response::error(415, "POST requests must be Content-Type: application/json");
+    }
+
+    let result = match request.path.as_str() {
+        "/status" => portal
+            .status()
+            .and_then(|status| crate::status::to_json(portal, &status)),
+        "/providers" => crate::list::to_json(portal, true),
+        path => match body_json(&request.body) {
+            Ok(body) if path == "/switch" => crate::switch_request(portal, &body, options.dry_run),
+            Ok(body) => revert(portal, &body, options.dry_run),
+            Err(e) => return Response::error(400, format!("{:#}", e)),
+        },
+    };
+    match result {
+        Ok(body) => Response::ok(body),
+        Err(e) => Response::error(500, format!("{:#}", e)),
+    }
+}
+
+/// A POST's body; empty is `{}`
+fn body_json(body: &[u8]) -> Result<Value> {
+    if body.iter().all(u8::is_ascii_whitespace) {
+        return Ok(json!({}));
+    }
+    let body: Value = serde_json::from_slice(body).context("Request body isn't valid JSON")?;
+    if !body.is_object() {
+        return Err(anyhow!("Request body must be a JSON object"));
+    }
+    Ok(body)
+}
+
+/// `POST /revert`: restore the latest backup, or the one `backup` names
+/// (a file name in the current backup channel, as `GET /status` lists
+/// them), as `portal revert` does
+fn revert(portal: &Portal, request: &Value, dry_run: bool) -> Result<Value> {
+    if crate::kiosk_providers(portal)?.is_some() {
+        return Err(anyhow!("Not available in kiosk mode"));
+    }
+    let backup = match request.get("backup") {
+        None | Some(Value::Null) => None,
+        Some(Value::String(name)) => Some(
+            backup::list_backups(&portal.backups())
+                .into_iter()
+                .find(|path| {
+                    path.file_name().is_some_and(|file| file == name.as_str())
+                        || path.as_os_str() == name.as_str()
+                })
+                .ok_or_else(|| anyhow!("No backup named '{}'", name))?,
+        ),
+        Some(_) => return Err(anyhow!("'backup' must be a string")),
+    };
+    let dry_run = dry_run || crate::bool_field(request, "dry_run")?;
+    let backup = if dry_run {
+        portal.resolve_backup(backup)?
+    } else {
+        portal.revert(backup)?
+    };
+    Ok(json!({
+        "dry_run": dry_run,
+        "config": portal.config_path(),
+        "backup": backup,
+    }))
+}
+
+/// Whether a request's `Host` header names this server: the address it
+/// was told to listen on or is bound to, or `localhost`, `127.0.0.1`, or
+/// `[::1]` on its port
+fn is_local_host(host: &str, listen: &str, local: SocketAddr) -> bool {
+    let host = host.to_ascii_lowercase();
+    host == listen.to_ascii_lowercase()
+        || host == local.to_string()
+        || ["localhost", "127.0.0.1", "[::1]"]
+            .iter()
+            .any(|name| host == format!("{}:{}", name, local.port()))
+}
+
+/// Compare tokens without stopping at the first difference, so response
+/// times don't give away how much of a guess was right
+fn same_token(given: &str, token: &str) -> bool {
+    given.len() == token.len()
+        && given
+            .bytes()
+            .zip(token.bytes())
+            .fold(0, |diff, (a, b)| diff | (a ^ b))
+            == 0
+}
+
+fn write_response(stream: &mut TcpStream, response: &Response) -> Result<()> {
+    let body = response.body.to_string();
+    let mut head = format!(
+        "HTTP/1.1 {} {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n",
+        response.status,
+        reason(response.status),
+        body.len() + 1
+    );
+    if let Some(allow) = response.allow {
+        head.push_str(&format!("Allow: {}\r\n", allow));
+    }
+    if response.status == 401 {
+        head.push_str("WWW-Authenticate: Bearer\r\n");
+    }
+    head.push_str("\r\n");
+    stream.write_all(head.as_bytes())?;
+    writeln!(stream, "{}", body)?;
+    stream.flush()?;
+    Ok(())
+}
+
+fn reason(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        400 => "Bad Request",
+        401 => "Unauthorized",
+        403 => "Forbidden",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        415 => "Unsupported Media Type",
+        _ => "Internal Server Error",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn accepts_only_local_host_names() {
+        let local: SocketAddr = "127.0.0.1:7777".parse().unwrap();
+        for host in [
+            "127.0.0.1:7777",
+            "localhost:7777",
+            "LocalHost:7777",
+            "[::1]:7777",
+        ] {
+            assert!(is_local_host(host, "127.0.0.1:7777", local), "{}", host);
+        }
+        assert!(is_local_host("portal.test:7777", "portal.test:7777", local));
+        for host in ["evil.example:7777", "localhost:80", "localhost", ""] {
+            assert!(!is_local_host(host, "127.0.0.1:7777", local), "{}", host);
+        }
+    }
+}
@@ -0,0 +1,166 @@
+//! `portal status`: the provider the config is on, each agent's model
+//! with its tier and approximate cost, and what changed since portal last
+//! wrote it.
+
+use anyhow::Result;
+use portal_core::changes::ConfigChange;
+use portal_core::pricing::Cost;
+use portal_core::{Portal, backup, ipc, settings, switch};
+use serde_json::{Value, json};
+
+use crate::{Cli, Format, current_project, expiry_json, print_config_changes, print_json};
+
+fn costs_json(portal: &Portal, status: &portal_core::Status) -> Result<Value> {
+    let pricing = portal.pricing()?;
+    let mappings = portal.mappings()?;
+    Ok(Value::Object(
+        status
+            .agents
+            .iter()
+            .filter_map(|(name, model)| Some((name, model.as_deref()?)))
+            .map(|(name, model)| {
+                let cost = pricing.cost(model, &mappings);
+                Ok((name.clone(), serde_json::to_value(cost)?))
+            })
+            .collect::<Result<_>>()?,
+    ))
+}
+
+/// Each agent's tier, for agents whose model has one
+fn tiers_json(portal: &Portal, status: &portal_core::Status) -> Result<Value> {
+    let mappings = portal.mappings()?;
+    Ok(status
+        .agents
+        .iter()
+        .filter_map(|(name, model)| Some((name.clone(), json!(mappings.tier(model.as_deref()?)?))))
+        .collect::<serde_json::Map<_, _>>()
+        .into())
+}
+
+/// `status --format json`, and the MCP server's `get_status`
+pub fn to_json(portal: &Portal, status: &portal_core::Status) -> Result<Value> {
+    Ok(json!({
+        "config": status.config_path,
+        "rendered": portal.linked_rendering(),
+        "provider": status.provider,
+        "providers": status.providers,
+        "agents": status.agents,
+        "compatibility": status.compatibility,
+        "costs": costs_json(portal, status)?,
+        "tiers": tiers_json(portal, status)?,
+        "environment": settings::active_environment(portal.state_dir())?,
+        "project": current_project()?.map(|project| project.path),
+        "temporary": portal.temporary_switch()?.map(|record| json!({
+            "provider": record.provider,
+            "expires": expiry_json(Some(&record.expiry())),
+            "previous_provider": record.previous_provider,
+        })),
+        "backup_channel": portal.backups().name(),
+        "backups": backup::list_backups(&portal.backups()),
+        "changes_since_write": changes_since_write(portal)?,
+    }))
+}
+
+/// What changed since portal last wrote the config; `None` if portal
+/// never wrote it
+pub fn changes_since_write(portal: &Portal) -> Result<Option<Vec<ConfigChange>>> {
+    if !portal.modified_since_write()? {
+        return Ok(Some(Vec::new()));
+    }
+    portal.changes_since_write()
+}
+
+/// Print the config's status, listing the changes since portal last wrote
+/// it with `show_changes`
+pub fn run(cli: &Cli, portal: &Portal, show_changes: bool) -> Result<()> {
+    // The running daemon has it parsed already
+    let status = match ipc::query_status(portal.state_dir(), portal.config_path()) {
+        Some(status) => status,
+        None => portal.status()?,
+    };
+
+    if cli.format == Format::Json {
+        return print_json(&to_json(portal, &status)?);
+    }
+
+    match portal.linked_rendering() {
+        Some(rendering) => println!(
+            "Config: {} -> {}",
+            status.config_path.display(),
+            rendering.display()
+        ),
+        None => println!("Config: {}", status.config_path.display()),
+    }
+    if let Some(environment) = settings::active_environment(portal.state_dir())? {
+        println!("Environment: {}", environment);
+    }
+    if let Some(project) = current_project()? {
+        println!("Project: {}", project.path.display());
+    }
+    println!();
+    let mixed = switch::mixed_providers(&status.providers);
+    match &status.provider {
+        Some(provider) => println!("Provider: {}", provider),
+        None if !mixed.is_empty() => println!("Provider: mixed ({})", mixed.join(", ")),
+        None => println!("Provider: Unknown"),
+    }
+    if status.compatibility.is_conservative() {
+        println!("Switching: model fields only, as portal doesn't fully understand this config:");
+        for reason in &status.compatibility.reasons {
+            println!("  - {}", reason);
+        }
+    }
+    if let Some(record) = portal.temporary_switch()? {
+        println!(
+            "Temporary: {}, then back to {}",
+            record.expiry().describe(),
+            record
+                .previous_provider
+                .as_deref()
+                .map(|p| format!("'{}'", p))
+                .unwrap_or_else(|| "the previous config".to_string())
+        );
+    }
+    let changes = changes_since_write(portal)?.unwrap_or_default();
+    if !changes.is_empty() {
+        println!(
+            "Modified since portal last wrote it: {} change(s){}",
+            changes.len(),
+            if show_changes {
+                ""
+            } else {
+                " (`portal status --changes` lists them)"
+            }
+        );
+    }
+    println!();
+    println!("Agents (tier, approximate cost per 1M tokens):");
+    let pricing = portal.pricing()?;
+    let mappings = portal.mappings()?;
+    for (name, model) in &status.agents {
+        let Some(model) = model else {
+            println!("  {}: (no model)", name);
+            continue;
+        };
+        let mut about = Vec::new();
+        if let Some(tier) = mappings.tier(model) {
+            about.push(tier.to_string());
+        }
+        match pricing.cost(model, &mappings) {
+            Cost::Unknown => {}
+            cost => about.push(cost.describe()),
+        }
+        if about.is_empty() {
+            println!("  {}: {}", name, model);
+        } else {
+            println!("  {}: {} ({})", name, model, about.join(", "));
+        }
+    }
+    if show_changes && !changes.is_empty() {
+        println!();
+        println!("Changes since portal last wrote the config:");
+        print_config_changes(&changes);
+    }
+
+    Ok(())
+}
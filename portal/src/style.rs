@@ -0,0 +1,49 @@
+//! Terminal styling for human-readable output
+
+use std::io::IsTerminal;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+static PLAIN: AtomicBool = AtomicBool::new(false);
+
+/// Turn on plain output (`--plain` or `PORTAL_PLAIN`) for the rest of the run
+pub fn set_plain(plain: bool) {
+    PLAIN.store(plain, Ordering::Relaxed);
+}
+
+/// Plain output is for screen readers: no colors or cursor tricks, and
+/// each fact on a labeled line of its own instead of in aligned columns
+pub fn is_plain() -> bool {
+    PLAIN.load(Ordering::Relaxed)
+}
+
+#[derive(Clone, Copy)]
+pub enum Color {
+    Red,
+    Green,
+    /// Faint, for lines that are there for context
+    Dim,
+}
+
+impl Color {
+    fn code(self) -> &'static str {
+        match self {
+            Color::Red => "31",
+            Color::Green => "32",
+            Color::Dim => "2",
+        }
+    }
+}
+
+/// Colors are used only when stdout is a terminal, `NO_COLOR` is unset,
+/// and output isn't plain
+pub fn colors_enabled() -> bool {
+    !is_plain() && std::env::var_os("NO_COLOR").is_none() && std::io::stdout().is_terminal()
+}
+
+pub fn paint(text: &str, color: Color) -> String {
+    if colors_enabled() {
+        format!("\x1b[{}m{}\x1b[0m", color.code(), text)
+    } else {
+        text.to_string()
+    }
+}
@@ -0,0 +1,74 @@
+//! Shell code for `portal hook --title`: before each prompt the terminal's
+//! window title is set to the working directory and the provider in the
+//! state file portal keeps up to date, so no portal process runs per prompt.
+
+use std::fmt::Write;
+use std::path::Path;
+
+use crate::completions::Shell;
+
+pub fn generate(shell: Shell, state_file: &Path) -> String {
+    let file = quote(shell, &state_file.to_string_lossy());
+    let mut out = String::new();
+    match shell {
+        Shell::Bash => {
+            writeln!(out, "_portal_title() {{").unwrap();
+            writeln!(out, "    local provider").unwrap();
+            writeln!(out, "    [[ -r {} ]] && read -r provider < {}", file, file).unwrap();
+            writeln!(out, "    if [[ -n $provider ]]; then").unwrap();
+            writeln!(
+                out,
+                "        printf '\\e]0;%s - portal: %s\\a' \"${{PWD/#$HOME/\\~}}\" \"$provider\""
+            )
+            .unwrap();
+            writeln!(out, "    fi").unwrap();
+            writeln!(out, "}}").unwrap();
+            writeln!(out, "case \";${{PROMPT_COMMAND}};\" in").unwrap();
+            writeln!(out, "    *\";_portal_title;\"*) ;;").unwrap();
+            writeln!(
+                out,
+                "    *) PROMPT_COMMAND=\"_portal_title${{PROMPT_COMMAND:+;$PROMPT_COMMAND}}\" ;;"
+            )
+            .unwrap();
+            writeln!(out, "esac").unwrap();
+        }
+        Shell::Zsh => {
+            writeln!(out, "_portal_title() {{").unwrap();
+            writeln!(out, "    local provider").unwrap();
+            writeln!(out, "    [[ -r {} ]] && read -r provider < {}", file, file).unwrap();
+            writeln!(out, "    if [[ -n $provider ]]; then").unwrap();
+            writeln!(
+                out,
+                "        printf '\\e]0;%s - portal: %s\\a' \"${{PWD/#$HOME/~}}\" \"$provider\""
+            )
+            .unwrap();
+            writeln!(out, "    fi").unwrap();
+            writeln!(out, "}}").unwrap();
+            writeln!(out, "autoload -Uz add-zsh-hook").unwrap();
+            writeln!(out, "add-zsh-hook precmd _portal_title").unwrap();
+        }
+        // fish sets the title itself from fish_title, before each prompt
+        // and each command
+        Shell::Fish => {
+            writeln!(out, "function fish_title").unwrap();
+            writeln!(out, "    set -l provider").unwrap();
+            writeln!(out, "    test -r {}; and read provider < {}", file, file).unwrap();
+            writeln!(out, "    if test -n \"$provider\"").unwrap();
+            writeln!(out, "        echo (prompt_pwd) - portal: $provider").unwrap();
+            writeln!(out, "    else").unwrap();
+            writeln!(out, "        prompt_pwd").unwrap();
+            writeln!(out, "    end").unwrap();
+            writeln!(out, "end").unwrap();
+        }
+    }
+    out
+}
+
+/// `text` in single quotes, which the shell reads literally
+fn quote(shell: Shell, text: &str) -> String {
+    match shell {
+        // fish allows `\'` inside single quotes, the others don't
+        Shell::Fish => format!("'{}'", text.replace('\\', "\\\\").replace('\'', "\\'")),
+        Shell::Bash | Shell::Zsh => format!("'{}'", text.replace('\'', "'\\''")),
+    }
+}
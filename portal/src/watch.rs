@@ -0,0 +1,267 @@
+//! Wait for changes to a set of files.
+//!
+//! Uses inotify on Linux, watching each file's directory so editors and
+//! tools that replace a file by renaming are noticed too. Elsewhere it
+//! falls back to polling the files' size and modification time.
+
+use anyhow::Result;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+pub use imp::Watcher;
+
+/// How often the polling fallback looks at the file
+#[cfg(not(target_os = "linux"))]
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+#[cfg(target_os = "linux")]
+mod imp {
+    use super::*;
+    use anyhow::{Context, anyhow};
+    use std::collections::{BTreeMap, BTreeSet};
+    use std::ffi::{CString, OsString};
+    use std::io;
+    use std::os::unix::ffi::{OsStrExt, OsStringExt};
+    use std::time::Instant;
+
+    pub struct Watcher {
+        fd: libc::c_int,
+        /// Watch descriptor of each directory to the file names of interest in it
+        dirs: BTreeMap<libc::c_int, BTreeSet<OsString>>,
+    }
+
+    impl Watcher {
+        pub fn new(paths: &[PathBuf]) -> Result<Self> {
+            // SAFETY: inotify_init1 takes no pointers; the result is checked
+            let fd = unsafe { libc::inotify_init1(libc::IN_CLOEXEC) };
+            if fd < 0 {
+                return Err(io::Error::last_os_error()).context("Failed to start inotify");
+            }
+            let mut watcher = Self {
+                fd,
+                dirs: BTreeMap::new(),
+            };
+            watcher.set_files(paths)?;
+            Ok(watcher)
+        }
+
+        /// Watch exactly `paths` from now on, keeping the same inotify
+        /// instance (and the watches of directories still in use) and
+        /// removing the watches of directories no longer needed
+        pub fn set_files(&mut self, paths: &[PathBuf]) -> Result<()> {
+            let mut dirs: BTreeMap<libc::c_int, BTreeSet<OsString>> = BTreeMap::new();
+            for path in paths {
+                let dir = match path.parent() {
+                    Some(p) if !p.as_os_str().is_empty() => p,
+                    _ => Path::new("."),
+                };
+                let name = path
+                    .file_name()
+                    .ok_or_else(|| anyhow!("Not a file path: {}", path.display()))?
+                    .to_os_string();
+                let dir_c = CString::new(dir.as_os_str().as_bytes())?;
+                let mask = libc::IN_CLOSE_WRITE
+                    | libc::IN_MOVED_TO
+                    | libc::IN_MOVED_FROM
+                    | libc::IN_CREATE
+                    | libc::IN_DELETE;
+                // Adding a directory again returns its existing descriptor.
+                // SAFETY: `dir_c` is a NUL-terminated path that outlives the
+                // call, and `self.fd` is this watcher's inotify descriptor
+                let wd = unsafe { libc::inotify_add_watch(self.fd, dir_c.as_ptr(), mask) };
+                if wd < 0 {
+                    return Err(io::Error::last_os_error())
+                        .with_context(|| format!("Failed to watch {}", dir.display()));
+                }
+                dirs.entry(wd).or_default().insert(name);
+            }
+            for wd in self.dirs.keys().filter(|wd| !dirs.contains_key(wd)) {
+                // A directory that has gone away took its watch with it, so
+                // a failure here is nothing to report.
+                // SAFETY: only descriptors are passed, and a stale one just
+                // makes the call fail
+                unsafe { libc::inotify_rm_watch(self.fd, *wd) };
+            }
+            self.dirs = dirs;
+            Ok(())
+        }
+
+        /// Block until a watched file changes (`true`), or `timeout` passes
+        /// or a signal arrives (`false`). `None` waits indefinitely.
+        pub fn wait(&mut self, timeout: Option<Duration>) -> Result<bool> {
+            let deadline = timeout.map(|t| Instant::now() + t);
+            loop {
+                // Events about other files in the directories don't restart
+                // the timeout
+                let ms = deadline.map_or(-1, |deadline| {
+                    let left = deadline.saturating_duration_since(Instant::now());
+                    left.as_millis().min(i32::MAX as u128) as i32
+                });
+                let mut pfd = libc::pollfd {
+                    fd: self.fd,
+                    events: libc::POLLIN,
+                    revents: 0,
+                };
+                // SAFETY: `pfd` is one valid pollfd, as the count says
+                let ready = unsafe { libc::poll(&mut pfd, 1, ms) };
+                if ready < 0 {
+                    let err = io::Error::last_os_error();
+                    if err.kind() == io::ErrorKind::Interrupted {
+                        return Ok(false);
+                    }
+                    return Err(err).context("Failed to wait for file changes");
+                }
+                if ready == 0 {
+                    return Ok(false);
+                }
+                if self.read_events()? {
+                    return Ok(true);
+                }
+            }
+        }
+
+        /// Drain pending events; whether any was about a watched file
+        fn read_events(&mut self) -> Result<bool> {
+            let mut buf = [0u8; 4096];
+            // SAFETY: `buf` is writable for the `buf.len()` bytes asked for
+            let n = unsafe { libc::read(self.fd, buf.as_mut_ptr().cast(), buf.len()) };
+            if n < 0 {
+                return Err(io::Error::last_os_error()).context("Failed to read inotify events");
+            }
+            let header = std::mem::size_of::<libc::inotify_event>();
+            let mut offset = 0;
+            let mut hit = false;
+            while offset + header <= n as usize {
+                // SAFETY: the loop condition keeps a whole header within the
+                // `n` bytes read, and inotify_event is plain data
+                let event: libc::inotify_event =
+                    unsafe { std::ptr::read_unaligned(buf.as_ptr().add(offset).cast()) };
+                let start = offset + header;
+                let end = (start + event.len as usize).min(n as usize);
+                let name: Vec<u8> = buf[start..end]
+                    .iter()
+                    .copied()
+                    .take_while(|b| *b != 0)
+                    .collect();
+                let name = OsString::from_vec(name);
+                if self
+                    .dirs
+                    .get(&event.wd)
+                    .is_some_and(|names| names.contains(&name))
+                {
+                    hit = true;
+                }
+                offset = end;
+            }
+            Ok(hit)
+        }
+    }
+
+    impl Drop for Watcher {
+        fn drop(&mut self) {
+            // SAFETY: the watcher owns `fd`, and this is the only close
+            unsafe {
+                libc::close(self.fd);
+            }
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use std::fs;
+        use std::time::Instant;
+
+        fn scratch(name: &str) -> PathBuf {
+            let dir =
+                std::env::temp_dir().join(format!("portal-watch-{}-{}", name, std::process::id()));
+            let _ = fs::remove_dir_all(&dir);
+            fs::create_dir_all(&dir).unwrap();
+            dir
+        }
+
+        #[test]
+        fn other_files_changing_dont_extend_the_timeout() {
+            let dir = scratch("deadline");
+            let mut watcher = Watcher::new(&[dir.join("watched.json")]).unwrap();
+            let noise = dir.join("other.json");
+            let writer = std::thread::spawn(move || {
+                for i in 0..30 {
+                    fs::write(&noise, i.to_string()).unwrap();
+                    std::thread::sleep(Duration::from_millis(20));
+                }
+            });
+            let started = Instant::now();
+            assert!(!watcher.wait(Some(Duration::from_millis(200))).unwrap());
+            assert!(started.elapsed() < Duration::from_millis(500));
+            writer.join().unwrap();
+        }
+
+        #[test]
+        fn set_files_drops_directories_no_longer_watched() {
+            let (old, new) = (scratch("old"), scratch("new"));
+            let mut watcher = Watcher::new(&[old.join("a.json")]).unwrap();
+            watcher.set_files(&[new.join("b.json")]).unwrap();
+            assert_eq!(watcher.dirs.len(), 1);
+            let fdinfo = fs::read_to_string(format!("/proc/self/fdinfo/{}", watcher.fd)).unwrap();
+            assert_eq!(fdinfo.matches("inotify wd:").count(), 1);
+            fs::write(old.join("a.json"), "{}").unwrap();
+            assert!(!watcher.wait(Some(Duration::from_millis(50))).unwrap());
+            fs::write(new.join("b.json"), "{}").unwrap();
+            assert!(watcher.wait(Some(Duration::from_secs(5))).unwrap());
+        }
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+mod imp {
+    use super::*;
+    use std::fs;
+    use std::time::{Instant, SystemTime};
+
+    pub struct Watcher {
+        files: Vec<(PathBuf, Option<(u64, SystemTime)>)>,
+    }
+
+    fn stamp(path: &Path) -> Option<(u64, SystemTime)> {
+        let metadata = fs::metadata(path).ok()?;
+        Some((metadata.len(), metadata.modified().ok()?))
+    }
+
+    impl Watcher {
+        pub fn new(paths: &[PathBuf]) -> Result<Self> {
+            let mut watcher = Self { files: Vec::new() };
+            watcher.set_files(paths)?;
+            Ok(watcher)
+        }
+
+        /// Watch exactly `paths` from now on
+        pub fn set_files(&mut self, paths: &[PathBuf]) -> Result<()> {
+            self.files = paths.iter().map(|p| (p.clone(), stamp(p))).collect();
+            Ok(())
+        }
+
+        /// Block until a watched file changes (`true`) or `timeout` passes
+        /// (`false`). `None` waits indefinitely.
+        pub fn wait(&mut self, timeout: Option<Duration>) -> Result<bool> {
+            let started = Instant::now();
+            loop {
+                let mut changed = false;
+                for (path, last) in &mut self.files {
+                    let current = stamp(path);
+                    if current != *last {
+                        *last = current;
+                        changed = true;
+                    }
+                }
+                if changed {
+                    return Ok(true);
+                }
+                if timeout.is_some_and(|t| started.elapsed() >= t) {
+                    return Ok(false);
+                }
+                std::thread::sleep(POLL_INTERVAL);
+            }
+        }
+    }
+}